@@ -0,0 +1,212 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Benchmarks comparing `XcbDisplay` with `breadx`'s own connection and
+//! with `x11rb`'s `libxcb` connection.
+//!
+//! These talk to the X server in `$DISPLAY`. Connections that can't be
+//! made are left out, so with no server at all, nothing is measured.
+//!
+//! - `requests` sends a burst of `NoOperation` requests and waits for the
+//!   server to get through them.
+//! - `reply` waits for the reply to a single `GetInputFocus`.
+//! - `events` sends `ClientMessage` events to a window of our own and
+//!   waits for them to come back, parsing each one.
+
+use breadx::{
+    display::{Display, DisplayConnection, DisplayExt, DisplayFunctionsExt},
+    protocol::xproto::{self, ClientMessageEvent, EventMask, SendEventRequest, Window},
+};
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BenchmarkGroup, Criterion, Throughput,
+};
+use std::borrow::Cow;
+use whitebreadx::XcbDisplay;
+use x11rb::{
+    connection::Connection as _, protocol::xproto::ConnectionExt as _, xcb_ffi::XCBConnection,
+};
+
+/// The number of requests sent for each iteration of `requests`.
+const REQUESTS: u64 = 1000;
+
+/// The number of events sent for each iteration of `events`.
+const EVENTS: u64 = 100;
+
+/// A display for each of the connections being compared.
+struct Displays {
+    xcb: Option<XcbDisplay>,
+    breadx: Option<DisplayConnection>,
+    x11rb: Option<XCBConnection>,
+}
+
+impl Displays {
+    fn connect() -> Self {
+        let displays = Self {
+            xcb: XcbDisplay::connect(None).ok(),
+            breadx: DisplayConnection::connect(None).ok(),
+            x11rb: XCBConnection::connect(None).ok().map(|(conn, _)| conn),
+        };
+
+        if displays.xcb.is_none() && displays.breadx.is_none() && displays.x11rb.is_none() {
+            eprintln!("couldn't connect to an X server, skipping benchmarks");
+        }
+
+        displays
+    }
+}
+
+fn requests(c: &mut Criterion) {
+    let mut displays = Displays::connect();
+    let mut group = c.benchmark_group("requests");
+    group.throughput(Throughput::Elements(REQUESTS));
+
+    if let Some(display) = &mut displays.xcb {
+        group.bench_function("whitebreadx", |b| b.iter(|| breadx_requests(display)));
+    }
+    if let Some(display) = &mut displays.breadx {
+        group.bench_function("breadx", |b| b.iter(|| breadx_requests(display)));
+    }
+    if let Some(conn) = &displays.x11rb {
+        group.bench_function("x11rb", |b| {
+            b.iter(|| {
+                for _ in 0..REQUESTS {
+                    conn.no_operation().unwrap().ignore_error();
+                }
+                conn.get_input_focus().unwrap().reply().unwrap();
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn breadx_requests(display: &mut impl Display) {
+    for _ in 0..REQUESTS {
+        display.no_operation().unwrap();
+    }
+    display.get_input_focus_immediate().unwrap();
+}
+
+fn reply(c: &mut Criterion) {
+    let mut displays = Displays::connect();
+    let mut group = c.benchmark_group("reply");
+
+    if let Some(display) = &mut displays.xcb {
+        group.bench_function("whitebreadx", |b| {
+            b.iter(|| display.get_input_focus_immediate().unwrap())
+        });
+    }
+    if let Some(display) = &mut displays.breadx {
+        group.bench_function("breadx", |b| {
+            b.iter(|| display.get_input_focus_immediate().unwrap())
+        });
+    }
+    if let Some(conn) = &displays.x11rb {
+        group.bench_function("x11rb", |b| {
+            b.iter(|| conn.get_input_focus().unwrap().reply().unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn events(c: &mut Criterion) {
+    let mut displays = Displays::connect();
+    let mut group = c.benchmark_group("events");
+    group.throughput(Throughput::Elements(EVENTS));
+
+    if let Some(display) = &mut displays.xcb {
+        breadx_events(&mut group, "whitebreadx", display);
+    }
+    if let Some(display) = &mut displays.breadx {
+        breadx_events(&mut group, "breadx", display);
+    }
+    if let Some(conn) = &displays.x11rb {
+        let screen = &conn.setup().roots[0];
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            0,
+            window,
+            screen.root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            xproto::WindowClass::INPUT_ONLY,
+            0,
+            &xproto::CreateWindowAux::new(),
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+
+        let event = client_message(window);
+        group.bench_function("x11rb", |b| {
+            b.iter(|| {
+                for _ in 0..EVENTS {
+                    conn.send_event(false, window, EventMask::NO_EVENT, event)
+                        .unwrap()
+                        .ignore_error();
+                }
+                conn.flush().unwrap();
+                for _ in 0..EVENTS {
+                    conn.wait_for_event().unwrap();
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn breadx_events(group: &mut BenchmarkGroup<'_, WallTime>, name: &str, display: &mut impl Display) {
+    let root = display.default_screen().root;
+    let window = display.generate_xid().unwrap();
+    display
+        .create_window_checked(
+            0,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            xproto::WindowClass::INPUT_ONLY,
+            0,
+            xproto::CreateWindowAux::new(),
+        )
+        .unwrap();
+
+    // breadx's send_event() can't take a window as the destination
+    let event = client_message(window);
+    group.bench_function(name, |b| {
+        b.iter(|| {
+            for _ in 0..EVENTS {
+                let request = SendEventRequest {
+                    propagate: false,
+                    destination: window,
+                    event_mask: EventMask::NO_EVENT.into(),
+                    event: Cow::Borrowed(&event),
+                };
+                display.send_void_request(request, true).unwrap();
+            }
+            display.flush().unwrap();
+            for _ in 0..EVENTS {
+                display.wait_for_event().unwrap();
+            }
+        })
+    });
+}
+
+/// A `ClientMessage`, which the server sends back to whoever created
+/// `window` when it's sent with no event mask.
+fn client_message(window: Window) -> [u8; 32] {
+    ClientMessageEvent::new(32, window, xproto::AtomEnum::WM_NAME, [0u32; 5]).into()
+}
+
+criterion_group!(benches, requests, reply, events);
+criterion_main!(benches);
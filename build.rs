@@ -0,0 +1,107 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Tells the linker where to find `libxcb` and `libX11` when they aren't
+//! in the default search path, e.g. in a cross-compilation sysroot, and
+//! links their static archives with the `static` feature.
+
+use std::{env, process::Command};
+
+fn main() {
+    // with dynamic linking, nothing is linked at build time
+    if env::var_os("CARGO_FEATURE_DL").is_some() {
+        return;
+    }
+
+    let target = env::var("TARGET").unwrap_or_default();
+    let host = env::var("HOST").unwrap_or_default();
+
+    let xlib = env::var_os("CARGO_FEATURE_XLIB").is_some();
+    let link_static = env::var_os("CARGO_FEATURE_STATIC").is_some();
+
+    let mut libraries = vec![("XCB", "xcb")];
+    if xlib {
+        libraries.push(("X11", "x11"));
+    }
+
+    for (library, package) in libraries {
+        let var = format!("WHITEBREADX_{}_LIB_DIR", library);
+        let mut dir = target_var(&var, &target, &host);
+
+        // rustc doesn't search the system directories for static
+        // archives, so ask pkg-config where they are
+        if dir.is_none() && link_static {
+            dir = pkg_config_libdir(package);
+        }
+
+        if let Some(dir) = dir {
+            println!("cargo:rustc-link-search=native={}", dir);
+        }
+    }
+
+    if link_static {
+        // archives have to come before the archives they depend on
+        let mut archives: Vec<&str> = Vec::new();
+        if xlib {
+            archives.extend(&["X11-xcb", "X11"]);
+        }
+        archives.extend(&["xcb", "Xau", "Xdmcp"]);
+
+        for archive in archives {
+            println!("cargo:rustc-link-lib=static={}", archive);
+        }
+    }
+}
+
+/// Read a variable, preferring versions specific to the target.
+///
+/// Follows the same conventions as the `cc` crate: `VAR_<target>`, then
+/// `VAR_<target_with_underscores>`, then `TARGET_VAR` when cross compiling,
+/// then `VAR`.
+fn target_var(var: &str, target: &str, host: &str) -> Option<String> {
+    let mut names = vec![
+        format!("{}_{}", var, target),
+        format!("{}_{}", var, target.replace('-', "_")),
+    ];
+    if target != host {
+        names.push(format!("TARGET_{}", var));
+    }
+    names.push(var.to_string());
+
+    let mut found = None;
+    for name in names {
+        println!("cargo:rerun-if-env-changed={}", name);
+        if found.is_none() {
+            found = env::var(&name).ok().filter(|value| !value.is_empty());
+        }
+    }
+
+    found
+}
+
+/// Ask `pkg-config` for the library directory of a package.
+fn pkg_config_libdir(package: &str) -> Option<String> {
+    let pkg_config = env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_string());
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_PATH");
+    println!("cargo:rerun-if-env-changed=PKG_CONFIG_SYSROOT_DIR");
+
+    let output = Command::new(pkg_config)
+        .arg("--variable=libdir")
+        .arg(package)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let dir = String::from_utf8(output.stdout).ok()?;
+    let dir = dir.trim();
+    if dir.is_empty() {
+        None
+    } else {
+        Some(dir.to_string())
+    }
+}
@@ -116,7 +116,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_segment(
                     wid,
                     gc,
-                    &[
+                    [
                         xproto::Segment {
                             x1: 10,
                             y1: 10,
@@ -138,7 +138,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_fill_arc(
                     wid,
                     gc,
-                    &[xproto::Arc {
+                    [xproto::Arc {
                         x: 200,
                         y: 10,
                         width: 150,
@@ -153,7 +153,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_fill_arc(
                     wid,
                     gc,
-                    &[xproto::Arc {
+                    [xproto::Arc {
                         x: 200,
                         y: 10,
                         width: 150,
@@ -171,7 +171,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_arc(
                     wid,
                     gc,
-                    &[xproto::Arc {
+                    [xproto::Arc {
                         x: 200,
                         y: 10,
                         width: 150,
@@ -187,11 +187,9 @@ fn main() -> breadx::Result<()> {
                 // indicate that we have been clicked
                 println!("Detected click at ({}, {})", bp.event_x, bp.event_y);
             }
-            Event::ClientMessage(cme) => {
-                // check if it's telling us to exit
-                if cme.data.as_data32()[0] == wm_delete_window {
-                    break;
-                }
+            Event::ClientMessage(cme) if cme.data.as_data32()[0] == wm_delete_window => {
+                // it's telling us to exit
+                break;
             }
             _ => {}
         }
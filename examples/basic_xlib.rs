@@ -119,7 +119,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_segment(
                     wid,
                     gc,
-                    &[
+                    [
                         xproto::Segment {
                             x1: 10,
                             y1: 10,
@@ -141,7 +141,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_fill_arc(
                     wid,
                     gc,
-                    &[xproto::Arc {
+                    [xproto::Arc {
                         x: 200,
                         y: 10,
                         width: 150,
@@ -156,7 +156,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_fill_arc(
                     wid,
                     gc,
-                    &[xproto::Arc {
+                    [xproto::Arc {
                         x: 200,
                         y: 10,
                         width: 150,
@@ -174,7 +174,7 @@ fn main() -> breadx::Result<()> {
                 connection.poly_arc(
                     wid,
                     gc,
-                    &[xproto::Arc {
+                    [xproto::Arc {
                         x: 200,
                         y: 10,
                         width: 150,
@@ -190,11 +190,9 @@ fn main() -> breadx::Result<()> {
                 // indicate that we have been clicked
                 println!("Detected click at ({}, {})", bp.event_x, bp.event_y);
             }
-            Event::ClientMessage(cme) => {
-                // check if it's telling us to exit
-                if cme.data.as_data32()[0] == wm_delete_window {
-                    break;
-                }
+            Event::ClientMessage(cme) if cme.data.as_data32()[0] == wm_delete_window => {
+                // it's telling us to exit
+                break;
             }
             _ => {}
         }
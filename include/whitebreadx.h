@@ -0,0 +1,39 @@
+/*               Copyright John Nunley, 2022.
+ * Distributed under the Boost Software License, Version 1.0.
+ *       (See accompanying file LICENSE or copy at
+ *         https://www.boost.org/LICENSE_1_0.txt)
+ */
+
+/* C API for whitebreadx, available with the `c_api` feature. */
+
+#ifndef WHITEBREADX_H
+#define WHITEBREADX_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <xcb/xcb.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The request being sent will generate a reply. */
+#define WHITEBREADX_REQUEST_HAS_REPLY 1
+
+typedef struct WhitebreadxDisplay whitebreadx_display_t;
+
+whitebreadx_display_t *whitebreadx_connect(const char *display_name);
+void whitebreadx_destroy(whitebreadx_display_t *display);
+xcb_connection_t *whitebreadx_get_xcb_connection(whitebreadx_display_t *display);
+int whitebreadx_flush(whitebreadx_display_t *display);
+int whitebreadx_poll_event(whitebreadx_display_t *display, uint8_t *buffer,
+                           size_t capacity, size_t *length);
+uint64_t whitebreadx_send_request(whitebreadx_display_t *display,
+                                  const uint8_t *data, size_t length, int flags);
+xcb_void_cookie_t whitebreadx_void_cookie(uint64_t sequence);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* WHITEBREADX_H */
@@ -14,6 +14,8 @@ use core::{
 /// An allocation, made using the `libc` `alloc` function.
 pub(crate) struct CBox<T: ?Sized> {
     ptr: NonNull<T>,
+    /// How far into the allocation `ptr` starts, in bytes.
+    offset: usize,
 }
 
 impl<T: ?Sized> CBox<T> {
@@ -24,8 +26,20 @@ impl<T: ?Sized> CBox<T> {
     /// The pointer must be valid, not null and
     /// made from `alloc`.
     pub(crate) unsafe fn new(ptr: *mut T) -> Self {
+        Self::with_offset(ptr, 0)
+    }
+
+    /// Creates a new `CBox` from a pointer `offset` bytes into an
+    /// allocation.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be valid and not null, and the pointer `offset`
+    /// bytes before it must be made from `alloc`.
+    pub(crate) unsafe fn with_offset(ptr: *mut T, offset: usize) -> Self {
         CBox {
             ptr: NonNull::new_unchecked(ptr),
+            offset,
         }
     }
 
@@ -69,7 +83,8 @@ impl<T: Clone> CBox<[T]> {
 impl<T: ?Sized> Drop for CBox<T> {
     fn drop(&mut self) {
         unsafe {
-            libc::free(self.as_ptr() as *mut libc::c_void);
+            let start = (self.as_ptr() as *mut u8).sub(self.offset);
+            libc::free(start as *mut libc::c_void);
         }
     }
 }
@@ -0,0 +1,271 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Authorization protocols for connecting to the X server.
+//!
+//! `libxcb` sends a single protocol name and its data when connecting.
+//! `MIT-MAGIC-COOKIE-1` data is just the cookie from the Xauthority file,
+//! but other protocols compute new data for every connection.
+//! `XDM-AUTHORIZATION-1`, used by hardened `xdm` setups, encrypts the
+//! client's address and the current time with a key shared with the
+//! server, so it can't be replayed.
+//!
+//! [`AuthProtocol`] produces the data for a connection, and can be
+//! implemented for other protocols. [`protocol`] picks the implementation
+//! for an Xauthority entry, and
+//! [`XcbDisplay::connect_to_fd_with_protocol`] connects with one.
+//!
+//! [`XcbDisplay::connect_to_fd_with_protocol`]: crate::XcbDisplay::connect_to_fd_with_protocol
+
+mod des;
+
+use alloc::{boxed::Box, vec::Vec};
+use breadx::{Error, Result};
+use core::sync::atomic::{AtomicU32, Ordering};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The name of the `MIT-MAGIC-COOKIE-1` protocol.
+pub const MIT_MAGIC_COOKIE_1: &[u8] = b"MIT-MAGIC-COOKIE-1";
+/// The name of the `XDM-AUTHORIZATION-1` protocol.
+pub const XDM_AUTHORIZATION_1: &[u8] = b"XDM-AUTHORIZATION-1";
+
+/// The address of the client's end of the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientAddress {
+    /// A TCP connection over IPv4.
+    Inet(Ipv4Addr, u16),
+    /// A TCP connection over IPv6.
+    Inet6(Ipv6Addr, u16),
+    /// A connection over a Unix domain socket, or anything else without a
+    /// network address.
+    Local,
+}
+
+impl ClientAddress {
+    /// Get the address of the client's end of a connected socket.
+    #[cfg(unix)]
+    pub fn of_socket(fd: libc::c_int) -> std::io::Result<Self> {
+        use core::mem::{self, MaybeUninit};
+
+        let mut storage = MaybeUninit::<libc::sockaddr_storage>::zeroed();
+        let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+        if unsafe { libc::getsockname(fd, storage.as_mut_ptr().cast(), &mut len) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let storage = unsafe { storage.assume_init() };
+        let address = match libc::c_int::from(storage.ss_family) {
+            libc::AF_INET => {
+                let addr: libc::sockaddr_in = unsafe { mem::transmute_copy(&storage) };
+                ClientAddress::Inet(
+                    Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                    u16::from_be(addr.sin_port),
+                )
+            }
+            libc::AF_INET6 => {
+                let addr: libc::sockaddr_in6 = unsafe { mem::transmute_copy(&storage) };
+                ClientAddress::Inet6(
+                    Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                    u16::from_be(addr.sin6_port),
+                )
+            }
+            _ => ClientAddress::Local,
+        };
+
+        Ok(address)
+    }
+}
+
+/// An authorization protocol.
+pub trait AuthProtocol {
+    /// The name of the protocol, sent to the server.
+    fn name(&self) -> &[u8];
+
+    /// Produce the data sent to the server for a new connection made from
+    /// `client`.
+    fn data(&mut self, client: &ClientAddress) -> Result<Vec<u8>>;
+}
+
+/// `MIT-MAGIC-COOKIE-1`, which sends a fixed cookie.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MitMagicCookie {
+    cookie: Vec<u8>,
+}
+
+impl MitMagicCookie {
+    /// Use `cookie`, as read from an Xauthority file.
+    pub fn new(cookie: impl Into<Vec<u8>>) -> Self {
+        Self {
+            cookie: cookie.into(),
+        }
+    }
+}
+
+impl AuthProtocol for MitMagicCookie {
+    fn name(&self) -> &[u8] {
+        MIT_MAGIC_COOKIE_1
+    }
+
+    fn data(&mut self, _client: &ClientAddress) -> Result<Vec<u8>> {
+        Ok(self.cookie.clone())
+    }
+}
+
+/// `XDM-AUTHORIZATION-1`, which sends the client's address and the time,
+/// encrypted with DES.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XdmAuthorization {
+    /// The 8-byte authenticator, followed by the 8-byte key.
+    data: [u8; 16],
+}
+
+impl XdmAuthorization {
+    /// Use the 16 bytes of data from an Xauthority entry.
+    ///
+    /// Returns `None` if `data` isn't 16 bytes long.
+    pub fn new(data: &[u8]) -> Option<Self> {
+        if data.len() != 16 {
+            return None;
+        }
+
+        let mut this = Self { data: [0; 16] };
+        this.data.copy_from_slice(data);
+        Some(this)
+    }
+
+    /// Produce the data for a connection made from `client` at `time`,
+    /// in seconds since the Unix epoch.
+    pub fn data_at(&self, client: &ClientAddress, time: u32) -> Vec<u8> {
+        let mut plain = [0u8; 24];
+        plain[..8].copy_from_slice(&self.data[..8]);
+
+        // servers check the address and port of the peer, but can't for
+        // local connections or IPv6, which get a unique value instead
+        let (address, port) = match *client {
+            ClientAddress::Inet(address, port) => (address.octets(), port),
+            ClientAddress::Inet6(address, port) if is_v4_mapped(&address) => {
+                let octets = address.octets();
+                ([octets[12], octets[13], octets[14], octets[15]], port)
+            }
+            ClientAddress::Inet6(..) => ([0; 4], 0),
+            ClientAddress::Local => (
+                (0xffff_ffff - next_nonce()).to_be_bytes(),
+                std::process::id() as u16,
+            ),
+        };
+        plain[8..12].copy_from_slice(&address);
+        plain[12..14].copy_from_slice(&port.to_be_bytes());
+        plain[14..18].copy_from_slice(&time.to_be_bytes());
+
+        wrap(&plain, &self.data[8..])
+    }
+}
+
+impl AuthProtocol for XdmAuthorization {
+    fn name(&self) -> &[u8] {
+        XDM_AUTHORIZATION_1
+    }
+
+    fn data(&mut self, client: &ClientAddress) -> Result<Vec<u8>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| Error::make_msg("the system clock is set before 1970"))?;
+
+        Ok(self.data_at(client, now.as_secs() as u32))
+    }
+}
+
+/// Pick the protocol for an Xauthority entry with the given name and data.
+///
+/// Returns `None` for protocols this crate doesn't implement.
+pub fn protocol(name: &[u8], data: &[u8]) -> Option<Box<dyn AuthProtocol + Send>> {
+    if name == MIT_MAGIC_COOKIE_1 {
+        Some(Box::new(MitMagicCookie::new(data)))
+    } else if name == XDM_AUTHORIZATION_1 {
+        XdmAuthorization::new(data).map(|protocol| Box::new(protocol) as _)
+    } else {
+        None
+    }
+}
+
+/// Tell whether an IPv6 address is an IPv4 address mapped into IPv6.
+fn is_v4_mapped(address: &Ipv6Addr) -> bool {
+    address.segments()[..6] == [0, 0, 0, 0, 0, 0xffff]
+}
+
+/// Get a value that's different for every local connection.
+fn next_nonce() -> u32 {
+    static NONCE: AtomicU32 = AtomicU32::new(0);
+    NONCE.fetch_add(1, Ordering::Relaxed).wrapping_add(1)
+}
+
+/// Encrypt `input` with DES in CBC mode, the way `XdmcpWrap` does.
+fn wrap(input: &[u8; 24], wrapper: &[u8]) -> Vec<u8> {
+    let schedule = des::Schedule::new(key_from_wrapper(wrapper));
+
+    let mut output = Vec::with_capacity(input.len());
+    let mut previous = [0u8; 8];
+    for chunk in input.chunks(8) {
+        let mut block = [0u8; 8];
+        for ((block, &input), &previous) in block.iter_mut().zip(chunk).zip(previous.iter()) {
+            *block = input ^ previous;
+        }
+
+        previous = schedule.encrypt(block);
+        output.extend_from_slice(&previous);
+    }
+
+    output
+}
+
+/// Spread the 56 bits after the first byte of `wrapper` into the seven
+/// high bits of each byte of a DES key.
+fn key_from_wrapper(wrapper: &[u8]) -> [u8; 8] {
+    let bits = wrapper[1..8]
+        .iter()
+        .fold(0u64, |bits, &byte| (bits << 8) | u64::from(byte));
+
+    let mut key = [0u8; 8];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = (((bits >> (49 - 7 * i)) & 0x7f) as u8) << 1;
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ClientAddress, XdmAuthorization};
+    use std::net::Ipv4Addr;
+
+    const DATA: [u8; 16] = [
+        0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0x00, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54,
+        0x32,
+    ];
+
+    #[test]
+    fn xdm_authorization_matches_xdmcp_wrap() {
+        let auth = XdmAuthorization::new(&DATA).unwrap();
+        let client = ClientAddress::Inet(Ipv4Addr::new(192, 168, 1, 2), 40000);
+
+        // produced by `XdmcpWrap` from libXdmcp, given the same input
+        assert_eq!(
+            auth.data_at(&client, 1_000_000_000),
+            [
+                0x21, 0xc0, 0xf6, 0x0c, 0x61, 0x32, 0x1a, 0xca, 0x36, 0x98, 0x68, 0x62, 0xb2, 0x56,
+                0x1c, 0x04, 0xf4, 0x65, 0x6c, 0xe6, 0x12, 0xe9, 0x0e, 0xd6,
+            ]
+        );
+    }
+
+    #[test]
+    fn xdm_authorization_wants_16_bytes() {
+        assert!(XdmAuthorization::new(&DATA[..15]).is_none());
+        assert!(XdmAuthorization::new(&[0; 17]).is_none());
+    }
+}
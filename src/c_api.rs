@@ -0,0 +1,225 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! An `extern "C"` API for embedding a `whitebreadx` connection in C
+//! and C++ programs.
+//!
+//! The functions in this module are exported with unmangled names, and
+//! are declared in the `include/whitebreadx.h` header shipped with this
+//! crate. They allow a program written in another language to own an
+//! [`XcbDisplay`] while sharing it with Rust code, which can access it
+//! through [`whitebreadx_display_as_rust`].
+//!
+//! Functions that can fail return a negative value (or a null pointer)
+//! on failure. The connection is typically unusable after an error.
+
+use crate::{cbox::CBox, XcbCookie, XcbDisplay};
+use alloc::{boxed::Box, vec::Vec};
+use breadx::{
+    display::{Display, RawRequest},
+    protocol::ReplyFdKind,
+};
+use core::{ptr, slice};
+use cstr_core::CStr;
+use libc::{c_char, c_int, c_void};
+use std::io::IoSlice;
+
+/// The request being sent will generate a reply.
+pub const WHITEBREADX_REQUEST_HAS_REPLY: c_int = 1;
+
+/// The event was successfully written to the buffer.
+const EVENT_WRITTEN: c_int = 1;
+/// There was no event available.
+const NO_EVENT: c_int = 0;
+/// An error occurred.
+const ERROR: c_int = -1;
+/// The buffer was too small to hold the event.
+const BUFFER_TOO_SMALL: c_int = -2;
+
+/// A display owned by C code.
+pub struct WhitebreadxDisplay {
+    /// The underlying display.
+    display: XcbDisplay,
+    /// An event that was polled, but didn't fit in the caller's buffer.
+    pending_event: Option<CBox<[u8]>>,
+}
+
+/// Connect to the X server, returning a null pointer on failure.
+///
+/// `display_name` may be null, in which case the `DISPLAY` environment
+/// variable is used.
+///
+/// # Safety
+///
+/// `display_name` must be null or a valid, NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn whitebreadx_connect(
+    display_name: *const c_char,
+) -> *mut WhitebreadxDisplay {
+    let display_name = if display_name.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(display_name) })
+    };
+
+    match XcbDisplay::connect(display_name) {
+        Ok(display) => Box::into_raw(Box::new(WhitebreadxDisplay {
+            display,
+            pending_event: None,
+        })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Close the connection and free the display.
+///
+/// # Safety
+///
+/// `display` must be null or a pointer returned by [`whitebreadx_connect`]
+/// that has not already been destroyed.
+#[no_mangle]
+pub unsafe extern "C" fn whitebreadx_destroy(display: *mut WhitebreadxDisplay) {
+    if !display.is_null() {
+        drop(unsafe { Box::from_raw(display) });
+    }
+}
+
+/// Get the `xcb_connection_t` that backs the display.
+///
+/// # Safety
+///
+/// `display` must be a valid pointer returned by [`whitebreadx_connect`].
+#[no_mangle]
+pub unsafe extern "C" fn whitebreadx_get_xcb_connection(
+    display: *mut WhitebreadxDisplay,
+) -> *mut c_void {
+    unsafe { &*display }.display.as_raw_connection()
+}
+
+/// Flush all pending requests to the server.
+///
+/// Returns zero on success and a negative value on failure.
+///
+/// # Safety
+///
+/// `display` must be a valid pointer returned by [`whitebreadx_connect`].
+#[no_mangle]
+pub unsafe extern "C" fn whitebreadx_flush(display: *mut WhitebreadxDisplay) -> c_int {
+    let mut display = &unsafe { &*display }.display;
+
+    match display.flush() {
+        Ok(()) => 0,
+        Err(_) => ERROR,
+    }
+}
+
+/// Poll for an event, writing its raw bytes into `buffer`.
+///
+/// Returns `1` if an event was written, `0` if no event was available and
+/// a negative value on error. The length of the event is written to
+/// `length`. If `capacity` is too small to hold the event, `-2` is
+/// returned and `length` contains the required capacity; the event is
+/// kept and returned by the next call.
+///
+/// # Safety
+///
+/// `display` must be a valid pointer returned by [`whitebreadx_connect`],
+/// `buffer` must be valid for writes of `capacity` bytes and `length`
+/// must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn whitebreadx_poll_event(
+    display: *mut WhitebreadxDisplay,
+    buffer: *mut u8,
+    capacity: usize,
+    length: *mut usize,
+) -> c_int {
+    let display = unsafe { &mut *display };
+
+    let event = match display.pending_event.take() {
+        Some(event) => event,
+        None => match display.display.poll_for_raw_event_impl() {
+            Ok(Some((event, _))) => event,
+            Ok(None) => return NO_EVENT,
+            Err(_) => return ERROR,
+        },
+    };
+
+    unsafe {
+        *length = event.len();
+    }
+
+    if event.len() > capacity {
+        display.pending_event = Some(event);
+        return BUFFER_TOO_SMALL;
+    }
+
+    unsafe {
+        ptr::copy_nonoverlapping(event.as_ref().as_ptr(), buffer, event.len());
+    }
+
+    EVENT_WRITTEN
+}
+
+/// Send a raw request to the server, returning its sequence number.
+///
+/// `data` should contain the entire request, including its header. The
+/// length field of the header is filled in automatically. `flags` may
+/// contain [`WHITEBREADX_REQUEST_HAS_REPLY`] if the request generates a
+/// reply. Returns zero on failure.
+///
+/// # Safety
+///
+/// `display` must be a valid pointer returned by [`whitebreadx_connect`],
+/// and `data` must be valid for reads of `length` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn whitebreadx_send_request(
+    display: *mut WhitebreadxDisplay,
+    data: *const u8,
+    length: usize,
+    flags: c_int,
+) -> u64 {
+    let mut display = &unsafe { &*display }.display;
+
+    // requests are made up of a header and some number of 4-byte units
+    if data.is_null() || length < 4 || length & 3 != 0 {
+        return 0;
+    }
+
+    let data = unsafe { slice::from_raw_parts(data, length) };
+    let variant = if flags & WHITEBREADX_REQUEST_HAS_REPLY != 0 {
+        ReplyFdKind::ReplyWithoutFDs
+    } else {
+        ReplyFdKind::NoReply
+    };
+
+    let mut slices = [IoSlice::new(&[]), IoSlice::new(&[]), IoSlice::new(data)];
+    let mut buffer = [0; 8];
+    let request = RawRequest::new(&mut slices, Vec::new(), variant, None, &mut buffer);
+
+    display.send_request_raw(request).unwrap_or(0)
+}
+
+/// Convert a sequence number returned by [`whitebreadx_send_request`] into
+/// an `xcb_void_cookie_t`.
+///
+/// The cookie can be passed to `xcb_request_check` or `xcb_discard_reply`
+/// on the connection from [`whitebreadx_get_xcb_connection`]. Requests
+/// sent without [`WHITEBREADX_REQUEST_HAS_REPLY`] are sent checked, so
+/// their errors are returned by `xcb_request_check` rather than as events.
+#[no_mangle]
+pub extern "C" fn whitebreadx_void_cookie(sequence: u64) -> XcbCookie {
+    XcbCookie::from_sequence(sequence)
+}
+
+/// Get a reference to the [`XcbDisplay`] behind a display created by C
+/// code.
+///
+/// # Safety
+///
+/// `display` must be a valid pointer returned by [`whitebreadx_connect`],
+/// and must not be destroyed while the reference is in use.
+pub unsafe fn whitebreadx_display_as_rust<'a>(display: *mut WhitebreadxDisplay) -> &'a XcbDisplay {
+    unsafe { &(*display).display }
+}
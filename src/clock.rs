@@ -0,0 +1,255 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Mapping X server timestamps to the local monotonic clock.
+//!
+//! The server stamps input and property events with its own millisecond
+//! clock, which has nothing to do with [`Instant`]. Measuring input latency
+//! needs the two to be related, and so do requests such as grabs and
+//! selection conversions, which misbehave when given `CurrentTime` and
+//! should be given a real server time instead.
+//!
+//! An event can only be received after it is stamped, so every event gives
+//! a lower bound on the offset between the two clocks. [`ServerClock`]
+//! keeps the tightest bound from a window of recent events. Forgetting
+//! older events lets the estimate follow the drift between the clocks.
+//! When no input arrives for a while, [`ServerClock::probe`] makes the
+//! server send a fresh timestamp.
+
+use alloc::collections::VecDeque;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        xproto::{Atom, AtomEnum, PropMode, Timestamp, Window},
+        Event,
+    },
+    Result,
+};
+use std::time::{Duration, Instant};
+
+/// How long samples are kept for by default.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(30);
+
+/// The length of the server's clock, before it wraps around.
+const WRAP: i64 = 1 << 32;
+
+/// Correlates server timestamps with [`Instant`]s.
+#[derive(Debug, Clone)]
+pub struct ServerClock {
+    /// The instant local times are measured from.
+    origin: Instant,
+    /// The samples taken within the window, oldest first.
+    samples: VecDeque<Sample>,
+    /// How long samples are kept for.
+    window: Duration,
+    /// The latest server time seen, counting wraparounds.
+    latest: Option<i64>,
+    /// The atom used by `probe`, or zero if it hasn't been interned yet.
+    probe_atom: Atom,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    received: Instant,
+    /// The server time minus the local time, in milliseconds.
+    offset: i64,
+}
+
+impl Default for ServerClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerClock {
+    /// Create a clock that keeps samples for [`DEFAULT_WINDOW`].
+    pub fn new() -> Self {
+        Self::with_window(DEFAULT_WINDOW)
+    }
+
+    /// Create a clock that keeps samples for `window`.
+    ///
+    /// A longer window gives a better estimate when events are sparse,
+    /// but follows drift between the clocks more slowly.
+    pub fn with_window(window: Duration) -> Self {
+        Self {
+            origin: Instant::now(),
+            samples: VecDeque::new(),
+            window,
+            latest: None,
+            probe_atom: 0,
+        }
+    }
+
+    /// Tell whether any timestamps have been observed yet.
+    ///
+    /// Conversions return `None` until one has.
+    pub fn is_synchronized(&self) -> bool {
+        !self.samples.is_empty()
+    }
+
+    /// Record a server timestamp that was just received.
+    pub fn observe(&mut self, time: Timestamp) {
+        self.observe_at(time, Instant::now());
+    }
+
+    /// Record a server timestamp that was received at `received`.
+    ///
+    /// A timestamp of `CurrentTime` is ignored.
+    pub fn observe_at(&mut self, time: Timestamp, received: Instant) {
+        if time == 0 {
+            return;
+        }
+
+        let time = unwrap(time, self.latest.unwrap_or_else(|| time.into()));
+        if !matches!(self.latest, Some(latest) if latest >= time) {
+            self.latest = Some(time);
+        }
+
+        // keep the samples sorted by when they were received, even if
+        // they are recorded out of order
+        let offset = time - self.local_millis(received);
+        let index = self
+            .samples
+            .iter()
+            .rposition(|sample| sample.received <= received)
+            .map_or(0, |index| index + 1);
+        self.samples.insert(index, Sample { received, offset });
+
+        self.expire(received);
+    }
+
+    /// Record the timestamp carried by `event`, if it has one that was set
+    /// by the server.
+    ///
+    /// Call this for every event, as soon as it is received.
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Some(time) = event_time(event) {
+            self.observe(time);
+        }
+    }
+
+    /// Make the server send a fresh timestamp.
+    ///
+    /// This appends nothing to a property on `window`, which causes a
+    /// `PropertyNotify` event. `window` must have selected
+    /// `PropertyChange` events, and the event must be passed to
+    /// [`handle_event`] once it arrives. Probing every few seconds keeps
+    /// the clock accurate while there is no input.
+    ///
+    /// [`handle_event`]: ServerClock::handle_event
+    pub fn probe<D: Display + ?Sized>(&mut self, display: &mut D, window: Window) -> Result<()> {
+        if self.probe_atom == 0 {
+            let atom = display.intern_atom(false, b"WHITEBREADX_TIME_PROBE")?;
+            self.probe_atom = display.wait_for_reply(atom)?.atom;
+        }
+
+        display.change_property(
+            PropMode::APPEND,
+            window,
+            self.probe_atom,
+            AtomEnum::STRING.into(),
+            8,
+            0,
+            &[0u8; 0][..],
+        )?;
+        display.flush()
+    }
+
+    /// Get the instant at which the server's clock read `time`.
+    pub fn to_instant(&self, time: Timestamp) -> Option<Instant> {
+        let offset = self.offset()?;
+        let time = unwrap(time, self.latest?);
+        let local = time - offset;
+
+        if local >= 0 {
+            self.origin.checked_add(millis(local))
+        } else {
+            self.origin.checked_sub(millis(-local))
+        }
+    }
+
+    /// Get the time the server's clock read at `instant`.
+    pub fn to_server_time(&self, instant: Instant) -> Option<Timestamp> {
+        let offset = self.offset()?;
+        Some((self.local_millis(instant) + offset) as Timestamp)
+    }
+
+    /// Get the current time on the server's clock.
+    ///
+    /// Use this instead of `CurrentTime` for grabs and selections.
+    pub fn now(&self) -> Option<Timestamp> {
+        self.to_server_time(Instant::now())
+    }
+
+    /// Get how long ago the server's clock read `time`.
+    ///
+    /// For input events, this is the latency between the input and its
+    /// handling.
+    pub fn elapsed(&self, time: Timestamp) -> Option<Duration> {
+        self.to_instant(time)
+            .map(|instant| Instant::now().saturating_duration_since(instant))
+    }
+
+    /// The estimated offset from local to server time.
+    fn offset(&self) -> Option<i64> {
+        self.samples.iter().map(|sample| sample.offset).max()
+    }
+
+    /// Forget samples that have left the window, always keeping the newest.
+    fn expire(&mut self, now: Instant) {
+        while self.samples.len() > 1
+            && now.saturating_duration_since(self.samples[0].received) > self.window
+        {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Get `instant` in milliseconds since the origin.
+    fn local_millis(&self, instant: Instant) -> i64 {
+        match instant.checked_duration_since(self.origin) {
+            Some(since) => since.as_millis() as i64,
+            None => -(self.origin.duration_since(instant).as_millis() as i64),
+        }
+    }
+}
+
+/// Extend `time` to the wraparound of the server's clock nearest to
+/// `reference`.
+fn unwrap(time: Timestamp, reference: i64) -> i64 {
+    let time = (reference & !(WRAP - 1)) | i64::from(time);
+
+    if time - reference > WRAP / 2 {
+        time - WRAP
+    } else if reference - time > WRAP / 2 {
+        time + WRAP
+    } else {
+        time
+    }
+}
+
+fn millis(millis: i64) -> Duration {
+    Duration::from_millis(millis as u64)
+}
+
+/// Get the server-set timestamp of an event.
+///
+/// Selection events are left out, since their timestamps come from other
+/// clients.
+fn event_time(event: &Event) -> Option<Timestamp> {
+    let time = match event {
+        Event::KeyPress(event) => event.time,
+        Event::KeyRelease(event) => event.time,
+        Event::ButtonPress(event) => event.time,
+        Event::ButtonRelease(event) => event.time,
+        Event::MotionNotify(event) => event.time,
+        Event::EnterNotify(event) => event.time,
+        Event::LeaveNotify(event) => event.time,
+        Event::PropertyNotify(event) => event.time,
+        _ => return None,
+    };
+
+    Some(time)
+}
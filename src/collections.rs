@@ -0,0 +1,28 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Map and set types used throughout the crate.
+//!
+//! With the `fast_hash` feature, these are `hashbrown` collections using
+//! the Fx hasher from `rustc-hash`, which is faster than the standard
+//! library hasher and the `ahash` crate used in the `breadx` crate.
+//! Collision chances are higher, but none of our maps are keyed on
+//! untrusted data, so it shouldn't come up.
+//!
+//! Without it, the B-tree collections from `alloc` are used instead. The
+//! maps here are small enough that the difference is rarely noticeable,
+//! and it keeps two dependencies out of minimal builds.
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "fast_hash")] {
+        type BuildHasher = core::hash::BuildHasherDefault<rustc_hash::FxHasher>;
+
+        pub(crate) type Map<K, V> = hashbrown::HashMap<K, V, BuildHasher>;
+        pub(crate) type Set<T> = hashbrown::HashSet<T, BuildHasher>;
+    } else {
+        pub(crate) type Map<K, V> = alloc::collections::BTreeMap<K, V>;
+        pub(crate) type Set<T> = alloc::collections::BTreeSet<T>;
+    }
+}
@@ -0,0 +1,717 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Working out why a connection failed.
+//!
+//! `libxcb` reports almost every connection failure as a generic I/O
+//! error, and prints the server's reason for refusing a connection to
+//! stderr. Once it has failed, the display string is checked here and the
+//! connection retraced by hand, far enough to tell what went wrong.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use breadx::Error;
+use core::{fmt, str, time::Duration};
+use cstr_core::CStr;
+use std::{
+    env,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::Instant,
+};
+
+#[cfg(unix)]
+use crate::xauth;
+#[cfg(unix)]
+use std::os::unix::{
+    io::{AsRawFd, IntoRawFd, RawFd},
+    net::UnixStream,
+};
+
+/// How long to wait for the server while diagnosing a failure.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The first TCP port used by X servers.
+const X_TCP_PORT: u16 = 6000;
+
+/// The reason a connection to the X server failed.
+///
+/// Connection failures are returned as [`std::io::Error`]s wrapped in
+/// `breadx` errors, with a `ConnectError` as the inner error. It can be
+/// recovered with `as_io_error()` and `downcast_ref()`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConnectError {
+    /// No display was given, and `$DISPLAY` is not set.
+    NoDisplay,
+    /// The display string could not be parsed.
+    InvalidDisplay(String),
+    /// The host name in the display string could not be resolved.
+    UnknownHost(String),
+    /// There is no X server for the display.
+    NoSuchDisplay(String),
+    /// The X server for the display exists, but couldn't be connected to.
+    ConnectionRefused(String, io::Error),
+    /// The X server refused the authorization that was offered, giving
+    /// this reason.
+    AuthorizationDenied(String),
+    /// The X server refused the connection for another reason, such as
+    /// having too many clients.
+    SetupFailed(String),
+    /// The X server has no screen with this index.
+    InvalidScreen(usize),
+    /// The X server speaks a different version of the protocol.
+    ProtocolMismatch {
+        /// The major version the server speaks.
+        major: u16,
+        /// The minor version the server speaks.
+        minor: u16,
+    },
+}
+
+impl ConnectError {
+    fn kind(&self) -> io::ErrorKind {
+        match self {
+            ConnectError::NoDisplay | ConnectError::InvalidDisplay(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            ConnectError::UnknownHost(_) | ConnectError::NoSuchDisplay(_) => {
+                io::ErrorKind::NotFound
+            }
+            ConnectError::ConnectionRefused(_, err) => err.kind(),
+            ConnectError::AuthorizationDenied(_) => io::ErrorKind::PermissionDenied,
+            ConnectError::SetupFailed(_) => io::ErrorKind::ConnectionRefused,
+            ConnectError::InvalidScreen(_) => io::ErrorKind::InvalidInput,
+            ConnectError::ProtocolMismatch { .. } => io::ErrorKind::InvalidData,
+        }
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectError::NoDisplay => f.write_str("no display was given and $DISPLAY is not set"),
+            ConnectError::InvalidDisplay(display) => {
+                write!(f, "invalid display string `{}`", display)
+            }
+            ConnectError::UnknownHost(host) => write!(f, "unknown host {}", host),
+            ConnectError::NoSuchDisplay(display) => write!(f, "no such display: {}", display),
+            ConnectError::ConnectionRefused(display, err) => {
+                write!(f, "could not connect to {}: {}", display, err)
+            }
+            ConnectError::AuthorizationDenied(reason) => {
+                write!(f, "authorization denied: {}", reason)
+            }
+            ConnectError::SetupFailed(reason) => {
+                write!(f, "the server refused the connection: {}", reason)
+            }
+            ConnectError::InvalidScreen(screen) => {
+                write!(f, "the server has no screen {}", screen)
+            }
+            ConnectError::ProtocolMismatch { major, minor } => write!(
+                f,
+                "the server speaks X{}.{}, but this client needs X11",
+                major, minor
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConnectError::ConnectionRefused(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<ConnectError> for Error {
+    fn from(err: ConnectError) -> Self {
+        io::Error::new(err.kind(), err).into()
+    }
+}
+
+/// A parsed display string, `[protocol/][host]:display[.screen]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedDisplay {
+    /// The protocol to connect with, such as `tcp` or `unix`, if one was
+    /// given.
+    pub protocol: Option<String>,
+    /// The host the server runs on, without brackets around IPv6
+    /// addresses. Empty for the local machine.
+    pub host: String,
+    /// The number of the display.
+    pub display: u16,
+    /// The screen to use, or 0 if none was given.
+    pub screen: usize,
+}
+
+impl ParsedDisplay {
+    /// Tell whether this display is reached through a local socket.
+    pub(crate) fn is_local(&self) -> bool {
+        matches!(
+            self.protocol.as_deref(),
+            None | Some("unix") | Some("local")
+        ) && matches!(self.host.as_str(), "" | "unix")
+    }
+}
+
+/// Parse a display string the way `libxcb` does.
+///
+/// This can be used to check `$DISPLAY` or pick a screen before
+/// connecting. Returns `None` if the string isn't a valid display string.
+pub fn parse_display(display: &str) -> Option<ParsedDisplay> {
+    let colon = display.rfind(':')?;
+    let (host, rest) = (&display[..colon], &display[colon + 1..]);
+
+    let (protocol, host) = match host.rfind('/') {
+        Some(slash) => (Some(host[..slash].to_string()), &host[slash + 1..]),
+        None => (None, host),
+    };
+
+    // IPv6 addresses may be written in brackets
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+
+    let (number, screen) = match rest.find('.') {
+        Some(dot) => (&rest[..dot], rest[dot + 1..].parse().ok()?),
+        None => (rest, 0),
+    };
+
+    Some(ParsedDisplay {
+        protocol,
+        host: host.to_string(),
+        display: number.parse().ok()?,
+        screen,
+    })
+}
+
+/// Get the display string to use, and parse it.
+fn resolve(display: Option<&CStr>) -> Result<(String, ParsedDisplay), ConnectError> {
+    let display = match display {
+        Some(display) => display.to_string_lossy().into_owned(),
+        None => match env::var("DISPLAY") {
+            Ok(display) if !display.is_empty() => display,
+            _ => return Err(ConnectError::NoDisplay),
+        },
+    };
+
+    match parse_display(&display) {
+        Some(parsed) => Ok((display, parsed)),
+        None => Err(ConnectError::InvalidDisplay(display)),
+    }
+}
+
+/// A socket connected to the X server by hand.
+#[cfg(unix)]
+pub(crate) struct Opened {
+    /// The socket, which the caller now owns.
+    pub(crate) fd: RawFd,
+    /// The address of the server, as Xauthority entries give it.
+    pub(crate) family: u16,
+    pub(crate) address: Vec<u8>,
+    pub(crate) display: u16,
+    pub(crate) screen: usize,
+    /// The display string the socket was opened for.
+    pub(crate) name: String,
+}
+
+/// Connect a socket to `display`, giving up on TCP servers that don't
+/// answer within `timeout`, if one is given.
+#[cfg(unix)]
+pub(crate) fn open(
+    display: Option<&CStr>,
+    timeout: Option<Duration>,
+) -> Result<Opened, ConnectError> {
+    let (display, parsed) = resolve(display)?;
+
+    if !parsed.is_local() {
+        let mut opened = open_tcp(&parsed.host, parsed.display, parsed.screen, timeout)?;
+        opened.name = display;
+        return Ok(opened);
+    }
+
+    let stream = connect_local(&display, &parsed)?;
+    Ok(Opened {
+        fd: stream.into_raw_fd(),
+        family: xauth::FAMILY_LOCAL,
+        address: xauth::hostname(),
+        display: parsed.display,
+        screen: parsed.screen,
+        name: display,
+    })
+}
+
+/// Connect a TCP socket to the display with the given number on `host`.
+#[cfg(unix)]
+pub(crate) fn open_tcp(
+    host: &str,
+    number: u16,
+    screen: usize,
+    timeout: Option<Duration>,
+) -> Result<Opened, ConnectError> {
+    let stream = connect_tcp(host, number, timeout)?;
+    let _ = stream.set_nodelay(true);
+
+    let (family, address) = tcp_server_address(&stream);
+
+    // IPv6 addresses need brackets to be told apart from the display
+    let name = if host.contains(':') {
+        format!("[{}]:{}.{}", host, number, screen)
+    } else {
+        format!("{}:{}.{}", host, number, screen)
+    };
+
+    Ok(Opened {
+        fd: stream.into_raw_fd(),
+        family,
+        address,
+        display: number,
+        screen,
+        name,
+    })
+}
+
+/// Connect a socket to the server listening at `path`, where a leading
+/// `@` names a socket in the abstract namespace.
+#[cfg(unix)]
+pub(crate) fn open_unix(path: &str, screen: usize) -> Result<Opened, ConnectError> {
+    let stream = match path.strip_prefix('@') {
+        Some(name) => connect_abstract(name),
+        None => UnixStream::connect(path),
+    };
+    let stream = match stream {
+        Ok(stream) => stream,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(ConnectError::NoSuchDisplay(format!(
+                "no socket at {}",
+                path
+            )))
+        }
+        Err(err) => return Err(ConnectError::ConnectionRefused(path.to_string(), err)),
+    };
+
+    // servers name their sockets "X<display>", which Xauthority entries
+    // are looked up by
+    let display = path
+        .rfind('X')
+        .and_then(|x| path[x + 1..].parse().ok())
+        .unwrap_or(0);
+
+    Ok(Opened {
+        fd: stream.into_raw_fd(),
+        family: xauth::FAMILY_LOCAL,
+        address: xauth::hostname(),
+        display,
+        screen,
+        name: path.to_string(),
+    })
+}
+
+/// Find the server a connected socket leads to, as Xauthority entries
+/// give its address, along with the display number.
+///
+/// Returns `None` if the socket doesn't lead to an X server that can be
+/// recognized.
+#[cfg(all(unix, feature = "to_socket"))]
+pub(crate) fn peer(fd: RawFd) -> Option<(u16, Vec<u8>, u16)> {
+    use core::mem::{self, MaybeUninit};
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    let mut storage = MaybeUninit::<libc::sockaddr_storage>::zeroed();
+    let mut len = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    if unsafe { libc::getpeername(fd, storage.as_mut_ptr().cast(), &mut len) } != 0 {
+        return None;
+    }
+
+    let storage = unsafe { storage.assume_init() };
+    match libc::c_int::from(storage.ss_family) {
+        libc::AF_INET => {
+            let addr: libc::sockaddr_in = unsafe { mem::transmute_copy(&storage) };
+            let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+            let display = u16::from_be(addr.sin_port).checked_sub(X_TCP_PORT)?;
+            let (family, address) = xauth::server_address(IpAddr::V4(ip));
+            Some((family, address, display))
+        }
+        libc::AF_INET6 => {
+            let addr: libc::sockaddr_in6 = unsafe { mem::transmute_copy(&storage) };
+            let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+            let display = u16::from_be(addr.sin6_port).checked_sub(X_TCP_PORT)?;
+            let (family, address) = xauth::server_address(IpAddr::V6(ip));
+            Some((family, address, display))
+        }
+        libc::AF_UNIX => {
+            // servers listen on a path ending in "X<display>", which may
+            // be in the abstract namespace
+            let addr: libc::sockaddr_un = unsafe { mem::transmute_copy(&storage) };
+            let path_len = (len as usize).saturating_sub(mem::size_of::<libc::sa_family_t>());
+            let path: Vec<u8> = addr.sun_path[..path_len.min(addr.sun_path.len())]
+                .iter()
+                .map(|&c| c as u8)
+                .collect();
+            let path = match path.split_first() {
+                Some((0, rest)) => rest,
+                _ => &path[..],
+            };
+            let path = path.split(|&c| c == 0).next().unwrap_or(&[]);
+
+            let x = path.iter().rposition(|&c| c == b'X')?;
+            let display = str::from_utf8(&path[x + 1..]).ok()?.parse().ok()?;
+            Some((xauth::FAMILY_LOCAL, xauth::hostname(), display))
+        }
+        _ => None,
+    }
+}
+
+/// Get the error for a display string `libxcb` couldn't parse.
+pub(crate) fn invalid_display(display: Option<&CStr>) -> Error {
+    match resolve(display) {
+        Ok((display, _)) => ConnectError::InvalidDisplay(display),
+        Err(err) => err,
+    }
+    .into()
+}
+
+/// Get the error for a display string naming a screen the server doesn't
+/// have.
+pub(crate) fn invalid_screen(display: Option<&CStr>) -> Option<Error> {
+    resolve(display)
+        .ok()
+        .map(|(_, parsed)| ConnectError::InvalidScreen(parsed.screen).into())
+}
+
+/// Work out why connecting to `display` with the given authorization
+/// failed.
+///
+/// Without an authorization, the one `libxcb` would have found in the
+/// Xauthority file is sent, so that servers needing it aren't mistaken
+/// for ones refusing it. Returns `None` if nothing seems to be wrong.
+pub(crate) fn diagnose(display: Option<&CStr>, auth: Option<(&[u8], &[u8])>) -> Option<Error> {
+    let (display, parsed) = match resolve(display) {
+        Ok(resolved) => resolved,
+        Err(err) => return Some(err.into()),
+    };
+
+    let result = if parsed.is_local() {
+        diagnose_local(&display, &parsed, auth)
+    } else {
+        diagnose_tcp(&parsed, auth)
+    };
+
+    result.err().map(Into::into)
+}
+
+#[cfg(unix)]
+fn diagnose_local(
+    display: &str,
+    parsed: &ParsedDisplay,
+    auth: Option<(&[u8], &[u8])>,
+) -> Result<(), ConnectError> {
+    let mut stream = connect_local(display, parsed)?;
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+
+    let hostname = xauth::hostname();
+    let found = lookup_auth(
+        &stream,
+        auth,
+        xauth::FAMILY_LOCAL,
+        &hostname,
+        parsed.display,
+    );
+    handshake(&mut stream, auth.or_else(|| as_auth(&found)))
+}
+
+/// Look up the authorization `libxcb` would have sent, if none was given.
+///
+/// Errors are ignored, since this only retraces the connection to find
+/// out why it failed.
+#[cfg(unix)]
+fn lookup_auth(
+    stream: &impl AsRawFd,
+    auth: Option<(&[u8], &[u8])>,
+    family: u16,
+    address: &[u8],
+    display: u16,
+) -> Option<(Vec<u8>, Vec<u8>)> {
+    match auth {
+        Some(_) => None,
+        None => xauth::authorize(stream.as_raw_fd(), family, address, display).ok(),
+    }
+}
+
+#[cfg(unix)]
+fn as_auth(found: &Option<(Vec<u8>, Vec<u8>)>) -> Option<(&[u8], &[u8])> {
+    found.as_ref().map(|(name, data)| (&name[..], &data[..]))
+}
+
+/// Get the address Xauthority entries give for the server `stream` is
+/// connected to.
+#[cfg(unix)]
+fn tcp_server_address(stream: &TcpStream) -> (u16, Vec<u8>) {
+    match stream.peer_addr() {
+        Ok(peer) => xauth::server_address(peer.ip()),
+        Err(_) => (xauth::FAMILY_LOCAL, xauth::hostname()),
+    }
+}
+
+#[cfg(unix)]
+fn connect_local(display: &str, parsed: &ParsedDisplay) -> Result<UnixStream, ConnectError> {
+    let path = format!("/tmp/.X11-unix/X{}", parsed.display);
+
+    match connect_abstract(&path).or_else(|_| UnixStream::connect(&path)) {
+        Ok(stream) => Ok(stream),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(ConnectError::NoSuchDisplay(
+            format!("{} (no socket at {})", display, path),
+        )),
+        Err(err) => Err(ConnectError::ConnectionRefused(path, err)),
+    }
+}
+
+/// Connect to the socket in the abstract namespace that Linux servers
+/// listen on, which `libxcb` tries first.
+#[cfg(target_os = "linux")]
+fn connect_abstract(path: &str) -> io::Result<UnixStream> {
+    use core::mem;
+    use std::os::unix::io::FromRawFd;
+
+    let mut address: libc::sockaddr_un = unsafe { mem::zeroed() };
+    address.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    if path.len() + 1 > address.sun_path.len() {
+        return Err(io::ErrorKind::InvalidInput.into());
+    }
+    for (dest, &byte) in address.sun_path[1..].iter_mut().zip(path.as_bytes()) {
+        *dest = byte as libc::c_char;
+    }
+
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_STREAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the socket was just created, and is owned by nobody else
+    let stream = unsafe { UnixStream::from_raw_fd(fd) };
+
+    let len = mem::size_of::<libc::sa_family_t>() + 1 + path.len();
+    let result = unsafe {
+        libc::connect(
+            fd,
+            &address as *const libc::sockaddr_un as *const libc::sockaddr,
+            len as libc::socklen_t,
+        )
+    };
+
+    if result == 0 {
+        Ok(stream)
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn connect_abstract(_path: &str) -> io::Result<UnixStream> {
+    Err(io::ErrorKind::NotFound.into())
+}
+
+#[cfg(not(unix))]
+fn diagnose_local(
+    display: &str,
+    _parsed: &ParsedDisplay,
+    _auth: Option<(&[u8], &[u8])>,
+) -> Result<(), ConnectError> {
+    Err(ConnectError::NoSuchDisplay(format!(
+        "{} (local sockets aren't supported on this platform)",
+        display
+    )))
+}
+
+fn diagnose_tcp(parsed: &ParsedDisplay, auth: Option<(&[u8], &[u8])>) -> Result<(), ConnectError> {
+    let mut stream = connect_tcp(&parsed.host, parsed.display, Some(TIMEOUT))?;
+    let _ = stream.set_read_timeout(Some(TIMEOUT));
+
+    #[cfg(unix)]
+    {
+        let (family, address) = tcp_server_address(&stream);
+        let found = lookup_auth(&stream, auth, family, &address, parsed.display);
+        handshake(&mut stream, auth.or_else(|| as_auth(&found)))
+    }
+    #[cfg(not(unix))]
+    handshake(&mut stream, auth)
+}
+
+/// Connect to the display with the given number on `host`, trying each of
+/// its addresses in turn.
+///
+/// Without a timeout, this waits as long as the operating system does.
+fn connect_tcp(
+    host: &str,
+    number: u16,
+    timeout: Option<Duration>,
+) -> Result<TcpStream, ConnectError> {
+    let port = X_TCP_PORT.saturating_add(number);
+    let host = if host.is_empty() { "localhost" } else { host };
+
+    let addresses: Vec<_> = match (host, port).to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(_) => return Err(ConnectError::UnknownHost(host.to_string())),
+    };
+
+    // the timeout covers every address together, not each of them
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let mut last_error = None;
+    for address in addresses {
+        let stream = match deadline {
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    last_error = Some(io::ErrorKind::TimedOut.into());
+                    break;
+                }
+
+                TcpStream::connect_timeout(&address, deadline - now)
+            }
+            None => TcpStream::connect(address),
+        };
+        match stream {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    let err = last_error.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound));
+    Err(ConnectError::ConnectionRefused(
+        format!("{}:{}", host, port),
+        err,
+    ))
+}
+
+/// Send a setup request, and see what the server thinks of it.
+fn handshake(
+    stream: &mut (impl Read + Write),
+    auth: Option<(&[u8], &[u8])>,
+) -> Result<(), ConnectError> {
+    let (name, data) = auth.unwrap_or((&[], &[]));
+
+    let mut request = Vec::with_capacity(12 + name.len() + data.len() + 6);
+    request.push(if cfg!(target_endian = "little") {
+        b'l'
+    } else {
+        b'B'
+    });
+    request.push(0);
+    request.extend_from_slice(&11u16.to_ne_bytes());
+    request.extend_from_slice(&0u16.to_ne_bytes());
+    request.extend_from_slice(&(name.len() as u16).to_ne_bytes());
+    request.extend_from_slice(&(data.len() as u16).to_ne_bytes());
+    request.extend_from_slice(&[0, 0]);
+    for part in &[name, data] {
+        request.extend_from_slice(part);
+        request.resize(request.len() + (4 - part.len() % 4) % 4, 0);
+    }
+
+    // failures here are I/O problems that libxcb reported correctly
+    let mut header = [0u8; 8];
+    let io = stream
+        .write_all(&request)
+        .and_then(|()| stream.read_exact(&mut header));
+    if io.is_err() {
+        return Ok(());
+    }
+
+    let major = u16::from_ne_bytes([header[2], header[3]]);
+    let minor = u16::from_ne_bytes([header[4], header[5]]);
+    let length = u16::from_ne_bytes([header[6], header[7]]) as usize * 4;
+
+    match header[0] {
+        // failed
+        0 => {
+            if major != 11 {
+                return Err(ConnectError::ProtocolMismatch { major, minor });
+            }
+
+            let mut reason = vec![0; length];
+            let _ = stream.read_exact(&mut reason);
+            reason.truncate(header[1] as usize);
+
+            let reason = reason_string(reason);
+            if reason.contains("uthoriz") || reason.contains("uthentic") {
+                Err(ConnectError::AuthorizationDenied(reason))
+            } else {
+                Err(ConnectError::SetupFailed(reason))
+            }
+        }
+        // further authentication required, which libxcb can't do
+        2 => {
+            let mut reason = vec![0; length];
+            let _ = stream.read_exact(&mut reason);
+
+            Err(ConnectError::AuthorizationDenied(reason_string(reason)))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn reason_string(reason: Vec<u8>) -> String {
+    let reason = String::from_utf8_lossy(&reason);
+    let reason = reason.trim_end_matches(|c: char| c == '\0' || c.is_whitespace());
+
+    if reason.is_empty() {
+        "the server gave no reason".to_string()
+    } else {
+        reason.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_display, ParsedDisplay};
+    use alloc::string::ToString;
+
+    fn parsed(protocol: Option<&str>, host: &str, display: u16, screen: usize) -> ParsedDisplay {
+        ParsedDisplay {
+            protocol: protocol.map(ToString::to_string),
+            host: host.to_string(),
+            display,
+            screen,
+        }
+    }
+
+    #[test]
+    fn local_displays() {
+        assert_eq!(parse_display(":0"), Some(parsed(None, "", 0, 0)));
+        assert_eq!(parse_display(":0.1"), Some(parsed(None, "", 0, 1)));
+        assert_eq!(parse_display("unix:0"), Some(parsed(None, "unix", 0, 0)));
+
+        for &display in &[":0", ":0.1", "unix:0"] {
+            assert!(parse_display(display).unwrap().is_local());
+        }
+    }
+
+    #[test]
+    fn remote_displays() {
+        let display = parse_display("tcp/host:1").unwrap();
+        assert_eq!(display, parsed(Some("tcp"), "host", 1, 0));
+        assert!(!display.is_local());
+    }
+
+    #[test]
+    fn ipv6_hosts() {
+        assert_eq!(parse_display("[::1]:0.1"), Some(parsed(None, "::1", 0, 1)));
+        assert_eq!(parse_display("::1:0"), Some(parsed(None, "::1", 0, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_displays() {
+        for &display in &["host", ":", ":0.", ":x"] {
+            assert_eq!(parse_display(display), None, "{:?}", display);
+        }
+    }
+}
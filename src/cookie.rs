@@ -0,0 +1,78 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Cookie types compatible with the ones used by `libxcb`.
+
+use breadx::display::Cookie;
+use libc::c_uint;
+
+/// A cookie, laid out identically to the cookie types used by `libxcb`.
+///
+/// In C, every request function returns a cookie such as
+/// `xcb_void_cookie_t` or `xcb_get_geometry_cookie_t`. All of these
+/// are a struct containing a single `unsigned int`, so this type can be
+/// passed by value wherever C code expects any one of them. This allows
+/// a request sent through `breadx` to be checked or have its reply
+/// fetched by a C library sharing the connection, and vice versa.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[repr(C)]
+pub struct XcbCookie {
+    /// The lower 32 bits of the request's sequence number.
+    pub sequence: c_uint,
+}
+
+impl XcbCookie {
+    /// Create a cookie from the full sequence number of a request, as
+    /// returned by `breadx`.
+    ///
+    /// C cookies only hold 32 bits, so the upper bits are discarded. Use
+    /// [`XcbDisplay::widen_cookie`] to recover them.
+    ///
+    /// [`XcbDisplay::widen_cookie`]: crate::XcbDisplay::widen_cookie
+    #[must_use]
+    pub fn from_sequence(sequence: u64) -> Self {
+        XcbCookie {
+            sequence: sequence as c_uint,
+        }
+    }
+}
+
+impl<T> From<Cookie<T>> for XcbCookie {
+    fn from(cookie: Cookie<T>) -> Self {
+        Self::from_sequence(cookie.sequence())
+    }
+}
+
+/// Widen the lower 32 bits of a sequence number into a full sequence
+/// number, using `reference` as a nearby known sequence number.
+///
+/// The result is the sequence number closest to `reference` whose lower
+/// 32 bits are `low`.
+pub(crate) fn widen(reference: u64, low: u32) -> u64 {
+    widen_bits(reference, u64::from(low), 32)
+}
+
+/// Widen the 16-bit sequence number sent over the wire into a full
+/// sequence number, in the same way as [`widen`].
+pub(crate) fn widen16(reference: u64, low: u16) -> u64 {
+    widen_bits(reference, u64::from(low), 16)
+}
+
+fn widen_bits(reference: u64, low: u64, bits: u32) -> u64 {
+    let span: u64 = 1 << bits;
+    let half_span = span >> 1;
+
+    let candidate = (reference & !(span - 1)) | low;
+
+    if candidate > reference.saturating_add(half_span) && candidate >= span {
+        // the sequence number was sent before the last wraparound
+        candidate - span
+    } else if candidate.saturating_add(half_span) < reference {
+        // the sequence number was sent after the next wraparound
+        candidate.saturating_add(span)
+    } else {
+        candidate
+    }
+}
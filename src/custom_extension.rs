@@ -0,0 +1,150 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Parsing events and errors from extensions `breadx` doesn't know.
+
+use crate::sync::{rwl_read, rwl_write, RwLock};
+use alloc::{boxed::Box, vec::Vec};
+use breadx::{
+    protocol::{xproto::GE_GENERIC_EVENT, Event, ExtensionInformation},
+    Error, Result,
+};
+use core::mem;
+
+/// Parses the events and errors of an extension.
+///
+/// `breadx` can only parse the extensions it was generated with, and
+/// returns everything else as [`Event::Unknown`] or as an error with an
+/// unknown kind. A parser registered with
+/// [`XcbDisplay::register_extension`] is consulted first, and may turn
+/// those bytes into something more useful.
+///
+/// [`XcbDisplay::register_extension`]: crate::XcbDisplay::register_extension
+pub trait ExtensionParser: Send + Sync {
+    /// Parse an event from the extension.
+    ///
+    /// `event_type` is the event's code relative to the extension's first
+    /// event or, for generic events, the event type in the generic event
+    /// header. `event` contains the entire event. Returning `None` lets
+    /// `breadx` parse the event instead.
+    fn parse_event(&self, event_type: u16, event: &[u8]) -> Option<Result<Event>>;
+
+    /// Parse an error from the extension.
+    ///
+    /// `error_code` is the error's code relative to the extension's first
+    /// error, and `error` contains the entire error. Returning `None` lets
+    /// `breadx` parse the error instead, which it does by default.
+    fn parse_error(&self, error_code: u8, error: &[u8]) -> Option<Error> {
+        let _ = (error_code, error);
+        None
+    }
+}
+
+/// The parsers registered for a display.
+pub(crate) struct CustomExtensions {
+    entries: RwLock<Vec<Entry>>,
+}
+
+struct Entry {
+    name: &'static str,
+    info: ExtensionInformation,
+    event_count: u8,
+    error_count: u8,
+    parser: Box<dyn ExtensionParser>,
+}
+
+impl CustomExtensions {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn register(
+        &self,
+        name: &'static str,
+        info: ExtensionInformation,
+        event_count: u8,
+        error_count: u8,
+        parser: Box<dyn ExtensionParser>,
+    ) {
+        let mut entries = rwl_write(&self.entries);
+
+        // a later registration for the same extension replaces the earlier
+        entries.retain(|entry| entry.info.major_opcode != info.major_opcode);
+        entries.push(Entry {
+            name,
+            info,
+            event_count,
+            error_count,
+            parser,
+        });
+    }
+
+    /// Update the codes of every extension after reconnecting, dropping
+    /// the parsers of extensions the server no longer supports.
+    pub(crate) fn revalidate(
+        &self,
+        mut lookup: impl FnMut(&'static str) -> Option<ExtensionInformation>,
+    ) {
+        let mut entries = rwl_write(&self.entries);
+        let old = mem::take(&mut *entries);
+
+        *entries = old
+            .into_iter()
+            .filter_map(|mut entry| {
+                entry.info = lookup(entry.name)?;
+                Some(entry)
+            })
+            .collect();
+    }
+
+    /// Parse an event with a registered parser, if one claims it.
+    pub(crate) fn parse_event(&self, event: &[u8]) -> Option<Result<Event>> {
+        let entries = rwl_read(&self.entries);
+        if entries.is_empty() {
+            return None;
+        }
+
+        let code = event[0] & 0x7F;
+
+        if code == GE_GENERIC_EVENT {
+            let extension = event[1];
+            let event_type = u16::from_ne_bytes([event[8], event[9]]);
+
+            entries
+                .iter()
+                .find(|entry| entry.info.major_opcode == extension)
+                .and_then(|entry| entry.parser.parse_event(event_type, event))
+        } else {
+            entries
+                .iter()
+                .find(|entry| in_range(code, entry.info.first_event, entry.event_count))
+                .and_then(|entry| {
+                    let event_type = code - entry.info.first_event;
+                    entry.parser.parse_event(event_type.into(), event)
+                })
+        }
+    }
+
+    /// Parse an error with a registered parser, if one claims it.
+    pub(crate) fn parse_error(&self, error: &[u8]) -> Option<Error> {
+        let entries = rwl_read(&self.entries);
+        let code = error[1];
+
+        entries
+            .iter()
+            .find(|entry| in_range(code, entry.info.first_error, entry.error_count))
+            .and_then(|entry| {
+                let error_code = code - entry.info.first_error;
+                entry.parser.parse_error(error_code, error)
+            })
+    }
+}
+
+fn in_range(code: u8, first: u8, count: u8) -> bool {
+    // extensions without events or errors report a first code of zero
+    first != 0 && code >= first && u16::from(code) < u16::from(first) + u16::from(count)
+}
@@ -0,0 +1,137 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Reporting the traffic on a connection to a callback.
+//!
+//! This works without the standard library or a logging framework: the
+//! application installs a plain function with [`set_logger`], which
+//! receives a [`Record`] for every request sent and every event, reply and
+//! error received by an [`XcbDisplay`]. The function can forward them to
+//! `defmt`, a serial port or anything else.
+//!
+//! The logger is process-wide and is called on whichever thread is using
+//! the connection, so it should be quick.
+//!
+//! [`XcbDisplay`]: crate::XcbDisplay
+
+use crate::sync::{call_once, rwl_read, rwl_write, OnceCell, RwLock};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Something that happened on a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Record {
+    /// A request was sent.
+    RequestSent {
+        /// The sequence number of the request.
+        sequence: u64,
+        /// The major opcode of the request.
+        major_opcode: u8,
+        /// The minor opcode of an extension request, or the data byte of a
+        /// core request.
+        minor_opcode: u8,
+        /// The length of the request, in bytes.
+        length: usize,
+    },
+    /// An event was received.
+    EventReceived {
+        /// The type of the event, without the `send_event` bit.
+        response_type: u8,
+        /// Whether the event was sent by another client.
+        send_event: bool,
+        /// The sequence number of the last request the server processed.
+        sequence: u64,
+    },
+    /// A reply was received.
+    ReplyReceived {
+        /// The sequence number of the request the reply is for.
+        sequence: u64,
+        /// The length of the reply, in bytes.
+        length: usize,
+    },
+    /// An error was received.
+    ErrorReceived {
+        /// The error code.
+        error_code: u8,
+        /// The major opcode of the request that caused the error.
+        major_opcode: u8,
+        /// The minor opcode of the request that caused the error.
+        minor_opcode: u16,
+        /// The low 16 bits of the sequence number of the request that
+        /// caused the error.
+        sequence: u16,
+    },
+}
+
+/// A function that receives records.
+pub type Logger = fn(&Record);
+
+/// Whether a logger is installed, checked before taking the lock.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOGGER: OnceCell<RwLock<Option<Logger>>> = OnceCell::new();
+
+fn logger() -> &'static RwLock<Option<Logger>> {
+    call_once(&LOGGER, || RwLock::new(None))
+}
+
+/// Install `logger`, or remove the current logger with `None`.
+pub fn set_logger(logger_fn: Option<Logger>) {
+    *rwl_write(logger()) = logger_fn;
+    ENABLED.store(logger_fn.is_some(), Ordering::Release);
+}
+
+/// Pass a record to the logger, if there is one.
+///
+/// `record` is only called if a logger is installed, so building the
+/// record costs nothing otherwise.
+fn log(record: impl FnOnce() -> Record) {
+    if !ENABLED.load(Ordering::Acquire) {
+        return;
+    }
+
+    let logger_fn = *rwl_read(logger());
+    if let Some(logger_fn) = logger_fn {
+        logger_fn(&record());
+    }
+}
+
+/// Record a request that was sent with the given opcodes and length.
+///
+/// These are taken before the request is sent, since `libxcb` may empty
+/// the request's iovecs while writing them.
+pub(crate) fn request_sent(opcodes: [u8; 2], length: usize, sequence: u64) {
+    log(|| Record::RequestSent {
+        sequence,
+        major_opcode: opcodes[0],
+        minor_opcode: opcodes[1],
+        length,
+    });
+}
+
+/// Record an event received in its wire format.
+pub(crate) fn event_received(event: &[u8], sequence: u64) {
+    log(|| Record::EventReceived {
+        response_type: event[0] & 0x7f,
+        send_event: event[0] & 0x80 != 0,
+        sequence,
+    });
+}
+
+/// Record a reply received in its wire format.
+pub(crate) fn reply_received(reply: &[u8], sequence: u64) {
+    log(|| Record::ReplyReceived {
+        sequence,
+        length: reply.len(),
+    });
+}
+
+/// Record an error received in its wire format.
+pub(crate) fn error_received(error: &[u8; 32]) {
+    log(|| Record::ErrorReceived {
+        error_code: error[1],
+        major_opcode: error[10],
+        minor_opcode: u16::from_ne_bytes([error[8], error[9]]),
+        sequence: u16::from_ne_bytes([error[2], error[3]]),
+    });
+}
@@ -0,0 +1,116 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Writing requests straight to the socket, past `libxcb`'s output buffer.
+//!
+//! `libxcb` copies each request into its output buffer if there's room for
+//! it, which for bulk uploads means copying the data once more before it's
+//! written. Instead, the socket can be taken from `libxcb` with
+//! `xcb_take_socket` and written to with `xcb_writev`, which only counts
+//! the requests.
+//!
+//! `libxcb` takes the socket back whenever anyone else sends a request,
+//! calling `return_socket` first. That may happen between taking the
+//! socket and writing to it, which would make the sequence number we were
+//! given out of date, so `return_socket` waits for writes in progress, and
+//! writers check that nobody took the socket back while they weren't
+//! looking.
+
+use crate::{
+    sync::{call_once, mtx_lock, Mutex, OnceCell},
+    xcb_ffi::{xcb, Connection, Iovec, XcbFfi},
+};
+use core::{
+    cell::Cell,
+    ptr::null_mut,
+    sync::atomic::{AtomicUsize, Ordering::SeqCst},
+};
+use libc::{c_int, c_void};
+
+/// The number of times the socket has been taken back from us.
+static RECLAIMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Held while writing to a socket we've taken.
+static WRITING: OnceCell<Mutex<()>> = OnceCell::new();
+
+std::thread_local! {
+    /// Whether this thread is taking a socket, in which case it may be
+    /// the one `libxcb` takes the socket back from.
+    // `const` initializers need Rust 1.59
+    #[allow(clippy::missing_const_for_thread_local)]
+    static TAKING: Cell<bool> = Cell::new(false);
+}
+
+fn writing() -> &'static Mutex<()> {
+    call_once(&WRITING, || Mutex::new(()))
+}
+
+/// Called by `libxcb` when it takes back a socket we took.
+unsafe extern "C" fn return_socket(_closure: *mut c_void) {
+    if TAKING.with(Cell::get) {
+        return;
+    }
+
+    RECLAIMS.fetch_add(1, SeqCst);
+
+    // let any write in progress finish first
+    drop(mtx_lock(writing()));
+}
+
+/// Write `requests` whole requests straight to the socket.
+///
+/// `accept` is given the sequence number the first request will have, and
+/// can turn the write down by returning `false`, leaving the requests to
+/// be sent some other way.
+///
+/// Returns the sequence number of the first request if it was written,
+/// `Ok(None)` if `accept` turned it down, and `Err(())` if the connection
+/// failed.
+///
+/// # Safety
+///
+/// `conn` must be a valid connection, and `iovecs` must make up exactly
+/// `requests` requests, formatted as they go on the wire.
+pub(crate) unsafe fn write_requests(
+    conn: *mut Connection,
+    iovecs: &mut [Iovec],
+    requests: u64,
+    accept: impl FnOnce(u64) -> bool,
+) -> Result<Option<u64>, ()> {
+    loop {
+        TAKING.with(|taking| taking.set(true));
+        let reclaims = RECLAIMS.load(SeqCst);
+        let mut sent = 0;
+        let taken =
+            unsafe { xcb().xcb_take_socket(conn, Some(return_socket), null_mut(), 0, &mut sent) };
+        TAKING.with(|taking| taking.set(false));
+
+        if taken == 0 {
+            return Err(());
+        }
+
+        let guard = mtx_lock(writing());
+        if RECLAIMS.load(SeqCst) != reclaims {
+            // someone may have sent requests since we took the socket, so
+            // take it again to get the sequence number right
+            continue;
+        }
+
+        let first = sent + 1;
+        if !accept(first) {
+            return Ok(None);
+        }
+
+        let written =
+            unsafe { xcb().xcb_writev(conn, iovecs.as_mut_ptr(), iovecs.len() as c_int, requests) };
+        drop(guard);
+
+        return if written == 0 {
+            Err(())
+        } else {
+            Ok(Some(first))
+        };
+    }
+}
@@ -0,0 +1,155 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Diverting events into queues of their own.
+//!
+//! `libxcb` can only pick generic events out of the main queue by the
+//! event context ID at the start of their data, which few extensions
+//! besides Present send. XInput2 puts a timestamp there instead, so its
+//! events are picked out here, as they're taken from `libxcb`.
+
+use crate::{collections::Map, XcbDisplay};
+use alloc::{boxed::Box, collections::VecDeque};
+use breadx::{protocol::Event, Result};
+
+/// A predicate on the first 32 bytes of an event, deciding whether it is
+/// diverted.
+pub(crate) type DivertFilter = Box<dyn Fn(&[u8; 32]) -> bool + Send + Sync>;
+
+/// The queues events are being diverted into.
+pub(crate) struct Diversions {
+    /// The ID of the next queue.
+    next_id: u64,
+    queues: Map<u64, Diversion>,
+}
+
+struct Diversion {
+    filter: DivertFilter,
+    /// Events that have been diverted, along with their sequence numbers.
+    events: VecDeque<(Event, u64)>,
+}
+
+impl Diversions {
+    pub(crate) fn new() -> Self {
+        Self {
+            next_id: 0,
+            queues: Map::default(),
+        }
+    }
+
+    /// Start diverting the events `filter` picks out, returning the ID of
+    /// the new queue.
+    pub(crate) fn add(&mut self, filter: DivertFilter) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queues.insert(
+            id,
+            Diversion {
+                filter,
+                events: VecDeque::new(),
+            },
+        );
+        id
+    }
+
+    /// Stop diverting events into a queue, dropping any left in it.
+    pub(crate) fn remove(&mut self, id: u64) {
+        self.queues.remove(&id);
+    }
+
+    /// Find the queue an event is diverted into, if any.
+    ///
+    /// Queues registered earlier get the first pick.
+    pub(crate) fn find(&self, event: &[u8; 32]) -> Option<u64> {
+        if self.queues.is_empty() {
+            return None;
+        }
+
+        self.queues
+            .iter()
+            .filter(|(_, diversion)| (diversion.filter)(event))
+            .map(|(&id, _)| id)
+            .min()
+    }
+
+    /// Add an event to a queue.
+    pub(crate) fn push(&mut self, id: u64, event: (Event, u64)) {
+        if let Some(diversion) = self.queues.get_mut(&id) {
+            diversion.events.push_back(event);
+        }
+    }
+
+    /// Take the oldest event from a queue.
+    pub(crate) fn pop(&mut self, id: u64) -> Option<(Event, u64)> {
+        self.queues.get_mut(&id)?.events.pop_front()
+    }
+
+    /// Drop the events in every queue, keeping the queues themselves.
+    pub(crate) fn clear_events(&mut self) {
+        for diversion in self.queues.values_mut() {
+            diversion.events.clear();
+        }
+    }
+}
+
+/// A queue of events picked out of the main event queue by a predicate.
+///
+/// Created by [`XcbDisplay::divert_events`]. This does the same job as a
+/// [`SpecialEventQueue`] for events that `libxcb` can't pick out by
+/// itself. Unlike a `SpecialEventQueue`, events are only diverted as this
+/// display takes them from `libxcb`, and polling or waiting on this queue
+/// reads the events meant for the main queue too, holding them until the
+/// main queue is next polled. A thread already blocked waiting on the
+/// main queue won't notice them until another event arrives, so the two
+/// are best used from the same thread.
+///
+/// Events are diverted for as long as the queue exists. Any left in it
+/// when it's dropped are lost.
+///
+/// [`XcbDisplay::divert_events`]: crate::XcbDisplay::divert_events
+/// [`SpecialEventQueue`]: crate::SpecialEventQueue
+pub struct DivertedEventQueue<'a> {
+    display: &'a XcbDisplay,
+    id: u64,
+}
+
+impl<'a> DivertedEventQueue<'a> {
+    pub(crate) fn new(display: &'a XcbDisplay, id: u64) -> Self {
+        Self { display, id }
+    }
+
+    /// Get the display events are diverted from.
+    pub fn display(&self) -> &'a XcbDisplay {
+        self.display
+    }
+
+    /// Wait for the next event in the queue.
+    pub fn wait_for_event(&self) -> Result<Event> {
+        self.wait_for_event_with_sequence().map(|(event, _)| event)
+    }
+
+    /// Poll for the next event in the queue.
+    pub fn poll_for_event(&self) -> Result<Option<Event>> {
+        Ok(self.poll_for_event_with_sequence()?.map(|(event, _)| event))
+    }
+
+    /// Wait for the next event in the queue, along with the sequence
+    /// number of the last request the server processed before sending it.
+    pub fn wait_for_event_with_sequence(&self) -> Result<(Event, u64)> {
+        self.display.wait_for_diverted_event(self.id)
+    }
+
+    /// Poll for the next event in the queue, along with the sequence
+    /// number of the last request the server processed before sending it.
+    pub fn poll_for_event_with_sequence(&self) -> Result<Option<(Event, u64)>> {
+        self.display.poll_for_diverted_event(self.id)
+    }
+}
+
+impl Drop for DivertedEventQueue<'_> {
+    fn drop(&mut self) {
+        self.display.stop_diverting(self.id);
+    }
+}
@@ -0,0 +1,83 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Supplying the libraries used with dynamic linking.
+//!
+//! By default, `libxcb` and `libX11` are opened by name the first time
+//! they're needed. A plugin loaded into a host that already uses these
+//! libraries may need to use the host's copies instead, since two copies of
+//! `libX11` in one process don't share their global state. The functions
+//! here hand the crate libraries that were opened by the application.
+//!
+//! They must be called before any connection is opened. Symbols can be
+//! taken from the process itself with `Library::this()` on Unix, and a
+//! handle returned by `dlopen` can be wrapped with `Library::from_raw()`;
+//! both are in `libloading::os::unix`.
+//!
+//! The names tried when opening the libraries depend on the platform. On
+//! Linux, the versioned sonames are used. The BSDs version their libraries
+//! differently and often install X11 outside of the default search path, so
+//! there the unversioned names are tried first, followed by the usual
+//! install prefixes (`/usr/local/lib`, `/usr/X11R6/lib`, `/usr/X11R7/lib`
+//! and `/usr/pkg/lib`).
+
+use alloc::{format, string::String};
+pub use libloading::Library;
+
+/// Open the first of `names` that can be loaded.
+///
+/// # Panics
+///
+/// Panics if none of them can, listing every name that was tried.
+pub(crate) fn open_library(names: &[&str]) -> Library {
+    let mut errors = String::new();
+
+    for name in names {
+        match unsafe { Library::new(name) } {
+            Ok(library) => return library,
+            Err(err) => errors.push_str(&format!("\n  {}: {}", name, err)),
+        }
+    }
+
+    panic!("Unable to open {} dynamically:{}", names[0], errors)
+}
+
+/// Use `library` as `libxcb`.
+///
+/// If `libxcb` has already been loaded, the library is given back in the
+/// `Err` variant.
+///
+/// # Panics
+///
+/// Panics if the library is missing a `libxcb` function this crate uses.
+///
+/// # Safety
+///
+/// `library` must be `libxcb`, or a library whose functions of the same
+/// names have the same signatures and behavior. They are called with the
+/// signatures this crate expects, without any way to check them.
+pub unsafe fn use_libxcb(library: Library) -> Result<(), Library> {
+    crate::xcb_ffi::use_library(library)
+}
+
+/// Use `xlib` as `libX11` and `xlib_xcb` as `libX11-xcb`.
+///
+/// These may be the same library, as long as it contains the functions of
+/// both. If `libX11` has already been loaded, the libraries are given back
+/// in the `Err` variant.
+///
+/// # Panics
+///
+/// Panics if the libraries are missing a function this crate uses.
+///
+/// # Safety
+///
+/// `xlib` and `xlib_xcb` must be `libX11` and `libX11-xcb`, or libraries
+/// whose functions of the same names have the same signatures and
+/// behavior, as with [`use_libxcb`].
+#[cfg(feature = "xlib")]
+pub unsafe fn use_libx11(xlib: Library, xlib_xcb: Library) -> Result<(), (Library, Library)> {
+    crate::xlib_ffi::use_libraries(xlib, xlib_xcb)
+}
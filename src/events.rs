@@ -0,0 +1,79 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Iterating over the events an `XcbDisplay` receives.
+
+use crate::XcbDisplay;
+use breadx::{protocol::Event, Result};
+use core::iter::FusedIterator;
+
+/// An iterator over the events received by an [`XcbDisplay`].
+///
+/// Created by [`XcbDisplay::events`] and [`XcbDisplay::events_blocking`].
+/// The iterator ends after yielding an error, since the connection is
+/// usually unusable by then.
+///
+/// ```no_run
+/// use whitebreadx::XcbDisplay;
+/// # fn main() -> breadx::Result<()> {
+/// let display = XcbDisplay::connect(None)?;
+///
+/// for event in display.events_blocking() {
+///     println!("{:?}", event?);
+/// }
+/// # Ok(()) }
+/// ```
+///
+/// [`XcbDisplay`]: crate::XcbDisplay
+/// [`XcbDisplay::events`]: crate::XcbDisplay::events
+/// [`XcbDisplay::events_blocking`]: crate::XcbDisplay::events_blocking
+pub struct Events<'a> {
+    display: &'a XcbDisplay,
+    /// Whether to wait for events, rather than stopping once there are
+    /// none.
+    blocking: bool,
+    /// Set once an error or the end of the queue has been reached.
+    done: bool,
+}
+
+impl<'a> Events<'a> {
+    pub(crate) fn new(display: &'a XcbDisplay, blocking: bool) -> Self {
+        Self {
+            display,
+            blocking,
+            done: false,
+        }
+    }
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Result<Event>> {
+        if self.done {
+            return None;
+        }
+
+        let event = if self.blocking {
+            self.display.wait_for_event_with_sequence().map(Some)
+        } else {
+            self.display.poll_for_event_with_sequence()
+        };
+
+        match event {
+            Ok(Some((event, _))) => Some(Ok(event)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl FusedIterator for Events<'_> {}
@@ -3,87 +3,270 @@
 //       (See accompanying file LICENSE or copy at
 //         https://www.boost.org/LICENSE_1_0.txt)
 
-use crate::sync::{rwl_read, rwl_write, RwLock};
+use crate::{
+    collections::Map,
+    sync::{call_once, rwl_get_mut, rwl_read, rwl_write, OnceCell, RwLock},
+    XcbDisplay,
+};
+use alloc::{boxed::Box, vec::Vec};
 use breadx::{
-    display::{Display, DisplayFunctionsExt},
+    display::DisplayFunctionsExt,
     protocol::{ExtInfoProvider, ExtensionInformation},
     Result,
 };
-use core::mem;
+/// The names of every extension `breadx` knows how to talk to.
+///
+/// Requests name their extension with a `&'static str`, so these are the
+/// only names worth remembering from the server's list. They're sorted by
+/// byte value, so they can be binary searched.
+const KNOWN_EXTENSIONS: &[&str] = &[
+    "BIG-REQUESTS",
+    "Composite",
+    "DAMAGE",
+    "DPMS",
+    "DRI2",
+    "DRI3",
+    "GLX",
+    "Generic Event Extension",
+    "MIT-SCREEN-SAVER",
+    "MIT-SHM",
+    "Present",
+    "RANDR",
+    "RECORD",
+    "RENDER",
+    "SELinux",
+    "SHAPE",
+    "SYNC",
+    "X-Resource",
+    "XC-MISC",
+    "XEVIE",
+    "XFIXES",
+    "XFree86-DRI",
+    "XFree86-VidModeExtension",
+    "XINERAMA",
+    "XInputExtension",
+    "XKEYBOARD",
+    "XTEST",
+    "XVideo",
+    "XVideo-MotionCompensation",
+    "XpExtension",
+];
+
+/// What's known about an extension: its information if the server has it,
+/// or `None` if it doesn't.
+type Entry = Option<ExtensionInformation>;
 
 pub(crate) struct ExtensionManager {
-    entries: RwLock<HashMap<&'static str, Option<ExtensionInformation>>>,
+    /// The extensions in `KNOWN_EXTENSIONS`, in the same order.
+    ///
+    /// Each is set once it's been queried, so looking one up afterwards
+    /// doesn't lock anything.
+    known: Box<[OnceCell<Entry>]>,
+    /// Any other extensions, such as ones with custom parsers.
+    others: RwLock<Map<&'static str, Entry>>,
 }
 
 impl ExtensionManager {
     pub(crate) fn new() -> Self {
         Self {
-            entries: RwLock::new(HashMap::with_hasher(Default::default())),
+            known: KNOWN_EXTENSIONS.iter().map(|_| OnceCell::new()).collect(),
+            others: RwLock::new(Map::default()),
         }
     }
 
     pub(crate) fn extension_code(
         &self,
-        display: &mut impl Display,
+        display: &XcbDisplay,
         name: &'static str,
     ) -> Result<Option<u8>> {
         // fast path: do we already have it
-        let guard = rwl_read(&self.entries);
+        if let Some(entry) = self.cached(name) {
+            return Ok(entry.map(|entry| entry.major_opcode));
+        }
+
+        // slow path: query it without holding anything, so that other
+        // extensions can be looked up in the meantime; libxcb's cache
+        // gives anyone querying the same one the same answer
+        let entry = display.query_extension_data(name)?;
+        Ok(self.insert(name, entry).map(|entry| entry.major_opcode))
+    }
+
+    /// Tell whether an extension has been queried already.
+    pub(crate) fn is_cached(&self, name: &'static str) -> bool {
+        self.cached(name).is_some()
+    }
 
-        if let Some(entry) = guard.get(&name) {
-            return Ok(entry.as_ref().map(|entry| entry.major_opcode));
+    /// Look up the code of an extension that was already queried, without
+    /// locking.
+    ///
+    /// Returns `None` if it hasn't been queried yet.
+    pub(crate) fn cached_code(&mut self, name: &'static str) -> Option<Option<u8>> {
+        let entry = match known_index(name) {
+            Some(index) => self.known[index].get().copied(),
+            None => rwl_get_mut(&mut self.others).get(&name).copied(),
+        };
+
+        entry.map(|entry| entry.map(|entry| entry.major_opcode))
+    }
+
+    /// Query every extension the server supports that hasn't been queried
+    /// yet, all at once.
+    ///
+    /// The server lists its extensions, and then they're all queried
+    /// before waiting for any of the replies, so this takes two round
+    /// trips however many there are. Extensions the server doesn't list
+    /// are remembered as missing.
+    pub(crate) fn prefetch(&self, display: &XcbDisplay) -> Result<()> {
+        let mut this = display;
+        let listed = this.list_extensions_immediate()?;
+        let (present, missing): (Vec<&'static str>, Vec<&'static str>) = KNOWN_EXTENSIONS
+            .iter()
+            .copied()
+            .filter(|name| !self.is_cached(name))
+            .partition(|name| {
+                listed
+                    .names
+                    .iter()
+                    .any(|listed| listed.name == name.as_bytes())
+            });
+
+        for name in &present {
+            display.prefetch_extension_data(name);
+        }
+        for name in present {
+            self.insert(name, display.query_extension_data(name)?);
         }
+        for name in missing {
+            self.insert(name, None);
+        }
+
+        Ok(())
+    }
+
+    /// Forget every extension, such as after reconnecting to a server that
+    /// may have given them new codes, and return the names of the ones
+    /// that had been queried.
+    pub(crate) fn clear(&mut self) -> Vec<&'static str> {
+        let known = KNOWN_EXTENSIONS
+            .iter()
+            .zip(self.known.iter_mut())
+            .filter(|(_, cell)| cell.get().is_some())
+            .map(|(name, cell)| {
+                *cell = OnceCell::new();
+                *name
+            });
+        let mut names: Vec<&'static str> = known.collect();
 
-        // slow path: we don't have it, so we need to query it
-        mem::drop(guard);
-        let mut guard = rwl_write(&self.entries);
+        let others = rwl_get_mut(&mut self.others);
+        names.extend(others.keys().copied());
+        others.clear();
 
-        // someone else may have queried it while we were waiting
-        // check if so
-        if let Some(entry) = guard.get(&name) {
-            return Ok(entry.as_ref().map(|entry| entry.major_opcode));
+        names
+    }
+
+    /// Query each of `names`, all at once.
+    pub(crate) fn query_all(&self, display: &XcbDisplay, names: &[&'static str]) -> Result<()> {
+        for &name in names {
+            display.prefetch_extension_data(name);
+        }
+        for &name in names {
+            self.extension_code(display, name)?;
         }
 
-        let res = display.query_extension_immediate(name)?;
-        let ext_info = if res.present {
-            Some(ExtensionInformation {
-                major_opcode: res.major_opcode,
-                first_event: res.first_event,
-                first_error: res.first_error,
-            })
-        } else {
-            None
-        };
+        Ok(())
+    }
 
-        guard.insert(name, ext_info);
-        Ok(Some(res.major_opcode).filter(|_| res.present))
+    /// Get the information for an extension, if it has been queried and
+    /// is present.
+    pub(crate) fn extension_info(&self, name: &'static str) -> Option<ExtensionInformation> {
+        self.cached(name).flatten()
+    }
+
+    /// Get the name of the extension with the given major opcode, if it
+    /// has been queried.
+    pub(crate) fn extension_name(&self, major_opcode: u8) -> Option<&'static str> {
+        self.find_extension_info(|qer| qer.major_opcode == major_opcode)
+            .map(|(name, _)| name)
+    }
+
+    /// Get what's known about an extension, if it has been queried.
+    fn cached(&self, name: &str) -> Option<Entry> {
+        match known_index(name) {
+            Some(index) => self.known[index].get().copied(),
+            None => rwl_read(&self.others).get(name).copied(),
+        }
+    }
+
+    /// Remember what's known about an extension, returning what's
+    /// remembered.
+    ///
+    /// If someone else got there first, their answer is kept. It's the same
+    /// one, since both came from the same connection.
+    fn insert(&self, name: &'static str, entry: Entry) -> Entry {
+        match known_index(name) {
+            Some(index) => *call_once(&self.known[index], || entry),
+            None => *rwl_write(&self.others).entry(name).or_insert(entry),
+        }
+    }
+
+    /// Iterate over the extensions that have been queried and are present.
+    fn present<'a>(
+        &'a self,
+        others: &'a Map<&'static str, Entry>,
+    ) -> impl Iterator<Item = (&'static str, ExtensionInformation)> + 'a {
+        let known = KNOWN_EXTENSIONS
+            .iter()
+            .zip(self.known.iter())
+            .filter_map(|(name, cell)| Some((*name, (*cell.get()?)?)));
+        let others = others
+            .iter()
+            .filter_map(|(name, entry)| Some((*name, (*entry)?)));
+
+        known.chain(others)
+    }
+
+    /// Find the extension whose range of codes contains `code`.
+    ///
+    /// Extensions only report the first code they use, so this is the
+    /// extension with the highest first code that isn't above `code`.
+    fn find_closest(
+        &self,
+        code: u8,
+        first_code: impl Fn(&ExtensionInformation) -> u8,
+    ) -> Option<(&'static str, ExtensionInformation)> {
+        let guard = rwl_read(&self.others);
+
+        self.present(&guard)
+            .filter(|(_, qer)| {
+                // extensions without events or errors report zero
+                let first = first_code(qer);
+                first != 0 && first <= code
+            })
+            .max_by_key(|(_, qer)| first_code(qer))
     }
 
     fn find_extension_info(
         &self,
         mut f: impl FnMut(&ExtensionInformation) -> bool,
     ) -> Option<(&'static str, ExtensionInformation)> {
-        let guard = rwl_read(&self.entries);
-
-        for (name, qer) in guard.iter() {
-            if let Some(qer) = qer {
-                if f(qer) {
-                    return Some((name, *qer));
-                }
-            }
-        }
-
-        None
+        let guard = rwl_read(&self.others);
+        let found = self.present(&guard).find(|(_, qer)| f(qer));
+        found
     }
 }
 
+/// Get the position of an extension in `KNOWN_EXTENSIONS`.
+fn known_index(name: &str) -> Option<usize> {
+    KNOWN_EXTENSIONS.binary_search(&name).ok()
+}
+
 impl ExtInfoProvider for ExtensionManager {
     fn get_from_error_code(&self, error_code: u8) -> Option<(&str, ExtensionInformation)> {
-        self.find_extension_info(|qer| qer.first_error == error_code)
+        self.find_closest(error_code, |qer| qer.first_error)
     }
 
     fn get_from_event_code(&self, event_code: u8) -> Option<(&str, ExtensionInformation)> {
-        self.find_extension_info(|qer| qer.first_event == event_code)
+        self.find_closest(event_code, |qer| qer.first_event)
     }
 
     fn get_from_major_opcode(&self, major_opcode: u8) -> Option<(&str, ExtensionInformation)> {
@@ -91,4 +274,21 @@ impl ExtInfoProvider for ExtensionManager {
     }
 }
 
-type HashMap<K, V> = hashbrown::HashMap<K, V, core::hash::BuildHasherDefault<rustc_hash::FxHasher>>;
+#[cfg(test)]
+mod tests {
+    use super::{known_index, KNOWN_EXTENSIONS};
+
+    #[test]
+    fn known_extensions_are_sorted() {
+        // `known_index` binary searches the list
+        assert!(KNOWN_EXTENSIONS.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn finds_known_extensions() {
+        for (i, &name) in KNOWN_EXTENSIONS.iter().enumerate() {
+            assert_eq!(known_index(name), Some(i));
+        }
+        assert_eq!(known_index("NOT-AN-EXTENSION"), None);
+    }
+}
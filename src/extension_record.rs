@@ -0,0 +1,100 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! `xcb_extension_t` records, for sending extension requests the way the
+//! `libxcb-*` extension libraries do.
+
+use crate::{
+    collections::Map,
+    sync::{call_once, mtx_lock, Mutex, OnceCell},
+    xcb_ffi::Extension,
+};
+use alloc::{boxed::Box, vec::Vec};
+use libc::{c_char, c_int};
+
+/// The layout of `xcb_extension_t`.
+#[repr(C)]
+struct Record {
+    name: *const c_char,
+    global_id: c_int,
+}
+
+/// The symbols the `libxcb-*` libraries keep their records in, by the
+/// name of the extension.
+#[cfg(unix)]
+const SYMBOLS: &[(&str, &[u8])] = &[
+    ("BIG-REQUESTS", b"xcb_big_requests_id\0"),
+    ("Composite", b"xcb_composite_id\0"),
+    ("DAMAGE", b"xcb_damage_id\0"),
+    ("DPMS", b"xcb_dpms_id\0"),
+    ("DRI2", b"xcb_dri2_id\0"),
+    ("DRI3", b"xcb_dri3_id\0"),
+    ("GLX", b"xcb_glx_id\0"),
+    ("MIT-SCREEN-SAVER", b"xcb_screensaver_id\0"),
+    ("MIT-SHM", b"xcb_shm_id\0"),
+    ("Present", b"xcb_present_id\0"),
+    ("RANDR", b"xcb_randr_id\0"),
+    ("RECORD", b"xcb_record_id\0"),
+    ("RENDER", b"xcb_render_id\0"),
+    ("SHAPE", b"xcb_shape_id\0"),
+    ("SYNC", b"xcb_sync_id\0"),
+    ("X-Resource", b"xcb_res_id\0"),
+    ("XC-MISC", b"xcb_xc_misc_id\0"),
+    ("XFIXES", b"xcb_xfixes_id\0"),
+    ("XINERAMA", b"xcb_xinerama_id\0"),
+    ("XInputExtension", b"xcb_input_id\0"),
+    ("XKEYBOARD", b"xcb_xkb_id\0"),
+    ("XTEST", b"xcb_test_id\0"),
+    ("XVideo", b"xcb_xv_id\0"),
+];
+
+/// The record for each extension, as an address.
+static RECORDS: OnceCell<Mutex<Map<&'static str, usize>>> = OnceCell::new();
+
+/// Get the record for the extension named `name`.
+///
+/// If the `libxcb-*` library for the extension is loaded into the process,
+/// its record is used, so `libxcb` only queries the extension once for
+/// both of us. Otherwise, a record is made that lives as long as the
+/// process.
+pub(crate) fn record(name: &'static str) -> *mut Extension {
+    let records = call_once(&RECORDS, || Mutex::new(Map::default()));
+    let mut records = mtx_lock(records);
+    let record = records
+        .entry(name)
+        .or_insert_with(|| loaded(name).unwrap_or_else(|| make(name)) as usize);
+    *record as *mut Extension
+}
+
+#[cfg(unix)]
+fn loaded(name: &str) -> Option<*mut Extension> {
+    let (_, symbol) = SYMBOLS.iter().find(|(ext, _)| *ext == name)?;
+    let record = unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr().cast()) };
+
+    if record.is_null() {
+        None
+    } else {
+        Some(record.cast())
+    }
+}
+
+#[cfg(not(unix))]
+fn loaded(_name: &str) -> Option<*mut Extension> {
+    None
+}
+
+fn make(name: &str) -> *mut Extension {
+    let mut c_name = Vec::with_capacity(name.len() + 1);
+    c_name.extend_from_slice(name.as_bytes());
+    c_name.push(0);
+    let c_name: &'static [u8] = Box::leak(c_name.into_boxed_slice());
+
+    // libxcb sets global_id the first time the record is used
+    let record = Box::new(Record {
+        name: c_name.as_ptr().cast(),
+        global_id: 0,
+    });
+    Box::into_raw(record).cast()
+}
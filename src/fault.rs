@@ -0,0 +1,399 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Injecting failures into `libxcb` calls, for testing error handling.
+//!
+//! A real X server rarely fails in the ways an application has to handle,
+//! so this module lets tests make `libxcb` fail on demand. Every call the
+//! crate makes into `libxcb` goes through a wrapper that checks the faults
+//! set here before passing the call along.
+//!
+//! Faults are process-wide and apply to every connection, so tests that use
+//! them should not run in parallel with each other. Call [`reset`] when a
+//! test is done.
+
+use crate::xcb_ffi::{
+    errors, AuthInfo, Connection, Extension, GenericError, GenericEvent, Iovec, ProtocolRequest,
+    QueryExtensionReply, ReturnSocket, Setup, SpecialEvent, VoidCookie, XcbFfi,
+};
+use core::{
+    ptr::{null, null_mut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+};
+use libc::{c_char, c_int, c_void};
+
+/// Whether `xcb_flush` should fail.
+static FAIL_FLUSH: AtomicBool = AtomicBool::new(false);
+/// One more than the number of calls left before the connection breaks,
+/// or zero if it shouldn't break.
+static CALLS_UNTIL_BROKEN: AtomicUsize = AtomicUsize::new(0);
+/// Whether the connection has been broken by `CALLS_UNTIL_BROKEN`.
+static BROKEN: AtomicBool = AtomicBool::new(false);
+/// The number of replies left to drop.
+static DROP_REPLIES: AtomicUsize = AtomicUsize::new(0);
+/// The number of event polls left to report no events for.
+static DELAY_EVENTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Make every flush fail, or stop doing so.
+pub fn fail_flush(fail: bool) {
+    FAIL_FLUSH.store(fail, SeqCst);
+}
+
+/// Break the connection after `calls` more calls that communicate with the
+/// server.
+///
+/// Once broken, the connection reports an I/O error, as if the server had
+/// gone away, until [`reset`] is called. Passing `None` cancels a pending
+/// break but doesn't repair a connection that is already broken.
+pub fn fail_after(calls: Option<usize>) {
+    CALLS_UNTIL_BROKEN.store(calls.map_or(0, |calls| calls.saturating_add(1)), SeqCst);
+}
+
+/// Drop the next `count` replies.
+///
+/// A dropped reply is freed as soon as it arrives, and waiting for it
+/// returns neither a reply nor an error, the same as for a reply that was
+/// discarded.
+pub fn drop_replies(count: usize) {
+    DROP_REPLIES.store(count, SeqCst);
+}
+
+/// Report no events for the next `count` event polls.
+///
+/// The events stay queued, so they are returned by later polls. Blocking
+/// waits for events aren't affected.
+pub fn delay_events(count: usize) {
+    DELAY_EVENTS.store(count, SeqCst);
+}
+
+/// Clear every fault, and repair a connection broken by [`fail_after`].
+pub fn reset() {
+    FAIL_FLUSH.store(false, SeqCst);
+    CALLS_UNTIL_BROKEN.store(0, SeqCst);
+    BROKEN.store(false, SeqCst);
+    DROP_REPLIES.store(0, SeqCst);
+    DELAY_EVENTS.store(0, SeqCst);
+}
+
+/// Count down a counter, returning whether it was nonzero.
+fn take(counter: &AtomicUsize) -> bool {
+    counter
+        .fetch_update(SeqCst, SeqCst, |count| count.checked_sub(1))
+        .is_ok()
+}
+
+/// Count a call that communicates with the server, returning whether the
+/// connection is broken.
+fn broken() -> bool {
+    let remaining = CALLS_UNTIL_BROKEN.fetch_update(SeqCst, SeqCst, |count| match count {
+        0 => None,
+        count => Some(count - 1),
+    });
+
+    if remaining == Ok(1) {
+        BROKEN.store(true, SeqCst);
+    }
+
+    BROKEN.load(SeqCst)
+}
+
+/// Wraps another `libxcb` backend, injecting the faults set in this module.
+pub(crate) struct FaultFfi<F> {
+    inner: F,
+}
+
+impl<F> FaultFfi<F> {
+    pub(crate) fn new(inner: F) -> Self {
+        Self { inner }
+    }
+}
+
+unsafe impl<F: XcbFfi> XcbFfi for FaultFfi<F> {
+    unsafe fn xcb_connect(&self, display: *const c_char, screenp: *mut c_int) -> *mut Connection {
+        self.inner.xcb_connect(display, screenp)
+    }
+
+    unsafe fn xcb_connect_to_display_with_auth_info(
+        &self,
+        display: *const c_char,
+        auth_info: *mut AuthInfo,
+        screenp: *mut c_int,
+    ) -> *mut Connection {
+        self.inner
+            .xcb_connect_to_display_with_auth_info(display, auth_info, screenp)
+    }
+
+    unsafe fn xcb_connect_to_fd(&self, fd: c_int, auth_info: *mut AuthInfo) -> *mut Connection {
+        self.inner.xcb_connect_to_fd(fd, auth_info)
+    }
+
+    unsafe fn xcb_get_file_descriptor(&self, conn: *mut Connection) -> c_int {
+        self.inner.xcb_get_file_descriptor(conn)
+    }
+
+    unsafe fn xcb_connection_has_error(&self, conn: *mut Connection) -> c_int {
+        if BROKEN.load(SeqCst) {
+            errors::XCB_CONN_ERROR
+        } else {
+            self.inner.xcb_connection_has_error(conn)
+        }
+    }
+
+    unsafe fn xcb_disconnect(&self, conn: *mut Connection) {
+        self.inner.xcb_disconnect(conn)
+    }
+
+    unsafe fn xcb_get_setup(&self, conn: *mut Connection) -> *mut Setup {
+        self.inner.xcb_get_setup(conn)
+    }
+
+    unsafe fn xcb_generate_id(&self, conn: *mut Connection) -> u32 {
+        if broken() {
+            u32::MAX
+        } else {
+            self.inner.xcb_generate_id(conn)
+        }
+    }
+
+    unsafe fn xcb_flush(&self, conn: *mut Connection) -> c_int {
+        if broken() || FAIL_FLUSH.load(SeqCst) {
+            0
+        } else {
+            self.inner.xcb_flush(conn)
+        }
+    }
+
+    unsafe fn xcb_get_maximum_request_length(&self, conn: *mut Connection) -> u32 {
+        self.inner.xcb_get_maximum_request_length(conn)
+    }
+
+    unsafe fn xcb_prefetch_maximum_request_length(&self, conn: *mut Connection) {
+        self.inner.xcb_prefetch_maximum_request_length(conn)
+    }
+
+    unsafe fn xcb_total_read(&self, conn: *mut Connection) -> u64 {
+        self.inner.xcb_total_read(conn)
+    }
+
+    unsafe fn xcb_total_written(&self, conn: *mut Connection) -> u64 {
+        self.inner.xcb_total_written(conn)
+    }
+
+    unsafe fn xcb_wait_for_event(&self, conn: *mut Connection) -> *mut GenericEvent {
+        if broken() {
+            null_mut()
+        } else {
+            self.inner.xcb_wait_for_event(conn)
+        }
+    }
+
+    unsafe fn xcb_poll_for_event(&self, conn: *mut Connection) -> *mut GenericEvent {
+        if broken() || take(&DELAY_EVENTS) {
+            null_mut()
+        } else {
+            self.inner.xcb_poll_for_event(conn)
+        }
+    }
+
+    unsafe fn xcb_poll_for_queued_event(&self, conn: *mut Connection) -> *mut GenericEvent {
+        if broken() {
+            null_mut()
+        } else {
+            self.inner.xcb_poll_for_queued_event(conn)
+        }
+    }
+
+    unsafe fn xcb_register_for_special_xge(
+        &self,
+        conn: *mut Connection,
+        ext: *mut Extension,
+        eid: u32,
+        stamp: *mut u32,
+    ) -> *mut SpecialEvent {
+        if broken() {
+            null_mut()
+        } else {
+            self.inner
+                .xcb_register_for_special_xge(conn, ext, eid, stamp)
+        }
+    }
+
+    unsafe fn xcb_unregister_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) {
+        self.inner.xcb_unregister_for_special_event(conn, se)
+    }
+
+    unsafe fn xcb_wait_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent {
+        if broken() {
+            null_mut()
+        } else {
+            self.inner.xcb_wait_for_special_event(conn, se)
+        }
+    }
+
+    unsafe fn xcb_poll_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent {
+        if broken() || take(&DELAY_EVENTS) {
+            null_mut()
+        } else {
+            self.inner.xcb_poll_for_special_event(conn, se)
+        }
+    }
+
+    unsafe fn xcb_prefetch_extension_data(&self, conn: *mut Connection, ext: *mut Extension) {
+        self.inner.xcb_prefetch_extension_data(conn, ext)
+    }
+
+    unsafe fn xcb_get_extension_data(
+        &self,
+        conn: *mut Connection,
+        ext: *mut Extension,
+    ) -> *const QueryExtensionReply {
+        if broken() {
+            null()
+        } else {
+            self.inner.xcb_get_extension_data(conn, ext)
+        }
+    }
+
+    unsafe fn xcb_send_request64(
+        &self,
+        conn: *mut Connection,
+        flags: c_int,
+        iov: *mut Iovec,
+        request: *const ProtocolRequest,
+    ) -> u64 {
+        if broken() {
+            0
+        } else {
+            self.inner.xcb_send_request64(conn, flags, iov, request)
+        }
+    }
+
+    unsafe fn xcb_send_request_with_fds64(
+        &self,
+        conn: *mut Connection,
+        flags: c_int,
+        iov: *mut Iovec,
+        request: *const ProtocolRequest,
+        num_fds: c_int,
+        fds: *mut c_int,
+    ) -> u64 {
+        if broken() {
+            // libxcb closes the fds even if it fails to send them
+            #[cfg(unix)]
+            for i in 0..num_fds as usize {
+                libc::close(*fds.add(i));
+            }
+
+            0
+        } else {
+            self.inner
+                .xcb_send_request_with_fds64(conn, flags, iov, request, num_fds, fds)
+        }
+    }
+
+    unsafe fn xcb_take_socket(
+        &self,
+        conn: *mut Connection,
+        return_socket: ReturnSocket,
+        closure: *mut c_void,
+        flags: c_int,
+        sent: *mut u64,
+    ) -> c_int {
+        if broken() {
+            0
+        } else {
+            self.inner
+                .xcb_take_socket(conn, return_socket, closure, flags, sent)
+        }
+    }
+
+    unsafe fn xcb_writev(
+        &self,
+        conn: *mut Connection,
+        vector: *mut Iovec,
+        count: c_int,
+        requests: u64,
+    ) -> c_int {
+        if broken() {
+            0
+        } else {
+            self.inner.xcb_writev(conn, vector, count, requests)
+        }
+    }
+
+    unsafe fn xcb_wait_for_reply64(
+        &self,
+        conn: *mut Connection,
+        seq: u64,
+        error: *mut *mut GenericError,
+    ) -> *mut c_void {
+        if broken() {
+            return null_mut();
+        }
+
+        let reply = self.inner.xcb_wait_for_reply64(conn, seq, error);
+
+        if !reply.is_null() && take(&DROP_REPLIES) {
+            libc::free(reply);
+            null_mut()
+        } else {
+            reply
+        }
+    }
+
+    unsafe fn xcb_poll_for_reply64(
+        &self,
+        conn: *mut Connection,
+        seq: u64,
+        reply: *mut *mut c_void,
+        error: *mut *mut GenericError,
+    ) -> c_int {
+        if broken() {
+            // libxcb answers for a broken connection with neither
+            *reply = null_mut();
+            if !error.is_null() {
+                *error = null_mut();
+            }
+            return 1;
+        }
+
+        let found = self.inner.xcb_poll_for_reply64(conn, seq, reply, error);
+
+        if found != 0 && !(*reply).is_null() && take(&DROP_REPLIES) {
+            libc::free(*reply);
+            *reply = null_mut();
+        }
+
+        found
+    }
+
+    unsafe fn xcb_request_check(
+        &self,
+        conn: *mut Connection,
+        cookie: VoidCookie,
+    ) -> *mut GenericError {
+        if broken() {
+            null_mut()
+        } else {
+            self.inner.xcb_request_check(conn, cookie)
+        }
+    }
+
+    unsafe fn xcb_discard_reply64(&self, conn: *mut Connection, seq: u64) {
+        self.inner.xcb_discard_reply64(conn, seq)
+    }
+}
@@ -0,0 +1,258 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! File descriptors that arrive with replies.
+
+use crate::{
+    collections::Set,
+    sync::{mtx_get_mut, mtx_lock, Mutex},
+};
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use libc::c_int;
+
+/// The sequence numbers of replies that will carry file descriptors.
+///
+/// Very few requests have replies like this, so the set is nearly always
+/// empty. A count of its entries is kept alongside it, so that the reply
+/// path can tell that without taking the lock.
+#[derive(Debug, Default)]
+pub(crate) struct PendingFds {
+    /// The number of entries in `seqs`.
+    len: AtomicUsize,
+    seqs: Mutex<Set<u64>>,
+}
+
+impl PendingFds {
+    /// Note that the reply to `seq` will carry file descriptors.
+    pub(crate) fn insert(&self, seq: u64) {
+        self.extend(Some(seq));
+    }
+
+    /// Note that the replies to all of `seqs` will carry file descriptors.
+    pub(crate) fn extend(&self, seqs: impl IntoIterator<Item = u64>) {
+        let mut set = mtx_lock(&self.seqs);
+        set.extend(seqs);
+
+        // whoever waits for these replies learns the sequence numbers
+        // after this, so the release here is enough for them to see it
+        self.len.store(set.len(), Ordering::Release);
+    }
+
+    /// Note that the reply to `seq` will carry file descriptors, without
+    /// locking.
+    pub(crate) fn insert_mut(&mut self, seq: u64) {
+        let set = mtx_get_mut(&mut self.seqs);
+        set.insert(seq);
+        *self.len.get_mut() = set.len();
+    }
+
+    /// Stop tracking `seq`, telling whether its reply carries file
+    /// descriptors.
+    pub(crate) fn remove(&self, seq: u64) -> bool {
+        if self.len.load(Ordering::Acquire) == 0 {
+            return false;
+        }
+
+        let mut set = mtx_lock(&self.seqs);
+        let removed = set.remove(&seq);
+        self.len.store(set.len(), Ordering::Release);
+        removed
+    }
+
+    /// Stop tracking `seq`, without locking.
+    pub(crate) fn remove_mut(&mut self, seq: u64) -> bool {
+        let set = mtx_get_mut(&mut self.seqs);
+        let removed = set.remove(&seq);
+        *self.len.get_mut() = set.len();
+        removed
+    }
+
+    /// Tell whether the reply to `seq` carries file descriptors, without
+    /// locking.
+    pub(crate) fn contains_mut(&mut self, seq: u64) -> bool {
+        *self.len.get_mut() != 0 && mtx_get_mut(&mut self.seqs).contains(&seq)
+    }
+
+    /// Stop tracking every reply from before `seq`, returning their
+    /// sequence numbers.
+    pub(crate) fn remove_before(&self, seq: u64) -> Vec<u64> {
+        let mut set = mtx_lock(&self.seqs);
+        let mut stale = Vec::new();
+        set.retain(|&pending| {
+            if pending < seq {
+                stale.push(pending);
+                false
+            } else {
+                true
+            }
+        });
+
+        self.len.store(set.len(), Ordering::Release);
+        stale
+    }
+
+    /// Forget every sequence number.
+    pub(crate) fn clear(&mut self) {
+        mtx_get_mut(&mut self.seqs).clear();
+        *self.len.get_mut() = 0;
+    }
+}
+
+/// File descriptors passed to us by the server, closed unless they're
+/// handed on.
+#[derive(Debug, Default)]
+pub(crate) struct ReplyFds {
+    fds: Vec<c_int>,
+}
+
+impl ReplyFds {
+    /// Take the file descriptors `libxcb` stored along with `reply`.
+    ///
+    /// `libxcb` allocates them in the same buffer, straight after the
+    /// reply. The reply's length is always a multiple of four, so they're
+    /// aligned without padding. The count is in the second byte of the
+    /// reply, which other replies use for their own data, so this must
+    /// only be called for replies that are known to carry descriptors.
+    ///
+    /// # Safety
+    ///
+    /// `reply` must be a whole reply allocated by `libxcb`, for a request
+    /// whose reply carries file descriptors.
+    #[cfg(unix)]
+    pub(crate) unsafe fn from_reply(reply: &[u8]) -> Self {
+        debug_assert_eq!(reply.len() % core::mem::align_of::<c_int>(), 0);
+
+        let count = reply[1] as usize;
+        let start = reply.as_ptr().add(reply.len()).cast::<c_int>();
+        let fds = core::slice::from_raw_parts(start, count);
+
+        Self { fds: fds.to_vec() }
+    }
+
+    /// Tell whether there are no file descriptors.
+    #[cfg(not(all(unix, feature = "std")))]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.fds.is_empty()
+    }
+
+    /// Hand the file descriptors on to `breadx`, which takes over closing
+    /// them.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn into_breadx(mut self) -> Vec<breadx::Fd> {
+        core::mem::take(&mut self.fds)
+            .into_iter()
+            .map(breadx::Fd::new)
+            .collect()
+    }
+}
+
+impl Drop for ReplyFds {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        for &fd in &self.fds {
+            unsafe {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix, feature = "std"))]
+mod tests {
+    use super::ReplyFds;
+    use alloc::vec::Vec;
+    use core::{mem, ptr, slice};
+    use libc::c_int;
+
+    /// Open a pipe, returning its non-blocking read end and its write end.
+    ///
+    /// Reading from the read end tells whether the write end is still
+    /// open, without racing other tests for reused descriptor numbers.
+    fn pipe() -> (c_int, c_int) {
+        let mut fds = [0; 2];
+        unsafe {
+            assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+            assert_ne!(libc::fcntl(fds[0], libc::F_SETFL, libc::O_NONBLOCK), -1);
+        }
+        (fds[0], fds[1])
+    }
+
+    fn write_end_open(read_end: c_int) -> bool {
+        let mut byte = 0u8;
+        // an empty pipe reads as end of file once every write end is
+        // closed, and would block before that
+        unsafe { libc::read(read_end, (&mut byte as *mut u8).cast(), 1) != 0 }
+    }
+
+    /// Lay out a reply the way `libxcb` does, with `fds` straight after
+    /// it in the same allocation.
+    fn with_reply<R>(fds: &[c_int], f: impl FnOnce(&[u8]) -> R) -> R {
+        const REPLY_LEN: usize = 32;
+        let total = REPLY_LEN + mem::size_of_val(fds);
+
+        unsafe {
+            let buffer = libc::malloc(total).cast::<u8>();
+            assert!(!buffer.is_null());
+            ptr::write_bytes(buffer, 0, REPLY_LEN);
+            *buffer = 1;
+            *buffer.add(1) = fds.len() as u8;
+            ptr::copy_nonoverlapping(
+                fds.as_ptr(),
+                buffer.add(REPLY_LEN).cast::<c_int>(),
+                fds.len(),
+            );
+
+            let result = f(slice::from_raw_parts(buffer, REPLY_LEN));
+            libc::free(buffer.cast());
+            result
+        }
+    }
+
+    #[test]
+    fn from_reply_reads_trailing_fds() {
+        let (read_a, write_a) = pipe();
+        let (read_b, write_b) = pipe();
+
+        let fds = [write_a, write_b];
+        let reply_fds = with_reply(&fds, |reply| unsafe { ReplyFds::from_reply(reply) });
+        assert_eq!(reply_fds.fds, fds);
+
+        drop(reply_fds);
+        unsafe {
+            libc::close(read_a);
+            libc::close(read_b);
+        }
+    }
+
+    #[test]
+    fn closes_on_drop() {
+        let (read_end, write_end) = pipe();
+
+        let reply_fds = with_reply(&[write_end], |reply| unsafe { ReplyFds::from_reply(reply) });
+        assert!(write_end_open(read_end));
+
+        drop(reply_fds);
+        assert!(!write_end_open(read_end));
+        unsafe { libc::close(read_end) };
+    }
+
+    #[test]
+    fn into_breadx_keeps_fds_open() {
+        let (read_end, write_end) = pipe();
+
+        let handed_on =
+            with_reply(&[write_end], |reply| unsafe { ReplyFds::from_reply(reply) }).into_breadx();
+        assert!(write_end_open(read_end));
+
+        let raw: Vec<c_int> = handed_on.into_iter().map(breadx::Fd::into_raw_fd).collect();
+        assert_eq!(raw, [write_end]);
+
+        unsafe {
+            libc::close(write_end);
+            libc::close(read_end);
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Policies for automatically flushing the output buffer.
+
+#[cfg(feature = "std")]
+use std::time::{Duration, Instant};
+
+/// Determines when an [`XcbDisplay`] flushes its output buffer on its own.
+///
+/// `libxcb` only writes requests to the server when its internal buffer
+/// fills up, when a reply is waited on, or when it is explicitly flushed.
+/// For interactive applications, this means that requests can sit in the
+/// buffer for a while unless `flush()` is called after every batch of
+/// requests. A `FlushPolicy` lets the display take care of this.
+///
+/// The default policy is [`FlushPolicy::manual`], which never flushes
+/// beyond what `libxcb` does already. `libxcb` still writes out its buffer
+/// when it fills up or a reply is waited on, so renderers that want to
+/// choose when the socket is written should keep their batches smaller
+/// than the buffer and send requests with replies last.
+///
+/// [`XcbDisplay`]: crate::XcbDisplay
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FlushPolicy {
+    /// Flush after this many requests have been sent.
+    after_requests: Option<usize>,
+    /// Flush after this many bytes of requests have been sent.
+    after_bytes: Option<usize>,
+    /// Flush once the oldest unflushed request is this old.
+    #[cfg(feature = "std")]
+    after_duration: Option<Duration>,
+    /// Flush before blocking on an event or reply.
+    before_wait: bool,
+}
+
+impl FlushPolicy {
+    /// A policy that never flushes automatically.
+    #[must_use]
+    pub fn manual() -> Self {
+        Self::default()
+    }
+
+    /// Flush once `count` requests have been sent since the last flush.
+    #[must_use]
+    pub fn flush_after_requests(mut self, count: usize) -> Self {
+        self.after_requests = Some(count);
+        self
+    }
+
+    /// Flush once `bytes` bytes of requests have been sent since the last
+    /// flush.
+    #[must_use]
+    pub fn flush_after_bytes(mut self, bytes: usize) -> Self {
+        self.after_bytes = Some(bytes);
+        self
+    }
+
+    /// Flush once the oldest unflushed request was sent more than
+    /// `duration` ago.
+    ///
+    /// This is checked whenever a request is sent, so requests may stay
+    /// in the buffer for longer than `duration` if no new requests are
+    /// sent.
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn flush_after(mut self, duration: Duration) -> Self {
+        self.after_duration = Some(duration);
+        self
+    }
+
+    /// Whether or not to flush before blocking on an event or a reply.
+    #[must_use]
+    pub fn flush_before_wait(mut self, flush: bool) -> Self {
+        self.before_wait = flush;
+        self
+    }
+}
+
+/// The current state of the flush policy for a display.
+pub(crate) struct FlushState {
+    /// The policy in use.
+    policy: FlushPolicy,
+    /// The number of requests sent since the last flush.
+    unflushed: usize,
+    /// The number of bytes sent since the last flush.
+    unflushed_bytes: usize,
+    /// The time the oldest unflushed request was sent at.
+    #[cfg(feature = "std")]
+    oldest_unflushed: Option<Instant>,
+}
+
+impl FlushState {
+    pub(crate) fn new() -> Self {
+        Self {
+            policy: FlushPolicy::manual(),
+            unflushed: 0,
+            unflushed_bytes: 0,
+            #[cfg(feature = "std")]
+            oldest_unflushed: None,
+        }
+    }
+
+    pub(crate) fn policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    pub(crate) fn set_policy(&mut self, policy: FlushPolicy) {
+        self.policy = policy;
+    }
+
+    /// Record that a request of `bytes` bytes has been sent, and tell
+    /// whether we should flush now.
+    pub(crate) fn request_sent(&mut self, bytes: usize) -> bool {
+        self.requests_sent(1, bytes)
+    }
+
+    /// Record that `count` requests have been sent, making up `bytes`
+    /// bytes, and tell whether we should flush now.
+    pub(crate) fn requests_sent(&mut self, count: usize, bytes: usize) -> bool {
+        self.unflushed += count;
+        self.unflushed_bytes += bytes;
+
+        let too_many = matches!(
+            self.policy.after_requests,
+            Some(count) if self.unflushed >= count
+        );
+        let too_large = matches!(
+            self.policy.after_bytes,
+            Some(bytes) if self.unflushed_bytes >= bytes
+        );
+
+        too_many || too_large || self.too_old()
+    }
+
+    /// The number of bytes sent since the last flush.
+    pub(crate) fn unflushed_bytes(&self) -> usize {
+        self.unflushed_bytes
+    }
+
+    /// Tell whether the oldest unflushed request has been waiting for
+    /// longer than the policy allows.
+    #[cfg(feature = "std")]
+    fn too_old(&mut self) -> bool {
+        match self.policy.after_duration {
+            Some(duration) => {
+                let oldest = *self.oldest_unflushed.get_or_insert_with(Instant::now);
+                oldest.elapsed() >= duration
+            }
+            None => false,
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    fn too_old(&mut self) -> bool {
+        false
+    }
+
+    /// Tell whether we should flush before blocking.
+    pub(crate) fn flush_before_wait(&self) -> bool {
+        self.policy.before_wait && self.unflushed > 0
+    }
+
+    /// Record that the output buffer has been flushed.
+    pub(crate) fn flushed(&mut self) {
+        self.unflushed = 0;
+        self.unflushed_bytes = 0;
+
+        #[cfg(feature = "std")]
+        {
+            self.oldest_unflushed = None;
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Passive grabs that ignore the lock modifiers.
+//!
+//! A passive grab only activates when exactly the requested modifiers are
+//! held, so a hotkey grabbed for `Mod4` stops working once NumLock or
+//! CapsLock is turned on. The usual workaround is to install the grab once
+//! for every combination of lock modifiers, which the functions in this
+//! module do.
+//!
+//! NumLock and ScrollLock have no fixed modifier bit, so they are looked up
+//! with [`LockModifiers::query`]. The result should be queried again when a
+//! `MappingNotify` event for the modifier mapping is received, and existing
+//! grabs reinstalled.
+
+use alloc::vec::Vec;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::xproto::{ButtonIndex, EventMask, GrabMode, Keycode, Keysym, ModMask, Window},
+    Result,
+};
+
+const NUM_LOCK: Keysym = 0xFF7F;
+const SCROLL_LOCK: Keysym = 0xFF14;
+
+/// The modifier bits used by the lock keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockModifiers {
+    /// The modifier NumLock is bound to, or zero if it isn't bound.
+    pub num_lock: u16,
+    /// The modifier CapsLock is bound to. This is always `Lock`.
+    pub caps_lock: u16,
+    /// The modifier ScrollLock is bound to, or zero if it isn't bound.
+    pub scroll_lock: u16,
+}
+
+impl LockModifiers {
+    /// Find the modifiers the lock keys are bound to.
+    pub fn query<D: Display + ?Sized>(display: &mut D) -> Result<Self> {
+        let (min_keycode, max_keycode) = {
+            let setup = display.setup();
+            (setup.min_keycode, setup.max_keycode)
+        };
+
+        let modifiers = display.get_modifier_mapping()?;
+        let keysyms = display.get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?;
+        let modifiers = display.wait_for_reply(modifiers)?;
+        let keysyms = display.wait_for_reply(keysyms)?;
+
+        let per_keycode = usize::from(keysyms.keysyms_per_keycode);
+        let keycode_has = |keycode: Keycode, keysym: Keysym| -> bool {
+            if per_keycode == 0 || keycode < min_keycode {
+                return false;
+            }
+
+            let start = usize::from(keycode - min_keycode) * per_keycode;
+            matches!(
+                keysyms.keysyms.get(start..start + per_keycode),
+                Some(syms) if syms.contains(&keysym)
+            )
+        };
+
+        // the modifier mapping lists a fixed number of keycodes for each
+        // of the eight modifiers
+        let per_modifier = modifiers.keycodes.len() / 8;
+        let modifier_for = |keysym: Keysym| -> u16 {
+            if per_modifier == 0 {
+                return 0;
+            }
+
+            modifiers
+                .keycodes
+                .chunks(per_modifier)
+                .position(|keycodes| {
+                    keycodes
+                        .iter()
+                        .any(|&keycode| keycode != 0 && keycode_has(keycode, keysym))
+                })
+                .map_or(0, |index| 1 << index)
+        };
+
+        Ok(Self {
+            num_lock: modifier_for(NUM_LOCK),
+            caps_lock: u16::from(ModMask::LOCK),
+            scroll_lock: modifier_for(SCROLL_LOCK),
+        })
+    }
+
+    /// Get every combination of lock modifiers added to `modifiers`.
+    ///
+    /// Lock modifiers that are already part of `modifiers` are left as they
+    /// are.
+    pub fn combinations(&self, modifiers: ModMask) -> Vec<ModMask> {
+        let modifiers = u16::from(modifiers);
+
+        let mut locks: Vec<u16> = Vec::new();
+        for &lock in &[self.num_lock, self.caps_lock, self.scroll_lock] {
+            if lock != 0 && modifiers & lock == 0 && !locks.contains(&lock) {
+                locks.push(lock);
+            }
+        }
+
+        (0..1u16 << locks.len())
+            .map(|subset| {
+                let extra = locks
+                    .iter()
+                    .enumerate()
+                    .filter(|&(i, _)| subset & (1 << i) != 0)
+                    .fold(0, |extra, (_, &lock)| extra | lock);
+                ModMask::from(modifiers | extra)
+            })
+            .collect()
+    }
+}
+
+/// How a passive grab behaves once it activates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrabOptions {
+    /// Whether events are reported to the window they occur in, if it is
+    /// one of ours, rather than to the grab window.
+    pub owner_events: bool,
+    /// Whether pointer events keep being processed during the grab.
+    pub pointer_mode: GrabMode,
+    /// Whether keyboard events keep being processed during the grab.
+    pub keyboard_mode: GrabMode,
+}
+
+impl Default for GrabOptions {
+    fn default() -> Self {
+        Self {
+            owner_events: false,
+            pointer_mode: GrabMode::ASYNC,
+            keyboard_mode: GrabMode::ASYNC,
+        }
+    }
+}
+
+/// Grab a key on `window` with the given modifiers, regardless of which
+/// lock modifiers are active.
+pub fn grab_key<D: Display + ?Sized>(
+    display: &mut D,
+    locks: &LockModifiers,
+    window: Window,
+    keycode: Keycode,
+    modifiers: ModMask,
+    options: GrabOptions,
+) -> Result<()> {
+    for modifiers in locks.combinations(modifiers) {
+        display.grab_key(
+            options.owner_events,
+            window,
+            modifiers,
+            keycode,
+            options.pointer_mode,
+            options.keyboard_mode,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Release a grab installed with [`grab_key`].
+pub fn ungrab_key<D: Display + ?Sized>(
+    display: &mut D,
+    locks: &LockModifiers,
+    window: Window,
+    keycode: Keycode,
+    modifiers: ModMask,
+) -> Result<()> {
+    for modifiers in locks.combinations(modifiers) {
+        display.ungrab_key(keycode, window, modifiers)?;
+    }
+
+    Ok(())
+}
+
+/// Grab a pointer button on `window` with the given modifiers, regardless
+/// of which lock modifiers are active.
+pub fn grab_button<D: Display + ?Sized>(
+    display: &mut D,
+    locks: &LockModifiers,
+    window: Window,
+    button: ButtonIndex,
+    modifiers: ModMask,
+    event_mask: EventMask,
+    options: GrabOptions,
+) -> Result<()> {
+    for modifiers in locks.combinations(modifiers) {
+        display.grab_button(
+            options.owner_events,
+            window,
+            event_mask,
+            options.pointer_mode,
+            options.keyboard_mode,
+            0u32,
+            0u32,
+            button,
+            modifiers,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Release a grab installed with [`grab_button`].
+pub fn ungrab_button<D: Display + ?Sized>(
+    display: &mut D,
+    locks: &LockModifiers,
+    window: Window,
+    button: ButtonIndex,
+    modifiers: ModMask,
+) -> Result<()> {
+    for modifiers in locks.combinations(modifiers) {
+        display.ungrab_button(button, window, modifiers)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! A shared handle to an `XcbDisplay`.
+
+use crate::XcbDisplay;
+use alloc::sync::Arc;
+use breadx::{
+    display::{Display, DisplayBase, RawReply, RawRequest},
+    protocol::{xproto::Setup, Event},
+    Result,
+};
+use core::ops::Deref;
+
+/// A cheaply cloneable handle to an [`XcbDisplay`].
+///
+/// `&XcbDisplay` already implements [`Display`], but borrows make it hard
+/// to hand the connection to several threads or subsystems that each
+/// outlive the others. Each clone of a handle shares the same
+/// connection, which is disconnected once the last clone is dropped.
+///
+/// ```no_run
+/// use breadx::prelude::*;
+/// use whitebreadx::{XcbDisplay, XcbDisplayHandle};
+/// # fn main() -> breadx::Result<()> {
+/// let mut display = XcbDisplayHandle::new(XcbDisplay::connect(None)?);
+///
+/// let mut events = display.clone();
+/// std::thread::spawn(move || {
+///     while let Ok(event) = events.wait_for_event() {
+///         println!("{:?}", event);
+///     }
+/// });
+///
+/// display.synchronize()?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct XcbDisplayHandle {
+    display: Arc<XcbDisplay>,
+}
+
+impl XcbDisplayHandle {
+    /// Share `display` through a new handle.
+    pub fn new(display: XcbDisplay) -> Self {
+        Self {
+            display: Arc::new(display),
+        }
+    }
+
+    /// Get the display this handle shares.
+    pub fn display(&self) -> &XcbDisplay {
+        &self.display
+    }
+
+    /// Get the `Arc` the display is kept in.
+    pub fn into_inner(self) -> Arc<XcbDisplay> {
+        self.display
+    }
+
+    /// Take the display back, if this is the last handle to it.
+    pub fn try_unwrap(self) -> core::result::Result<XcbDisplay, Self> {
+        Arc::try_unwrap(self.display).map_err(|display| Self { display })
+    }
+}
+
+impl From<XcbDisplay> for XcbDisplayHandle {
+    fn from(display: XcbDisplay) -> Self {
+        Self::new(display)
+    }
+}
+
+impl From<Arc<XcbDisplay>> for XcbDisplayHandle {
+    fn from(display: Arc<XcbDisplay>) -> Self {
+        Self { display }
+    }
+}
+
+impl Deref for XcbDisplayHandle {
+    type Target = XcbDisplay;
+
+    fn deref(&self) -> &XcbDisplay {
+        &self.display
+    }
+}
+
+impl AsRef<XcbDisplay> for XcbDisplayHandle {
+    fn as_ref(&self) -> &XcbDisplay {
+        &self.display
+    }
+}
+
+impl DisplayBase for XcbDisplayHandle {
+    fn setup(&self) -> &Arc<Setup> {
+        self.display.get_setup()
+    }
+
+    fn default_screen_index(&self) -> usize {
+        DisplayBase::default_screen_index(&&*self.display)
+    }
+
+    fn poll_for_event(&mut self) -> Result<Option<Event>> {
+        DisplayBase::poll_for_event(&mut &*self.display)
+    }
+
+    fn poll_for_reply_raw(&mut self, seq: u64) -> Result<Option<RawReply>> {
+        DisplayBase::poll_for_reply_raw(&mut &*self.display, seq)
+    }
+}
+
+impl Display for XcbDisplayHandle {
+    fn send_request_raw(&mut self, req: RawRequest<'_, '_>) -> Result<u64> {
+        Display::send_request_raw(&mut &*self.display, req)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Display::flush(&mut &*self.display)
+    }
+
+    fn generate_xid(&mut self) -> Result<u32> {
+        Display::generate_xid(&mut &*self.display)
+    }
+
+    fn maximum_request_length(&mut self) -> Result<usize> {
+        Display::maximum_request_length(&mut &*self.display)
+    }
+
+    fn synchronize(&mut self) -> Result<()> {
+        Display::synchronize(&mut &*self.display)
+    }
+
+    fn wait_for_event(&mut self) -> Result<Event> {
+        Display::wait_for_event(&mut &*self.display)
+    }
+
+    fn wait_for_reply_raw(&mut self, seq: u64) -> Result<RawReply> {
+        Display::wait_for_reply_raw(&mut &*self.display, seq)
+    }
+
+    fn check_for_error(&mut self, seq: u64) -> Result<()> {
+        Display::check_for_error(&mut &*self.display, seq)
+    }
+}
@@ -0,0 +1,140 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Observing requests as they're sent and answered.
+
+use crate::{
+    collections::Map,
+    sync::{mtx_lock, rwl_read, rwl_write, Mutex, RwLock},
+};
+use alloc::boxed::Box;
+
+/// What happened to a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestStage {
+    /// The request was handed to `libxcb` to be sent.
+    Sent,
+    /// The reply to the request arrived.
+    Replied {
+        /// The length of the reply, in bytes.
+        length: usize,
+    },
+    /// The request caused an error.
+    Failed {
+        /// The error code.
+        error_code: u8,
+    },
+}
+
+/// A request, as passed to the hook installed with
+/// [`XcbDisplay::set_request_hook`].
+///
+/// [`XcbDisplay::set_request_hook`]: crate::XcbDisplay::set_request_hook
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestInfo {
+    /// What happened to the request.
+    pub stage: RequestStage,
+    /// The sequence number of the request.
+    pub sequence: u64,
+    /// The major opcode of the request.
+    pub major_opcode: u8,
+    /// The minor opcode of an extension request, or the data byte of a
+    /// core request.
+    pub minor_opcode: u8,
+    /// The name of the extension the request belongs to, if it's known.
+    pub extension: Option<&'static str>,
+    /// The length of the request, in bytes, or zero if it's not known.
+    pub length: usize,
+}
+
+/// A function called for each stage of a request.
+type RequestHook = Box<dyn Fn(&RequestInfo) + Send + Sync>;
+
+/// The request hook of a display, along with the requests it's waiting
+/// to see answered.
+pub(crate) struct RequestHooks {
+    hook: RwLock<Option<RequestHook>>,
+    /// Requests that will get a reply, or whose error will be returned
+    /// rather than delivered as an event.
+    pending: Mutex<Map<u64, RequestInfo>>,
+}
+
+impl RequestHooks {
+    pub(crate) fn new() -> Self {
+        Self {
+            hook: RwLock::new(None),
+            pending: Mutex::new(Map::default()),
+        }
+    }
+
+    /// Replace the hook, forgetting about requests sent before.
+    pub(crate) fn set(&self, hook: Option<RequestHook>) {
+        *rwl_write(&self.hook) = hook;
+        mtx_lock(&self.pending).clear();
+    }
+
+    /// A request was sent. If `answered`, a reply or error will be
+    /// returned for it.
+    pub(crate) fn sent(&self, info: RequestInfo, answered: bool) {
+        let hook = rwl_read(&self.hook);
+        if let Some(hook) = &*hook {
+            if answered {
+                mtx_lock(&self.pending).insert(info.sequence, info);
+            }
+
+            hook(&info);
+        }
+    }
+
+    /// The reply to a request arrived.
+    pub(crate) fn replied(&self, sequence: u64, length: usize) {
+        let hook = rwl_read(&self.hook);
+        if let Some(hook) = &*hook {
+            let info = mtx_lock(&self.pending).remove(&sequence);
+            if let Some(mut info) = info {
+                info.stage = RequestStage::Replied { length };
+                hook(&info);
+            }
+        }
+    }
+
+    /// A request caused `error`.
+    ///
+    /// Requests this hasn't seen sent are described by what the error
+    /// says about them, and `extension_name` is called to name the
+    /// extension of their major opcode.
+    pub(crate) fn failed(
+        &self,
+        sequence: u64,
+        error: &[u8; 32],
+        extension_name: impl FnOnce(u8) -> Option<&'static str>,
+    ) {
+        let hook = rwl_read(&self.hook);
+        if let Some(hook) = &*hook {
+            let stage = RequestStage::Failed {
+                error_code: error[1],
+            };
+            let info = match mtx_lock(&self.pending).remove(&sequence) {
+                Some(info) => RequestInfo { stage, ..info },
+                None => RequestInfo {
+                    stage,
+                    sequence,
+                    major_opcode: error[10],
+                    minor_opcode: u16::from_ne_bytes([error[8], error[9]]) as u8,
+                    extension: extension_name(error[10]),
+                    length: 0,
+                },
+            };
+            hook(&info);
+        }
+    }
+
+    /// A request will never be answered.
+    pub(crate) fn forget(&self, sequence: u64) {
+        if rwl_read(&self.hook).is_some() {
+            mtx_lock(&self.pending).remove(&sequence);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Querying user idle time through the MIT-SCREEN-SAVER extension.
+
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        screensaver::{Kind, State},
+        xproto::{Drawable, Window},
+    },
+    Result,
+};
+use core::time::Duration;
+
+/// The state of the screen saver, as reported by the X server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleInfo {
+    /// The time since the user last provided input.
+    pub idle_time: Duration,
+    /// The current state of the screen saver.
+    pub state: State,
+    /// How the screen saver is drawn.
+    pub kind: Kind,
+    /// If the screen saver is off, the time until it activates. If it is
+    /// on, the time since it activated. `None` if it is disabled.
+    pub saver_time: Option<Duration>,
+    /// The window the screen saver is drawn on, if it is on.
+    pub saver_window: Option<Window>,
+}
+
+impl IdleInfo {
+    /// Tell whether the screen saver is currently active.
+    pub fn is_active(&self) -> bool {
+        self.state == State::ON || self.state == State::CYCLE
+    }
+}
+
+/// Query the screen saver state for the screen containing `drawable`.
+pub fn query_idle<D: Display + ?Sized>(display: &mut D, drawable: Drawable) -> Result<IdleInfo> {
+    let info = display.screensaver_query_info(drawable)?;
+    let info = display.wait_for_reply(info)?;
+    let state = State::from(info.state);
+
+    Ok(IdleInfo {
+        idle_time: Duration::from_millis(info.ms_since_user_input.into()),
+        state,
+        kind: info.kind,
+        saver_time: if state == State::DISABLED {
+            None
+        } else {
+            Some(Duration::from_millis(info.ms_until_server.into()))
+        },
+        saver_window: if info.saver_window == 0 {
+            None
+        } else {
+            Some(info.saver_window)
+        },
+    })
+}
+
+/// Get the time since the user last provided input on the default screen.
+pub fn idle_time<D: Display + ?Sized>(display: &mut D) -> Result<Duration> {
+    let root = display.default_screen().root;
+    query_idle(display, root).map(|info| info.idle_time)
+}
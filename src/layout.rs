@@ -0,0 +1,141 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Keyboard layout switching through XKB groups.
+//!
+//! XKB supports up to four groups, each of which usually holds a keyboard
+//! layout. Layout indicators display the name of the active group and
+//! switchers lock a different one. Groups are numbered from zero.
+
+use crate::repeat::use_xkb;
+use alloc::{string::String, vec::Vec};
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        xkb::{
+            EventType, Group, NameDetail, SelectEventsAux, SelectEventsAuxBitcase2,
+            SelectEventsAuxBitcase6, StatePart, ID,
+        },
+        Event,
+    },
+    Error, Result,
+};
+
+/// The maximum number of groups XKB supports.
+pub const MAX_GROUPS: u8 = 4;
+
+/// Get the names of the groups configured on the core keyboard.
+///
+/// The index of a name is the index of its group. Groups without a name
+/// have an empty string.
+pub fn group_names<D: Display + ?Sized>(display: &mut D) -> Result<Vec<String>> {
+    use_xkb(display)?;
+
+    let names = display.xkb_get_names(ID::USE_CORE_KBD.into(), NameDetail::GROUP_NAMES)?;
+    let names = display.wait_for_reply(names)?;
+    let atoms = names.value_list.groups.unwrap_or_default();
+
+    // send all of the requests before waiting on any of them
+    let cookies = atoms
+        .iter()
+        .map(|&atom| {
+            if atom == 0 {
+                Ok(None)
+            } else {
+                display.get_atom_name(atom).map(Some)
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    cookies
+        .into_iter()
+        .map(|cookie| match cookie {
+            Some(cookie) => {
+                let name = display.wait_for_reply(cookie)?.name;
+                Ok(String::from_utf8_lossy(&name).into_owned())
+            }
+            None => Ok(String::new()),
+        })
+        .collect()
+}
+
+/// Get the index of the active group on the core keyboard.
+pub fn active_group<D: Display + ?Sized>(display: &mut D) -> Result<u8> {
+    use_xkb(display)?;
+
+    let state = display.xkb_get_state(ID::USE_CORE_KBD.into())?;
+    Ok(display.wait_for_reply(state)?.group.into())
+}
+
+/// Lock the given group on the core keyboard, making it the active group.
+pub fn lock_group<D: Display + ?Sized>(display: &mut D, group: u8) -> Result<()> {
+    if group >= MAX_GROUPS {
+        return Err(Error::make_msg("XKB group index out of range"));
+    }
+
+    use_xkb(display)?;
+    display.xkb_latch_lock_state(
+        ID::USE_CORE_KBD.into(),
+        0u8,
+        0u8,
+        true,
+        Group::from(group),
+        0u8,
+        false,
+        0,
+    )?;
+    Ok(())
+}
+
+/// Select the events needed for [`group_event`] to report changes to the
+/// active group and to the group names.
+pub fn select_group_events<D: Display + ?Sized>(display: &mut D) -> Result<()> {
+    use_xkb(display)?;
+
+    let state = u16::from(StatePart::GROUP_STATE);
+    let names = u16::from(NameDetail::GROUP_NAMES);
+    display.xkb_select_events(
+        ID::USE_CORE_KBD.into(),
+        EventType::from(0u8),
+        EventType::from(0u8),
+        0u8,
+        0u8,
+        SelectEventsAux::new()
+            .bitcase2(SelectEventsAuxBitcase2 {
+                affect_state: state,
+                state_details: state,
+            })
+            .bitcase6(SelectEventsAuxBitcase6 {
+                affect_names: names,
+                names_details: names,
+            }),
+    )?;
+    Ok(())
+}
+
+/// A change to the keyboard groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupEvent {
+    /// The active group changed to the given group.
+    Changed(u8),
+    /// The group names changed, and should be read again with
+    /// [`group_names`].
+    NamesChanged,
+}
+
+/// Tell whether an event reports a change to the keyboard groups.
+///
+/// The events must have been selected with [`select_group_events`].
+pub fn group_event(event: &Event) -> Option<GroupEvent> {
+    match event {
+        Event::XkbStateNotify(event) if event.changed & u16::from(StatePart::GROUP_STATE) != 0 => {
+            Some(GroupEvent::Changed(event.group.into()))
+        }
+        Event::XkbNamesNotify(event) if event.changed & u16::from(NameDetail::GROUP_NAMES) != 0 => {
+            Some(GroupEvent::NamesChanged)
+        }
+        _ => None,
+    }
+}
@@ -36,17 +36,85 @@
 //!   not used, but spinlocks are used to secure data instead. Think
 //!   carefully before disabling this feature, since spinlocks are
 //!   [considered harmful].
+//! - `std` (enabled by default) - Imports the standard library, and
+//!   enables the [`clock`] module, which maps X server timestamps to
+//!   `Instant`s, the [`auth`] module, which implements authorization
+//!   protocols such as `XDM-AUTHORIZATION-1`, and the [`xauth`] module,
+//!   which reads the Xauthority file.
 //! - `xlib` (enabled by default) - Enables use of the `libX11`-based
-//!   [`Display`]s.
+//!   [`Display`]s. [`XlibFunctions`] lets a display call functions other
+//!   than the ones from the linked `libX11`.
+//! - `fast_hash` (enabled by default) - Uses `hashbrown` maps with a fast
+//!   hasher for internal bookkeeping. With this feature disabled, the
+//!   B-tree collections from `alloc` are used instead.
 //! - `dl` - By default, this library statically links to `libxcb` and.
 //!   optionally, `libX11`. Enabling this feature uses dynamic, runtime
-//!   linking instead. This also imports the standard library.
+//!   linking instead. The [`dl`] module lets the application supply
+//!   libraries it has already loaded. This also imports the standard
+//!   library.
+//! - `static` - Links the static archives of `libxcb` and, optionally,
+//!   `libX11`, along with `libXau` and `libXdmcp`, instead of their shared
+//!   libraries. The resulting binary runs on systems without the X11
+//!   libraries installed. Has no effect together with `dl`.
 //! - `pl` - Uses `parking_lot` mutexes instead of `std` mutexes throughout
 //!   the program. Implies `real_mutex`.
 //! - `to_socket` - On Unix, enables the [`XcbDisplay::connect_to_socket`]
-//!   function, which allows one to safely wrap around any [`AsRawFd`] type.
+//!   function, which allows one to safely wrap around any [`AsRawFd`] type,
+//!   and [`XcbDisplay::connect_to_owned_socket`], which takes ownership of
+//!   a socket and looks up its authorization in the Xauthority file.
 //!   Also imports the standard library and adds `AsRawFd` impls to
 //!   `XcbDisplay` and `XlibDisplay`.
+//! - `c_api` - Exports an `extern "C"` API, declared in `include/whitebreadx.h`,
+//!   that allows C and C++ programs to create and use an [`XcbDisplay`].
+//!   See the [`c_api`] module for more information. Imports the standard
+//!   library.
+//! - `fault_injection` - Enables the [`fault`] module, which makes calls
+//!   into `libxcb` fail on demand so that error handling can be tested.
+//!   This adds overhead to every call and should only be used in tests.
+//! - `diagnostics` - Enables the [`diagnostics`] module, which passes every
+//!   request, event, reply and error to a callback. It doesn't need the
+//!   standard library, so it also works for `no_std` builds.
+//! - `stats` - Counts the requests sent and the replies, events and errors
+//!   received by each [`XcbDisplay`], which [`XcbDisplay::stats`] returns
+//!   as a [`Stats`]. Each count is a relaxed atomic increment; without
+//!   this feature, nothing is counted.
+//! - `present` - Enables the [`present`] module, which receives a
+//!   window's Present events on their own queue.
+//! - `randr` - Enables the [`output`] module, which reads RandR output
+//!   properties and EDIDs. When the standard library is also enabled,
+//!   this enables the [`lock`] and [`scale`] modules as well.
+//! - `screensaver` - Enables the [`idle`] module, which queries the user's
+//!   idle time through the MIT-SCREEN-SAVER extension.
+//! - `xkb` - Enables the [`layout`] module, which switches between XKB
+//!   groups, and the parts of the [`repeat`] module that use XKB.
+//! - `shm` - Enables the [`shm`] module, which tracks when MIT-SHM
+//!   segments can be reused.
+//! - `sync` - Enables the [`xsync`] module, which wraps the counters,
+//!   alarms and fences of the SYNC extension.
+//! - `xinput` - Enables the [`touch`] module, which tracks XInput2 touch
+//!   sequences and recognizes simple gestures, and the [`raw_input`]
+//!   module, which keeps XInput2 events out of the main event queue.
+//!   Imports the standard library.
+//!
+//! ### Minimal builds
+//!
+//! With `default-features = false`, this crate only provides
+//! [`XcbDisplay`], does not use the standard library, and depends on
+//! nothing beyond `breadx`, `libc` and a few small `no_std` crates. This
+//! is meant for embedding in size-constrained binaries. Enable `fast_hash`
+//! or `real_mutex` again if the binary already pulls in their
+//! dependencies.
+//!
+//! ### Cross compilation
+//!
+//! Without the `dl` feature, `libxcb` and `libX11` are linked at build
+//! time. If they aren't in the linker's default search path, as is usual
+//! for a cross-compilation sysroot, set `WHITEBREADX_XCB_LIB_DIR` and
+//! `WHITEBREADX_X11_LIB_DIR` to the directories containing them. Like the
+//! variables read by the `cc` crate, these can be made specific to a
+//! target by adding it as a suffix, e.g.
+//! `WHITEBREADX_XCB_LIB_DIR_aarch64_unknown_linux_gnu`, or by adding a
+//! `TARGET_` prefix. No headers are needed.
 //!
 //! [considered harmful]: https://matklad.github.io/2020/01/02/spinlocks-considered-harmful.html
 
@@ -60,17 +128,118 @@ extern crate std;
 
 #[path = "alloc.rs"]
 pub(crate) mod cbox;
+mod collections;
+#[cfg(feature = "std")]
+mod connect;
+mod cookie;
+mod custom_extension;
+#[cfg(all(unix, feature = "std"))]
+mod direct_write;
+mod divert;
+mod events;
 pub(crate) mod extension_manager;
+mod extension_record;
+mod fds;
+mod flush;
+mod handle;
+mod hook;
+mod local;
+mod motion;
+mod options;
+mod requests;
+mod special_event;
+mod stats;
+mod status;
 pub(crate) mod sync;
 pub(crate) mod xcb_ffi;
+#[cfg(feature = "std")]
+mod xid;
 
 #[cfg(feature = "xlib")]
 pub(crate) mod xlib_ffi;
 
+#[cfg(feature = "c_api")]
+pub mod c_api;
+
+#[cfg(feature = "std")]
+pub mod auth;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod xauth;
+
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+
+#[cfg(feature = "dl")]
+pub mod dl;
+
+#[cfg(feature = "fault_injection")]
+pub mod fault;
+
+pub mod grab;
+
+#[cfg(feature = "screensaver")]
+pub mod idle;
+
+#[cfg(feature = "xkb")]
+pub mod layout;
+
+#[cfg(all(feature = "randr", feature = "std"))]
+pub mod lock;
+
+#[cfg(feature = "randr")]
+pub mod output;
+
+#[cfg(feature = "present")]
+pub mod present;
+
+#[cfg(feature = "xinput")]
+pub mod raw_input;
+
+pub mod repeat;
+
+#[cfg(all(feature = "randr", feature = "std"))]
+pub mod scale;
+
+pub mod selection;
+
+#[cfg(feature = "shm")]
+pub mod shm;
+
+pub mod small_request;
+
+#[cfg(feature = "xinput")]
+pub mod touch;
+
+#[cfg(feature = "sync")]
+pub mod xsync;
+
+pub use cookie::XcbCookie;
+pub use custom_extension::ExtensionParser;
+pub use divert::DivertedEventQueue;
+pub use events::Events;
+pub use flush::FlushPolicy;
+pub use handle::XcbDisplayHandle;
+pub use hook::{RequestInfo, RequestStage};
+pub use local::XcbDisplayLocal;
+pub use options::XcbConnectOptions;
+pub use special_event::SpecialEventQueue;
+#[cfg(feature = "stats")]
+pub use stats::Stats;
+pub use status::ConnectionStatus;
+
 mod xcb_connection;
-pub use xcb_connection::XcbDisplay;
+pub use xcb_connection::{RawEvent, XcbDisplay, XcbReply};
+
+#[cfg(feature = "std")]
+pub use connect::{parse_display, ConnectError, ParsedDisplay};
+#[cfg(feature = "std")]
+pub use xid::XidsExhausted;
 
 #[cfg(feature = "xlib")]
 mod xlib;
 #[cfg(feature = "xlib")]
-pub use xlib::{ThreadSafe, ThreadSafety, ThreadUnsafe, XlibDisplay};
+pub use xlib::{EventQueueOwner, ThreadSafe, ThreadSafety, ThreadUnsafe, XlibDisplay, XlibEvent};
+#[cfg(feature = "xlib")]
+pub use xlib_ffi::{XVisualInfo, XlibFunctions, XlibWireToEvent, XlibWireToEventCookie};
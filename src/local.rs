@@ -0,0 +1,111 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! A display for clients that only use it from one thread.
+
+use crate::XcbDisplay;
+use alloc::sync::Arc;
+use breadx::{
+    display::{Display, DisplayBase, RawReply, RawRequest},
+    protocol::{xproto::Setup, Event},
+    Result,
+};
+use core::{cell::Cell, marker::PhantomData, ops::Deref};
+
+/// An [`XcbDisplay`] that can't be shared between threads.
+///
+/// An `XcbDisplay` locks the state it keeps for requests and replies on
+/// every call, since it may be used through `&XcbDisplay` on several
+/// threads at once. This display can only be used through `&mut`, so
+/// sending requests and reading replies skips locking the set of replies
+/// that carry file descriptors and the table of extension codes. It can
+/// still be moved to another thread.
+///
+/// [`XcbDisplay`]: crate::XcbDisplay
+pub struct XcbDisplayLocal {
+    display: XcbDisplay,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl XcbDisplayLocal {
+    /// Use `display` from a single thread.
+    pub fn new(display: XcbDisplay) -> Self {
+        Self {
+            display,
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Get the display back, so that it can be shared again.
+    pub fn into_inner(self) -> XcbDisplay {
+        self.display
+    }
+}
+
+impl From<XcbDisplay> for XcbDisplayLocal {
+    fn from(display: XcbDisplay) -> Self {
+        Self::new(display)
+    }
+}
+
+impl Deref for XcbDisplayLocal {
+    type Target = XcbDisplay;
+
+    fn deref(&self) -> &XcbDisplay {
+        &self.display
+    }
+}
+
+impl DisplayBase for XcbDisplayLocal {
+    fn setup(&self) -> &Arc<Setup> {
+        self.display.get_setup()
+    }
+
+    fn default_screen_index(&self) -> usize {
+        DisplayBase::default_screen_index(&self.display)
+    }
+
+    fn poll_for_event(&mut self) -> Result<Option<Event>> {
+        DisplayBase::poll_for_event(&mut self.display)
+    }
+
+    fn poll_for_reply_raw(&mut self, seq: u64) -> Result<Option<RawReply>> {
+        self.display.poll_for_reply_mut(seq)
+    }
+}
+
+impl Display for XcbDisplayLocal {
+    fn send_request_raw(&mut self, req: RawRequest<'_, '_>) -> Result<u64> {
+        self.display.send_request_mut(req)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Display::flush(&mut self.display)
+    }
+
+    fn generate_xid(&mut self) -> Result<u32> {
+        Display::generate_xid(&mut self.display)
+    }
+
+    fn maximum_request_length(&mut self) -> Result<usize> {
+        Display::maximum_request_length(&mut self.display)
+    }
+
+    fn synchronize(&mut self) -> Result<()> {
+        Display::synchronize(&mut self.display)
+    }
+
+    fn wait_for_event(&mut self) -> Result<Event> {
+        Display::wait_for_event(&mut self.display)
+    }
+
+    fn wait_for_reply_raw(&mut self, seq: u64) -> Result<RawReply> {
+        self.display.wait_for_reply_mut(seq)
+    }
+
+    fn check_for_error(&mut self, seq: u64) -> Result<()> {
+        Display::check_for_error(&mut self.display, seq)
+    }
+}
@@ -0,0 +1,348 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Building blocks for screen lockers.
+//!
+//! A screen locker needs to grab all input, cover every monitor and keep
+//! doing both for as long as the screen is locked. Getting any of these
+//! wrong lets the user bypass the lock, so [`ScreenLock`] takes care of
+//! them: it grabs the keyboard and pointer, creates an override-redirect
+//! window on each RandR monitor, and keeps those windows on top and
+//! covering the monitors as the output configuration changes.
+//!
+//! Drawing the lock screen and authenticating the user are left to the
+//! application.
+
+use alloc::{vec, vec::Vec};
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        randr::{self, NotifyMask},
+        xproto::{
+            ChangeWindowAttributesAux, ConfigureWindowAux, CreateWindowAux, EventMask, GrabMode,
+            GrabStatus, StackMode, Time, Visibility, Window, WindowClass,
+        },
+        Event,
+    },
+    Error, Result,
+};
+use std::{thread, time::Duration};
+
+/// Grab the keyboard and the pointer, retrying if another client holds
+/// a grab.
+///
+/// The server is grabbed while the grabs are made, so either both grabs
+/// succeed or neither is left in place. If the grabs still fail after
+/// `attempts` tries, with `delay` between each, an error is returned.
+/// Clients such as menus often hold short-lived grabs, so a few attempts
+/// over about a second is a good default.
+pub fn grab_input<D: Display + ?Sized>(
+    display: &mut D,
+    window: Window,
+    attempts: usize,
+    delay: Duration,
+) -> Result<()> {
+    for attempt in 0..attempts {
+        if attempt > 0 {
+            thread::sleep(delay);
+        }
+
+        display.grab_server()?;
+        let result = try_grab_input(display, window);
+        display.ungrab_server()?;
+        display.flush()?;
+
+        if result? {
+            return Ok(());
+        }
+    }
+
+    Err(Error::make_msg(
+        "failed to grab the keyboard and pointer for the screen lock",
+    ))
+}
+
+fn try_grab_input<D: Display + ?Sized>(display: &mut D, window: Window) -> Result<bool> {
+    let keyboard = display.grab_keyboard(
+        false,
+        window,
+        Time::CURRENT_TIME,
+        GrabMode::ASYNC,
+        GrabMode::ASYNC,
+    )?;
+    let pointer = display.grab_pointer(
+        false,
+        window,
+        EventMask::BUTTON_PRESS | EventMask::BUTTON_RELEASE | EventMask::POINTER_MOTION,
+        GrabMode::ASYNC,
+        GrabMode::ASYNC,
+        0u32,
+        0u32,
+        Time::CURRENT_TIME,
+    )?;
+
+    let keyboard = display.wait_for_reply(keyboard)?.status == GrabStatus::SUCCESS;
+    let pointer = display.wait_for_reply(pointer)?.status == GrabStatus::SUCCESS;
+
+    // don't leave half of the grab in place
+    if keyboard && !pointer {
+        display.ungrab_keyboard(Time::CURRENT_TIME)?;
+    } else if pointer && !keyboard {
+        display.ungrab_pointer(Time::CURRENT_TIME)?;
+    }
+
+    Ok(keyboard && pointer)
+}
+
+/// The area covered by a monitor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Area {
+    x: i16,
+    y: i16,
+    width: u16,
+    height: u16,
+}
+
+/// A locked screen.
+///
+/// Pass every event received on the display to [`ScreenLock::handle_event`]
+/// while the screen is locked, and call [`ScreenLock::unlock`] once the
+/// user has authenticated.
+pub struct ScreenLock {
+    /// The root window of the locked screen.
+    root: Window,
+    /// The event mask we had on the root window before locking.
+    root_event_mask: u32,
+    /// The lock windows, along with the monitor they cover.
+    windows: Vec<(Area, Window)>,
+    /// The number of times to try grabbing input.
+    grab_attempts: usize,
+    /// The delay between attempts at grabbing input.
+    grab_delay: Duration,
+}
+
+impl ScreenLock {
+    /// Lock the default screen.
+    ///
+    /// Input is grabbed before any windows are created, so if this fails,
+    /// nothing is shown.
+    pub fn lock<D: Display + ?Sized>(display: &mut D) -> Result<Self> {
+        let root = display.default_screen().root;
+        let grab_attempts = 10;
+        let grab_delay = Duration::from_millis(100);
+
+        grab_input(display, root, grab_attempts, grab_delay)?;
+
+        // watch for windows that may end up above ours, without
+        // clobbering the event mask the application already has
+        let attributes = display.get_window_attributes(root)?;
+        let root_event_mask = display.wait_for_reply(attributes)?.your_event_mask;
+        let event_mask = root_event_mask | u32::from(EventMask::SUBSTRUCTURE_NOTIFY);
+        display.change_window_attributes(
+            root,
+            ChangeWindowAttributesAux::new().event_mask(event_mask),
+        )?;
+
+        // watch for monitor changes, if RandR is available
+        if monitors(display, root)?.is_some() {
+            display.randr_select_input(
+                root,
+                NotifyMask::SCREEN_CHANGE | NotifyMask::CRTC_CHANGE | NotifyMask::OUTPUT_CHANGE,
+            )?;
+        }
+
+        let mut lock = Self {
+            root,
+            root_event_mask,
+            windows: Vec::new(),
+            grab_attempts,
+            grab_delay,
+        };
+        lock.update_windows(display)?;
+        display.flush()?;
+
+        Ok(lock)
+    }
+
+    /// Get the windows covering each monitor.
+    ///
+    /// These windows receive `Expose` events and should be drawn to by
+    /// the application.
+    pub fn windows(&self) -> impl Iterator<Item = Window> + '_ {
+        self.windows.iter().map(|&(_, window)| window)
+    }
+
+    /// Process an event, keeping the lock windows on top and covering
+    /// every monitor.
+    pub fn handle_event<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &Event,
+    ) -> Result<()> {
+        match event {
+            Event::RandrScreenChangeNotify(_) | Event::RandrNotify(_) => {
+                self.update_windows(display)?;
+                grab_input(display, self.root, self.grab_attempts, self.grab_delay)?;
+            }
+            Event::VisibilityNotify(event)
+                if event.state != Visibility::UNOBSCURED && self.is_lock_window(event.window) =>
+            {
+                self.raise(display)?;
+            }
+            Event::MapNotify(event) if !self.is_lock_window(event.window) => {
+                self.raise(display)?;
+            }
+            Event::ConfigureNotify(event)
+                if event.event == self.root && !self.is_lock_window(event.window) =>
+            {
+                self.raise(display)?;
+            }
+            _ => return Ok(()),
+        }
+
+        display.flush()
+    }
+
+    /// Unlock the screen, releasing the grabs and destroying the lock
+    /// windows.
+    pub fn unlock<D: Display + ?Sized>(self, display: &mut D) -> Result<()> {
+        display.ungrab_keyboard(Time::CURRENT_TIME)?;
+        display.ungrab_pointer(Time::CURRENT_TIME)?;
+
+        for (_, window) in self.windows {
+            display.destroy_window(window)?;
+        }
+
+        display.change_window_attributes(
+            self.root,
+            ChangeWindowAttributesAux::new().event_mask(self.root_event_mask),
+        )?;
+        display.flush()
+    }
+
+    fn is_lock_window(&self, window: Window) -> bool {
+        self.windows.iter().any(|&(_, lock)| lock == window)
+    }
+
+    /// Make sure there's exactly one lock window for each monitor.
+    fn update_windows<D: Display + ?Sized>(&mut self, display: &mut D) -> Result<()> {
+        let areas = monitors(display, self.root)?.unwrap_or_else(|| {
+            // without RandR monitors, cover the entire screen
+            let screen = display.default_screen();
+            vec![Area {
+                x: 0,
+                y: 0,
+                width: screen.width_in_pixels,
+                height: screen.height_in_pixels,
+            }]
+        });
+
+        // create windows for new monitors
+        for &area in &areas {
+            if !self.windows.iter().any(|&(covered, _)| covered == area) {
+                let window = create_lock_window(display, self.root, area)?;
+                self.windows.push((area, window));
+            }
+        }
+
+        // destroy windows for monitors that are gone, only after the new
+        // ones are up so nothing is ever left uncovered
+        let mut i = 0;
+        while i < self.windows.len() {
+            if areas.contains(&self.windows[i].0) {
+                i += 1;
+            } else {
+                let (_, window) = self.windows.swap_remove(i);
+                display.destroy_window(window)?;
+            }
+        }
+
+        self.raise(display)
+    }
+
+    /// Raise all of the lock windows above every other window.
+    fn raise<D: Display + ?Sized>(&self, display: &mut D) -> Result<()> {
+        for &(_, window) in &self.windows {
+            display.configure_window(
+                window,
+                ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn create_lock_window<D: Display + ?Sized>(
+    display: &mut D,
+    root: Window,
+    area: Area,
+) -> Result<Window> {
+    let black = display.default_screen().black_pixel;
+    let window = display.generate_xid()?;
+    let event_mask = EventMask::EXPOSURE
+        | EventMask::VISIBILITY_CHANGE
+        | EventMask::KEY_PRESS
+        | EventMask::KEY_RELEASE
+        | EventMask::BUTTON_PRESS;
+
+    display.create_window(
+        0,
+        window,
+        root,
+        area.x,
+        area.y,
+        area.width,
+        area.height,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        0,
+        CreateWindowAux::new()
+            .background_pixel(black)
+            .override_redirect(1)
+            .event_mask(u32::from(event_mask)),
+    )?;
+    display.map_window(window)?;
+
+    Ok(window)
+}
+
+/// Get the areas covered by the active RandR monitors.
+///
+/// Returns `None` if the server doesn't support RandR 1.5 or has no
+/// active monitors.
+fn monitors<D: Display + ?Sized>(display: &mut D, root: Window) -> Result<Option<Vec<Area>>> {
+    let present = display.query_extension(randr::X11_EXTENSION_NAME.as_bytes())?;
+    if !display.wait_for_reply(present)?.present {
+        return Ok(None);
+    }
+
+    // monitors were introduced in RandR 1.5
+    let version = display.randr_query_version(1, 5)?;
+    let version = display.wait_for_reply(version)?;
+    if (version.major_version, version.minor_version) < (1, 5) {
+        return Ok(None);
+    }
+
+    let monitors = display.randr_get_monitors(root, true)?;
+    let monitors = display.wait_for_reply(monitors)?;
+
+    if monitors.monitors.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        monitors
+            .monitors
+            .iter()
+            .map(|monitor| Area {
+                x: monitor.x,
+                y: monitor.y,
+                width: monitor.width,
+                height: monitor.height,
+            })
+            .collect(),
+    ))
+}
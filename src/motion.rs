@@ -0,0 +1,120 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Coalescing runs of motion events.
+//!
+//! When an application falls behind, motion events pile up in the queue,
+//! and handling every one of them only makes it fall further behind. With
+//! coalescing enabled, an [`XcbDisplay`] that reads a motion event also
+//! takes the events that have already arrived after it, and skips to the
+//! last one in an unbroken run of motion events for the same window.
+//!
+//! [`XcbDisplay`]: crate::XcbDisplay
+
+use crate::xcb_ffi::GenericEvent;
+use core::ptr::NonNull;
+
+const MOTION_NOTIFY: u8 = 6;
+const GE_GENERIC: u8 = 35;
+const XI_MOTION: u16 = 6;
+
+/// What makes two motion events interchangeable.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MotionKey {
+    /// A core `MotionNotify` on this window.
+    Core { window: u32 },
+    /// An XInput2 `XI_Motion` from this device, on this window.
+    XInput { device: u16, window: u32 },
+}
+
+impl MotionKey {
+    /// Get the key of a motion event, or `None` for any other event.
+    ///
+    /// Raw motion events aren't coalesced, since they carry how far the
+    /// device moved rather than where it is now, and dropping them would
+    /// lose movement.
+    ///
+    /// `xinput` is the major opcode of the XInput extension, if it's known.
+    pub(crate) fn of(event: &[u8; 32], xinput: Option<u8>) -> Option<Self> {
+        let u16_at = |i: usize| u16::from_ne_bytes([event[i], event[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_ne_bytes([event[i], event[i + 1], event[i + 2], event[i + 3]]);
+
+        match event[0] & 0x7f {
+            MOTION_NOTIFY => Some(MotionKey::Core { window: u32_at(12) }),
+            GE_GENERIC if xinput == Some(event[1]) => {
+                if u16_at(8) != XI_MOTION {
+                    return None;
+                }
+
+                Some(MotionKey::XInput {
+                    device: u16_at(10),
+                    window: u32_at(24),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An event held back after ending a run of motion events.
+struct Pending(NonNull<GenericEvent>);
+
+// SAFETY: the event is a plain buffer allocated by libxcb
+unsafe impl Send for Pending {}
+
+/// The coalescing state of a display.
+pub(crate) struct MotionState {
+    enabled: bool,
+    /// The number of events skipped so far.
+    skipped: u64,
+    /// The event read after a run of motion events, to be returned next.
+    pending: Option<Pending>,
+}
+
+impl MotionState {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            skipped: 0,
+            pending: None,
+        }
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub(crate) fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub(crate) fn skip(&mut self) {
+        self.skipped += 1;
+    }
+
+    /// Take the event held back by the last run.
+    pub(crate) fn take_pending(&mut self) -> Option<*mut GenericEvent> {
+        self.pending.take().map(|pending| pending.0.as_ptr())
+    }
+
+    /// Hold back an event until the next read.
+    pub(crate) fn set_pending(&mut self, event: NonNull<GenericEvent>) {
+        debug_assert!(self.pending.is_none());
+        self.pending = Some(Pending(event));
+    }
+}
+
+impl Drop for MotionState {
+    fn drop(&mut self) {
+        if let Some(event) = self.take_pending() {
+            unsafe { libc::free(event.cast()) };
+        }
+    }
+}
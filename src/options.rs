@@ -0,0 +1,153 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Options for connecting to the X server.
+
+use crate::XcbDisplay;
+use breadx::Result;
+use cstr_core::CStr;
+
+#[cfg(all(unix, feature = "std"))]
+use core::time::Duration;
+
+/// Settings for connecting an [`XcbDisplay`], combined before connecting.
+///
+/// The `connect*` constructors on `XcbDisplay` each cover one case. This
+/// covers them all at once:
+///
+/// ```no_run
+/// use whitebreadx::XcbConnectOptions;
+/// # fn main() -> breadx::Result<()> {
+/// let display = XcbConnectOptions::new()
+///     .auth_info(b"MIT-MAGIC-COOKIE-1", &[0; 16])
+///     .screen(1)
+///     .connect()?;
+/// # Ok(()) }
+/// ```
+///
+/// [`XcbDisplay`]: crate::XcbDisplay
+#[derive(Debug, Clone, Copy, Default)]
+pub struct XcbConnectOptions<'a> {
+    /// The display string, or `None` for `$DISPLAY`.
+    display: Option<&'a CStr>,
+    /// The screen to use instead of the one in the display string.
+    screen: Option<usize>,
+    /// The authorization protocol name and data, instead of the ones from
+    /// the Xauthority file.
+    auth: Option<(&'a [u8], &'a [u8])>,
+    /// How long to wait for the server to accept the connection.
+    #[cfg(all(unix, feature = "std"))]
+    timeout: Option<Duration>,
+    /// Whether to query every extension once connected.
+    prefetch_extensions: bool,
+    /// The extensions to start querying once connected.
+    extensions: &'a [&'static str],
+}
+
+impl<'a> XcbConnectOptions<'a> {
+    /// Connect to `$DISPLAY`, with the authorization from the Xauthority
+    /// file.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect to `display` instead of `$DISPLAY`.
+    #[must_use]
+    pub fn display(mut self, display: &'a CStr) -> Self {
+        self.display = Some(display);
+        self
+    }
+
+    /// Use `screen` instead of the screen given in the display string.
+    ///
+    /// Connecting fails if the server has no such screen.
+    #[must_use]
+    pub fn screen(mut self, screen: usize) -> Self {
+        self.screen = Some(screen);
+        self
+    }
+
+    /// Authorize with the given protocol name and data, instead of looking
+    /// them up in the Xauthority file.
+    #[must_use]
+    pub fn auth_info(mut self, name: &'a [u8], data: &'a [u8]) -> Self {
+        self.auth = Some((name, data));
+        self
+    }
+
+    /// Give up if a TCP connection to the server isn't established within
+    /// `timeout`.
+    ///
+    /// The timeout covers every address the host name resolves to, not
+    /// each one. `libxcb` leaves this to the operating system, which can
+    /// take minutes. With a timeout, the socket is opened by this crate
+    /// instead, and the authorization looked up the same way `libxcb`
+    /// would.
+    #[cfg(all(unix, feature = "std"))]
+    #[must_use]
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Query every extension the server supports as soon as the
+    /// connection is made.
+    ///
+    /// See [`XcbDisplay::prefetch_extensions`].
+    #[must_use]
+    pub fn prefetch_extensions(mut self, prefetch: bool) -> Self {
+        self.prefetch_extensions = prefetch;
+        self
+    }
+
+    /// Start querying `extensions` as soon as the connection is made.
+    ///
+    /// See [`XcbDisplay::declare_extensions`].
+    #[must_use]
+    pub fn extensions(mut self, extensions: &'a [&'static str]) -> Self {
+        self.extensions = extensions;
+        self
+    }
+
+    /// Connect to the server with these options.
+    pub fn connect(&self) -> Result<XcbDisplay> {
+        let display = self.connect_display()?;
+        display.declare_extensions(self.extensions);
+
+        if self.prefetch_extensions {
+            display.prefetch_extensions()?;
+        }
+
+        Ok(display)
+    }
+
+    fn connect_display(&self) -> Result<XcbDisplay> {
+        #[cfg(all(unix, feature = "std"))]
+        {
+            if let Some(timeout) = self.timeout {
+                return self.connect_with_timeout(timeout);
+            }
+        }
+
+        let display = match self.auth {
+            Some((name, data)) => XcbDisplay::connect_with_auth_info(self.display, name, data)?,
+            None => XcbDisplay::connect(self.display)?,
+        };
+
+        match self.screen {
+            Some(screen) => display.with_screen(screen),
+            None => Ok(display),
+        }
+    }
+
+    #[cfg(all(unix, feature = "std"))]
+    fn connect_with_timeout(&self, timeout: Duration) -> Result<XcbDisplay> {
+        let opened = crate::connect::open(self.display, Some(timeout))?;
+        let screen = self.screen.unwrap_or(opened.screen);
+
+        XcbDisplay::connect_opened(opened, self.auth, screen)
+    }
+}
@@ -0,0 +1,239 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Reading RandR output properties and EDID data.
+//!
+//! Output names like `HDMI-1` depend on the driver and on which port a
+//! monitor is plugged into. To recognize a monitor, use the EDID block
+//! it reports instead, which can be read with [`output_edid`].
+
+use alloc::{string::String, vec::Vec};
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        randr::Output,
+        xproto::{Atom, GetPropertyType},
+    },
+    Result,
+};
+
+/// The value of an output property.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputProperty {
+    /// The type of the property.
+    pub type_: Atom,
+    /// The format of the property: 8, 16 or 32.
+    pub format: u8,
+    /// The raw data of the property.
+    pub data: Vec<u8>,
+}
+
+/// List the properties set on an output.
+pub fn list_output_properties<D: Display + ?Sized>(
+    display: &mut D,
+    output: Output,
+) -> Result<Vec<Atom>> {
+    let properties = display.randr_list_output_properties(output)?;
+    Ok(display.wait_for_reply(properties)?.atoms)
+}
+
+/// Get the value of a property on an output, or `None` if it isn't set.
+pub fn get_output_property<D: Display + ?Sized>(
+    display: &mut D,
+    output: Output,
+    property: Atom,
+) -> Result<Option<OutputProperty>> {
+    let value = display.randr_get_output_property(
+        output,
+        property,
+        GetPropertyType::ANY,
+        0,
+        u32::MAX / 4,
+        false,
+        false,
+    )?;
+    let value = display.wait_for_reply(value)?;
+
+    // a type of None means that the property doesn't exist
+    if value.type_ == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(OutputProperty {
+        type_: value.type_,
+        format: value.format,
+        data: value.data,
+    }))
+}
+
+/// Get the EDID of the monitor connected to an output.
+///
+/// Returns `None` if the output doesn't provide an EDID, or if the EDID
+/// is invalid.
+pub fn output_edid<D: Display + ?Sized>(display: &mut D, output: Output) -> Result<Option<Edid>> {
+    let atom = display.intern_atom(true, "EDID")?;
+    let atom = display.wait_for_reply(atom)?.atom;
+
+    // if the atom doesn't exist, no output has an EDID
+    if atom == 0 {
+        return Ok(None);
+    }
+
+    Ok(
+        get_output_property(display, output, atom)?
+            .and_then(|property| Edid::parse(&property.data)),
+    )
+}
+
+/// The identifying information from an EDID block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edid {
+    /// The three letter PNP ID of the manufacturer, e.g. `DEL`.
+    pub manufacturer: [u8; 3],
+    /// The manufacturer's product code.
+    pub product_code: u16,
+    /// The numeric serial number, or zero if not provided.
+    pub serial_number: u32,
+    /// The week of manufacture, or zero if not provided.
+    pub manufacture_week: u8,
+    /// The year of manufacture.
+    pub manufacture_year: u16,
+    /// The physical width and height of the screen in millimeters, or
+    /// `None` if not provided.
+    pub physical_size: Option<(u32, u32)>,
+    /// The monitor name, if provided.
+    pub name: Option<String>,
+    /// The serial number as a string, if provided.
+    pub serial_string: Option<String>,
+}
+
+impl Edid {
+    /// Parse the base block of an EDID.
+    ///
+    /// Returns `None` if the data is too short, if the header is wrong or
+    /// if the checksum doesn't match.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        const HEADER: [u8; 8] = [0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00];
+
+        let data = data.get(..128)?;
+        if data[..8] != HEADER {
+            return None;
+        }
+
+        // all 128 bytes should sum to zero
+        let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        if sum != 0 {
+            return None;
+        }
+
+        // the manufacturer is three 5-bit letters, starting from 'A' = 1
+        let id = u16::from_be_bytes([data[8], data[9]]);
+        let letter = |shift: u16| b'A' - 1 + ((id >> shift) & 0x1F) as u8;
+
+        // a size of zero means the size is unknown or variable
+        let physical_size = match (data[21], data[22]) {
+            (0, _) | (_, 0) => None,
+            (width, height) => Some((u32::from(width) * 10, u32::from(height) * 10)),
+        };
+
+        let mut edid = Edid {
+            manufacturer: [letter(10), letter(5), letter(0)],
+            product_code: u16::from_le_bytes([data[10], data[11]]),
+            serial_number: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            manufacture_week: data[16],
+            manufacture_year: 1990 + u16::from(data[17]),
+            physical_size,
+            name: None,
+            serial_string: None,
+        };
+
+        // look through the display descriptors for strings
+        for descriptor in data[54..126].chunks_exact(18) {
+            if descriptor[..3] != [0, 0, 0] {
+                // this is a timing descriptor
+                continue;
+            }
+
+            match descriptor[3] {
+                0xFC => edid.name = Some(descriptor_string(&descriptor[5..])),
+                0xFF => edid.serial_string = Some(descriptor_string(&descriptor[5..])),
+                _ => {}
+            }
+        }
+
+        Some(edid)
+    }
+}
+
+/// Read a string from a display descriptor, which is terminated by a
+/// newline and padded with spaces.
+fn descriptor_string(text: &[u8]) -> String {
+    let end = text.iter().position(|&c| c == b'\n').unwrap_or(text.len());
+    let text: String = text[..end].iter().map(|&c| char::from(c)).collect();
+    String::from(text.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{descriptor_string, Edid};
+    use alloc::string::String;
+
+    /// The generic 1024x768 EDID built into Linux, `edid/1024x768.bin`.
+    const LINUX_XGA: [u8; 128] = [
+        0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x31, 0xd8, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x05, 0x16, 0x01, 0x03, 0x6d, 0x23, 0x1a, 0x78, 0xea, 0x5e, 0xc0, 0xa4, 0x59, 0x4a,
+        0x98, 0x25, 0x20, 0x50, 0x54, 0x00, 0x08, 0x00, 0x61, 0x40, 0x01, 0x01, 0x01, 0x01, 0x01,
+        0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x64, 0x19, 0x00, 0x40, 0x41, 0x00,
+        0x26, 0x30, 0x08, 0x90, 0x36, 0x00, 0x63, 0x0a, 0x11, 0x00, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0xff, 0x00, 0x4c, 0x69, 0x6e, 0x75, 0x78, 0x20, 0x23, 0x30, 0x0a, 0x20, 0x20, 0x20, 0x20,
+        0x00, 0x00, 0x00, 0xfd, 0x00, 0x3b, 0x3d, 0x2f, 0x31, 0x07, 0x00, 0x0a, 0x20, 0x20, 0x20,
+        0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0xfc, 0x00, 0x4c, 0x69, 0x6e, 0x75, 0x78, 0x20, 0x58,
+        0x47, 0x41, 0x0a, 0x20, 0x20, 0x20, 0x00, 0x55,
+    ];
+
+    #[test]
+    fn parses_a_real_block() {
+        assert_eq!(
+            Edid::parse(&LINUX_XGA),
+            Some(Edid {
+                manufacturer: *b"LNX",
+                product_code: 0,
+                serial_number: 0,
+                manufacture_week: 5,
+                manufacture_year: 2012,
+                physical_size: Some((350, 260)),
+                name: Some(String::from("Linux XGA")),
+                serial_string: Some(String::from("Linux #0")),
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_extension_blocks() {
+        let mut data = LINUX_XGA.to_vec();
+        data.extend_from_slice(&[0xff; 128]);
+        assert!(Edid::parse(&data).is_some());
+    }
+
+    #[test]
+    fn rejects_a_bad_checksum() {
+        let mut data = LINUX_XGA;
+        data[127] = data[127].wrapping_add(1);
+        assert_eq!(Edid::parse(&data), None);
+    }
+
+    #[test]
+    fn rejects_a_short_buffer() {
+        assert_eq!(Edid::parse(&LINUX_XGA[..127]), None);
+        assert_eq!(Edid::parse(&[]), None);
+    }
+
+    #[test]
+    fn descriptor_strings_stop_at_the_newline() {
+        assert_eq!(descriptor_string(b"DELL U2415\n  "), "DELL U2415");
+        assert_eq!(descriptor_string(b"ABCDEFGHIJKLM"), "ABCDEFGHIJKLM");
+        assert_eq!(descriptor_string(b"padded    \n"), "padded");
+    }
+}
@@ -0,0 +1,139 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Receiving a window's Present events on their own queue.
+//!
+//! Swapchains built on the Present extension need to know when each
+//! presented pixmap has hit the screen and when it can be drawn into
+//! again. Those events are of no interest to the rest of the application,
+//! so [`PresentEventStream`] keeps them out of the main event queue, the
+//! same way the Mesa drivers do.
+
+use crate::{SpecialEventQueue, XcbDisplay};
+use alloc::vec::Vec;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        present::{
+            CompleteNotifyEvent, ConfigureNotifyEvent, EventMask, IdleNotifyEvent,
+            X11_EXTENSION_NAME,
+        },
+        xproto::Window,
+        Event,
+    },
+    Result,
+};
+
+/// An event received on a [`PresentEventStream`].
+#[derive(Debug, Clone)]
+pub enum PresentEvent {
+    /// A `PresentPixmap` or `PresentNotifyMSC` request has completed.
+    Complete(CompleteNotifyEvent),
+    /// A pixmap is no longer in use by the server, and can be drawn into.
+    Idle(IdleNotifyEvent),
+    /// The window has been resized or moved.
+    Configure(ConfigureNotifyEvent),
+    /// Any other event, such as a `RedirectNotify`.
+    Other(Event),
+}
+
+impl From<Event> for PresentEvent {
+    fn from(event: Event) -> Self {
+        match event {
+            Event::PresentCompleteNotify(event) => PresentEvent::Complete(event),
+            Event::PresentIdleNotify(event) => PresentEvent::Idle(event),
+            Event::PresentConfigureNotify(event) => PresentEvent::Configure(event),
+            event => PresentEvent::Other(event),
+        }
+    }
+}
+
+/// The Present events for one window, kept out of the main event queue.
+///
+/// Selecting for the events happens when the stream is created, and is
+/// undone when it is dropped.
+///
+/// To wait on the stream from an async runtime, wait for the display's
+/// file descriptor from [`XcbDisplay::get_fd`] to become readable, then
+/// call [`PresentEventStream::poll_for_event`] until it returns `None`.
+///
+/// [`XcbDisplay::get_fd`]: crate::XcbDisplay::get_fd
+pub struct PresentEventStream<'a> {
+    queue: SpecialEventQueue<'a>,
+    eid: u32,
+    window: Window,
+}
+
+impl<'a> PresentEventStream<'a> {
+    /// Select for the Present events in `event_mask` on `window`.
+    pub fn new(
+        display: &'a XcbDisplay,
+        window: Window,
+        event_mask: impl Into<EventMask>,
+    ) -> Result<Self> {
+        let mut conn = display;
+
+        // Present only exists from 1.0, but 1.2 is the version we know
+        let version = conn.present_query_version(1, 2)?;
+        conn.wait_for_reply(version)?;
+
+        // register before selecting, so that no event can slip into the
+        // main queue
+        let eid = conn.generate_xid()?;
+        let queue = display.register_for_special_xge(X11_EXTENSION_NAME, eid)?;
+        conn.present_select_input_checked(eid, window, event_mask)?;
+
+        Ok(Self { queue, eid, window })
+    }
+
+    /// Get the event context the events are selected with.
+    pub fn eid(&self) -> u32 {
+        self.eid
+    }
+
+    /// Get the window the events are for.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Wait for the next event.
+    pub fn wait_for_event(&self) -> Result<PresentEvent> {
+        self.queue.wait_for_event().map(PresentEvent::from)
+    }
+
+    /// Poll for the next event.
+    pub fn poll_for_event(&self) -> Result<Option<PresentEvent>> {
+        Ok(self.queue.poll_for_event()?.map(PresentEvent::from))
+    }
+
+    /// Wait for the `CompleteNotify` for the request with the given
+    /// serial.
+    ///
+    /// Other events received while waiting are returned in the order they
+    /// arrived, for the caller to process.
+    pub fn wait_for_complete(
+        &self,
+        serial: u32,
+    ) -> Result<(CompleteNotifyEvent, Vec<PresentEvent>)> {
+        let mut others = Vec::new();
+
+        loop {
+            match self.wait_for_event()? {
+                PresentEvent::Complete(event) if event.serial == serial => {
+                    return Ok((event, others))
+                }
+                event => others.push(event),
+            }
+        }
+    }
+}
+
+impl Drop for PresentEventStream<'_> {
+    fn drop(&mut self) {
+        // the queue is unregistered after this, once it's dropped
+        let mut conn = self.queue.display();
+        let _ = conn.present_select_input(self.eid, self.window, EventMask::NO_EVENT);
+    }
+}
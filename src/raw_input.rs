@@ -0,0 +1,152 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Receiving XInput2 events on a queue of their own.
+//!
+//! Games read raw motion to steer a camera, at a rate that would swamp the
+//! events the rest of the program cares about. [`XInputEventQueue`] keeps
+//! the XInput2 events it selects out of the main event queue.
+//!
+//! `libxcb` can't pick XInput2 events out of the main queue by itself,
+//! since they don't carry an event context ID, so they're diverted with
+//! [`XcbDisplay::divert_events`] as they're read. See
+//! [`DivertedEventQueue`] for how that differs from a special event queue.
+//!
+//! [`XcbDisplay::divert_events`]: crate::XcbDisplay::divert_events
+
+use crate::{DivertedEventQueue, XcbDisplay};
+use alloc::vec;
+use breadx::{
+    display::{DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        xinput::{DeviceId, EventMask, XIEventMask, X11_EXTENSION_NAME},
+        xproto::Window,
+        Event,
+    },
+    Error, Result,
+};
+
+const GE_GENERIC: u8 = 35;
+
+/// The device ID that selects events from every device.
+const ALL_DEVICES: DeviceId = 0;
+/// The device ID that selects events from every master device.
+const ALL_MASTER_DEVICES: DeviceId = 1;
+
+/// The XInput2 events selected for one device on one window, kept out of
+/// the main event queue.
+///
+/// Selecting for the events happens when the queue is created, and is
+/// undone when it is dropped.
+///
+/// ```no_run
+/// use breadx::{prelude::*, protocol::xinput::XIEventMask};
+/// use whitebreadx::{raw_input::XInputEventQueue, XcbDisplay};
+/// # fn main() -> breadx::Result<()> {
+/// let display = XcbDisplay::connect(None)?;
+/// let root = display.default_screen().root;
+///
+/// // raw events are only delivered to the root window
+/// let raw = XInputEventQueue::new(&display, root, 1, XIEventMask::RAW_MOTION)?;
+/// while let Some(event) = raw.poll_for_event()? {
+///     println!("{:?}", event);
+/// }
+/// # Ok(()) }
+/// ```
+pub struct XInputEventQueue<'a> {
+    queue: DivertedEventQueue<'a>,
+    window: Window,
+    device: DeviceId,
+}
+
+impl<'a> XInputEventQueue<'a> {
+    /// Select the XInput2 events in `mask` on `window`, for `device`.
+    ///
+    /// `device` may also be 0 for every device or 1 for every master
+    /// device. This asks the server for XInput 2.2, and returns an error if
+    /// it doesn't support at least 2.0, the first version with raw events.
+    ///
+    /// The server may refuse to negotiate a lower version than one already
+    /// negotiated on the same connection, so an application that asked for
+    /// a version newer than 2.2 itself will get a protocol error here.
+    pub fn new(
+        display: &'a XcbDisplay,
+        window: Window,
+        device: DeviceId,
+        mask: impl Into<XIEventMask>,
+    ) -> Result<Self> {
+        let mut conn = display;
+
+        let version = conn.xinput_xi_query_version(2, 2)?;
+        let version = conn.wait_for_reply(version)?;
+        if version.major_version < 2 {
+            return Err(Error::make_msg("the X server does not support XInput 2.0"));
+        }
+
+        let opcode = display.extension_opcode(X11_EXTENSION_NAME)?;
+        let mask: u32 = mask.into().into();
+
+        // divert before selecting, so that no event can slip into the main
+        // queue
+        let queue = display.divert_events(move |event| {
+            let evtype = u16::from_ne_bytes([event[8], event[9]]);
+            let deviceid = u16::from_ne_bytes([event[10], event[11]]);
+
+            event[0] & 0x7F == GE_GENERIC
+                && event[1] == opcode
+                && evtype < 32
+                && mask & (1 << evtype) != 0
+                && (device == ALL_DEVICES || device == ALL_MASTER_DEVICES || deviceid == device)
+        });
+
+        conn.xinput_xi_select_events_checked(
+            window,
+            [EventMask {
+                deviceid: device,
+                mask: vec![mask],
+            }],
+        )?;
+
+        Ok(Self {
+            queue,
+            window,
+            device,
+        })
+    }
+
+    /// Get the window the events are selected on.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Get the device the events are selected for.
+    pub fn device(&self) -> DeviceId {
+        self.device
+    }
+
+    /// Wait for the next event.
+    pub fn wait_for_event(&self) -> Result<Event> {
+        self.queue.wait_for_event()
+    }
+
+    /// Poll for the next event.
+    pub fn poll_for_event(&self) -> Result<Option<Event>> {
+        self.queue.poll_for_event()
+    }
+}
+
+impl Drop for XInputEventQueue<'_> {
+    fn drop(&mut self) {
+        // the queue stops diverting after this, once it's dropped
+        let mut conn = self.queue.display();
+        let _ = conn.xinput_xi_select_events(
+            self.window,
+            [EventMask {
+                deviceid: self.device,
+                mask: vec![0],
+            }],
+        );
+    }
+}
@@ -0,0 +1,250 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Reading and changing keyboard auto-repeat settings.
+//!
+//! Auto-repeat can be turned on or off globally and for individual keys
+//! through the core protocol. With the `xkb` feature enabled, the repeat
+//! delay and interval can be changed as well.
+//!
+//! These settings are global to the X server, so applications that change
+//! them should put them back afterwards. [`AutoRepeatGuard`] does this
+//! automatically.
+
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::xproto::{AutoRepeatMode, ChangeKeyboardControlAux},
+    Result,
+};
+use core::ops::{Deref, DerefMut};
+
+#[cfg(feature = "xkb")]
+use breadx::{
+    protocol::xkb::{BoolCtrl, ID},
+    Error,
+};
+
+/// The auto-repeat state of the keyboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AutoRepeat {
+    /// Whether auto-repeat is enabled at all.
+    pub global: bool,
+    /// A bit vector with a bit set for each key that auto-repeats.
+    pub keys: [u8; 32],
+}
+
+impl AutoRepeat {
+    /// Tell whether the given key auto-repeats, assuming auto-repeat is
+    /// enabled globally.
+    pub fn key_repeats(&self, keycode: u8) -> bool {
+        self.keys[usize::from(keycode / 8)] & (1 << (keycode % 8)) != 0
+    }
+}
+
+/// Get the current auto-repeat state of the keyboard.
+pub fn auto_repeat<D: Display + ?Sized>(display: &mut D) -> Result<AutoRepeat> {
+    let control = display.get_keyboard_control()?;
+    let control = display.wait_for_reply(control)?;
+
+    Ok(AutoRepeat {
+        global: control.global_auto_repeat == AutoRepeatMode::ON,
+        keys: control.auto_repeats,
+    })
+}
+
+/// Enable or disable auto-repeat for the entire keyboard.
+pub fn set_global_auto_repeat<D: Display + ?Sized>(display: &mut D, enabled: bool) -> Result<()> {
+    display.change_keyboard_control(
+        ChangeKeyboardControlAux::new().auto_repeat_mode(repeat_mode(enabled)),
+    )?;
+    Ok(())
+}
+
+/// Enable or disable auto-repeat for a single key.
+pub fn set_key_auto_repeat<D: Display + ?Sized>(
+    display: &mut D,
+    keycode: u8,
+    enabled: bool,
+) -> Result<()> {
+    display.change_keyboard_control(
+        ChangeKeyboardControlAux::new()
+            .key(u32::from(keycode))
+            .auto_repeat_mode(repeat_mode(enabled)),
+    )?;
+    Ok(())
+}
+
+/// Set the auto-repeat state of the keyboard.
+///
+/// Only the keys whose state differs from the current state are changed.
+pub fn set_auto_repeat<D: Display + ?Sized>(display: &mut D, state: &AutoRepeat) -> Result<()> {
+    let current = auto_repeat(display)?;
+
+    for keycode in 8..=255u8 {
+        let enabled = state.key_repeats(keycode);
+        if current.key_repeats(keycode) != enabled {
+            set_key_auto_repeat(display, keycode, enabled)?;
+        }
+    }
+
+    if current.global != state.global {
+        set_global_auto_repeat(display, state.global)?;
+    }
+
+    Ok(())
+}
+
+fn repeat_mode(enabled: bool) -> AutoRepeatMode {
+    if enabled {
+        AutoRepeatMode::ON
+    } else {
+        AutoRepeatMode::OFF
+    }
+}
+
+/// The delay before a held key starts repeating, and the interval between
+/// repeats.
+#[cfg(feature = "xkb")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepeatRate {
+    /// The delay before a key starts repeating, in milliseconds.
+    pub delay: u16,
+    /// The interval between repeats, in milliseconds.
+    pub interval: u16,
+}
+
+/// Get the repeat rate of the core keyboard through XKB.
+#[cfg(feature = "xkb")]
+pub fn repeat_rate<D: Display + ?Sized>(display: &mut D) -> Result<RepeatRate> {
+    use_xkb(display)?;
+
+    let controls = display.xkb_get_controls(ID::USE_CORE_KBD.into())?;
+    let controls = display.wait_for_reply(controls)?;
+
+    Ok(RepeatRate {
+        delay: controls.repeat_delay,
+        interval: controls.repeat_interval,
+    })
+}
+
+/// Set the repeat rate of the core keyboard through XKB.
+#[cfg(feature = "xkb")]
+pub fn set_repeat_rate<D: Display + ?Sized>(display: &mut D, rate: RepeatRate) -> Result<()> {
+    use_xkb(display)?;
+
+    // only the repeat controls are changed, so the rest are ignored
+    display.xkb_set_controls(
+        ID::USE_CORE_KBD.into(),
+        0u8,
+        0u8,
+        0u8,
+        0u8,
+        0u16,
+        0u16,
+        0u16,
+        0u16,
+        0,
+        0,
+        0u16,
+        0u8,
+        0u8,
+        u32::from(BoolCtrl::REPEAT_KEYS),
+        rate.delay,
+        rate.interval,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0u8,
+        0u8,
+        0u16,
+        0u16,
+        [0; 32],
+    )?;
+    Ok(())
+}
+
+/// Initialize the XKB extension, which is required before using it.
+#[cfg(feature = "xkb")]
+pub(crate) fn use_xkb<D: Display + ?Sized>(display: &mut D) -> Result<()> {
+    let reply = display.xkb_use_extension(1, 0)?;
+
+    if display.wait_for_reply(reply)?.supported {
+        Ok(())
+    } else {
+        Err(Error::make_msg("the X server does not support XKB 1.0"))
+    }
+}
+
+/// Restores the auto-repeat settings of the keyboard when dropped.
+///
+/// Created by [`AutoRepeatGuard::new`], which saves the current settings.
+/// The guard dereferences to the display, so it can be used in its place
+/// while the settings are changed.
+pub struct AutoRepeatGuard<'a, D: Display + ?Sized> {
+    display: &'a mut D,
+    auto_repeat: AutoRepeat,
+    #[cfg(feature = "xkb")]
+    rate: Option<RepeatRate>,
+}
+
+impl<'a, D: Display + ?Sized> AutoRepeatGuard<'a, D> {
+    /// Save the current auto-repeat settings.
+    ///
+    /// With the `xkb` feature enabled, the repeat rate is saved as well if
+    /// the server supports XKB.
+    pub fn new(display: &'a mut D) -> Result<Self> {
+        let auto_repeat = auto_repeat(display)?;
+
+        Ok(Self {
+            #[cfg(feature = "xkb")]
+            rate: repeat_rate(display).ok(),
+            display,
+            auto_repeat,
+        })
+    }
+
+    /// Restore the saved settings now, reporting any errors.
+    pub fn restore(self) -> Result<()> {
+        let mut this = core::mem::ManuallyDrop::new(self);
+        this.restore_impl()
+    }
+
+    fn restore_impl(&mut self) -> Result<()> {
+        set_auto_repeat(self.display, &self.auto_repeat)?;
+
+        #[cfg(feature = "xkb")]
+        if let Some(rate) = self.rate {
+            set_repeat_rate(self.display, rate)?;
+        }
+
+        self.display.flush()
+    }
+}
+
+impl<'a, D: Display + ?Sized> Deref for AutoRepeatGuard<'a, D> {
+    type Target = D;
+
+    fn deref(&self) -> &D {
+        self.display
+    }
+}
+
+impl<'a, D: Display + ?Sized> DerefMut for AutoRepeatGuard<'a, D> {
+    fn deref_mut(&mut self) -> &mut D {
+        self.display
+    }
+}
+
+impl<'a, D: Display + ?Sized> Drop for AutoRepeatGuard<'a, D> {
+    fn drop(&mut self) {
+        // errors can't be reported from a destructor
+        let _ = self.restore_impl();
+    }
+}
@@ -0,0 +1,75 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Requests that the helper modules send by hand.
+//!
+//! The wrappers generated by `breadx` only accept `CurrentTime` for
+//! timestamps, `AnyPropertyType` or a fixed set of atoms for property
+//! types and a fixed set of destinations for `SendEvent`. These build the
+//! requests directly so that any value can be used.
+
+use alloc::borrow::Cow;
+use breadx::{
+    display::{Display, DisplayExt},
+    protocol::xproto::{
+        Atom, EventMask, GetPropertyReply, GetPropertyRequest, SendEventRequest,
+        SetSelectionOwnerRequest, Timestamp, Window,
+    },
+    Result,
+};
+
+pub(crate) fn set_selection_owner<D: Display + ?Sized>(
+    display: &mut D,
+    owner: Window,
+    selection: Atom,
+    time: Timestamp,
+) -> Result<()> {
+    let request = SetSelectionOwnerRequest {
+        owner,
+        selection,
+        time,
+    };
+
+    display.send_void_request(request, true)?;
+    Ok(())
+}
+
+pub(crate) fn send_event<D: Display + ?Sized>(
+    display: &mut D,
+    destination: Window,
+    event_mask: EventMask,
+    event: [u8; 32],
+) -> Result<()> {
+    let request = SendEventRequest {
+        propagate: false,
+        destination,
+        event_mask: event_mask.into(),
+        event: Cow::Owned(event),
+    };
+
+    display.send_void_request(request, true)?;
+    Ok(())
+}
+
+/// Get the entire value of a property. A `type_` of zero matches any type.
+pub(crate) fn get_property<D: Display + ?Sized>(
+    display: &mut D,
+    delete: bool,
+    window: Window,
+    property: Atom,
+    type_: Atom,
+) -> Result<GetPropertyReply> {
+    let request = GetPropertyRequest {
+        delete,
+        window,
+        property,
+        type_,
+        long_offset: 0,
+        long_length: u32::MAX / 4,
+    };
+
+    let cookie = display.send_reply_request(request)?;
+    display.wait_for_reply(cookie)
+}
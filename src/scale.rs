@@ -0,0 +1,363 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Determining the DPI and scale factor of the screen.
+//!
+//! X11 has no single source of truth for HiDPI scaling. Desktop
+//! environments communicate it through XSETTINGS or the `Xft.dpi`
+//! resource, and the physical size reported by the monitor can be used
+//! when neither is set. [`scale_factor`] checks these in order:
+//!
+//! 1. The `Gdk/WindowScalingFactor` XSETTINGS value.
+//! 2. The `Xft.dpi` resource, divided by 96.
+//! 3. The DPI computed from the RandR output's physical size, divided by
+//!    96.
+//! 4. A scale factor of 1.
+
+use crate::requests::get_property;
+use alloc::format;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{randr::Output, xproto::AtomEnum},
+    resource_manager::Database,
+    Result,
+};
+
+/// The DPI that corresponds to a scale factor of 1.
+pub const BASE_DPI: f64 = 96.0;
+
+/// Where a scale factor came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleSource {
+    /// The `Gdk/WindowScalingFactor` XSETTINGS value.
+    XSettings,
+    /// The `Xft.dpi` X resource.
+    XftDpi,
+    /// The physical size of the output.
+    Physical,
+    /// None of the above were available.
+    Default,
+}
+
+/// A scale factor, along with where it came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scale {
+    /// The scale factor, where 1 is 96 DPI.
+    pub factor: f64,
+    /// Where the scale factor came from.
+    pub source: ScaleSource,
+}
+
+/// Get the scale factor to use for windows on `output`.
+///
+/// See the [module-level documentation](self) for the order in which
+/// sources are checked.
+pub fn scale_factor<D: Display + ?Sized>(display: &mut D, output: Output) -> Result<Scale> {
+    if let Some(factor) = xsettings_scaling_factor(display)? {
+        return Ok(Scale {
+            factor: f64::from(factor),
+            source: ScaleSource::XSettings,
+        });
+    }
+
+    if let Some(dpi) = xft_dpi(display)? {
+        return Ok(Scale {
+            factor: dpi / BASE_DPI,
+            source: ScaleSource::XftDpi,
+        });
+    }
+
+    if let Some(dpi) = output_dpi(display, output)? {
+        return Ok(Scale {
+            factor: dpi / BASE_DPI,
+            source: ScaleSource::Physical,
+        });
+    }
+
+    Ok(Scale {
+        factor: 1.0,
+        source: ScaleSource::Default,
+    })
+}
+
+/// Get the DPI of the default screen.
+///
+/// This is the `Xft.dpi` resource if it is set. Otherwise, it is computed
+/// from the physical size the X server reports for the screen, falling
+/// back to [`BASE_DPI`] if that is unknown.
+pub fn dpi<D: Display + ?Sized>(display: &mut D) -> Result<f64> {
+    if let Some(dpi) = xft_dpi(display)? {
+        return Ok(dpi);
+    }
+
+    let screen = display.default_screen();
+    Ok(compute_dpi(
+        screen.width_in_pixels.into(),
+        screen.width_in_millimeters.into(),
+    )
+    .unwrap_or(BASE_DPI))
+}
+
+/// Get the `Xft.dpi` resource from the resource database, if it is set.
+pub fn xft_dpi<D: Display + ?Sized>(display: &mut D) -> Result<Option<f64>> {
+    let root = display.default_screen().root;
+    let resources = get_property(
+        display,
+        false,
+        root,
+        AtomEnum::RESOURCE_MANAGER.into(),
+        AtomEnum::STRING.into(),
+    )?;
+
+    let database = Database::new_from_data(&resources.value);
+    Ok(database
+        .get_value::<f64>("Xft.dpi", "")
+        .ok()
+        .flatten()
+        .filter(|&dpi| dpi > 0.0))
+}
+
+/// Get the DPI of an output from its physical size, if it is known.
+pub fn output_dpi<D: Display + ?Sized>(display: &mut D, output: Output) -> Result<Option<f64>> {
+    let info = display.randr_get_output_info(output, 0)?;
+    let info = display.wait_for_reply(info)?;
+
+    // the output must be driven by a CRTC for it to have a size in pixels
+    if info.crtc == 0 {
+        return Ok(None);
+    }
+
+    let crtc = display.randr_get_crtc_info(info.crtc, 0)?;
+    let crtc = display.wait_for_reply(crtc)?;
+
+    // the CRTC size accounts for rotation but the physical size doesn't,
+    // so compare the larger dimensions of each
+    let pixels = crtc.width.max(crtc.height);
+    let millimeters = info.mm_width.max(info.mm_height);
+
+    Ok(compute_dpi(pixels.into(), millimeters))
+}
+
+/// Get the `Gdk/WindowScalingFactor` XSETTINGS value, if an XSETTINGS
+/// manager is running and has set it.
+pub fn xsettings_scaling_factor<D: Display + ?Sized>(display: &mut D) -> Result<Option<i32>> {
+    let selection = format!("_XSETTINGS_S{}", display.default_screen_index());
+    // a manager interns these atoms, so they're only looked up here
+    let selection = display.intern_atom(true, selection)?;
+    let settings = display.intern_atom(true, "_XSETTINGS_SETTINGS")?;
+    let selection = display.wait_for_reply(selection)?.atom;
+    let settings = display.wait_for_reply(settings)?.atom;
+    if selection == u32::from(AtomEnum::NONE) || settings == u32::from(AtomEnum::NONE) {
+        return Ok(None);
+    }
+
+    let owner = display.get_selection_owner(selection)?;
+    let owner = display.wait_for_reply(owner)?.owner;
+    if owner == 0 {
+        return Ok(None);
+    }
+
+    let settings = get_property(display, false, owner, settings, settings)?;
+    Ok(xsettings_int(&settings.value, b"Gdk/WindowScalingFactor").filter(|&factor| factor > 0))
+}
+
+fn compute_dpi(pixels: u32, millimeters: u32) -> Option<f64> {
+    // some monitors report tiny sizes, or their aspect ratio in centimeters
+    if millimeters < 50 {
+        return None;
+    }
+
+    Some(f64::from(pixels) * 25.4 / f64::from(millimeters))
+}
+
+/// Find an integer setting in XSETTINGS data.
+fn xsettings_int(data: &[u8], name: &[u8]) -> Option<i32> {
+    const INTEGER: u8 = 0;
+    const STRING: u8 = 1;
+    const COLOR: u8 = 2;
+
+    let big_endian = *data.first()? != 0;
+    let read_u16 = |data: &[u8], at: usize| -> Option<u16> {
+        let bytes = [*data.get(at)?, *data.get(at + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let read_u32 = |data: &[u8], at: usize| -> Option<u32> {
+        let bytes = [
+            *data.get(at)?,
+            *data.get(at + 1)?,
+            *data.get(at + 2)?,
+            *data.get(at + 3)?,
+        ];
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let pad = |len: usize| (len + 3) & !3;
+
+    // header: byte order, padding, serial, number of settings
+    let count = read_u32(data, 8)?;
+    let mut offset = 12;
+
+    for _ in 0..count {
+        let kind = *data.get(offset)?;
+        let name_len = usize::from(read_u16(data, offset + 2)?);
+        let setting_name = data.get(offset + 4..offset + 4 + name_len)?;
+
+        // skip the name and the last-changed serial
+        let value = offset + 4 + pad(name_len) + 4;
+
+        offset = match kind {
+            INTEGER if setting_name == name => return Some(read_u32(data, value)? as i32),
+            INTEGER => value + 4,
+            STRING => value + 4 + pad(read_u32(data, value)? as usize),
+            COLOR => value + 8,
+            _ => return None,
+        };
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_dpi, xsettings_int};
+    use alloc::vec::Vec;
+
+    /// Build an XSETTINGS blob the way a settings manager lays it out.
+    struct Settings {
+        big_endian: bool,
+        count: u32,
+        data: Vec<u8>,
+    }
+
+    impl Settings {
+        fn new(big_endian: bool) -> Self {
+            Self {
+                big_endian,
+                count: 0,
+                data: Vec::new(),
+            }
+        }
+
+        fn u16(&mut self, value: u16) {
+            let bytes = if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            self.data.extend_from_slice(&bytes);
+        }
+
+        fn u32(&mut self, value: u32) {
+            let bytes = if self.big_endian {
+                value.to_be_bytes()
+            } else {
+                value.to_le_bytes()
+            };
+            self.data.extend_from_slice(&bytes);
+        }
+
+        fn padded(&mut self, bytes: &[u8]) {
+            self.data.extend_from_slice(bytes);
+            self.data.resize((self.data.len() + 3) & !3, 0);
+        }
+
+        fn header(&mut self, kind: u8, name: &[u8]) {
+            self.count += 1;
+            self.data.extend_from_slice(&[kind, 0]);
+            self.u16(name.len() as u16);
+            self.padded(name);
+            // last-changed serial
+            self.u32(7);
+        }
+
+        fn int(mut self, name: &[u8], value: i32) -> Self {
+            self.header(0, name);
+            self.u32(value as u32);
+            self
+        }
+
+        fn string(mut self, name: &[u8], value: &[u8]) -> Self {
+            self.header(1, name);
+            self.u32(value.len() as u32);
+            self.padded(value);
+            self
+        }
+
+        fn color(mut self, name: &[u8]) -> Self {
+            self.header(2, name);
+            for &channel in &[0xffff, 0x8000, 0, 0xffff] {
+                self.u16(channel);
+            }
+            self
+        }
+
+        fn finish(self) -> Vec<u8> {
+            let mut blob = Settings::new(self.big_endian);
+            blob.data
+                .extend_from_slice(&[self.big_endian.into(), 0, 0, 0]);
+            blob.u32(1);
+            blob.u32(self.count);
+            blob.data.extend_from_slice(&self.data);
+            blob.data
+        }
+    }
+
+    const SCALE: &[u8] = b"Gdk/WindowScalingFactor";
+
+    #[test]
+    fn reads_either_byte_order() {
+        for &big_endian in &[false, true] {
+            let blob = Settings::new(big_endian).int(SCALE, 2).finish();
+            assert_eq!(xsettings_int(&blob, SCALE), Some(2));
+        }
+    }
+
+    #[test]
+    fn skips_other_settings() {
+        for &big_endian in &[false, true] {
+            let blob = Settings::new(big_endian)
+                .string(b"Net/ThemeName", b"Adwaita")
+                .color(b"Gtk/Color")
+                .int(b"Xft/DPI", 98_304)
+                .int(SCALE, -1)
+                .finish();
+
+            assert_eq!(xsettings_int(&blob, SCALE), Some(-1));
+            assert_eq!(xsettings_int(&blob, b"Xft/DPI"), Some(98_304));
+            assert_eq!(xsettings_int(&blob, b"Net/ThemeName"), None);
+            assert_eq!(xsettings_int(&blob, b"Gtk/Missing"), None);
+        }
+    }
+
+    #[test]
+    fn truncated_blob_is_none() {
+        let blob = Settings::new(false)
+            .string(b"Net/ThemeName", b"Adwaita")
+            .int(SCALE, 2)
+            .finish();
+
+        for len in 0..blob.len() {
+            assert_eq!(xsettings_int(&blob[..len], SCALE), None, "{}", len);
+        }
+    }
+
+    #[test]
+    fn dpi_from_physical_size() {
+        // a 24 inch 1920x1200 monitor
+        let dpi = compute_dpi(1920, 518).unwrap();
+        assert!((dpi - 94.15).abs() < 0.01, "{}", dpi);
+
+        // sizes that are really aspect ratios, or missing
+        assert_eq!(compute_dpi(1920, 16), None);
+        assert_eq!(compute_dpi(1920, 0), None);
+    }
+}
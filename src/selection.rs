@@ -0,0 +1,494 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Clipboard manager support.
+//!
+//! On X11, the contents of the clipboard are owned by the client that
+//! copied them, so they vanish when that client exits. A clipboard
+//! manager solves this by owning the `CLIPBOARD_MANAGER` selection: well
+//! behaved clients convert it to the `SAVE_TARGETS` target before exiting,
+//! and the manager copies the clipboard contents and serves them from then
+//! on. See the [freedesktop.org specification] for more information.
+//!
+//! [freedesktop.org specification]: https://www.freedesktop.org/wiki/ClipboardManager/
+
+use crate::requests::{get_property, send_event, set_selection_owner};
+use alloc::vec::Vec;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::xproto::{
+        Atom, AtomEnum, ClientMessageEvent, ConvertSelectionRequest, EventMask, PropMode,
+        SelectionClearEvent, SelectionNotifyEvent, SelectionRequestEvent, Timestamp, Window,
+        SELECTION_NOTIFY_EVENT,
+    },
+    protocol::Event,
+    Error, Result,
+};
+
+/// The atoms used by the clipboard manager.
+#[derive(Debug, Clone, Copy)]
+struct Atoms {
+    clipboard: Atom,
+    clipboard_manager: Atom,
+    save_targets: Atom,
+    targets: Atom,
+    multiple: Atom,
+    timestamp: Atom,
+    delete: Atom,
+    incr: Atom,
+    manager: Atom,
+    transfer: Atom,
+}
+
+impl Atoms {
+    fn intern<D: Display + ?Sized>(display: &mut D) -> Result<Self> {
+        // send all of the requests before waiting for any of them
+        let names: [&[u8]; 10] = [
+            b"CLIPBOARD",
+            b"CLIPBOARD_MANAGER",
+            b"SAVE_TARGETS",
+            b"TARGETS",
+            b"MULTIPLE",
+            b"TIMESTAMP",
+            b"DELETE",
+            b"INCR",
+            b"MANAGER",
+            b"WHITEBREADX_CLIPBOARD",
+        ];
+        let cookies = names
+            .iter()
+            .map(|name| display.intern_atom(false, name))
+            .collect::<Result<Vec<_>>>()?;
+        let atoms = cookies
+            .into_iter()
+            .map(|cookie| display.wait_for_reply(cookie).map(|reply| reply.atom))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self {
+            clipboard: atoms[0],
+            clipboard_manager: atoms[1],
+            save_targets: atoms[2],
+            targets: atoms[3],
+            multiple: atoms[4],
+            timestamp: atoms[5],
+            delete: atoms[6],
+            incr: atoms[7],
+            manager: atoms[8],
+            transfer: atoms[9],
+        })
+    }
+
+    /// Tell whether a target is one that describes the selection instead
+    /// of holding data, and therefore shouldn't be saved.
+    fn is_meta_target(&self, target: Atom) -> bool {
+        [
+            self.targets,
+            self.multiple,
+            self.timestamp,
+            self.delete,
+            self.save_targets,
+        ]
+        .contains(&target)
+    }
+}
+
+/// A saved conversion of the clipboard.
+#[derive(Debug, Clone)]
+struct SavedTarget {
+    target: Atom,
+    type_: Atom,
+    format: u8,
+    data: Vec<u8>,
+}
+
+/// The state of an in-progress `SAVE_TARGETS` request.
+#[derive(Debug, Clone)]
+struct SaveState {
+    /// The client that asked us to save the clipboard.
+    requestor: Window,
+    /// The property to report success with.
+    property: Atom,
+    /// The time of the request.
+    time: Timestamp,
+    /// The target we are currently converting.
+    current: Atom,
+    /// The targets left to convert.
+    remaining: Vec<Atom>,
+}
+
+/// A clipboard manager, which saves the contents of the clipboard when
+/// its owner exits.
+///
+/// Create one with [`ClipboardManager::acquire`], then pass every event
+/// received on the display to [`ClipboardManager::handle_event`].
+///
+/// Targets that the owner can only transfer incrementally (using `INCR`)
+/// are not saved, nor are targets too large to be served in a single
+/// request.
+#[derive(Debug, Clone)]
+pub struct ClipboardManager {
+    /// The window we own selections with.
+    window: Window,
+    /// Atoms used by the manager.
+    atoms: Atoms,
+    /// The saved contents of the clipboard.
+    contents: Vec<SavedTarget>,
+    /// The save currently in progress, if any.
+    save: Option<SaveState>,
+    /// Whether we currently own the clipboard.
+    owns_clipboard: bool,
+    /// Whether we still own the `CLIPBOARD_MANAGER` selection.
+    active: bool,
+}
+
+impl ClipboardManager {
+    /// Become the clipboard manager, using `window` to own selections.
+    ///
+    /// `time` should be the timestamp of the event that prompted this
+    /// call, rather than `CurrentTime`, as required by the ICCCM for
+    /// manager selections. Returns an error if another client owns
+    /// `CLIPBOARD_MANAGER` after we attempt to take it.
+    pub fn acquire<D: Display + ?Sized>(
+        display: &mut D,
+        window: Window,
+        time: Timestamp,
+    ) -> Result<Self> {
+        let atoms = Atoms::intern(display)?;
+
+        set_selection_owner(display, window, atoms.clipboard_manager, time)?;
+        let owner = display.get_selection_owner(atoms.clipboard_manager)?;
+        if display.wait_for_reply(owner)?.owner != window {
+            return Err(Error::make_msg(
+                "failed to acquire the CLIPBOARD_MANAGER selection",
+            ));
+        }
+
+        // announce the new manager to other clients
+        let root = display.default_screen().root;
+        let announcement = ClientMessageEvent::new(
+            32,
+            root,
+            atoms.manager,
+            [time, atoms.clipboard_manager, window, 0, 0],
+        );
+        send_event(
+            display,
+            root,
+            EventMask::STRUCTURE_NOTIFY,
+            announcement.into(),
+        )?;
+
+        Ok(Self {
+            window,
+            atoms,
+            contents: Vec::new(),
+            save: None,
+            owns_clipboard: false,
+            active: true,
+        })
+    }
+
+    /// Get the window used to own selections.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Tell whether this is still the clipboard manager.
+    ///
+    /// This becomes `false` once another client takes the
+    /// `CLIPBOARD_MANAGER` selection from us.
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Tell whether we are currently serving saved clipboard contents.
+    pub fn owns_clipboard(&self) -> bool {
+        self.owns_clipboard
+    }
+
+    /// Process an event, returning `true` if it was meant for the
+    /// clipboard manager.
+    pub fn handle_event<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &Event,
+    ) -> Result<bool> {
+        match event {
+            Event::SelectionRequest(event) if event.owner == self.window => {
+                self.selection_request(display, event)?;
+                Ok(true)
+            }
+            Event::SelectionNotify(event)
+                if event.requestor == self.window && event.selection == self.atoms.clipboard =>
+            {
+                self.selection_notify(display, event)?;
+                Ok(true)
+            }
+            Event::SelectionClear(event) if event.owner == self.window => {
+                self.selection_clear(event);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn selection_request<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &SelectionRequestEvent,
+    ) -> Result<()> {
+        // obsolete clients may use None as the property
+        let property = if event.property == u32::from(AtomEnum::NONE) {
+            event.target
+        } else {
+            event.property
+        };
+
+        let converted = if event.selection == self.atoms.clipboard_manager {
+            if event.target == self.atoms.save_targets && self.save.is_none() {
+                return self.begin_save(display, event);
+            } else if event.target == self.atoms.targets {
+                let targets = [self.atoms.targets, self.atoms.save_targets];
+                self.reply_targets(display, event.requestor, property, &targets)?;
+                true
+            } else {
+                false
+            }
+        } else if event.selection == self.atoms.clipboard && self.owns_clipboard {
+            self.serve(display, event.requestor, property, event.target)?
+        } else {
+            false
+        };
+
+        let property = if converted {
+            property
+        } else {
+            AtomEnum::NONE.into()
+        };
+        notify(display, event, property)
+    }
+
+    fn begin_save<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &SelectionRequestEvent,
+    ) -> Result<()> {
+        // the requestor may list the targets it wants saved
+        let mut remaining = Vec::new();
+        if event.property != u32::from(AtomEnum::NONE) {
+            let targets = get_property(
+                display,
+                false,
+                event.requestor,
+                event.property,
+                AtomEnum::ATOM.into(),
+            )?;
+            if let Some(targets) = targets.value32() {
+                let atoms = &self.atoms;
+                remaining.extend(targets.filter(|&target| !atoms.is_meta_target(target)));
+            };
+        }
+
+        // otherwise, ask the clipboard owner what it has
+        let current = if remaining.is_empty() {
+            self.atoms.targets
+        } else {
+            remaining.remove(0)
+        };
+
+        self.contents.clear();
+        self.save = Some(SaveState {
+            requestor: event.requestor,
+            property: event.property,
+            time: event.time,
+            current,
+            remaining,
+        });
+
+        self.convert(display, current)
+    }
+
+    fn selection_notify<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        event: &SelectionNotifyEvent,
+    ) -> Result<()> {
+        let save = match self.save.as_mut() {
+            Some(save) if save.current == event.target => save,
+            _ => return Ok(()),
+        };
+
+        if event.property != u32::from(AtomEnum::NONE) {
+            let reply = get_property(
+                display,
+                true,
+                self.window,
+                event.property,
+                AtomEnum::ANY.into(),
+            )?;
+
+            if event.target == self.atoms.targets {
+                if let Some(targets) = reply.value32() {
+                    let atoms = &self.atoms;
+                    save.remaining
+                        .extend(targets.filter(|&target| !atoms.is_meta_target(target)));
+                }
+            } else if reply.type_ != self.atoms.incr {
+                self.contents.push(SavedTarget {
+                    target: event.target,
+                    type_: reply.type_,
+                    format: reply.format,
+                    data: reply.value,
+                });
+            }
+        }
+
+        // move on to the next target, or finish up
+        if save.remaining.is_empty() {
+            self.finish_save(display)
+        } else {
+            let next = save.remaining.remove(0);
+            save.current = next;
+            self.convert(display, next)
+        }
+    }
+
+    fn finish_save<D: Display + ?Sized>(&mut self, display: &mut D) -> Result<()> {
+        let save = match self.save.take() {
+            Some(save) => save,
+            None => return Ok(()),
+        };
+
+        // take over the clipboard so we can serve what we saved
+        let mut property = AtomEnum::NONE.into();
+        if !self.contents.is_empty() {
+            set_selection_owner(display, self.window, self.atoms.clipboard, save.time)?;
+            let owner = display.get_selection_owner(self.atoms.clipboard)?;
+            self.owns_clipboard = display.wait_for_reply(owner)?.owner == self.window;
+
+            if self.owns_clipboard {
+                property = save.property;
+            }
+        }
+
+        let event = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: save.time,
+            requestor: save.requestor,
+            selection: self.atoms.clipboard_manager,
+            target: self.atoms.save_targets,
+            property,
+        };
+        send_event(display, save.requestor, EventMask::NO_EVENT, event.into())?;
+        Ok(())
+    }
+
+    fn selection_clear(&mut self, event: &SelectionClearEvent) {
+        if event.selection == self.atoms.clipboard {
+            self.owns_clipboard = false;
+            self.contents.clear();
+        } else if event.selection == self.atoms.clipboard_manager {
+            self.active = false;
+        }
+    }
+
+    /// Ask the clipboard owner to convert the clipboard to `target`.
+    fn convert<D: Display + ?Sized>(&mut self, display: &mut D, target: Atom) -> Result<()> {
+        let time = self.save.as_ref().map_or(0, |save| save.time);
+        let request = ConvertSelectionRequest {
+            requestor: self.window,
+            selection: self.atoms.clipboard,
+            target,
+            property: self.atoms.transfer,
+            time,
+        };
+
+        display.send_void_request(request, true)?;
+        Ok(())
+    }
+
+    /// Serve a saved target to a requestor, returning whether we had it.
+    fn serve<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        requestor: Window,
+        property: Atom,
+        target: Atom,
+    ) -> Result<bool> {
+        if target == self.atoms.targets {
+            let mut targets = Vec::with_capacity(self.contents.len() + 1);
+            targets.push(self.atoms.targets);
+            targets.extend(self.contents.iter().map(|saved| saved.target));
+            self.reply_targets(display, requestor, property, &targets)?;
+            return Ok(true);
+        }
+
+        let saved = match self.contents.iter().find(|saved| saved.target == target) {
+            Some(saved) => saved,
+            None => return Ok(false),
+        };
+
+        // format is in bits, data_len is in units of format
+        let unit = usize::from(saved.format / 8).max(1);
+        if saved.data.len() + 24 > display.maximum_request_length()? * 4 {
+            return Ok(false);
+        }
+
+        display.change_property(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            saved.type_,
+            saved.format,
+            (saved.data.len() / unit) as u32,
+            &saved.data[..],
+        )?;
+        Ok(true)
+    }
+
+    fn reply_targets<D: Display + ?Sized>(
+        &self,
+        display: &mut D,
+        requestor: Window,
+        property: Atom,
+        targets: &[Atom],
+    ) -> Result<()> {
+        display.change_property(
+            PropMode::REPLACE,
+            requestor,
+            property,
+            AtomEnum::ATOM.into(),
+            32,
+            targets.len() as u32,
+            targets,
+        )?;
+        Ok(())
+    }
+}
+
+/// Tell the requestor of a selection whether it was converted.
+fn notify<D: Display + ?Sized>(
+    display: &mut D,
+    request: &SelectionRequestEvent,
+    property: Atom,
+) -> Result<()> {
+    let event = SelectionNotifyEvent {
+        response_type: SELECTION_NOTIFY_EVENT,
+        sequence: 0,
+        time: request.time,
+        requestor: request.requestor,
+        selection: request.selection,
+        target: request.target,
+        property,
+    };
+
+    send_event(
+        display,
+        request.requestor,
+        EventMask::NO_EVENT,
+        event.into(),
+    )
+}
@@ -0,0 +1,149 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Knowing when a MIT-SHM segment can be reused.
+//!
+//! `ShmPutImage` returns before the server has read the image out of the
+//! shared segment, so writing the next frame into the segment right away
+//! tears or corrupts the image on screen. Sending the request with
+//! `send_event` set makes the server send a `ShmCompletion` event once it
+//! is done, and [`ShmTracker`] keeps count of the ones still outstanding.
+
+use crate::collections::Map;
+use alloc::{boxed::Box, vec::Vec};
+use breadx::{
+    display::Display,
+    protocol::{
+        shm::{CompletionEvent, Seg},
+        Event,
+    },
+    Result,
+};
+
+/// A callback for completed requests.
+type Callback = Box<dyn FnMut(&CompletionEvent) + Send>;
+
+/// Tracks the `ShmPutImage` requests the server hasn't finished with.
+///
+/// Call [`ShmTracker::track`] after sending each `ShmPutImage` request with
+/// `send_event` set, and pass every event received to
+/// [`ShmTracker::handle_event`].
+#[derive(Default)]
+pub struct ShmTracker {
+    /// The number of outstanding requests, by segment and offset.
+    outstanding: Map<(Seg, u32), usize>,
+    /// Called for every completion of a tracked request.
+    callbacks: Vec<Callback>,
+}
+
+impl ShmTracker {
+    /// Create a tracker with nothing outstanding.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a `ShmPutImage` request that reads from `offset` in
+    /// `shmseg`.
+    ///
+    /// The request must have been sent with `send_event` set, or it will
+    /// never complete.
+    pub fn track(&mut self, shmseg: Seg, offset: u32) {
+        *self.outstanding.entry((shmseg, offset)).or_insert(0) += 1;
+    }
+
+    /// Call `callback` whenever a tracked request completes.
+    pub fn on_completion(&mut self, callback: impl FnMut(&CompletionEvent) + Send + 'static) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Tell whether every tracked request reading from `shmseg` has
+    /// completed.
+    pub fn is_idle(&self, shmseg: Seg) -> bool {
+        !self.outstanding.keys().any(|&(seg, _)| seg == shmseg)
+    }
+
+    /// Tell whether every tracked request reading from `offset` in
+    /// `shmseg` has completed.
+    ///
+    /// This lets a segment holding several buffers reuse each of them as
+    /// soon as possible.
+    pub fn is_region_idle(&self, shmseg: Seg, offset: u32) -> bool {
+        !self.outstanding.contains_key(&(shmseg, offset))
+    }
+
+    /// Get the number of tracked requests reading from `shmseg` that
+    /// haven't completed.
+    pub fn outstanding(&self, shmseg: Seg) -> usize {
+        self.outstanding
+            .iter()
+            .filter(|&(&(seg, _), _)| seg == shmseg)
+            .map(|(_, &count)| count)
+            .sum()
+    }
+
+    /// Stop tracking requests reading from `shmseg`, e.g. once it has
+    /// been detached.
+    pub fn forget(&mut self, shmseg: Seg) {
+        let offsets: Vec<u32> = self
+            .outstanding
+            .keys()
+            .filter(|&&(seg, _)| seg == shmseg)
+            .map(|&(_, offset)| offset)
+            .collect();
+
+        for offset in offsets {
+            self.outstanding.remove(&(shmseg, offset));
+        }
+    }
+
+    /// Process an event, returning whether it completed a tracked request.
+    pub fn handle_event(&mut self, event: &Event) -> bool {
+        let event = match event {
+            Event::ShmCompletion(event) => event,
+            _ => return false,
+        };
+
+        let key = (event.shmseg, event.offset);
+        match self.outstanding.get_mut(&key) {
+            Some(count) if *count > 1 => *count -= 1,
+            Some(_) => {
+                self.outstanding.remove(&key);
+            }
+            None => return false,
+        }
+
+        for callback in &mut self.callbacks {
+            callback(event);
+        }
+
+        true
+    }
+
+    /// Wait until every tracked request reading from `shmseg` has
+    /// completed.
+    ///
+    /// Other events received while waiting are returned in the order they
+    /// arrived, for the caller to process.
+    pub fn wait_idle<D: Display + ?Sized>(
+        &mut self,
+        display: &mut D,
+        shmseg: Seg,
+    ) -> Result<Vec<Event>> {
+        let mut others = Vec::new();
+
+        if !self.is_idle(shmseg) {
+            display.flush()?;
+        }
+
+        while !self.is_idle(shmseg) {
+            let event = display.wait_for_event()?;
+            if !self.handle_event(&event) {
+                others.push(event);
+            }
+        }
+
+        Ok(others)
+    }
+}
@@ -0,0 +1,138 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Core requests that can be sent without allocating.
+//!
+//! `breadx` serializes every request into a buffer on the heap before a
+//! display sees it, even though most core requests are only 8 to 32 bytes
+//! long. The requests here know their own encoding, so
+//! [`XcbDisplay::send_small_requests`] builds them in a buffer on the
+//! stack instead, which adds up for clients that send many of them.
+//!
+//! [`XcbDisplay::send_small_requests`]: crate::XcbDisplay::send_small_requests
+
+use breadx::{
+    protocol::xproto::{
+        Allow, AllowEventsRequest, BellRequest, Circulate, CirculateWindowRequest,
+        ClearAreaRequest, CopyAreaRequest, DestroySubwindowsRequest, DestroyWindowRequest,
+        FreeColormapRequest, FreeCursorRequest, FreeGCRequest, FreePixmapRequest,
+        GrabServerRequest, InputFocus, KillClientRequest, MapSubwindowsRequest, MapWindowRequest,
+        SetInputFocusRequest, UngrabKeyboardRequest, UngrabPointerRequest, UngrabServerRequest,
+        UnmapSubwindowsRequest, UnmapWindowRequest, WarpPointerRequest, ALLOW_EVENTS_REQUEST,
+        BELL_REQUEST, CIRCULATE_WINDOW_REQUEST, CLEAR_AREA_REQUEST, COPY_AREA_REQUEST,
+        DESTROY_SUBWINDOWS_REQUEST, DESTROY_WINDOW_REQUEST, FREE_COLORMAP_REQUEST,
+        FREE_CURSOR_REQUEST, FREE_GC_REQUEST, FREE_PIXMAP_REQUEST, GRAB_SERVER_REQUEST,
+        KILL_CLIENT_REQUEST, MAP_SUBWINDOWS_REQUEST, MAP_WINDOW_REQUEST, SET_INPUT_FOCUS_REQUEST,
+        UNGRAB_KEYBOARD_REQUEST, UNGRAB_POINTER_REQUEST, UNGRAB_SERVER_REQUEST,
+        UNMAP_SUBWINDOWS_REQUEST, UNMAP_WINDOW_REQUEST, WARP_POINTER_REQUEST,
+    },
+    x11_utils::{Serialize, VoidRequest},
+};
+
+/// The longest a request encoded on the stack can be, in bytes.
+pub const SMALL_REQUEST_BYTES: usize = 32;
+
+/// Wrap part of a request the way `breadx` expects.
+#[cfg(feature = "std")]
+pub(crate) fn io_slice(data: &[u8]) -> std::io::IoSlice<'_> {
+    std::io::IoSlice::new(data)
+}
+
+/// Wrap part of a request the way `breadx` expects.
+#[cfg(not(feature = "std"))]
+pub(crate) fn io_slice(data: &[u8]) -> &[u8] {
+    data
+}
+
+/// A core request that can be encoded without allocating.
+pub trait SmallRequest: VoidRequest {
+    /// Encode the request into `buf`, returning its length in bytes.
+    ///
+    /// The length field in the header is left for the display to fill in.
+    fn encode(&self, buf: &mut [u8; SMALL_REQUEST_BYTES]) -> usize;
+}
+
+/// A value that goes in the byte after a core request's opcode.
+trait DataByte {
+    fn data_byte(self) -> u8;
+}
+
+impl DataByte for bool {
+    fn data_byte(self) -> u8 {
+        self.into()
+    }
+}
+
+impl DataByte for i8 {
+    fn data_byte(self) -> u8 {
+        self.to_ne_bytes()[0]
+    }
+}
+
+macro_rules! enum_data_bytes {
+    ($($name: ident),*) => {
+        $(
+            impl DataByte for $name {
+                fn data_byte(self) -> u8 {
+                    self.into()
+                }
+            }
+        )*
+    };
+}
+
+enum_data_bytes! { Allow, Circulate, InputFocus }
+
+macro_rules! small_requests {
+    ($($request: ident = $opcode: ident($($data: ident)?) { $($field: ident),* };)*) => {
+        $(
+            impl SmallRequest for $request {
+                #[allow(unused_mut)]
+                fn encode(&self, buf: &mut [u8; SMALL_REQUEST_BYTES]) -> usize {
+                    buf[0] = $opcode;
+                    buf[1] = 0;
+                    $(buf[1] = self.$data.data_byte();)?
+
+                    let mut len = 4;
+                    $(
+                        let bytes = self.$field.serialize();
+                        buf[len..len + bytes.len()].copy_from_slice(&bytes);
+                        len += bytes.len();
+                    )*
+                    len
+                }
+            }
+        )*
+    };
+}
+
+small_requests! {
+    DestroyWindowRequest = DESTROY_WINDOW_REQUEST() { window };
+    DestroySubwindowsRequest = DESTROY_SUBWINDOWS_REQUEST() { window };
+    MapWindowRequest = MAP_WINDOW_REQUEST() { window };
+    MapSubwindowsRequest = MAP_SUBWINDOWS_REQUEST() { window };
+    UnmapWindowRequest = UNMAP_WINDOW_REQUEST() { window };
+    UnmapSubwindowsRequest = UNMAP_SUBWINDOWS_REQUEST() { window };
+    CirculateWindowRequest = CIRCULATE_WINDOW_REQUEST(direction) { window };
+    UngrabPointerRequest = UNGRAB_POINTER_REQUEST() { time };
+    UngrabKeyboardRequest = UNGRAB_KEYBOARD_REQUEST() { time };
+    AllowEventsRequest = ALLOW_EVENTS_REQUEST(mode) { time };
+    GrabServerRequest = GRAB_SERVER_REQUEST() {};
+    UngrabServerRequest = UNGRAB_SERVER_REQUEST() {};
+    WarpPointerRequest = WARP_POINTER_REQUEST() {
+        src_window, dst_window, src_x, src_y, src_width, src_height, dst_x, dst_y
+    };
+    SetInputFocusRequest = SET_INPUT_FOCUS_REQUEST(revert_to) { focus, time };
+    FreePixmapRequest = FREE_PIXMAP_REQUEST() { pixmap };
+    FreeGCRequest = FREE_GC_REQUEST() { gc };
+    ClearAreaRequest = CLEAR_AREA_REQUEST(exposures) { window, x, y, width, height };
+    CopyAreaRequest = COPY_AREA_REQUEST() {
+        src_drawable, dst_drawable, gc, src_x, src_y, dst_x, dst_y, width, height
+    };
+    FreeColormapRequest = FREE_COLORMAP_REQUEST() { cmap };
+    FreeCursorRequest = FREE_CURSOR_REQUEST() { cursor };
+    BellRequest = BELL_REQUEST(percent) {};
+    KillClientRequest = KILL_CLIENT_REQUEST() { resource };
+}
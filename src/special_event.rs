@@ -0,0 +1,82 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Queues for the generic events of a single extension.
+//!
+//! Some extensions, such as Present, send generic events that are meant
+//! for one part of a program and would only get in the way of the rest of
+//! it. `libxcb` can divert these events into a queue of their own, so that
+//! they never show up in the main event queue. This is how the Mesa
+//! drivers receive Present events on a connection they share with the
+//! application.
+
+use crate::{xcb_ffi::SpecialEvent, XcbDisplay};
+use breadx::{protocol::Event, Result};
+use core::ptr::NonNull;
+
+/// A queue of the generic events an extension sends for one event
+/// context.
+///
+/// Created by [`XcbDisplay::register_for_special_xge`]. The events are
+/// taken out of the main event queue for as long as this queue exists,
+/// and go back to it once it is dropped.
+///
+/// [`XcbDisplay::register_for_special_xge`]: crate::XcbDisplay::register_for_special_xge
+pub struct SpecialEventQueue<'a> {
+    display: &'a XcbDisplay,
+    queue: NonNull<SpecialEvent>,
+}
+
+// libxcb locks the connection around every use of the queue
+unsafe impl Send for SpecialEventQueue<'_> {}
+unsafe impl Sync for SpecialEventQueue<'_> {}
+
+impl<'a> SpecialEventQueue<'a> {
+    /// # Safety
+    ///
+    /// `queue` must have been registered on `display`, and not been
+    /// unregistered.
+    pub(crate) unsafe fn new(display: &'a XcbDisplay, queue: NonNull<SpecialEvent>) -> Self {
+        Self { display, queue }
+    }
+
+    /// Get the display the queue was registered on.
+    pub fn display(&self) -> &'a XcbDisplay {
+        self.display
+    }
+
+    /// Wait for the next event in the queue.
+    ///
+    /// Like [`Display::wait_for_event`], this only flushes if the flush
+    /// policy says to.
+    ///
+    /// [`Display::wait_for_event`]: breadx::display::Display::wait_for_event
+    pub fn wait_for_event(&self) -> Result<Event> {
+        self.wait_for_event_with_sequence().map(|(event, _)| event)
+    }
+
+    /// Poll for the next event in the queue.
+    pub fn poll_for_event(&self) -> Result<Option<Event>> {
+        Ok(self.poll_for_event_with_sequence()?.map(|(event, _)| event))
+    }
+
+    /// Wait for the next event in the queue, along with the sequence
+    /// number of the last request the server processed before sending it.
+    pub fn wait_for_event_with_sequence(&self) -> Result<(Event, u64)> {
+        self.display.wait_for_special_event_impl(self.queue)
+    }
+
+    /// Poll for the next event in the queue, along with the sequence
+    /// number of the last request the server processed before sending it.
+    pub fn poll_for_event_with_sequence(&self) -> Result<Option<(Event, u64)>> {
+        self.display.poll_for_special_event_impl(self.queue)
+    }
+}
+
+impl Drop for SpecialEventQueue<'_> {
+    fn drop(&mut self) {
+        unsafe { self.display.unregister_for_special_event(self.queue) };
+    }
+}
@@ -0,0 +1,88 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Counting the traffic on a connection.
+//!
+//! With the `stats` feature, each count costs a relaxed atomic increment.
+//! Without it, [`Counters`] is empty and counting does nothing.
+
+#[cfg(feature = "stats")]
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of requests, replies, events and errors that have gone
+/// through a connection.
+///
+/// These only count what went through the [`XcbDisplay`], not through
+/// other libraries sharing its connection, and aren't reset when it
+/// reconnects.
+///
+/// [`XcbDisplay`]: crate::XcbDisplay
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stats {
+    /// The number of requests sent.
+    pub requests_sent: u64,
+    /// The number of replies received.
+    pub replies_received: u64,
+    /// The number of events received, not counting errors.
+    pub events_received: u64,
+    /// The number of errors received, whether as events or in place of a
+    /// reply.
+    pub errors_received: u64,
+}
+
+/// The counters behind [`Stats`].
+#[derive(Default)]
+pub(crate) struct Counters {
+    #[cfg(feature = "stats")]
+    requests_sent: AtomicU64,
+    #[cfg(feature = "stats")]
+    replies_received: AtomicU64,
+    #[cfg(feature = "stats")]
+    events_received: AtomicU64,
+    #[cfg(feature = "stats")]
+    errors_received: AtomicU64,
+}
+
+#[cfg(feature = "stats")]
+fn bump(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl Counters {
+    #[inline]
+    pub(crate) fn request_sent(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.requests_sent);
+    }
+
+    #[inline]
+    pub(crate) fn reply_received(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.replies_received);
+    }
+
+    #[inline]
+    pub(crate) fn event_received(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.events_received);
+    }
+
+    #[inline]
+    pub(crate) fn error_received(&self) {
+        #[cfg(feature = "stats")]
+        bump(&self.errors_received);
+    }
+
+    #[cfg(feature = "stats")]
+    pub(crate) fn get(&self) -> Stats {
+        Stats {
+            requests_sent: self.requests_sent.load(Ordering::Relaxed),
+            replies_received: self.replies_received.load(Ordering::Relaxed),
+            events_received: self.events_received.load(Ordering::Relaxed),
+            errors_received: self.errors_received.load(Ordering::Relaxed),
+        }
+    }
+}
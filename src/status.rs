@@ -0,0 +1,90 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! The state of a `libxcb` connection.
+
+use crate::xcb_ffi::errors;
+use core::fmt;
+use libc::c_int;
+
+/// Whether a connection is usable, and if not, why it was shut down.
+///
+/// Once `libxcb` shuts a connection down, it stays down, so every status
+/// other than [`Connected`] is final.
+///
+/// [`Connected`]: ConnectionStatus::Connected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionStatus {
+    /// The connection is usable.
+    Connected,
+    /// A socket, pipe or other stream error occurred.
+    IoError,
+    /// An extension the connection needed isn't supported by the server.
+    ExtensionNotSupported,
+    /// Memory ran out while handling the connection.
+    MemInsufficient,
+    /// A request was longer than the server accepts.
+    ReqLenExceeded,
+    /// The display string couldn't be parsed.
+    ParseError,
+    /// The server has no screen matching the display string.
+    InvalidScreen,
+    /// File descriptors couldn't be passed over the connection.
+    FdPassingFailed,
+    /// An error code this crate doesn't know about.
+    Unknown(c_int),
+}
+
+impl ConnectionStatus {
+    pub(crate) fn from_code(code: c_int) -> Self {
+        match code {
+            0 => ConnectionStatus::Connected,
+            errors::XCB_CONN_ERROR => ConnectionStatus::IoError,
+            errors::XCB_CONN_CLOSED_EXT_NOTSUPPORTED => ConnectionStatus::ExtensionNotSupported,
+            errors::XCB_CONN_CLOSED_MEM_INSUFFICIENT => ConnectionStatus::MemInsufficient,
+            errors::XCB_CONN_CLOSED_REQ_LEN_EXCEED => ConnectionStatus::ReqLenExceeded,
+            errors::XCB_CONN_CLOSED_PARSE_ERR => ConnectionStatus::ParseError,
+            errors::XCB_CONN_CLOSED_INVALID_SCREEN => ConnectionStatus::InvalidScreen,
+            errors::XCB_CONN_CLOSED_FDPASSING_FAILED => ConnectionStatus::FdPassingFailed,
+            code => ConnectionStatus::Unknown(code),
+        }
+    }
+
+    /// Get the `XCB_CONN_*` code for this status, or 0 if connected.
+    pub fn code(self) -> c_int {
+        match self {
+            ConnectionStatus::Connected => 0,
+            ConnectionStatus::IoError => errors::XCB_CONN_ERROR,
+            ConnectionStatus::ExtensionNotSupported => errors::XCB_CONN_CLOSED_EXT_NOTSUPPORTED,
+            ConnectionStatus::MemInsufficient => errors::XCB_CONN_CLOSED_MEM_INSUFFICIENT,
+            ConnectionStatus::ReqLenExceeded => errors::XCB_CONN_CLOSED_REQ_LEN_EXCEED,
+            ConnectionStatus::ParseError => errors::XCB_CONN_CLOSED_PARSE_ERR,
+            ConnectionStatus::InvalidScreen => errors::XCB_CONN_CLOSED_INVALID_SCREEN,
+            ConnectionStatus::FdPassingFailed => errors::XCB_CONN_CLOSED_FDPASSING_FAILED,
+            ConnectionStatus::Unknown(code) => code,
+        }
+    }
+
+    /// Tell whether the connection is usable.
+    pub fn is_connected(self) -> bool {
+        self == ConnectionStatus::Connected
+    }
+}
+
+impl fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionStatus::Connected => f.write_str("connected"),
+            ConnectionStatus::IoError => f.write_str("an I/O error occurred"),
+            ConnectionStatus::ExtensionNotSupported => f.write_str("extension not supported"),
+            ConnectionStatus::MemInsufficient => f.write_str("out of memory"),
+            ConnectionStatus::ReqLenExceeded => f.write_str("request length exceeded"),
+            ConnectionStatus::ParseError => f.write_str("failed to parse the display string"),
+            ConnectionStatus::InvalidScreen => f.write_str("invalid screen"),
+            ConnectionStatus::FdPassingFailed => f.write_str("failed to pass FD"),
+            ConnectionStatus::Unknown(code) => write!(f, "unknown error {}", code),
+        }
+    }
+}
@@ -14,7 +14,6 @@ cfg_if::cfg_if! {
             RwLockReadGuard,
             RwLockWriteGuard,
             Once as OnceCell,
-            lazy::Lazy,
         };
 
         pub(crate) fn mtx_lock<T>(mtx: &Mutex<T>) -> MutexGuard<'_, T> {
@@ -29,6 +28,14 @@ cfg_if::cfg_if! {
             rwl.write()
         }
 
+        pub(crate) fn mtx_get_mut<T>(mtx: &mut Mutex<T>) -> &mut T {
+            mtx.get_mut()
+        }
+
+        pub(crate) fn rwl_get_mut<T>(rwl: &mut RwLock<T>) -> &mut T {
+            rwl.get_mut()
+        }
+
         pub(crate) fn call_once<T>(
             once: &OnceCell<T>,
             f: impl FnOnce() -> T,
@@ -45,7 +52,7 @@ cfg_if::cfg_if! {
             RwLockReadGuard,
             RwLockWriteGuard,
         };
-        pub(crate) use once_cell::sync::{OnceCell, Lazy};
+        pub(crate) use once_cell::sync::OnceCell;
 
         pub(crate) fn mtx_lock<T>(mtx: &Mutex<T>) -> MutexGuard<'_, T> {
             match mtx.lock() {
@@ -68,6 +75,20 @@ cfg_if::cfg_if! {
             }
         }
 
+        pub(crate) fn mtx_get_mut<T>(mtx: &mut Mutex<T>) -> &mut T {
+            match mtx.get_mut() {
+                Ok(inner) => inner,
+                Err(poison) => poison.into_inner(),
+            }
+        }
+
+        pub(crate) fn rwl_get_mut<T>(rwl: &mut RwLock<T>) -> &mut T {
+            match rwl.get_mut() {
+                Ok(inner) => inner,
+                Err(poison) => poison.into_inner(),
+            }
+        }
+
         pub(crate) fn call_once<T>(
             once: &OnceCell<T>,
             f: impl FnOnce() -> T,
@@ -84,7 +105,7 @@ cfg_if::cfg_if! {
             RwLockReadGuard,
             RwLockWriteGuard,
         };
-        pub(crate) use once_cell::sync::{OnceCell, Lazy};
+        pub(crate) use once_cell::sync::OnceCell;
 
         pub(crate) fn mtx_lock<T>(mtx: &Mutex<T>) -> MutexGuard<'_, T> {
             mtx.lock()
@@ -98,6 +119,14 @@ cfg_if::cfg_if! {
             rwl.write()
         }
 
+        pub(crate) fn mtx_get_mut<T>(mtx: &mut Mutex<T>) -> &mut T {
+            mtx.get_mut()
+        }
+
+        pub(crate) fn rwl_get_mut<T>(rwl: &mut RwLock<T>) -> &mut T {
+            rwl.get_mut()
+        }
+
         pub(crate) fn call_once<T>(
             once: &OnceCell<T>,
             f: impl FnOnce() -> T,
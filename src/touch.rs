@@ -0,0 +1,364 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Tracking for XInput2 touch sequences.
+//!
+//! Touch events on X11 are stateful: each touch sequence is made up of a
+//! `TouchBegin` event, any number of `TouchUpdate` events and a `TouchEnd`
+//! event, and clients that don't own a touch must wait for the owner to
+//! accept or reject it. The [`TouchTracker`] follows these sequences and
+//! turns them into simple [`Gesture`]s.
+//!
+//! Touch events must first be selected on a window using
+//! [`select_touch_events`].
+
+use crate::collections::Map;
+use alloc::vec;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        xinput::{
+            DeviceId, EventMask, EventMode, Fp1616, TouchBeginEvent, TouchEventFlags,
+            TouchOwnershipEvent, XIAllowEventsRequest, XIEventMask,
+        },
+        xproto::{Timestamp, Window},
+        Event,
+    },
+    Error, Result,
+};
+
+/// Select touch events on the given window for the given device.
+///
+/// This negotiates XInput 2.2, the first version that supports touch
+/// events, and returns an error if the server doesn't support it. The
+/// `TouchOwnership` event is selected as well.
+pub fn select_touch_events<D: Display + ?Sized>(
+    display: &mut D,
+    window: Window,
+    device: DeviceId,
+) -> Result<()> {
+    let version = display.xinput_xi_query_version(2, 2)?;
+    let version = display.wait_for_reply(version)?;
+
+    if (version.major_version, version.minor_version) < (2, 2) {
+        return Err(Error::make_msg(
+            "the X server does not support XInput 2.2 touch events",
+        ));
+    }
+
+    let mask = XIEventMask::TOUCH_BEGIN
+        | XIEventMask::TOUCH_UPDATE
+        | XIEventMask::TOUCH_END
+        | XIEventMask::TOUCH_OWNERSHIP;
+    let mask = EventMask {
+        deviceid: device,
+        mask: vec![mask.into()],
+    };
+
+    display.xinput_xi_select_events(window, [mask])?;
+    Ok(())
+}
+
+/// The state of a single touch sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TouchPoint {
+    /// The ID of the touch, unique for the device.
+    pub touch_id: u32,
+    /// The device that the touch belongs to.
+    pub device: DeviceId,
+    /// The window that the touch events are being delivered to.
+    pub window: Window,
+    /// The position of the touch when it began, relative to `window`.
+    pub start_position: (f64, f64),
+    /// The current position of the touch, relative to `window`.
+    pub position: (f64, f64),
+    /// The server time the touch began at.
+    pub start_time: Timestamp,
+    /// The server time of the latest event for this touch.
+    pub time: Timestamp,
+    /// Whether this client owns the touch.
+    pub owned: bool,
+    /// Whether this touch is emulating pointer events.
+    pub emulating_pointer: bool,
+    /// Whether no more updates will be sent for this touch.
+    pub pending_end: bool,
+    /// Whether this touch has moved further than the tap slop.
+    moved: bool,
+    /// Whether another touch was active while this one was.
+    multi: bool,
+}
+
+impl TouchPoint {
+    /// The distance this touch has moved since it began.
+    pub fn distance_moved(&self) -> f64 {
+        distance(self.start_position, self.position)
+    }
+}
+
+/// A gesture recognized by a [`TouchTracker`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// A single touch began and ended without moving.
+    Tap {
+        /// The window that was tapped.
+        window: Window,
+        /// The position of the tap, relative to `window`.
+        position: (f64, f64),
+    },
+    /// A single touch moved.
+    Drag {
+        /// The window that the touch belongs to.
+        window: Window,
+        /// The distance moved since the last drag event.
+        delta: (f64, f64),
+    },
+    /// Two touches in the same window moved closer together or
+    /// further apart.
+    Pinch {
+        /// The window that the touches belong to.
+        window: Window,
+        /// The midpoint of the two touches, relative to `window`.
+        center: (f64, f64),
+        /// The ratio of the current distance between the touches to the
+        /// distance at the last pinch event.
+        scale: f64,
+    },
+}
+
+/// Follows touch sequences and recognizes gestures from them.
+#[derive(Debug, Clone)]
+pub struct TouchTracker {
+    /// The touches that are currently active.
+    touches: Map<(DeviceId, u32), TouchPoint>,
+    /// How far a touch can move while still being a tap.
+    tap_slop: f64,
+    /// How long a touch can last while still being a tap, in milliseconds.
+    tap_timeout: u32,
+    /// Whether touches need to be explicitly given to us by the owner.
+    track_ownership: bool,
+}
+
+impl Default for TouchTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TouchTracker {
+    /// Create a new, empty `TouchTracker`.
+    pub fn new() -> Self {
+        Self {
+            touches: Map::default(),
+            tap_slop: 10.0,
+            tap_timeout: 300,
+            track_ownership: false,
+        }
+    }
+
+    /// Set how far, in pixels, a touch can move while still counting as a
+    /// tap. Defaults to 10 pixels.
+    #[must_use]
+    pub fn tap_slop(mut self, slop: f64) -> Self {
+        self.tap_slop = slop;
+        self
+    }
+
+    /// Set how long, in milliseconds, a touch can last while still
+    /// counting as a tap. Defaults to 300 milliseconds.
+    #[must_use]
+    pub fn tap_timeout(mut self, timeout: u32) -> Self {
+        self.tap_timeout = timeout;
+        self
+    }
+
+    /// Set whether touches start out unowned until a `TouchOwnership`
+    /// event is received for them.
+    ///
+    /// This should be enabled if this client is not the only one listening
+    /// for touches on the window, e.g. if another client has a touch grab
+    /// on one of its ancestors. Defaults to `false`.
+    #[must_use]
+    pub fn track_ownership(mut self, track: bool) -> Self {
+        self.track_ownership = track;
+        self
+    }
+
+    /// Get the touch with the given device and touch ID, if it is active.
+    pub fn touch(&self, device: DeviceId, touch_id: u32) -> Option<&TouchPoint> {
+        self.touches.get(&(device, touch_id))
+    }
+
+    /// Iterate over all of the currently active touches.
+    pub fn touches(&self) -> impl Iterator<Item = &TouchPoint> + '_ {
+        self.touches.values()
+    }
+
+    /// Update the tracker with an event, returning a gesture if one was
+    /// recognized.
+    ///
+    /// Events other than XInput2 touch events are ignored.
+    pub fn handle_event(&mut self, event: &Event) -> Option<Gesture> {
+        match event {
+            Event::XinputTouchBegin(event) => {
+                self.touch_begin(event);
+                None
+            }
+            Event::XinputTouchUpdate(event) => self.touch_update(event),
+            Event::XinputTouchEnd(event) => self.touch_end(event),
+            Event::XinputTouchOwnership(event) => {
+                self.touch_ownership(event);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn touch_begin(&mut self, event: &TouchBeginEvent) {
+        let position = (fp1616(event.event_x), fp1616(event.event_y));
+        let multi = !self.touches.is_empty();
+
+        // any touch that's already active is now part of a multi-touch
+        for touch in self.touches.values_mut() {
+            touch.multi = true;
+        }
+
+        self.touches.insert(
+            (event.deviceid, event.detail),
+            TouchPoint {
+                touch_id: event.detail,
+                device: event.deviceid,
+                window: event.event,
+                start_position: position,
+                position,
+                start_time: event.time,
+                time: event.time,
+                owned: !self.track_ownership,
+                emulating_pointer: event.flags
+                    & u32::from(TouchEventFlags::TOUCH_EMULATING_POINTER)
+                    != 0,
+                pending_end: false,
+                moved: false,
+                multi,
+            },
+        );
+    }
+
+    fn touch_update(&mut self, event: &TouchBeginEvent) -> Option<Gesture> {
+        let tap_slop = self.tap_slop;
+        let touch = self.touches.get_mut(&(event.deviceid, event.detail))?;
+
+        let old_position = touch.position;
+        touch.position = (fp1616(event.event_x), fp1616(event.event_y));
+        touch.time = event.time;
+        touch.pending_end = event.flags & u32::from(TouchEventFlags::TOUCH_PENDING_END) != 0;
+        touch.moved |= touch.distance_moved() > tap_slop;
+
+        let touch = touch.clone();
+
+        match self.touches.len() {
+            1 if touch.moved => Some(Gesture::Drag {
+                window: touch.window,
+                delta: (
+                    touch.position.0 - old_position.0,
+                    touch.position.1 - old_position.1,
+                ),
+            }),
+            2 => {
+                let other = self.touches.values().find(|other| {
+                    other.touch_id != touch.touch_id || other.device != touch.device
+                })?;
+
+                if other.window != touch.window {
+                    return None;
+                }
+
+                let old_distance = distance(old_position, other.position);
+                let new_distance = distance(touch.position, other.position);
+
+                if old_distance <= 0.0 {
+                    return None;
+                }
+
+                Some(Gesture::Pinch {
+                    window: touch.window,
+                    center: (
+                        (touch.position.0 + other.position.0) / 2.0,
+                        (touch.position.1 + other.position.1) / 2.0,
+                    ),
+                    scale: new_distance / old_distance,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn touch_end(&mut self, event: &TouchBeginEvent) -> Option<Gesture> {
+        let mut touch = self.touches.remove(&(event.deviceid, event.detail))?;
+        touch.position = (fp1616(event.event_x), fp1616(event.event_y));
+
+        let duration = event.time.wrapping_sub(touch.start_time);
+        let is_tap = touch.owned
+            && !touch.multi
+            && duration <= self.tap_timeout
+            && touch.distance_moved() <= self.tap_slop;
+
+        if is_tap {
+            Some(Gesture::Tap {
+                window: touch.window,
+                position: touch.position,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn touch_ownership(&mut self, event: &TouchOwnershipEvent) {
+        if let Some(touch) = self.touches.get_mut(&(event.deviceid, event.touchid)) {
+            touch.owned = true;
+        }
+    }
+}
+
+/// Accept a touch that this client has a grab on, becoming its owner.
+///
+/// All other clients listening for this touch receive a `TouchEnd` event.
+pub fn accept_touch<D: Display + ?Sized>(display: &mut D, touch: &TouchPoint) -> Result<()> {
+    allow_touch(display, touch, EventMode::ACCEPT_TOUCH)
+}
+
+/// Reject a touch that this client has a grab on, passing it on to the
+/// next client listening for it.
+pub fn reject_touch<D: Display + ?Sized>(display: &mut D, touch: &TouchPoint) -> Result<()> {
+    allow_touch(display, touch, EventMode::REJECT_TOUCH)
+}
+
+fn allow_touch<D: Display + ?Sized>(
+    display: &mut D,
+    touch: &TouchPoint,
+    mode: EventMode,
+) -> Result<()> {
+    // the generated xi_allow_events() function takes an xinput::Device,
+    // which can't represent a device ID, so build the request by hand
+    let request = XIAllowEventsRequest {
+        time: 0,
+        deviceid: touch.device,
+        event_mode: mode,
+        touchid: touch.touch_id,
+        grab_window: touch.window,
+    };
+
+    display.send_void_request(request, true)?;
+    Ok(())
+}
+
+/// Convert a 16.16 fixed point number to a float.
+fn fp1616(value: Fp1616) -> f64 {
+    f64::from(value) / 65536.0
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+    (dx * dx + dy * dy).sqrt()
+}
@@ -0,0 +1,350 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Reading the Xauthority file.
+//!
+//! `libxcb` looks up the authorization for a display itself, but only when
+//! it opens the connection. Code that opens its own sockets can look it up
+//! here instead, the way `libXau` does, and pass the entry's name and data
+//! to [`XcbDisplay::connect_to_fd`] or [`XcbDisplay::connect_with_auth_info`].
+//!
+//! `XDM-AUTHORIZATION-1` entries hold a key rather than the data to send,
+//! so they should be passed through [`auth::protocol`] once the socket is
+//! connected.
+//!
+//! [`XcbDisplay::connect_to_fd`]: crate::XcbDisplay::connect_to_fd
+//! [`XcbDisplay::connect_with_auth_info`]: crate::XcbDisplay::connect_with_auth_info
+//! [`auth::protocol`]: crate::auth::protocol
+
+use crate::{
+    auth::{MIT_MAGIC_COOKIE_1, XDM_AUTHORIZATION_1},
+    connect::parse_display,
+};
+use alloc::{string::ToString, vec::Vec};
+use breadx::Result;
+use std::{
+    env, fs,
+    io::{self, Read},
+    net::{IpAddr, ToSocketAddrs},
+    path::PathBuf,
+};
+
+/// The address family of a server reached over IPv4, whose address is
+/// its four bytes.
+pub const FAMILY_INTERNET: u16 = 0;
+/// The address family of a server reached over IPv6, whose address is its
+/// sixteen bytes.
+pub const FAMILY_INTERNET6: u16 = 6;
+/// The address family of a server on the local machine, whose address is
+/// the host name.
+pub const FAMILY_LOCAL: u16 = 256;
+/// The address family of entries that match any address.
+pub const FAMILY_WILD: u16 = 65535;
+
+/// The protocols looked for, best first.
+const PROTOCOLS: &[&[u8]] = &[XDM_AUTHORIZATION_1, MIT_MAGIC_COOKIE_1];
+
+/// An entry in the Xauthority file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The address family of the server, such as [`FAMILY_LOCAL`].
+    pub family: u16,
+    /// The address of the server.
+    pub address: Vec<u8>,
+    /// The display number, in decimal. Empty entries match any display.
+    pub number: Vec<u8>,
+    /// The name of the authorization protocol.
+    pub name: Vec<u8>,
+    /// The data for the protocol.
+    pub data: Vec<u8>,
+}
+
+impl Entry {
+    /// Tell whether this entry is for the display with the given number,
+    /// on the server at `address`.
+    pub fn matches(&self, family: u16, address: &[u8], display: u16) -> bool {
+        (self.family == FAMILY_WILD || (self.family == family && self.address == address))
+            && (self.number.is_empty() || self.number == display.to_string().as_bytes())
+    }
+}
+
+/// Get the path of the Xauthority file, from `$XAUTHORITY` or the home
+/// directory.
+pub fn path() -> Option<PathBuf> {
+    match env::var_os("XAUTHORITY") {
+        Some(path) if !path.is_empty() => Some(path.into()),
+        _ => {
+            let mut path = PathBuf::from(env::var_os("HOME")?);
+            path.push(".Xauthority");
+            Some(path)
+        }
+    }
+}
+
+/// Read every entry from an Xauthority file.
+pub fn read(mut file: impl Read) -> io::Result<Vec<Entry>> {
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+
+    let mut rest = &bytes[..];
+    let mut entries = Vec::new();
+    while !rest.is_empty() {
+        entries.push(Entry {
+            family: take_u16(&mut rest)?,
+            address: take_field(&mut rest)?,
+            number: take_field(&mut rest)?,
+            name: take_field(&mut rest)?,
+            data: take_field(&mut rest)?,
+        });
+    }
+
+    Ok(entries)
+}
+
+fn take_u16(rest: &mut &[u8]) -> io::Result<u16> {
+    if rest.len() < 2 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let value = u16::from_be_bytes([rest[0], rest[1]]);
+    *rest = &rest[2..];
+    Ok(value)
+}
+
+/// Take a field prefixed with its length.
+fn take_field(rest: &mut &[u8]) -> io::Result<Vec<u8>> {
+    let len = take_u16(rest)? as usize;
+    if rest.len() < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    let field = rest[..len].to_vec();
+    *rest = &rest[len..];
+    Ok(field)
+}
+
+/// Find the best entry in the Xauthority file for the display with the
+/// given number, on the server at `address`.
+///
+/// Returns `None` if there is no Xauthority file, or no entry for a
+/// protocol this crate implements.
+pub fn find(family: u16, address: &[u8], display: u16) -> Option<Entry> {
+    let file = fs::File::open(path()?).ok()?;
+    find_in(read(file).ok()?, family, address, display)
+}
+
+/// Find the best of `entries` for the display with the given number, on
+/// the server at `address`.
+///
+/// Entries for `XDM-AUTHORIZATION-1` are preferred over
+/// `MIT-MAGIC-COOKIE-1`, and earlier entries over later ones. Entries for
+/// other protocols are skipped.
+pub fn find_in(
+    entries: impl IntoIterator<Item = Entry>,
+    family: u16,
+    address: &[u8],
+    display: u16,
+) -> Option<Entry> {
+    entries
+        .into_iter()
+        .filter(|entry| entry.matches(family, address, display))
+        .filter_map(|entry| {
+            let rank = PROTOCOLS.iter().position(|&name| name == entry.name)?;
+            Some((rank, entry))
+        })
+        .fold(
+            None,
+            |best: Option<(usize, Entry)>, (rank, entry)| match best {
+                // earlier entries win ties
+                Some(best) if best.0 <= rank => Some(best),
+                _ => Some((rank, entry)),
+            },
+        )
+        .map(|(_, entry)| entry)
+}
+
+/// Find the best entry in the Xauthority file for a display string, or
+/// `$DISPLAY` if `display` is `None`.
+///
+/// Remote hosts are resolved to find their address, and servers on this
+/// machine are looked up as local ones, as `libxcb` does.
+pub fn for_display(display: Option<&str>) -> Option<Entry> {
+    let display = match display {
+        Some(display) => display.to_string(),
+        None => env::var("DISPLAY").ok()?,
+    };
+    let parsed = parse_display(&display)?;
+
+    let (family, address) = if parsed.is_local() {
+        (FAMILY_LOCAL, hostname())
+    } else {
+        let address = (parsed.host.as_str(), 0).to_socket_addrs().ok()?.next()?;
+        server_address(address.ip())
+    };
+
+    find(family, &address, parsed.display)
+}
+
+/// Get the address Xauthority entries give for a server at `ip`.
+pub(crate) fn server_address(ip: IpAddr) -> (u16, Vec<u8>) {
+    match ip {
+        IpAddr::V4(ip) if ip.is_loopback() => (FAMILY_LOCAL, hostname()),
+        IpAddr::V4(ip) => (FAMILY_INTERNET, ip.octets().to_vec()),
+        IpAddr::V6(ip) if ip.is_loopback() => (FAMILY_LOCAL, hostname()),
+        IpAddr::V6(ip) => (FAMILY_INTERNET6, ip.octets().to_vec()),
+    }
+}
+
+/// Get the host name, which local entries use as their address.
+#[cfg(unix)]
+pub(crate) fn hostname() -> Vec<u8> {
+    let mut name = [0u8; 256];
+    let result = unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) };
+    if result != 0 {
+        return Vec::new();
+    }
+
+    let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+    name[..len].to_vec()
+}
+
+/// Produce the authorization for a socket connected to the display with
+/// the given number, on the server at `address`.
+///
+/// Returns an empty name and data if the Xauthority file has nothing for
+/// it, which some servers accept.
+#[cfg(unix)]
+pub(crate) fn authorize(
+    fd: libc::c_int,
+    family: u16,
+    address: &[u8],
+    display: u16,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    match find(family, address, display) {
+        Some(entry) => authorize_with(fd, &entry),
+        None => Ok((Vec::new(), Vec::new())),
+    }
+}
+
+/// Produce the authorization `entry` gives for a connected socket.
+///
+/// Entries for protocols this crate doesn't implement are passed on as
+/// they are.
+#[cfg(unix)]
+pub(crate) fn authorize_with(fd: libc::c_int, entry: &Entry) -> Result<(Vec<u8>, Vec<u8>)> {
+    use crate::auth::{self, ClientAddress};
+
+    match auth::protocol(&entry.name, &entry.data) {
+        Some(mut protocol) => {
+            let client = ClientAddress::of_socket(fd)?;
+            let data = protocol.data(&client)?;
+            Ok((protocol.name().to_vec(), data))
+        }
+        None => Ok((entry.name.clone(), entry.data.clone())),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn hostname() -> Vec<u8> {
+    env::var("COMPUTERNAME")
+        .map(alloc::string::String::into_bytes)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_in, read, Entry, FAMILY_INTERNET, FAMILY_LOCAL, FAMILY_WILD};
+    use crate::auth::{MIT_MAGIC_COOKIE_1, XDM_AUTHORIZATION_1};
+    use alloc::{vec, vec::Vec};
+    use std::io;
+
+    fn entry(family: u16, address: &[u8], number: &[u8], name: &[u8]) -> Entry {
+        Entry {
+            family,
+            address: address.to_vec(),
+            number: number.to_vec(),
+            name: name.to_vec(),
+            data: vec![0; 16],
+        }
+    }
+
+    /// Lay out an entry the way `libXau` writes it.
+    fn write(file: &mut Vec<u8>, entry: &Entry) {
+        file.extend_from_slice(&entry.family.to_be_bytes());
+        for field in &[&entry.address, &entry.number, &entry.name, &entry.data] {
+            file.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            file.extend_from_slice(field);
+        }
+    }
+
+    #[test]
+    fn reads_entries() {
+        let local = Entry {
+            family: FAMILY_LOCAL,
+            address: b"myhost".to_vec(),
+            number: b"0".to_vec(),
+            name: MIT_MAGIC_COOKIE_1.to_vec(),
+            data: (0..16).collect(),
+        };
+        let remote = Entry {
+            family: FAMILY_INTERNET,
+            address: vec![10, 0, 0, 1],
+            number: b"12".to_vec(),
+            name: XDM_AUTHORIZATION_1.to_vec(),
+            data: (16..32).collect(),
+        };
+
+        let mut file = Vec::new();
+        write(&mut file, &local);
+        write(&mut file, &remote);
+
+        assert_eq!(read(&file[..]).unwrap(), [local, remote]);
+    }
+
+    #[test]
+    fn truncated_field_is_unexpected_eof() {
+        let mut file = Vec::new();
+        write(
+            &mut file,
+            &entry(FAMILY_LOCAL, b"myhost", b"0", MIT_MAGIC_COOKIE_1),
+        );
+
+        // cut the data short, then cut into a length prefix
+        for &len in &[file.len() - 1, 3] {
+            let error = read(&file[..len]).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    #[test]
+    fn wild_family_and_empty_number_match_anything() {
+        let wild = entry(FAMILY_WILD, b"", b"3", MIT_MAGIC_COOKIE_1);
+        assert!(wild.matches(FAMILY_LOCAL, b"myhost", 3));
+        assert!(wild.matches(FAMILY_INTERNET, &[10, 0, 0, 1], 3));
+        assert!(!wild.matches(FAMILY_LOCAL, b"myhost", 4));
+
+        let any_display = entry(FAMILY_LOCAL, b"myhost", b"", MIT_MAGIC_COOKIE_1);
+        assert!(any_display.matches(FAMILY_LOCAL, b"myhost", 0));
+        assert!(any_display.matches(FAMILY_LOCAL, b"myhost", 12));
+        assert!(!any_display.matches(FAMILY_LOCAL, b"otherhost", 0));
+        assert!(!any_display.matches(FAMILY_INTERNET, b"myhost", 0));
+    }
+
+    #[test]
+    fn prefers_xdm_authorization() {
+        let cookie = entry(FAMILY_LOCAL, b"myhost", b"0", MIT_MAGIC_COOKIE_1);
+        let unknown = entry(FAMILY_LOCAL, b"myhost", b"0", b"SUN-DES-1");
+        let xdm = entry(FAMILY_LOCAL, b"myhost", b"0", XDM_AUTHORIZATION_1);
+        let later_cookie = entry(FAMILY_LOCAL, b"myhost", b"", MIT_MAGIC_COOKIE_1);
+
+        let entries = vec![cookie.clone(), unknown.clone(), xdm.clone()];
+        assert_eq!(find_in(entries, FAMILY_LOCAL, b"myhost", 0), Some(xdm));
+
+        let entries = vec![unknown.clone(), cookie.clone(), later_cookie];
+        assert_eq!(find_in(entries, FAMILY_LOCAL, b"myhost", 0), Some(cookie));
+
+        assert_eq!(find_in(vec![unknown], FAMILY_LOCAL, b"myhost", 0), None);
+    }
+}
@@ -5,31 +5,65 @@
 
 use crate::{
     cbox::CBox,
+    cookie::{self, XcbCookie},
+    custom_extension::{CustomExtensions, ExtensionParser},
+    divert::{Diversions, DivertedEventQueue},
+    events::Events,
     extension_manager::ExtensionManager,
-    sync::{call_once, mtx_lock, Mutex, OnceCell},
+    extension_record,
+    fds::{PendingFds, ReplyFds},
+    flush::{FlushPolicy, FlushState},
+    hook::{RequestHooks, RequestInfo, RequestStage},
+    motion::{MotionKey, MotionState},
+    small_request::{io_slice, SmallRequest, SMALL_REQUEST_BYTES},
+    special_event::SpecialEventQueue,
+    stats::Counters,
+    status::ConnectionStatus,
+    sync::{call_once, mtx_get_mut, mtx_lock, rwl_read, rwl_write, Mutex, OnceCell, RwLock},
     xcb_ffi::{
         errors, flags, xcb, AuthInfo, Connection, GenericError, GenericEvent, Iovec,
-        ProtocolRequest, VoidCookie, XcbFfi,
+        ProtocolRequest, SpecialEvent, VoidCookie, XcbFfi,
     },
 };
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{boxed::Box, collections::VecDeque, string::String, sync::Arc, vec::Vec};
 use breadx::{
-    display::{Display, DisplayBase, DisplayFunctionsExt, RawReply, RawRequest},
-    protocol::{xproto::Setup, Event, ReplyFdKind},
-    x11_utils::TryParse,
+    display::{
+        from_void_request, Cookie, Display, DisplayBase, DisplayFunctionsExt, RawReply, RawRequest,
+    },
+    protocol::{
+        xproto::{Screen, Setup, Visualid, Window},
+        Event, ExtensionInformation, ReplyFdKind, X11Error,
+    },
+    x11_utils::{TryParse, VoidRequest},
     Error, Result,
 };
 use core::{
     alloc::Layout,
+    convert::TryFrom,
     mem::{self, MaybeUninit},
     ptr::{null, null_mut, slice_from_raw_parts_mut, NonNull},
     slice,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
 };
 use cstr_core::CStr;
 use libc::{c_int, c_void};
-
+use smallvec::SmallVec;
+
+#[cfg(all(unix, feature = "std"))]
+use crate::auth::{AuthProtocol, ClientAddress};
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics;
+#[cfg(feature = "stats")]
+use crate::stats::Stats;
+#[cfg(feature = "xlib")]
+use crate::xlib_ffi::{xlib, X11Ffi};
 #[cfg(all(unix, feature = "to_socket"))]
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+#[cfg(all(unix, feature = "std"))]
+use std::{
+    io,
+    time::{Duration, Instant},
+};
 
 /// A [`Display`] that acts as a wrapper around a `libxcb`
 /// `xcb_connection_t`.
@@ -40,7 +74,13 @@ use std::os::unix::io::{AsRawFd, RawFd};
 /// is that this display can be used in foreign libraries built
 /// upon `libxcb`.
 ///
+/// Both `XcbDisplay` and `&XcbDisplay` implement [`Display`], and
+/// `breadx` covers `Box<XcbDisplay>`. The trait can't be implemented
+/// for `Arc<XcbDisplay>` or `Rc<XcbDisplay>` outside of `breadx`; use an
+/// [`XcbDisplayHandle`], or borrow the display out of the container.
+///
 /// [`Display`]: breadx::display::Display
+/// [`XcbDisplayHandle`]: crate::XcbDisplayHandle
 pub struct XcbDisplay {
     /// Pointer to the real connection object.
     connection: NonNull<Connection>,
@@ -48,12 +88,94 @@ pub struct XcbDisplay {
     disconnect: bool,
     /// The converted setup associated with this connection.
     setup: OnceCell<Arc<Setup>>,
+    /// The maximum request length, once it's known.
+    max_request_length: OnceCell<u32>,
     /// Extension info manager.
     extension_manager: ExtensionManager,
+    /// Parsers for extensions that `breadx` doesn't know.
+    custom_extensions: CustomExtensions,
+    /// Decides which events are dropped before being parsed.
+    event_filter: RwLock<Option<EventFilter>>,
+    /// Observes requests as they're sent and answered.
+    request_hooks: RequestHooks,
+    /// State for coalescing motion events.
+    motion: Mutex<MotionState>,
+    /// Events that have been read, but are to be returned again before
+    /// any others.
+    pushback: Mutex<VecDeque<(Event, u64)>>,
+    /// Queues that events are diverted into, away from the main queue.
+    diversions: Mutex<Diversions>,
     /// The set of all replies that will contain some number of FDs.
-    has_fds: Mutex<HashSet<u64>>,
+    has_fds: PendingFds,
+    /// The highest sequence number we've sent a request with.
+    last_sequence: AtomicU64,
+    /// The highest sequence number we've sent a request with a reply
+    /// with.
+    last_answered: AtomicU64,
+    /// The highest sequence number we've seen a reply, error or event
+    /// for.
+    last_processed: AtomicU64,
+    /// The number of XIDs we've generated.
+    generated_xids: AtomicU64,
+    /// Counts the requests, replies, events and errors we've seen.
+    counters: Counters,
+    /// Tracks when we should automatically flush.
+    flush_state: Mutex<FlushState>,
+    /// Whether extension requests are sent with `xcb_extension_t` records.
+    native_extension_records: AtomicBool,
     /// The screen we're using.
     screen: usize,
+    /// How to connect again, if the display was opened from a display
+    /// string.
+    reconnect: Option<ReconnectInfo>,
+    /// The display string we connected to, if we know it.
+    display_name: Option<String>,
+}
+
+/// The arguments a display was connected with.
+struct ReconnectInfo {
+    /// The display string, with its NUL terminator.
+    display: Option<Vec<u8>>,
+    /// The authorization protocol name and data.
+    auth: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl ReconnectInfo {
+    fn new(display: Option<&CStr>, auth: Option<(&[u8], &[u8])>) -> Self {
+        Self {
+            display: display.map(|display| display.to_bytes_with_nul().to_vec()),
+            auth: auth.map(|(name, data)| (name.to_vec(), data.to_vec())),
+        }
+    }
+}
+
+/// Get the name of the display `libxcb` connects to for `display`.
+fn display_name(display: Option<&CStr>) -> Option<String> {
+    if let Some(display) = display {
+        return Some(String::from_utf8_lossy(display.to_bytes()).into_owned());
+    }
+
+    cfg_if::cfg_if! {
+        if #[cfg(feature = "std")] {
+            std::env::var("DISPLAY").ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// A predicate on the first 32 bytes of an event.
+type EventFilter = Box<dyn Fn(&[u8; 32]) -> bool + Send + Sync>;
+
+/// Where the next event is taken from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum EventSource {
+    /// Block until an event arrives.
+    Wait,
+    /// Read from the socket if no events are queued.
+    Poll,
+    /// Only take events that have already been read.
+    Queued,
 }
 
 unsafe impl Send for XcbDisplay {}
@@ -63,11 +185,37 @@ impl XcbDisplay {
     /// Connect to the X server.
     pub fn connect(display: Option<&CStr>) -> Result<XcbDisplay> {
         let mut screen = MaybeUninit::uninit();
-        let display = display.map_or(null(), |display| display.as_ptr());
+        let display_ptr = display.map_or(null(), |display| display.as_ptr());
 
-        let connection = unsafe { xcb().xcb_connect(display, screen.as_mut_ptr()) };
+        let connection = unsafe { xcb().xcb_connect(display_ptr, screen.as_mut_ptr()) };
 
-        Ok(unsafe { XcbDisplay::connected(connection, screen.assume_init() as usize)? })
+        let mut this =
+            unsafe { XcbDisplay::connected_to_display(connection, display, None, screen)? };
+        this.reconnect = Some(ReconnectInfo::new(display, None));
+        this.display_name = display_name(display);
+        Ok(this)
+    }
+
+    /// Connect to the X server, naming the display with a Rust string.
+    ///
+    /// This is the same as [`connect`], but converts `display` to a C
+    /// string first. With `None`, the display is read from `$DISPLAY`.
+    /// Returns an error if `display` contains a NUL byte.
+    ///
+    /// [`connect`]: XcbDisplay::connect
+    pub fn connect_str(display: Option<&str>) -> Result<XcbDisplay> {
+        match display {
+            Some(display) => {
+                let mut name = Vec::with_capacity(display.len() + 1);
+                name.extend_from_slice(display.as_bytes());
+                name.push(0);
+
+                let name = CStr::from_bytes_with_nul(&name)
+                    .map_err(|_| Error::make_msg("display name contains a NUL byte"))?;
+                XcbDisplay::connect(Some(name))
+            }
+            None => XcbDisplay::connect(None),
+        }
     }
 
     /// Connect to the X11 server over the given auth address.
@@ -78,17 +226,22 @@ impl XcbDisplay {
     ) -> Result<XcbDisplay> {
         let mut screen = MaybeUninit::uninit();
         let mut auth_info = auth_info(auth_name, auth_data);
-        let display = display.map_or(null(), |display| display.as_ptr());
+        let display_ptr = display.map_or(null(), |display| display.as_ptr());
 
         let connection = unsafe {
             xcb().xcb_connect_to_display_with_auth_info(
-                display,
+                display_ptr,
                 &mut auth_info,
                 screen.as_mut_ptr(),
             )
         };
 
-        Ok(unsafe { XcbDisplay::connected(connection, screen.assume_init() as usize)? })
+        let auth = Some((auth_name, auth_data));
+        let mut this =
+            unsafe { XcbDisplay::connected_to_display(connection, display, auth, screen)? };
+        this.reconnect = Some(ReconnectInfo::new(display, auth));
+        this.display_name = display_name(display);
+        Ok(this)
     }
 
     /// Connect to an FD.
@@ -109,17 +262,197 @@ impl XcbDisplay {
         unsafe { XcbDisplay::connected(connection, screen) }
     }
 
+    /// Connect to an FD, authorizing with `protocol`.
+    ///
+    /// Unlike [`connect_to_fd`], this works with protocols that need new
+    /// data for every connection, such as `XDM-AUTHORIZATION-1`.
+    ///
+    /// # Safety
+    ///
+    /// FD must be a valid, connected socket.
+    ///
+    /// [`connect_to_fd`]: XcbDisplay::connect_to_fd
+    #[cfg(all(unix, feature = "std"))]
+    pub unsafe fn connect_to_fd_with_protocol(
+        fd: c_int,
+        protocol: &mut dyn AuthProtocol,
+        screen: usize,
+    ) -> Result<XcbDisplay> {
+        let client = ClientAddress::of_socket(fd)?;
+        let auth_data = protocol.data(&client)?;
+
+        unsafe { Self::connect_to_fd(fd, protocol.name(), &auth_data, screen) }
+    }
+
+    /// Connect to the display with the given number on `host` over TCP.
+    ///
+    /// The host name is resolved here rather than by `libxcb`, so IPv6
+    /// addresses and names are supported, with each address tried in turn.
+    /// `host` may be an IP address, a host name, or empty for the local
+    /// machine. Without `auth`, the authorization is looked up in the
+    /// Xauthority file.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn connect_tcp(
+        host: &str,
+        display: u16,
+        screen: usize,
+        auth: Option<(&[u8], &[u8])>,
+    ) -> Result<XcbDisplay> {
+        let opened = crate::connect::open_tcp(host, display, screen, None)?;
+
+        XcbDisplay::connect_opened(opened, auth, screen)
+    }
+
+    /// Connect to the X server, authorizing with an Xauthority entry.
+    ///
+    /// This suits clients that get their authorization some other way
+    /// than the Xauthority file, such as through XDMCP. Unlike
+    /// [`connect_with_auth_info`], entries for `XDM-AUTHORIZATION-1` hold
+    /// the key, and the data is worked out for the socket once it's
+    /// connected. The entry's family, address and number are what it
+    /// would be looked up by, and aren't checked against `display`.
+    ///
+    /// [`connect_with_auth_info`]: XcbDisplay::connect_with_auth_info
+    #[cfg(all(unix, feature = "std"))]
+    pub fn connect_with_auth_entry(
+        display: Option<&CStr>,
+        entry: &crate::xauth::Entry,
+    ) -> Result<XcbDisplay> {
+        let opened = crate::connect::open(display, None)?;
+        let (name, data) = match crate::xauth::authorize_with(opened.fd, entry) {
+            Ok(auth) => auth,
+            Err(err) => {
+                unsafe { libc::close(opened.fd) };
+                return Err(err);
+            }
+        };
+
+        let screen = opened.screen;
+        XcbDisplay::connect_opened(opened, Some((&name, &data)), screen)
+    }
+
+    /// Connect to the server listening on the Unix socket at `path`.
+    ///
+    /// A path starting with `@` names a socket in the abstract namespace,
+    /// as in `@/tmp/.X11-unix/X0`, which only Linux supports. This reaches
+    /// servers listening somewhere other than the usual place, such as
+    /// nested servers and test harnesses. Without `auth`, the
+    /// authorization is looked up in the Xauthority file for the display
+    /// number at the end of the path, or display 0 if there isn't one.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn connect_unix(
+        path: &str,
+        screen: usize,
+        auth: Option<(&[u8], &[u8])>,
+    ) -> Result<XcbDisplay> {
+        let opened = crate::connect::open_unix(path, screen)?;
+
+        XcbDisplay::connect_opened(opened, auth, screen)
+    }
+
+    /// Set up a connection over a socket this crate opened, looking up the
+    /// authorization unless `auth` is given.
+    #[cfg(all(unix, feature = "std"))]
+    pub(crate) fn connect_opened(
+        opened: crate::connect::Opened,
+        auth: Option<(&[u8], &[u8])>,
+        screen: usize,
+    ) -> Result<XcbDisplay> {
+        let auth = match auth {
+            Some((name, data)) => Ok((name.to_vec(), data.to_vec())),
+            None => {
+                crate::xauth::authorize(opened.fd, opened.family, &opened.address, opened.display)
+            }
+        };
+        let (name, data) = match auth {
+            Ok(auth) => auth,
+            Err(err) => {
+                unsafe { libc::close(opened.fd) };
+                return Err(err);
+            }
+        };
+
+        // SAFETY: the socket was just connected, and libxcb takes it over
+        let mut display = unsafe { XcbDisplay::connect_to_fd(opened.fd, &name, &data, screen)? };
+        display.display_name = Some(opened.name);
+        display.with_screen(screen)
+    }
+
+    /// Check a connection made to a display string, working out what went
+    /// wrong if it failed.
+    unsafe fn connected_to_display(
+        ptr: *mut Connection,
+        display: Option<&CStr>,
+        auth: Option<(&[u8], &[u8])>,
+        screen: MaybeUninit<c_int>,
+    ) -> Result<Self> {
+        assert!(!ptr.is_null());
+
+        let error = unsafe { xcb().xcb_connection_has_error(ptr) };
+        if error == 0 {
+            return unsafe { Self::connected(ptr, screen.assume_init() as usize) };
+        }
+
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "std")] {
+                // libxcb's error is found through errno, which diagnosing
+                // would overwrite
+                let original = unsafe { Self::ptr_take_error(ptr) };
+
+                if error == errors::XCB_CONN_CLOSED_PARSE_ERR {
+                    unsafe { xcb().xcb_disconnect(ptr) };
+                    return Err(crate::connect::invalid_display(display));
+                }
+
+                if error == errors::XCB_CONN_ERROR {
+                    let diagnosis = crate::connect::diagnose(display, auth);
+                    unsafe { xcb().xcb_disconnect(ptr) };
+                    return Err(diagnosis.or(original).expect("error was checked"));
+                }
+
+                if error == errors::XCB_CONN_CLOSED_INVALID_SCREEN {
+                    let invalid = crate::connect::invalid_screen(display);
+                    unsafe { xcb().xcb_disconnect(ptr) };
+                    return Err(invalid.or(original).expect("error was checked"));
+                }
+
+                unsafe { xcb().xcb_disconnect(ptr) };
+                Err(original.expect("error was checked"))
+            } else {
+                let _ = auth;
+
+                if error == errors::XCB_CONN_CLOSED_PARSE_ERR {
+                    unsafe { xcb().xcb_disconnect(ptr) };
+                    let display = display.map_or(
+                        alloc::borrow::Cow::Borrowed("$DISPLAY"),
+                        |display| alloc::string::String::from_utf8_lossy(display.to_bytes()),
+                    );
+                    return Err(Error::make_msg(alloc::format!(
+                        "invalid display string `{}`",
+                        display
+                    )));
+                }
+
+                unsafe { Self::connected(ptr, 0) }
+            }
+        }
+    }
+
     unsafe fn connected(ptr: *mut Connection, screen: usize) -> Result<Self> {
         assert!(!ptr.is_null());
 
         // check for a connection error
-        let this = Self::from_ptr(ptr.cast(), true, screen as usize);
+        let this = Self::from_ptr(ptr.cast(), true, screen);
 
         if let Some(err) = this.take_error() {
-            Err(err)
-        } else {
-            Ok(this)
+            return Err(err);
         }
+
+        // ask for BIG-REQUESTS now, so the first request doesn't have to
+        // wait for it
+        unsafe { xcb().xcb_prefetch_maximum_request_length(ptr) };
+
+        Ok(this)
     }
 
     /// Wrap around an existing ptr.
@@ -134,316 +467,1979 @@ impl XcbDisplay {
             connection: conn,
             disconnect,
             setup: OnceCell::new(),
+            max_request_length: OnceCell::new(),
             extension_manager: ExtensionManager::new(),
-            has_fds: Mutex::new(HashSet::with_hasher(Default::default())),
+            custom_extensions: CustomExtensions::new(),
+            event_filter: RwLock::new(None),
+            request_hooks: RequestHooks::new(),
+            motion: Mutex::new(MotionState::new()),
+            pushback: Mutex::new(VecDeque::new()),
+            diversions: Mutex::new(Diversions::new()),
+            has_fds: PendingFds::default(),
+            last_sequence: AtomicU64::new(0),
+            last_answered: AtomicU64::new(0),
+            last_processed: AtomicU64::new(0),
+            generated_xids: AtomicU64::new(0),
+            counters: Counters::default(),
+            flush_state: Mutex::new(FlushState::new()),
+            native_extension_records: AtomicBool::new(false),
             screen,
+            reconnect: None,
+            display_name: None,
         }
     }
 
-    fn as_ptr(&self) -> *mut Connection {
-        self.connection.as_ptr()
-    }
-
-    /// Get a pointer to the interior `libxcb` connection.
-    pub fn as_raw_connection(&self) -> *mut c_void {
-        self.as_ptr().cast()
+    /// Wrap around the `libxcb` connection used by an X11 `Display`, such
+    /// as one created by a toolkit.
+    ///
+    /// The `Display` keeps ownership of the connection, and is not closed
+    /// when the returned display is dropped. If `screen` is `None`, the
+    /// default screen of the `Display` is used.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, non-null pointer to an X11 `Display`, and it
+    /// must outlive the returned display.
+    #[cfg(feature = "xlib")]
+    pub unsafe fn from_xlib_ptr(ptr: *mut c_void, screen: Option<usize>) -> XcbDisplay {
+        let display = ptr.cast();
+        let screen = screen.unwrap_or_else(|| unsafe { xlib().XDefaultScreen(display) } as usize);
+        let connection = unsafe { xlib().XGetXCBConnection(display) };
+
+        unsafe { XcbDisplay::from_ptr(connection.cast(), false, screen) }
     }
 
-    /// Get the file descriptor for this FD.
-    pub fn get_fd(&self) -> c_int {
-        unsafe { xcb().xcb_get_file_descriptor(self.as_ptr()) }
+    /// Use `screen` as the default screen instead of the one the display
+    /// was connected with.
+    ///
+    /// Returns an error if the server has no such screen.
+    pub fn with_screen(mut self, screen: usize) -> Result<Self> {
+        self.set_default_screen(screen)?;
+        Ok(self)
     }
 
-    /// Given a conn ptr, get the error.
-    unsafe fn ptr_take_error(ptr: *mut Connection) -> Option<Error> {
-        let error = unsafe { xcb().xcb_connection_has_error(ptr) };
-
-        match error {
-            0 => None,
-            errors::XCB_CONN_ERROR => {
-                // this is an I/O error, see if we can use I/O errors
-                cfg_if::cfg_if! {
-                    if #[cfg(feature = "std")] {
-                        let io = std::io::Error::last_os_error();
-                        Some(io.into())
-                    } else {
-                        Some(Error::make_msg(
-                            "an unknown I/O error occurred"
-                        ))
-                    }
+    /// Change the default screen, checking that the server has it.
+    ///
+    /// On error, the default screen is left as it was.
+    pub fn set_default_screen(&mut self, screen: usize) -> Result<()> {
+        if screen >= self.setup().roots.len() {
+            cfg_if::cfg_if! {
+                if #[cfg(feature = "std")] {
+                    return Err(crate::ConnectError::InvalidScreen(screen).into());
+                } else {
+                    return Err(Error::make_msg("invalid screen"));
                 }
             }
-            errors::XCB_CONN_CLOSED_EXT_NOTSUPPORTED => {
-                Some(Error::make_missing_extension("<unknown>"))
-            }
-            errors::XCB_CONN_CLOSED_MEM_INSUFFICIENT => {
-                // standard Rust behavior when encountering an OOM
-                // is to abort the program
-                // we need a layout here for the error message
-                // we don't know the exact one, but we can take an
-                // educated guess
-                let layout = Layout::from_size_align_unchecked(32, 4);
-
-                alloc::alloc::handle_alloc_error(layout)
-            }
-            errors::XCB_CONN_CLOSED_REQ_LEN_EXCEED => {
-                Some(Error::make_msg("request length exceeded"))
-            }
-            errors::XCB_CONN_CLOSED_PARSE_ERR => {
-                Some(Error::make_msg("failed to parse server reply"))
-            }
-            errors::XCB_CONN_CLOSED_INVALID_SCREEN => Some(Error::make_msg("invalid screen")),
-            errors::XCB_CONN_CLOSED_FDPASSING_FAILED => Some(Error::make_msg("failed to pass FD")),
-            _ => Some(Error::make_msg("unknown error")),
         }
+
+        self.screen = screen;
+        Ok(())
     }
 
-    /// Convert our error into a `breadx` `Error`.
-    pub fn take_error(&self) -> Option<Error> {
-        unsafe { Self::ptr_take_error(self.as_ptr()) }
+    /// Get the display string this display connected to.
+    ///
+    /// If the display was connected without naming one, this is the value
+    /// `$DISPLAY` had at the time. Returns `None` for displays that were
+    /// not connected by name, such as those created from a pointer or a
+    /// file descriptor.
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
     }
 
-    /// Take an error we may not have.
-    pub fn take_maybe_error(&self) -> Error {
-        match self.take_error() {
-            Some(err) => err,
-            None => Error::make_msg("no error"),
-        }
+    /// Get the screen with index `n`, or `None` if the server has no such
+    /// screen.
+    ///
+    /// The default screen and the slice of all screens come from
+    /// [`DisplayBase::default_screen`] and [`DisplayBase::screens`].
+    pub fn screen(&self, n: usize) -> Option<&Screen> {
+        self.get_setup().roots.get(n)
     }
 
-    /// Get the `Setup` associated with this type.
-    pub fn get_setup(&self) -> &Arc<Setup> {
-        call_once(&self.setup, || {
-            // since xcb keeps its pointer types 1:1 equivalent with
-            // the byte streams, we can just parse the setup as a
-            // byte stream.
-            let setup_ptr = unsafe { xcb().xcb_get_setup(self.as_ptr()) } as *mut u8 as *const u8;
+    /// Get the root window of the default screen.
+    pub fn root_window(&self) -> Window {
+        self.default_screen().root
+    }
 
-            // figure out the length
-            let header = unsafe { slice::from_raw_parts(setup_ptr, 8) };
-            let xlen = u16::from_ne_bytes([header[6], header[7]]);
-            let length = ((xlen as usize) * 4) + 8;
+    /// Get the visual of the root window of the default screen.
+    pub fn root_visual(&self) -> Visualid {
+        self.default_screen().root_visual
+    }
 
-            // now, parse it
-            let setup_slice = unsafe { slice::from_raw_parts(setup_ptr, length) };
+    /// Flush the requests still in the output buffer and disconnect,
+    /// reporting whether the connection was still healthy.
+    ///
+    /// Dropping the display disconnects as well, but has nowhere to report
+    /// a server that went away while the last requests were written. The
+    /// connection is closed either way, unless this display doesn't own
+    /// it.
+    pub fn disconnect(self) -> Result<()> {
+        // the error has to be read before libxcb closes the socket
+        let result = self.flush_impl();
+        drop(self);
+        result
+    }
 
-            Setup::try_parse(setup_slice)
-                .expect("xcb had invalid setup struct")
-                .0
-                .into()
-        })
+    /// Wait for the server to process every request sent so far, then
+    /// disconnect.
+    ///
+    /// Unlike [`disconnect`], which only makes sure the requests were
+    /// written, this makes a round trip to the server, so a connection
+    /// that breaks while the server is still reading them is reported as
+    /// well. Replies nobody waited for are discarded. The connection is
+    /// closed either way, unless this display doesn't own it.
+    ///
+    /// [`disconnect`]: XcbDisplay::disconnect
+    pub fn shutdown(self) -> Result<()> {
+        let result = self.flush_impl().and_then(|()| self.synchronize_impl());
+        drop(self);
+        result
     }
 
-    /// Generate a new XID.
-    fn generate_xid_impl(&self) -> Result<u32> {
-        let xid = unsafe { xcb().xcb_generate_id(self.as_ptr()) };
+    /// Connect to the server again, after the connection was lost.
+    ///
+    /// The new connection is made with the same display string and
+    /// authorization as the old one, which is then disconnected. Settings
+    /// such as the flush policy, the event filter and registered extension
+    /// parsers are kept, but the setup is read again and every extension
+    /// used so far is queried again, since a restarted server may give it
+    /// different codes. Parsers for extensions the server no longer
+    /// supports are dropped.
+    ///
+    /// Resources, sequence numbers and outstanding replies from the old
+    /// connection mean nothing to the new one. Events still queued from
+    /// it, including those held by diverted queues, are dropped; the
+    /// diverted queues themselves are kept.
+    ///
+    /// Only displays created with [`connect`] or
+    /// [`connect_with_auth_info`] can reconnect.
+    ///
+    /// [`connect`]: XcbDisplay::connect
+    /// [`connect_with_auth_info`]: XcbDisplay::connect_with_auth_info
+    pub fn reconnect(&mut self) -> Result<()> {
+        let info = self.reconnect.as_ref().ok_or_else(|| {
+            Error::make_msg("only displays connected to a display string can reconnect")
+        })?;
+
+        let display = info
+            .display
+            .as_ref()
+            .map(|display| CStr::from_bytes_with_nul(display).expect("stored with a NUL"));
+        let mut fresh = match info.auth {
+            Some((ref name, ref data)) => XcbDisplay::connect_with_auth_info(display, name, data)?,
+            None => XcbDisplay::connect(display)?,
+        };
 
-        if xid == -1i32 as u32 {
-            Err(self.take_maybe_error())
-        } else {
-            Ok(xid)
+        // take over the new connection, and leave the old one to be
+        // dropped with the rest of `fresh`
+        mem::swap(&mut self.connection, &mut fresh.connection);
+        mem::swap(&mut self.disconnect, &mut fresh.disconnect);
+        mem::swap(&mut self.setup, &mut fresh.setup);
+        mem::swap(&mut self.max_request_length, &mut fresh.max_request_length);
+        mem::swap(&mut self.display_name, &mut fresh.display_name);
+
+        self.has_fds.clear();
+        self.last_sequence.store(0, Ordering::Relaxed);
+        self.last_answered.store(0, Ordering::Relaxed);
+        self.last_processed.store(0, Ordering::Relaxed);
+        self.generated_xids.store(0, Ordering::Relaxed);
+        {
+            let mut flush_state = mtx_lock(&self.flush_state);
+            let policy = flush_state.policy();
+            *flush_state = FlushState::new();
+            flush_state.set_policy(policy);
+        }
+        if let Some(event) = mtx_lock(&self.motion).take_pending() {
+            unsafe { libc::free(event.cast()) };
+        }
+        mtx_get_mut(&mut self.pushback).clear();
+        mtx_get_mut(&mut self.diversions).clear_events();
+
+        if self.screen >= self.setup().roots.len() {
+            self.screen = fresh.screen;
         }
+        drop(fresh);
+
+        let extensions = self.extension_manager.clear();
+        self.extension_manager.query_all(self, &extensions)?;
+        self.custom_extensions
+            .revalidate(|name| self.extension_manager.extension_info(name));
+
+        Ok(())
     }
 
-    /// Get the maxmimum request length.
-    fn maximum_request_length_impl(&self) -> u32 {
-        unsafe { xcb().xcb_get_maximum_request_length(self.as_ptr()) }
+    /// Give up the interior `libxcb` connection without disconnecting it.
+    ///
+    /// If this display owned the connection, the caller now does, and must
+    /// eventually pass it to `xcb_disconnect` or [`from_raw`].
+    ///
+    /// [`from_raw`]: XcbDisplay::from_raw
+    pub fn into_raw(mut self) -> *mut c_void {
+        self.disconnect = false;
+        self.as_raw_connection()
     }
 
-    fn synchronize_impl(&self) -> Result<()> {
-        // send a checked no-op request
-        let mut this = self;
-        let cookie = this.no_operation()?;
-        let seq = cookie.sequence();
+    /// Take ownership of a connection, such as one given up by
+    /// [`into_raw`], using `screen` as the default screen.
+    ///
+    /// The connection is disconnected once the returned display is
+    /// dropped.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be a valid, non-null pointer to a `xcb_connection_t`
+    /// that nothing else will disconnect.
+    ///
+    /// [`into_raw`]: XcbDisplay::into_raw
+    pub unsafe fn from_raw(ptr: *mut c_void, screen: usize) -> XcbDisplay {
+        unsafe { XcbDisplay::from_ptr(ptr, true, screen) }
+    }
 
-        self.check_for_error_impl(seq)
+    fn as_ptr(&self) -> *mut Connection {
+        self.connection.as_ptr()
     }
 
-    /// Flush to the server.
-    fn flush_impl(&self) -> Result<()> {
-        let res = unsafe { xcb().xcb_flush(self.as_ptr()) };
+    /// Get a pointer to the interior `libxcb` connection.
+    pub fn as_raw_connection(&self) -> *mut c_void {
+        self.as_ptr().cast()
+    }
 
-        if res <= 0 {
-            Err(self.take_maybe_error())
-        } else {
-            Ok(())
-        }
+    /// Get the file descriptor for this FD.
+    pub fn get_fd(&self) -> c_int {
+        unsafe { xcb().xcb_get_file_descriptor(self.as_ptr()) }
+    }
+
+    /// Recover the full sequence number from a `libxcb` cookie.
+    ///
+    /// Cookies only store the lower 32 bits of the sequence number. This
+    /// assumes that the request was sent within 2^31 requests of the last
+    /// request sent through this display, which will almost always be
+    /// the case.
+    pub fn widen_cookie(&self, cookie: XcbCookie) -> u64 {
+        cookie::widen(self.last_sequence.load(Ordering::Relaxed), cookie.sequence)
+    }
+
+    /// Recover the full sequence number from the 16 bits sent in the
+    /// header of a reply, error or event.
+    ///
+    /// Like [`widen_cookie`], this assumes the request was sent recently,
+    /// here within 2^15 requests of the last one sent through this
+    /// display.
+    ///
+    /// [`widen_cookie`]: XcbDisplay::widen_cookie
+    pub fn widen_sequence(&self, low: u16) -> u64 {
+        cookie::widen16(self.last_sequence.load(Ordering::Relaxed), low)
+    }
+
+    /// Get the sequence number of the last request sent through this
+    /// display.
+    ///
+    /// Requests sent through other libraries sharing the connection
+    /// aren't counted until a later request is sent through this one.
+    pub fn last_sequence_sent(&self) -> u64 {
+        self.last_sequence.load(Ordering::Relaxed)
+    }
+
+    /// Get the sequence number of the last request the server is known to
+    /// have processed.
+    ///
+    /// This is the highest sequence number of any reply, error or event
+    /// that this display has received, or of a request checked with
+    /// [`check_request`]. Events and replies taken by other libraries
+    /// sharing the connection aren't seen, so the server may have gotten
+    /// further than this.
+    ///
+    /// [`check_request`]: XcbDisplay::check_request
+    pub fn last_sequence_processed(&self) -> u64 {
+        self.last_processed.load(Ordering::Relaxed)
     }
 
-    unsafe fn parse_event(&self, event: *mut GenericEvent) -> Result<Event> {
-        // inspect the header for info
-        let header = event as *const GenericEvent as *const [u8; 32];
-        let evbytes = event as *mut u8;
-        let header = &*header;
+    /// Note that the server has processed the request with the given
+    /// sequence number.
+    fn processed(&self, seq: u64) {
+        self.last_processed.fetch_max(seq, Ordering::Relaxed);
+    }
 
-        // tell if we're dealing with a generic event
-        let mut length = 32;
-        if header[0] & 0x7F == breadx::protocol::xproto::GE_GENERIC_EVENT {
-            // read the length
-            let xlen = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
-            let xlen = xlen as usize * 4;
-            length += xlen;
+    /// Wait for the server to process a checked request, and return the
+    /// error it caused, if any.
+    ///
+    /// This is the same as [`Display::check_for_error`], but doesn't need
+    /// exclusive access. The request must have been sent checked, such as
+    /// with `send_void_request(request, false)`. The generated methods
+    /// like `map_window` send requests unchecked, and their errors are
+    /// delivered as events instead.
+    ///
+    /// [`Display::check_for_error`]: breadx::display::Display::check_for_error
+    pub fn check_request(&self, seq: u64) -> Result<()> {
+        self.check_for_error_impl(seq)
+    }
 
-            // xcb adds the sequence number for the event at 32 bytes,
-            // discard it
-            core::ptr::copy(evbytes.add(36), evbytes.add(32), xlen);
+    /// Wait for the server to process a checked request, and return the
+    /// X11 error it caused, if any.
+    ///
+    /// This is [`check_request`], but keeps the error typed: the
+    /// [`X11Error`] says which extension and request it's for, when the
+    /// extension has been used through this display. Errors that a custom
+    /// extension parser claimed, and failures of the connection itself,
+    /// are returned as `Err`.
+    ///
+    /// [`check_request`]: XcbDisplay::check_request
+    /// [`X11Error`]: breadx::protocol::X11Error
+    pub fn check_request_error(&self, seq: u64) -> Result<Option<X11Error>> {
+        match self.check_request_inner(seq)? {
+            Some(error) => self.parse_x11_error(&error).map(Some),
+            None => Ok(None),
         }
+    }
+
+    /// Check the request a void cookie was returned for.
+    ///
+    /// See [`check_request`].
+    ///
+    /// [`check_request`]: XcbDisplay::check_request
+    pub fn check_cookie(&self, cookie: Cookie<()>) -> Result<()> {
+        self.check_for_error_impl(cookie.sequence())
+    }
+
+    /// Send a request, choosing whether it's checked.
+    ///
+    /// Requests sent through [`Display`] are checked unless `breadx`
+    /// asked for their reply to be discarded. `libxcb` keeps track of a
+    /// checked void request until [`check_request`] is called or a later
+    /// reply shows it succeeded, so a loop that sends many drawing
+    /// requests does less work sending them unchecked. The errors of
+    /// unchecked requests are delivered as events.
+    ///
+    /// Requests with replies should usually be checked. If one isn't,
+    /// waiting for its reply after it fails returns an error that doesn't
+    /// say why, and the real error arrives as an event.
+    ///
+    /// [`Display`]: breadx::display::Display
+    /// [`check_request`]: XcbDisplay::check_request
+    pub fn send_request_with_checking(
+        &self,
+        request: RawRequest<'_, '_>,
+        checked: bool,
+    ) -> Result<u64> {
+        self.send_request_checking(request, checked)
+    }
+
+    /// Wait for the reply to a request, without copying it out of the
+    /// buffer `libxcb` allocated.
+    pub fn wait_for_xcb_reply(&self, seq: u64) -> Result<XcbReply> {
+        self.wait_for_reply_impl(seq)
+    }
+
+    /// Wait for the reply to a request, or the X11 error it caused.
+    ///
+    /// Like [`check_request_error`], this keeps the error typed rather than
+    /// wrapping it in a `breadx` [`Error`]. Failures of the connection
+    /// itself are returned as the outer `Err`.
+    ///
+    /// [`check_request_error`]: XcbDisplay::check_request_error
+    /// [`Error`]: breadx::Error
+    pub fn wait_for_reply_or_error(
+        &self,
+        seq: u64,
+    ) -> Result<core::result::Result<XcbReply, X11Error>> {
+        let reply = self.wait_for_reply_or_raw_error(seq, || self.has_fds.remove(seq))?;
+        match reply {
+            Ok(reply) => Ok(Ok(reply)),
+            Err(error) => self.parse_x11_error(&error).map(Err),
+        }
+    }
+
+    /// Poll for the reply to a request, without copying it out of the
+    /// buffer `libxcb` allocated.
+    pub fn poll_for_xcb_reply(&self, seq: u64) -> Result<Option<XcbReply>> {
+        self.poll_for_reply_impl(seq)
+    }
+
+    /// Tell `libxcb` that the reply to a request will never be waited
+    /// for, so it can be freed as soon as it arrives.
+    ///
+    /// `libxcb` otherwise keeps every reply until it's collected, so
+    /// cookies that are dropped without waiting on them leak the memory
+    /// for their replies. Errors caused by the request are dropped as
+    /// well, rather than being delivered as events.
+    ///
+    /// Replies that carry file descriptors are the exception: once the
+    /// server has processed about a million requests after one of them,
+    /// it's collected and its file descriptors closed, even if it wasn't
+    /// discarded.
+    pub fn discard_reply(&self, seq: u64) {
+        unsafe { xcb().xcb_discard_reply64(self.as_ptr(), seq) };
+        self.has_fds.remove(seq);
+        self.request_hooks.forget(seq);
+    }
+
+    /// Query every extension the server supports up front, so that the
+    /// first request to each one doesn't have to wait for a round trip.
+    ///
+    /// This takes two round trips in all: one to list the server's
+    /// extensions, and one for a burst of queries for the ones `breadx`
+    /// knows about. Extensions that were already queried are left alone.
+    pub fn prefetch_extensions(&self) -> Result<()> {
+        self.extension_manager.prefetch(self)
+    }
+
+    /// Start querying the extensions the application is going to use,
+    /// without waiting for the server to answer.
+    ///
+    /// The queries are sent together, and each extension's answer is
+    /// waited for the first time it's used. Otherwise, every extension
+    /// takes a round trip of its own. Extensions that were already queried
+    /// are skipped.
+    pub fn declare_extensions(&self, names: &[&'static str]) {
+        for &name in names {
+            if !self.extension_manager.is_cached(name) {
+                self.prefetch_extension_data(name);
+            }
+        }
+    }
+
+    /// Install a parser for the events and errors of an extension.
+    ///
+    /// The extension is queried from the server, and `event_count` and
+    /// `error_count` give the number of event and error codes it uses.
+    /// Events and errors in those ranges, as well as generic events from
+    /// the extension, are passed to `parser` before `breadx` parses them.
+    /// Registering a parser for the same extension again replaces it.
+    ///
+    /// Returns `false` if the server doesn't support the extension.
+    pub fn register_extension(
+        &self,
+        name: &'static str,
+        event_count: u8,
+        error_count: u8,
+        parser: impl ExtensionParser + 'static,
+    ) -> Result<bool> {
+        if self.extension_manager.extension_code(self, name)?.is_none() {
+            return Ok(false);
+        }
+
+        // extension_code() caches the information we need
+        let info = match self.extension_manager.extension_info(name) {
+            Some(info) => info,
+            None => return Ok(false),
+        };
+
+        self.custom_extensions
+            .register(name, info, event_count, error_count, Box::new(parser));
+        Ok(true)
+    }
+
+    /// Install a predicate that decides which events to keep, replacing
+    /// any installed before.
+    ///
+    /// The predicate is given the first 32 bytes of each event as they
+    /// were received, before anything is done to parse them, and returns
+    /// `false` to drop the event. Dropping events the application ignores
+    /// anyway, such as floods of `MotionNotify`, saves the cost of parsing
+    /// them.
+    pub fn set_event_filter(&self, filter: impl Fn(&[u8; 32]) -> bool + Send + Sync + 'static) {
+        *rwl_write(&self.event_filter) = Some(Box::new(filter));
+    }
+
+    /// Remove the predicate installed by [`set_event_filter`], keeping
+    /// every event.
+    ///
+    /// [`set_event_filter`]: XcbDisplay::set_event_filter
+    pub fn clear_event_filter(&self) {
+        *rwl_write(&self.event_filter) = None;
+    }
+
+    /// Install a function that is called as each request is sent, and
+    /// again once its reply or error arrives, replacing any installed
+    /// before.
+    ///
+    /// Replies and errors are only reported when they're taken from
+    /// `libxcb` through this display, and for requests sent after the
+    /// hook was installed. Errors delivered as events are reported for
+    /// any request, but without its length, and only name its extension
+    /// if the extension has been used through this display. The hook is
+    /// called on whichever thread is using the display, and must not
+    /// install or remove hooks itself.
+    pub fn set_request_hook(&self, hook: impl Fn(&RequestInfo) + Send + Sync + 'static) {
+        self.request_hooks.set(Some(Box::new(hook)));
+    }
+
+    /// Remove the function installed by [`set_request_hook`].
+    ///
+    /// [`set_request_hook`]: XcbDisplay::set_request_hook
+    pub fn clear_request_hook(&self) {
+        self.request_hooks.set(None);
+    }
+
+    /// Tell the request hook that a request was sent.
+    fn request_sent(
+        &self,
+        seq: u64,
+        opcodes: [u8; 2],
+        extension: Option<&'static str>,
+        length: usize,
+        answered: bool,
+    ) {
+        let info = RequestInfo {
+            stage: RequestStage::Sent,
+            sequence: seq,
+            major_opcode: opcodes[0],
+            minor_opcode: opcodes[1],
+            extension,
+            length,
+        };
+        self.request_hooks.sent(info, answered);
+        self.counters.request_sent();
+    }
+
+    /// Tell whether the event filter keeps `event`, freeing it if it
+    /// doesn't.
+    unsafe fn keep_event(&self, event: *mut GenericEvent) -> bool {
+        let filter = rwl_read(&self.event_filter);
+        let filter = match &*filter {
+            Some(filter) => filter,
+            None => return true,
+        };
+
+        let keep = filter(unsafe { &*(event as *const [u8; 32]) });
+        if !keep {
+            unsafe { libc::free(event.cast()) };
+        }
+
+        keep
+    }
+
+    /// Set whether runs of motion events are coalesced.
+    ///
+    /// When enabled, reading a `MotionNotify` or XInput2 `XI_Motion` event
+    /// skips ahead to the last event in the run of motion events for the
+    /// same window (and device, for XInput2) that has already been
+    /// received. Raw motion events are left alone. Only events already
+    /// read from the server are considered, so this never blocks.
+    /// Applications that draw along the pointer's path shouldn't use this,
+    /// since it loses the intermediate positions.
+    pub fn set_coalesce_motion(&self, coalesce: bool) {
+        mtx_lock(&self.motion).set_enabled(coalesce);
+    }
+
+    /// Tell whether runs of motion events are coalesced.
+    pub fn coalesce_motion(&self) -> bool {
+        mtx_lock(&self.motion).enabled()
+    }
+
+    /// Get the number of motion events skipped by coalescing so far.
+    pub fn coalesced_motion_events(&self) -> u64 {
+        mtx_lock(&self.motion).skipped()
+    }
+
+    /// Get the next event, after filtering and coalescing.
+    ///
+    /// Returns null if `source` doesn't wait and there are no events.
+    unsafe fn next_event(&self, source: EventSource) -> Result<*mut GenericEvent> {
+        // an event held back by coalescing comes first
+        let pending = mtx_lock(&self.motion).take_pending();
+        if let Some(event) = pending {
+            if !unsafe { self.divert(event)? } {
+                return Ok(event);
+            }
+        }
+
+        let event = loop {
+            let event = match source {
+                EventSource::Wait => unsafe { xcb().xcb_wait_for_event(self.as_ptr()) },
+                EventSource::Poll => unsafe { xcb().xcb_poll_for_event(self.as_ptr()) },
+                EventSource::Queued => unsafe { xcb().xcb_poll_for_queued_event(self.as_ptr()) },
+            };
+
+            if event.is_null() {
+                if source == EventSource::Wait {
+                    return Err(self.take_maybe_error());
+                }
+
+                // tell if the null corresponds to an error
+                return match self.take_error() {
+                    Some(err) => Err(err),
+                    None => Ok(event),
+                };
+            } else if unsafe { self.keep_event(event) && !self.divert(event)? } {
+                break event;
+            }
+        };
+
+        Ok(unsafe { self.coalesce(event) })
+    }
+
+    /// Move `event` into the queue it is diverted into, if any, returning
+    /// whether it was.
+    unsafe fn divert(&self, event: *mut GenericEvent) -> Result<bool> {
+        let id = match mtx_lock(&self.diversions).find(unsafe { &*(event as *const [u8; 32]) }) {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+
+        let event = unsafe { self.parse_event(event)? };
+        mtx_lock(&self.diversions).push(id, event);
+        Ok(true)
+    }
+
+    /// Divert the events `filter` picks out into a queue of their own.
+    ///
+    /// `filter` is passed the first 32 bytes of each event as it is read,
+    /// and returns whether to divert it. This is for events that
+    /// [`register_for_special_xge`] can't pick out, such as XInput2
+    /// events. If several queues pick out the same event, the one created
+    /// first gets it. Events dropped by the filter installed with
+    /// [`set_event_filter`] are never diverted.
+    ///
+    /// [`register_for_special_xge`]: XcbDisplay::register_for_special_xge
+    /// [`set_event_filter`]: XcbDisplay::set_event_filter
+    pub fn divert_events(
+        &self,
+        filter: impl Fn(&[u8; 32]) -> bool + Send + Sync + 'static,
+    ) -> DivertedEventQueue<'_> {
+        let id = mtx_lock(&self.diversions).add(Box::new(filter));
+        DivertedEventQueue::new(self, id)
+    }
+
+    /// Stop diverting events into a queue, dropping any left in it.
+    pub(crate) fn stop_diverting(&self, id: u64) {
+        mtx_lock(&self.diversions).remove(id);
+    }
+
+    /// Poll for an event diverted into a queue.
+    ///
+    /// Events read along the way that aren't diverted are kept for the
+    /// main queue.
+    pub(crate) fn poll_for_diverted_event(&self, id: u64) -> Result<Option<(Event, u64)>> {
+        loop {
+            if let Some(event) = mtx_lock(&self.diversions).pop(id) {
+                return Ok(Some(event));
+            }
+
+            let event = unsafe { self.next_event(EventSource::Poll)? };
+            if event.is_null() {
+                return Ok(mtx_lock(&self.diversions).pop(id));
+            }
+
+            let event = unsafe { self.parse_event(event)? };
+            mtx_lock(&self.pushback).push_back(event);
+        }
+    }
+
+    /// Wait for an event diverted into a queue.
+    ///
+    /// Events read along the way that aren't diverted are kept for the
+    /// main queue.
+    pub(crate) fn wait_for_diverted_event(&self, id: u64) -> Result<(Event, u64)> {
+        self.flush_before_wait()?;
+
+        loop {
+            if let Some(event) = mtx_lock(&self.diversions).pop(id) {
+                return Ok(event);
+            }
+
+            let event = unsafe { self.next_event(EventSource::Wait)? };
+            let event = unsafe { self.parse_event(event)? };
+            mtx_lock(&self.pushback).push_back(event);
+        }
+    }
+
+    /// Skip to the last motion event in the run starting with `event`.
+    unsafe fn coalesce(&self, mut event: *mut GenericEvent) -> *mut GenericEvent {
+        let mut motion = mtx_lock(&self.motion);
+        if !motion.enabled() {
+            return event;
+        }
+
+        let xinput = self
+            .extension_manager
+            .extension_info("XInputExtension")
+            .map(|info| info.major_opcode);
+        let key = match MotionKey::of(unsafe { &*(event as *const [u8; 32]) }, xinput) {
+            Some(key) => key,
+            None => return event,
+        };
+
+        loop {
+            let next = unsafe { xcb().xcb_poll_for_queued_event(self.as_ptr()) };
+            let next = match NonNull::new(next) {
+                Some(next) => next,
+                None => break,
+            };
+
+            if !unsafe { self.keep_event(next.as_ptr()) } {
+                continue;
+            }
+
+            if MotionKey::of(unsafe { &*next.as_ptr().cast::<[u8; 32]>() }, xinput) == Some(key) {
+                unsafe { libc::free(event.cast()) };
+                event = next.as_ptr();
+                motion.skip();
+            } else {
+                motion.set_pending(next);
+                break;
+            }
+        }
+
+        event
+    }
+
+    /// Get the policy used to decide when to automatically flush.
+    pub fn flush_policy(&self) -> FlushPolicy {
+        mtx_lock(&self.flush_state).policy()
+    }
+
+    /// Set the policy used to decide when to automatically flush.
+    ///
+    /// See [`FlushPolicy`] for more information.
+    pub fn set_flush_policy(&self, policy: FlushPolicy) {
+        mtx_lock(&self.flush_state).set_policy(policy);
+    }
+
+    /// Get the number of bytes of requests sent through this display
+    /// since it was last flushed.
+    ///
+    /// This is how much is waiting in `libxcb`'s buffer at most. `libxcb`
+    /// may have written some of it already, when its buffer filled up or
+    /// a reply was waited on.
+    pub fn buffered_bytes(&self) -> usize {
+        mtx_lock(&self.flush_state).unflushed_bytes()
+    }
+
+    /// Tell whether extension requests are sent with `libxcb`'s extension
+    /// records.
+    pub fn native_extension_records(&self) -> bool {
+        self.native_extension_records.load(Ordering::Relaxed)
+    }
+
+    /// Set whether extension requests are sent with `libxcb`'s extension
+    /// records.
+    ///
+    /// By default, requests are sent raw, with the major opcode this
+    /// display looked up already filled in, so `libxcb` doesn't know which
+    /// extension they belong to. With this set, each request is sent with
+    /// the `xcb_extension_t` for its extension, like the `libxcb-*`
+    /// libraries send theirs. The records those libraries export are used
+    /// when they're loaded into the process.
+    pub fn set_native_extension_records(&self, native: bool) {
+        self.native_extension_records
+            .store(native, Ordering::Relaxed);
+    }
+
+    /// Given a conn ptr, get the error.
+    unsafe fn ptr_take_error(ptr: *mut Connection) -> Option<Error> {
+        let error = unsafe { xcb().xcb_connection_has_error(ptr) };
+
+        match error {
+            0 => None,
+            errors::XCB_CONN_ERROR => {
+                // this is an I/O error, see if we can use I/O errors
+                cfg_if::cfg_if! {
+                    if #[cfg(feature = "std")] {
+                        let io = std::io::Error::last_os_error();
+                        Some(io.into())
+                    } else {
+                        Some(Error::make_msg(
+                            "an unknown I/O error occurred"
+                        ))
+                    }
+                }
+            }
+            errors::XCB_CONN_CLOSED_EXT_NOTSUPPORTED => {
+                Some(Error::make_missing_extension("<unknown>"))
+            }
+            errors::XCB_CONN_CLOSED_MEM_INSUFFICIENT => {
+                // standard Rust behavior when encountering an OOM
+                // is to abort the program
+                // we need a layout here for the error message
+                // we don't know the exact one, but we can take an
+                // educated guess
+                let layout = Layout::from_size_align_unchecked(32, 4);
+
+                alloc::alloc::handle_alloc_error(layout)
+            }
+            errors::XCB_CONN_CLOSED_REQ_LEN_EXCEED => {
+                Some(Error::make_msg("request length exceeded"))
+            }
+            errors::XCB_CONN_CLOSED_PARSE_ERR => {
+                Some(Error::make_msg("failed to parse the display string"))
+            }
+            errors::XCB_CONN_CLOSED_INVALID_SCREEN => Some(Error::make_msg("invalid screen")),
+            errors::XCB_CONN_CLOSED_FDPASSING_FAILED => Some(Error::make_msg("failed to pass FD")),
+            _ => Some(Error::make_msg("unknown error")),
+        }
+    }
+
+    /// Get the state of the connection.
+    ///
+    /// Unlike [`take_error`], this tells the reasons apart without
+    /// converting them into a `breadx` error.
+    ///
+    /// [`take_error`]: XcbDisplay::take_error
+    pub fn status(&self) -> ConnectionStatus {
+        ConnectionStatus::from_code(unsafe { xcb().xcb_connection_has_error(self.as_ptr()) })
+    }
+
+    /// Tell whether the connection is still usable.
+    pub fn is_connected(&self) -> bool {
+        self.status().is_connected()
+    }
+
+    /// Convert our error into a `breadx` `Error`.
+    pub fn take_error(&self) -> Option<Error> {
+        unsafe { Self::ptr_take_error(self.as_ptr()) }
+    }
+
+    /// Take an error we may not have.
+    pub fn take_maybe_error(&self) -> Error {
+        match self.take_error() {
+            Some(err) => err,
+            None => Error::make_msg("no error"),
+        }
+    }
+
+    /// Get the `Setup` associated with this type.
+    pub fn get_setup(&self) -> &Arc<Setup> {
+        call_once(&self.setup, || {
+            // since xcb keeps its pointer types 1:1 equivalent with
+            // the byte streams, we can just parse the setup as a
+            // byte stream.
+            let setup_ptr = unsafe { xcb().xcb_get_setup(self.as_ptr()) } as *mut u8 as *const u8;
+
+            // figure out the length
+            let header = unsafe { slice::from_raw_parts(setup_ptr, 8) };
+            let xlen = u16::from_ne_bytes([header[6], header[7]]);
+            let length = ((xlen as usize) * 4) + 8;
+
+            // now, parse it
+            let setup_slice = unsafe { slice::from_raw_parts(setup_ptr, length) };
+
+            Setup::try_parse(setup_slice)
+                .expect("xcb had invalid setup struct")
+                .0
+                .into()
+        })
+    }
+
+    /// Generate a new XID.
+    fn generate_xid_impl(&self) -> Result<u32> {
+        let xid = unsafe { xcb().xcb_generate_id(self.as_ptr()) };
+
+        if xid != -1i32 as u32 {
+            self.generated_xids.fetch_add(1, Ordering::Relaxed);
+            return Ok(xid);
+        }
+
+        // libxcb also fails when the connection is broken
+        if let Some(err) = self.take_error() {
+            return Err(err);
+        }
+
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "std")] {
+                let xc_misc = matches!(
+                    self.extension_manager.extension_code(self, "XC-MISC"),
+                    Ok(Some(_))
+                );
+                Err(crate::XidsExhausted { xc_misc }.into())
+            } else {
+                Err(Error::make_msg("out of resource IDs"))
+            }
+        }
+    }
+
+    /// Get the base that the server gave for resource IDs.
+    ///
+    /// IDs from the first range are this ORed with bits from
+    /// [`xid_mask`]. Ranges handed out later through `XC-MISC` don't
+    /// follow it.
+    ///
+    /// [`xid_mask`]: XcbDisplay::xid_mask
+    pub fn xid_base(&self) -> u32 {
+        self.get_setup().resource_id_base
+    }
+
+    /// Get the mask of the bits the client may set in resource IDs.
+    pub fn xid_mask(&self) -> u32 {
+        self.get_setup().resource_id_mask
+    }
+
+    /// Get the number of bytes read from the server over this connection.
+    pub fn bytes_read(&self) -> u64 {
+        unsafe { xcb().xcb_total_read(self.as_ptr()) }
+    }
+
+    /// Get the number of bytes written to the server over this
+    /// connection.
+    ///
+    /// Requests still in the output buffer haven't been written yet.
+    pub fn bytes_written(&self) -> u64 {
+        unsafe { xcb().xcb_total_written(self.as_ptr()) }
+    }
+
+    /// Get the number of resource IDs generated through this display.
+    ///
+    /// IDs that were generated through the raw connection, or before it
+    /// was wrapped, aren't counted.
+    pub fn generated_xids(&self) -> u64 {
+        self.generated_xids.load(Ordering::Relaxed)
+    }
+
+    /// Get the number of requests, replies, events and errors that have
+    /// gone through this display.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.counters.get()
+    }
+
+    /// Get the maxmimum request length.
+    ///
+    /// This blocks for the `BIG-REQUESTS` reply the first time, unless it
+    /// was prefetched, and is cached after that.
+    fn maximum_request_length_impl(&self) -> u32 {
+        *call_once(&self.max_request_length, || unsafe {
+            xcb().xcb_get_maximum_request_length(self.as_ptr())
+        })
+    }
+
+    fn synchronize_impl(&self) -> Result<()> {
+        // send a checked no-op request
+        let mut this = self;
+        let cookie = this.no_operation()?;
+        let seq = cookie.sequence();
+
+        self.check_for_error_impl(seq)
+    }
+
+    /// Flush to the server.
+    fn flush_impl(&self) -> Result<()> {
+        let res = unsafe { xcb().xcb_flush(self.as_ptr()) };
+
+        if res <= 0 {
+            Err(self.take_maybe_error())
+        } else {
+            mtx_lock(&self.flush_state).flushed();
+            Ok(())
+        }
+    }
+
+    /// Flush if the flush policy asks us to before blocking.
+    pub(crate) fn flush_before_wait(&self) -> Result<()> {
+        let flush = mtx_lock(&self.flush_state).flush_before_wait();
+
+        if flush {
+            self.flush_impl()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Parse a raw event into a `breadx` event, along with its full
+    /// sequence number.
+    unsafe fn parse_event(&self, event: *mut GenericEvent) -> Result<(Event, u64)> {
+        let sequence = unsafe { self.event_sequence(event) };
+        let event = unsafe { wrap_event(event) };
+
+        #[cfg(feature = "diagnostics")]
+        diagnostics::event_received(&event, sequence);
+
+        Ok((self.parse_event_bytes(&event)?, sequence))
+    }
+
+    /// Get the full sequence number of a raw event, note that the server
+    /// has processed it, and count it as an event or an error.
+    ///
+    /// This must be called before `wrap_event`, which overwrites it for
+    /// generic events.
+    unsafe fn event_sequence(&self, event: *const GenericEvent) -> u64 {
+        // libxcb stores the lower 32 bits of the sequence number right
+        // after the 32-byte header
+        let bytes = &*(event as *const u8).add(32).cast::<[u8; 4]>();
+        let low = u32::from_ne_bytes(*bytes);
+
+        let seq = cookie::widen(self.last_sequence.load(Ordering::Relaxed), low);
+        self.processed(seq);
+
+        // errors for unchecked requests are delivered as events
+        let event = &*(event as *const [u8; 32]);
+        if event[0] == 0 {
+            self.request_hooks
+                .failed(seq, event, |opcode| self.extension_name(opcode));
+            self.counters.error_received();
+        } else {
+            self.counters.event_received();
+        }
+
+        seq
+    }
+
+    /// Parse an event in its wire format into a `breadx` event.
+    pub(crate) fn parse_event_bytes(&self, event: &[u8]) -> Result<Event> {
+        // let the custom parsers have a go at it first
+        if let Some(result) = self.custom_extensions.parse_event(event) {
+            return result;
+        }
+
+        // event codes that breadx doesn't recognise already come back as
+        // `Event::Unknown`; a failure here means an event breadx does know
+        // was truncated or malformed, which shouldn't be papered over
+        Event::parse(event, &self.extension_manager).map_err(Error::make_parse_error)
+    }
+
+    /// Wait for an event, along with the sequence number of the last
+    /// request the server processed before sending it.
+    ///
+    /// This correlates events with the requests that caused them, e.g. an
+    /// `Expose` with the `ConfigureWindow` that preceded it.
+    pub fn wait_for_event_with_sequence(&self) -> Result<(Event, u64)> {
+        self.wait_for_event_impl()
+    }
+
+    /// Poll for an event, along with the sequence number of the last
+    /// request the server processed before sending it.
+    ///
+    /// See [`XcbDisplay::wait_for_event_with_sequence`].
+    pub fn poll_for_event_with_sequence(&self) -> Result<Option<(Event, u64)>> {
+        self.poll_for_event_impl()
+    }
+
+    /// Wait for an event.
+    fn wait_for_event_impl(&self) -> Result<(Event, u64)> {
+        if let Some(event) = mtx_lock(&self.pushback).pop_front() {
+            return Ok(event);
+        }
+
+        self.flush_before_wait()?;
+
+        let event = unsafe { self.next_event(EventSource::Wait)? };
+        unsafe { self.parse_event(event) }
+    }
+
+    /// Take an event that has already been read from the server, without
+    /// reading from the socket.
+    ///
+    /// This saves a system call for each event when draining a burst of
+    /// them: once [`poll_for_event`] or [`wait_for_event`] has returned
+    /// one, the rest of what was read along with it can be taken here.
+    /// Returns `None` once the queue is empty, even if more events are
+    /// waiting on the socket.
+    ///
+    /// [`poll_for_event`]: breadx::display::DisplayBase::poll_for_event
+    /// [`wait_for_event`]: breadx::display::Display::wait_for_event
+    pub fn poll_for_queued_event(&self) -> Result<Option<Event>> {
+        Ok(self
+            .poll_for_event_from(EventSource::Queued)?
+            .map(|(event, _)| event))
+    }
+
+    /// Poll for an event.
+    fn poll_for_event_impl(&self) -> Result<Option<(Event, u64)>> {
+        self.poll_for_event_from(EventSource::Poll)
+    }
+
+    /// Get the next event without consuming it.
+    ///
+    /// The event is read from the server if none are queued, without
+    /// blocking, and is then held until the next call to
+    /// [`poll_for_event`] or [`wait_for_event`] returns it. Returns `None`
+    /// if there are no events.
+    ///
+    /// This lets toolkits look ahead before deciding how to handle an
+    /// event, e.g. to merge a `ConfigureNotify` with the ones right after
+    /// it. Events polled for with [`poll_for_raw_event`] skip the held
+    /// event, and so may be returned out of order.
+    ///
+    /// [`poll_for_event`]: breadx::display::DisplayBase::poll_for_event
+    /// [`wait_for_event`]: breadx::display::Display::wait_for_event
+    /// [`poll_for_raw_event`]: XcbDisplay::poll_for_raw_event
+    pub fn peek_event(&self) -> Result<Option<Event>> {
+        if let Some((event, _)) = mtx_lock(&self.pushback).front() {
+            return Ok(Some(event.clone()));
+        }
+
+        let (event, sequence) = match self.poll_for_event_from(EventSource::Poll)? {
+            Some(event) => event,
+            None => return Ok(None),
+        };
+
+        // another thread may have pushed an event back in the meantime
+        let mut pushback = mtx_lock(&self.pushback);
+        pushback.push_back((event, sequence));
+        Ok(pushback.front().map(|(event, _)| event.clone()))
+    }
+
+    /// Iterate over the events that have been received, without blocking.
+    ///
+    /// The iterator ends once there are no more events to be had without
+    /// waiting, replacing the usual `while let Some(event) =
+    /// display.poll_for_event()?` loop.
+    pub fn events(&self) -> Events<'_> {
+        Events::new(self, false)
+    }
+
+    /// Iterate over events, waiting for each one to arrive.
+    ///
+    /// The iterator only ends if an error occurs.
+    pub fn events_blocking(&self) -> Events<'_> {
+        Events::new(self, true)
+    }
+
+    /// Push an event back onto the display, so that it is the next one
+    /// returned.
+    ///
+    /// The next call to [`poll_for_event`] or [`wait_for_event`] returns
+    /// `event` before any other, including events pushed back earlier.
+    /// This is for layers that route events and take one out of the queue
+    /// before deciding not to handle it. Its sequence number is recovered
+    /// from the event, as with [`widen_sequence`].
+    ///
+    /// [`poll_for_event`]: breadx::display::DisplayBase::poll_for_event
+    /// [`wait_for_event`]: breadx::display::Display::wait_for_event
+    /// [`widen_sequence`]: XcbDisplay::widen_sequence
+    pub fn put_back_event(&self, event: Event) {
+        // KeymapNotify is the only event without a sequence number
+        let sequence = match event.wire_sequence_number() {
+            Some(low) => self.widen_sequence(low),
+            None => self.last_sequence_processed(),
+        };
+        self.put_back_event_with_sequence(event, sequence);
+    }
+
+    /// Push an event back onto the display, along with the sequence
+    /// number it was returned with.
+    ///
+    /// See [`XcbDisplay::put_back_event`].
+    pub fn put_back_event_with_sequence(&self, event: Event, sequence: u64) {
+        mtx_lock(&self.pushback).push_front((event, sequence));
+    }
+
+    /// Poll for an event from `source`.
+    fn poll_for_event_from(&self, source: EventSource) -> Result<Option<(Event, u64)>> {
+        if let Some(event) = mtx_lock(&self.pushback).pop_front() {
+            return Ok(Some(event));
+        }
+
+        let event = unsafe { self.next_event(source)? };
+        if event.is_null() {
+            return Ok(None);
+        }
+
+        unsafe { self.parse_event(event) }.map(Some)
+    }
+
+    /// Divert the generic events an extension sends for the event context
+    /// `eid` into a queue of their own.
+    ///
+    /// The events stop showing up in the main event queue until the
+    /// returned queue is dropped. `eid` is the ID the client chose when
+    /// selecting for the events, such as the `eid` of a Present
+    /// `SelectInput` request. Only one queue can be registered for the
+    /// same extension and event context at a time.
+    pub fn register_for_special_xge(
+        &self,
+        extension: &'static str,
+        eid: u32,
+    ) -> Result<SpecialEventQueue<'_>> {
+        // libxcb doesn't say why registering failed, so check for the
+        // extension ourselves
+        self.extension_opcode(extension)?;
+
+        let record = extension_record::record(extension);
+        let queue =
+            unsafe { xcb().xcb_register_for_special_xge(self.as_ptr(), record, eid, null_mut()) };
+
+        match NonNull::new(queue) {
+            Some(queue) => Ok(unsafe { SpecialEventQueue::new(self, queue) }),
+            None => Err(self.take_error().unwrap_or_else(|| {
+                Error::make_msg("a queue is already registered for these events")
+            })),
+        }
+    }
+
+    /// Wait for an event from a special event queue.
+    pub(crate) fn wait_for_special_event_impl(
+        &self,
+        queue: NonNull<SpecialEvent>,
+    ) -> Result<(Event, u64)> {
+        self.flush_before_wait()?;
+
+        let event = unsafe { xcb().xcb_wait_for_special_event(self.as_ptr(), queue.as_ptr()) };
+        if event.is_null() {
+            return Err(self.take_maybe_error());
+        }
+
+        unsafe { self.parse_event(event) }
+    }
+
+    /// Poll for an event from a special event queue.
+    pub(crate) fn poll_for_special_event_impl(
+        &self,
+        queue: NonNull<SpecialEvent>,
+    ) -> Result<Option<(Event, u64)>> {
+        let event = unsafe { xcb().xcb_poll_for_special_event(self.as_ptr(), queue.as_ptr()) };
+        if event.is_null() {
+            return match self.take_error() {
+                Some(err) => Err(err),
+                None => Ok(None),
+            };
+        }
+
+        unsafe { self.parse_event(event) }.map(Some)
+    }
+
+    /// Unregister a special event queue, freeing any events left in it.
+    ///
+    /// # Safety
+    ///
+    /// `queue` must have been registered on this display, and must not be
+    /// used again.
+    pub(crate) unsafe fn unregister_for_special_event(&self, queue: NonNull<SpecialEvent>) {
+        unsafe { xcb().xcb_unregister_for_special_event(self.as_ptr(), queue.as_ptr()) };
+    }
+
+    /// Poll for an event, without parsing it.
+    ///
+    /// This returns the event exactly as it was received from the server,
+    /// which is useful for events that `breadx` can't parse.
+    pub fn poll_for_raw_event(&self) -> Result<Option<RawEvent>> {
+        Ok(self
+            .poll_for_raw_event_impl()?
+            .map(|(event, sequence)| RawEvent {
+                data: event.clone_slice().into_boxed_slice(),
+                fds: Vec::new(),
+                sequence,
+            }))
+    }
+
+    /// Poll for an event, without parsing it or copying it out of the
+    /// buffer `libxcb` allocated.
+    pub(crate) fn poll_for_raw_event_impl(&self) -> Result<Option<(CBox<[u8]>, u64)>> {
+        let event = unsafe { self.next_event(EventSource::Poll)? };
+        if event.is_null() {
+            return Ok(None);
+        }
+
+        let sequence = unsafe { self.event_sequence(event) };
+        let event = unsafe { wrap_event(event) };
+
+        #[cfg(feature = "diagnostics")]
+        diagnostics::event_received(&event, sequence);
+
+        Ok(Some((event, sequence)))
+    }
+
+    /// Send a request to the server.
+    fn send_request_impl(&self, request: RawRequest) -> Result<u64> {
+        let checked = request.discard_mode().is_none();
+        self.send_request_checking(request, checked)
+    }
+
+    fn send_request_checking(&self, request: RawRequest, checked: bool) -> Result<u64> {
+        let ext_opcode = request
+            .extension()
+            .map(|ext| self.extension_opcode(ext))
+            .transpose()?;
+        let (seq, reply_has_fds, length) =
+            self.send_formatted(&mut SendScratch::default(), request, ext_opcode, checked)?;
+
+        if reply_has_fds {
+            self.has_fds.insert(seq);
+            self.collect_stale_fd_replies();
+        }
+
+        // flush if the policy says so
+        let flush = mtx_lock(&self.flush_state).request_sent(length);
+        if flush {
+            self.flush_impl()?;
+        }
+
+        Ok(seq)
+    }
+
+    /// Send a request to the server, using exclusive access to skip
+    /// locking the state kept for it.
+    pub(crate) fn send_request_mut(&mut self, request: RawRequest) -> Result<u64> {
+        let ext_opcode = match request.extension() {
+            Some(ext) => Some(match self.extension_manager.cached_code(ext) {
+                Some(Some(code)) => code,
+                Some(None) => return Err(Error::make_missing_extension(ext)),
+                None => self.extension_opcode(ext)?,
+            }),
+            None => None,
+        };
+        let checked = request.discard_mode().is_none();
+        let (seq, reply_has_fds, length) =
+            self.send_formatted(&mut SendScratch::default(), request, ext_opcode, checked)?;
+
+        if reply_has_fds {
+            self.has_fds.insert_mut(seq);
+            self.collect_stale_fd_replies();
+        }
+
+        let flush = mtx_get_mut(&mut self.flush_state).request_sent(length);
+        if flush {
+            self.flush_impl()?;
+        }
+
+        Ok(seq)
+    }
+
+    /// Get the major opcode of an extension, querying it if need be.
+    pub(crate) fn extension_opcode(&self, ext: &'static str) -> Result<u8> {
+        match self.extension_manager.extension_code(self, ext)? {
+            Some(code) => Ok(code),
+            None => Err(Error::make_missing_extension(ext)),
+        }
+    }
+
+    /// Have `libxcb` query an extension, without waiting for the reply.
+    pub(crate) fn prefetch_extension_data(&self, name: &'static str) {
+        let record = extension_record::record(name);
+        unsafe { xcb().xcb_prefetch_extension_data(self.as_ptr(), record) };
+    }
+
+    /// Get the information for an extension from `libxcb`'s cache, waiting
+    /// for it to be queried if need be.
+    pub(crate) fn query_extension_data(
+        &self,
+        name: &'static str,
+    ) -> Result<Option<ExtensionInformation>> {
+        let record = extension_record::record(name);
+        let reply = unsafe { xcb().xcb_get_extension_data(self.as_ptr(), record) };
+
+        // libxcb only fails here if the connection does
+        let reply = match unsafe { reply.as_ref() } {
+            Some(reply) => reply,
+            None => {
+                return Err(self
+                    .take_error()
+                    .unwrap_or_else(|| Error::make_msg("failed to query the extension")))
+            }
+        };
+
+        Ok(Some(ExtensionInformation {
+            major_opcode: reply.major_opcode,
+            first_event: reply.first_event,
+            first_error: reply.first_error,
+        })
+        .filter(|_| reply.present != 0))
+    }
+
+    /// Format a request and send it, returning its sequence number and
+    /// whether its reply will carry file descriptors.
+    ///
+    /// `scratch` is left empty, so it can be used for the next request.
+    fn send_formatted(
+        &self,
+        scratch: &mut SendScratch,
+        mut request: RawRequest,
+        ext_opcode: Option<u8>,
+        checked: bool,
+    ) -> Result<(u64, bool, usize)> {
+        request.format(ext_opcode, self.maximum_request_length_impl() as usize)?;
+
+        let variant = request.variant();
+        let reply_has_fds = matches!(variant, ReplyFdKind::ReplyWithFDs);
+        let extension_name = request.extension();
+        let extension = extension_name
+            .filter(|_| self.native_extension_records())
+            .map(extension_record::record);
+        let (buf, fds) = request.mut_parts();
+
+        // don't send anything we can't handle the fds for
+        if !FD_PASSING && (reply_has_fds || !fds.is_empty()) {
+            return Err(fd_passing_unsupported());
+        }
+
+        // libxcb may consume the buffers as it writes them
+        let opcodes = [buf[1][0], buf[1][1]];
+        let length = buf[1..].iter().map(|slice| slice.len()).sum();
+
+        let isvoid = matches!(variant, ReplyFdKind::NoReply) as u8;
+        let mut header = [0u8; 4];
+        let native_iov = &mut scratch.iovecs;
+        debug_assert!(native_iov.is_empty());
+
+        // determine protocol request
+        let (iov, proto_request, mut sr_flags) = match extension {
+            Some(extension) => {
+                // libxcb fills in the major opcode and the length itself,
+                // and needs two writable slots before the header to do so
+                header.copy_from_slice(&buf[1][..4]);
+                native_iov.reserve(buf.len() + 1);
+                native_iov.extend((0..2).map(|_| Iovec {
+                    iov_base: null_mut(),
+                    iov_len: 0,
+                }));
+                native_iov.push(Iovec {
+                    iov_base: header.as_mut_ptr().cast(),
+                    iov_len: header.len() as _,
+                });
+                native_iov.extend(buf[2..].iter().map(|slice| Iovec {
+                    iov_base: slice.as_ptr() as *mut c_void,
+                    iov_len: slice.len() as _,
+                }));
+
+                let proto_request = ProtocolRequest {
+                    count: native_iov.len() - 2,
+                    extension,
+                    opcode: header[1],
+                    isvoid,
+                };
+                (native_iov[2..].as_mut_ptr(), proto_request, 0)
+            }
+            None => {
+                let proto_request = ProtocolRequest {
+                    count: buf.len() - 1,
+                    extension: null_mut(),
+                    opcode: 0,
+                    isvoid,
+                };
+                (
+                    buf[1..].as_mut_ptr() as *mut Iovec,
+                    proto_request,
+                    flags::RAW,
+                )
+            }
+        };
+
+        if checked {
+            sr_flags |= flags::CHECKED;
+        }
+        if reply_has_fds {
+            sr_flags |= flags::REPLY_HAS_FDS;
+        }
+
+        let seq = if fds.is_empty() {
+            // no fds
+            unsafe { xcb().xcb_send_request64(self.as_ptr(), sr_flags, iov, &proto_request) }
+        } else {
+            // we have fds
+            let raw_fds = &mut scratch.fds;
+            raw_fds.extend(mem::take(fds).into_iter().map(|fd| {
+                cfg_if::cfg_if! {
+                    if #[cfg(all(unix, feature = "std"))] {
+                        fd.into_raw_fd()
+                    } else {
+                        // rejected above
+                        let _ = fd;
+                        -1
+                    }
+                }
+            }));
+            let seq = unsafe {
+                xcb().xcb_send_request_with_fds64(
+                    self.as_ptr(),
+                    sr_flags,
+                    iov,
+                    &proto_request,
+                    raw_fds.len() as i32,
+                    raw_fds.as_mut_ptr(),
+                )
+            };
+
+            // fds are closed by libxcb, even if sending fails
+            raw_fds.clear();
+            seq
+        };
+        scratch.iovecs.clear();
+
+        // check for an error
+        if seq == 0 {
+            return Err(self.take_maybe_error());
+        }
+
+        #[cfg(feature = "diagnostics")]
+        diagnostics::request_sent(opcodes, length, seq);
+
+        self.request_sent(seq, opcodes, extension_name, length, checked || isvoid == 0);
+        self.last_sequence.fetch_max(seq, Ordering::Relaxed);
+        if isvoid == 0 {
+            self.last_answered.fetch_max(seq, Ordering::Relaxed);
+        }
+
+        Ok((seq, reply_has_fds, length))
+    }
+
+    /// Send a burst of void requests, skipping the bookkeeping done for
+    /// each request by [`Display::send_void_request`].
+    ///
+    /// The extension opcode and maximum request length are looked up once
+    /// for the whole burst, and the requests are sent unchecked, so any
+    /// errors they cause arrive as events. This is meant for sending many
+    /// small requests at once, such as thousands of `PolyFillRectangle`s.
+    ///
+    /// Returns the sequence number of the last request sent, or `None` if
+    /// `requests` was empty. Requests that pass file descriptors are
+    /// rejected.
+    ///
+    /// [`Display::send_void_request`]: breadx::display::DisplayExt::send_void_request
+    pub fn send_void_requests<R: VoidRequest>(
+        &self,
+        requests: impl IntoIterator<Item = R>,
+    ) -> Result<Option<u64>> {
+        self.send_burst(R::EXTENSION_NAME, requests, |request, send| {
+            from_void_request(request, true, send)
+        })
+    }
+
+    /// Send a burst of small core requests, encoding each one on the
+    /// stack.
+    ///
+    /// This works like [`send_void_requests`], for the requests that
+    /// implement [`SmallRequest`]. Those are encoded into a buffer on the
+    /// stack rather than serialized onto the heap by `breadx`, so sending
+    /// them doesn't allocate. A single request can be sent by passing
+    /// `Some(request)`.
+    ///
+    /// [`send_void_requests`]: XcbDisplay::send_void_requests
+    pub fn send_small_requests<R: SmallRequest>(
+        &self,
+        requests: impl IntoIterator<Item = R>,
+    ) -> Result<Option<u64>> {
+        self.send_burst(None, requests, |request, send| {
+            let mut data = [0; SMALL_REQUEST_BYTES];
+            let len = request.encode(&mut data);
+            let mut slices = [io_slice(&[]), io_slice(&[]), io_slice(&data[..len])];
+            let mut buffer = [0; 8];
+            send(RawRequest::new(
+                &mut slices,
+                Vec::new(),
+                ReplyFdKind::NoReply,
+                None,
+                &mut buffer,
+            ))
+        })
+    }
+
+    /// Send a burst of void requests, with `encode` handing each one to
+    /// the function that sends it.
+    fn send_burst<R>(
+        &self,
+        extension: Option<&'static str>,
+        requests: impl IntoIterator<Item = R>,
+        encode: impl Fn(R, &mut dyn FnMut(RawRequest<'_, '_>) -> Result<u64>) -> Result<u64>,
+    ) -> Result<Option<u64>> {
+        let ext_opcode = match extension {
+            Some(ext) => match self.extension_manager.extension_code(self, ext)? {
+                Some(code) => Some(code),
+                None => return Err(Error::make_missing_extension(ext)),
+            },
+            None => None,
+        };
+        let max_len = self.maximum_request_length_impl() as usize;
+
+        let mut last = None;
+        let mut count = 0;
+        let mut bytes = 0;
+        let mut result = Ok(());
+        for request in requests {
+            let seq = encode(request, &mut |mut request| {
+                request.format(ext_opcode, max_len)?;
+
+                let (buf, fds) = request.mut_parts();
+                if !fds.is_empty() {
+                    return Err(Error::make_msg(
+                        "requests sent in bulk can't pass file descriptors",
+                    ));
+                }
 
-        // create a CBox over the byte slice
-        let event = slice_from_raw_parts_mut(evbytes, length);
-        let event = unsafe { CBox::new(event) };
+                let opcodes = [buf[1][0], buf[1][1]];
+                let length = buf[1..].iter().map(|slice| slice.len()).sum();
+
+                let proto_request = ProtocolRequest {
+                    count: buf.len() - 1,
+                    extension: null_mut(),
+                    opcode: 0,
+                    isvoid: 1,
+                };
+                let iov = buf[1..].as_mut_ptr() as *mut Iovec;
+
+                match unsafe {
+                    xcb().xcb_send_request64(self.as_ptr(), flags::RAW, iov, &proto_request)
+                } {
+                    0 => Err(self.take_maybe_error()),
+                    seq => {
+                        #[cfg(feature = "diagnostics")]
+                        diagnostics::request_sent(opcodes, length, seq);
+
+                        self.request_sent(seq, opcodes, extension, length, false);
+                        bytes += length;
+
+                        Ok(seq)
+                    }
+                }
+            });
 
-        // parse the event
-        Event::parse(&event, &self.extension_manager).map_err(Error::make_parse_error)
-    }
+            match seq {
+                Ok(seq) => {
+                    last = Some(seq);
+                    count += 1;
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
 
-    /// Wait for an event.
-    fn wait_for_event_impl(&self) -> Result<Event> {
-        let event = unsafe { xcb().xcb_wait_for_event(self.as_ptr()) };
+        // account for the requests sent before any failure
+        if let Some(seq) = last {
+            self.last_sequence.fetch_max(seq, Ordering::Relaxed);
 
-        let event = if event.is_null() {
-            return Err(self.take_maybe_error());
-        } else {
-            event
-        };
+            let flush = mtx_lock(&self.flush_state).requests_sent(count, bytes);
+            if flush && result.is_ok() {
+                self.flush_impl()?;
+            }
+        }
 
-        unsafe { self.parse_event(event) }
+        result.map(|()| last)
     }
 
-    /// Poll for an event.
-    fn poll_for_event_impl(&self) -> Result<Option<Event>> {
-        let event = unsafe { xcb().xcb_poll_for_event(self.as_ptr()) };
+    /// Send a batch of requests, updating the state kept for them once
+    /// for the whole batch.
+    ///
+    /// Each request is checked unless `breadx` asked for its reply to be
+    /// discarded, as when it's sent through [`Display`]. `libxcb` needs a
+    /// call for each request to give it a sequence number, but the set of
+    /// replies with file descriptors and the flush policy are only locked
+    /// once, the buffers each request is sent from are reused, and the
+    /// batch is flushed at most once at the end. This suits
+    /// toolkits that send many small requests of different kinds for each
+    /// frame.
+    ///
+    /// Returns the sequence number of each request, in order. If a request
+    /// can't be sent, its error is returned, and the requests before it
+    /// are flushed as usual.
+    ///
+    /// [`Display`]: breadx::display::Display
+    pub fn send_requests<'a, 'b: 'a>(
+        &self,
+        requests: impl IntoIterator<Item = RawRequest<'a, 'b>>,
+    ) -> Result<Vec<u64>> {
+        let requests = requests.into_iter();
+        let mut seqs = Vec::with_capacity(requests.size_hint().0);
+        let mut with_fds = Vec::new();
+        let mut scratch = SendScratch::default();
+        let mut bytes = 0;
+        let mut result = Ok(());
+        for request in requests {
+            let sent = request
+                .extension()
+                .map(|ext| self.extension_opcode(ext))
+                .transpose()
+                .and_then(|ext_opcode| {
+                    let checked = request.discard_mode().is_none();
+                    self.send_formatted(&mut scratch, request, ext_opcode, checked)
+                });
+
+            match sent {
+                Ok((seq, reply_has_fds, length)) => {
+                    seqs.push(seq);
+                    bytes += length;
+                    if reply_has_fds {
+                        with_fds.push(seq);
+                    }
+                }
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
 
-        let event = if event.is_null() {
-            // tell if the null corresponds to an error
-            if let Some(err) = self.take_error() {
-                return Err(err);
-            } else {
-                return Ok(None);
+        // account for the requests sent before any failure
+        if !with_fds.is_empty() {
+            self.has_fds.extend(with_fds);
+            self.collect_stale_fd_replies();
+        }
+        if !seqs.is_empty() {
+            let flush = mtx_lock(&self.flush_state).requests_sent(seqs.len(), bytes);
+            if flush && result.is_ok() {
+                self.flush_impl()?;
             }
-        } else {
-            event
-        };
+        }
 
-        unsafe { self.parse_event(event) }.map(Some)
+        result.map(|()| seqs)
     }
 
-    /// Send a request to the server.
-    fn send_request_impl(&self, mut request: RawRequest) -> Result<u64> {
-        // format the request
-        let ext_opcode = request
-            .extension()
-            .map(|ext| {
-                let mut this = self;
-                match self.extension_manager.extension_code(&mut this, ext)? {
-                    Some(code) => Ok(code),
-                    None => Err(Error::make_missing_extension(ext)),
-                }
-            })
+    /// Send a void request whose bytes are split across several buffers,
+    /// without joining them first.
+    ///
+    /// `breadx` serializes each request into a single buffer, which
+    /// means copying bulk data such as the pixels of a `PutImage`. Here,
+    /// `parts` are handed to `libxcb` as they are. Together they make up
+    /// the request as it goes on the wire, starting with its 4-byte
+    /// header. The length in the header is filled in here, and padding is
+    /// added to the end. For extension requests, the major opcode is
+    /// filled in as well. Nothing is allocated for requests made of a few
+    /// parts, so small requests serialized into a buffer on the stack can
+    /// be sent without touching the heap.
+    ///
+    /// Errors caused by the request are delivered as events if
+    /// `discard_reply` is set, and can otherwise be checked with
+    /// [`check_request`].
+    ///
+    /// [`check_request`]: XcbDisplay::check_request
+    #[cfg(unix)]
+    pub fn send_void_request_vectored(
+        &self,
+        extension: Option<&'static str>,
+        parts: &[&[u8]],
+        discard_reply: bool,
+    ) -> Result<u64> {
+        let first = match parts.first() {
+            Some(first) if first.len() >= 4 => first,
+            _ => return Err(Error::make_msg("request is shorter than its header")),
+        };
+        let ext_opcode = extension
+            .map(|ext| self.extension_opcode(ext))
             .transpose()?;
 
-        request.format(ext_opcode, self.maximum_request_length_impl() as usize)?;
-
-        let variant = request.variant();
-        let reply_has_fds = matches!(variant, ReplyFdKind::ReplyWithFDs);
-        let check_reply = request.discard_mode().is_none();
-        let (buf, fds) = request.mut_parts();
+        let len: usize = parts.iter().map(|part| part.len()).sum();
+        let padding = (4 - len % 4) % 4;
+        let x_len = (len + padding) / 4;
+        let max_len = self.maximum_request_length_impl() as usize;
+
+        // the header is rewritten in a buffer of our own, using the long
+        // form of the length when it doesn't fit in 16 bits
+        let mut header = [0u8; 8];
+        header[0] = ext_opcode.unwrap_or(first[0]);
+        header[1] = first[1];
+        let header_len = match u16::try_from(x_len) {
+            Ok(short) if x_len <= max_len => {
+                header[2..4].copy_from_slice(&short.to_ne_bytes());
+                4
+            }
+            _ if x_len < max_len => {
+                header[4..8].copy_from_slice(&((x_len + 1) as u32).to_ne_bytes());
+                8
+            }
+            _ => {
+                return Err(Error::make_msg(alloc::format!(
+                    "request of {} units is longer than the maximum of {}",
+                    x_len,
+                    max_len
+                )))
+            }
+        };
 
-        let iov = (&mut buf[1..]).as_mut_ptr() as *mut Iovec;
+        let zeroes = [0u8; 3];
+        let slices = Some(&header[..header_len])
+            .into_iter()
+            .chain(Some(&first[4..]))
+            .chain(parts[1..].iter().copied())
+            .chain(Some(&zeroes[..padding]))
+            .filter(|slice| !slice.is_empty());
+
+        // libxcb may use the two slots before the first one
+        let mut iovecs = SmallVec::<[Iovec; SMALL_IOVECS]>::new();
+        iovecs.extend((0..2).map(|_| Iovec {
+            iov_base: null_mut(),
+            iov_len: 0,
+        }));
+        iovecs.extend(slices.map(|slice| Iovec {
+            iov_base: slice.as_ptr() as *mut c_void,
+            iov_len: slice.len(),
+        }));
 
-        // determine protocol request
         let proto_request = ProtocolRequest {
-            count: buf.len() - 1,
+            count: iovecs.len() - 2,
             extension: null_mut(),
             opcode: 0,
-            isvoid: matches!(variant, ReplyFdKind::NoReply) as u8,
+            isvoid: 1,
         };
-
         let mut sr_flags = flags::RAW;
-        if check_reply {
+        if !discard_reply {
             sr_flags |= flags::CHECKED;
         }
-        if reply_has_fds {
-            sr_flags |= flags::REPLY_HAS_FDS;
-        }
 
-        let seq = if fds.is_empty() {
-            // no fds
-            unsafe { xcb().xcb_send_request64(self.as_ptr(), sr_flags, iov, &proto_request) }
+        // large requests are written out as they are, rather than copied
+        // into libxcb's buffer first
+        let direct = discard_reply && len >= DIRECT_WRITE_BYTES;
+        let written = if direct {
+            self.write_direct(&mut iovecs[2..])?
         } else {
-            // we have fds
-            let mut fds = mem::take(fds)
-                .into_iter()
-                .map(|fd| {
-                    cfg_if::cfg_if! {
-                        if #[cfg(all(unix, feature = "std"))] {
-                            fd.into_raw_fd()
-                        } else {
-                            let _ = fd;
-                            unreachable!()
-                        }
-                    }
-                })
-                .collect::<Vec<_>>();
-            unsafe {
-                xcb().xcb_send_request_with_fds64(
-                    self.as_ptr(),
-                    sr_flags,
-                    iov,
-                    &proto_request,
-                    fds.len() as i32,
-                    fds.as_mut_ptr(),
-                )
-            }
-
-            // fds are manually closed by libxcb
+            None
         };
 
-        // check for an error
-        if seq == 0 {
-            return Err(self.take_maybe_error());
-        }
+        let seq = match written {
+            Some(seq) => seq,
+            None => {
+                let iov = iovecs[2..].as_mut_ptr();
+                match unsafe {
+                    xcb().xcb_send_request64(self.as_ptr(), sr_flags, iov, &proto_request)
+                } {
+                    0 => return Err(self.take_maybe_error()),
+                    seq => seq,
+                }
+            }
+        };
 
-        // setup sequence number
-        if reply_has_fds {
-            mtx_lock(&self.has_fds).insert(seq);
+        let length = x_len * 4 + header_len - 4;
+
+        #[cfg(feature = "diagnostics")]
+        diagnostics::request_sent([header[0], header[1]], length, seq);
+
+        self.request_sent(
+            seq,
+            [header[0], header[1]],
+            extension,
+            length,
+            !discard_reply,
+        );
+        self.last_sequence.fetch_max(seq, Ordering::Relaxed);
+
+        if written.is_some() {
+            // everything before it was flushed when the socket was taken
+            mtx_lock(&self.flush_state).flushed();
+        } else if mtx_lock(&self.flush_state).request_sent(length) {
+            self.flush_impl()?;
         }
 
         Ok(seq)
     }
 
-    #[cfg(unix)]
-    unsafe fn extract_fds(&self, reply: &[u8], seq: u64) -> Vec<c_int> {
-        // if the sequenc number is not in our set, return
-        if !mtx_lock(&self.has_fds).remove(&seq) {
-            return Vec::new();
-        }
+    /// Write a void request straight to the socket, returning its sequence
+    /// number.
+    ///
+    /// Returns `None` if it should be sent through `libxcb` instead.
+    /// `libxcb` sends a request with a reply once too many requests
+    /// without one have been sent in a row, so that it can keep track of
+    /// sequence numbers, but requests written here aren't seen by that, so
+    /// they're only written while that's a long way off.
+    #[cfg(all(unix, feature = "std"))]
+    fn write_direct(&self, iovecs: &mut [Iovec]) -> Result<Option<u64>> {
+        let last_answered = self.last_answered.load(Ordering::Relaxed);
+        let written = unsafe {
+            crate::direct_write::write_requests(self.as_ptr(), iovecs, 1, |seq| {
+                seq - last_answered < DIRECT_WRITE_SEQUENCES
+            })
+        };
+
+        written.map_err(|()| self.take_maybe_error())
+    }
 
-        let nfds = reply[1];
-        let fd_ptr = (reply.as_ptr() as *const c_int).add(reply.len());
-        let fd_slice = slice::from_raw_parts(fd_ptr, nfds as usize);
+    #[cfg(not(all(unix, feature = "std")))]
+    fn write_direct(&self, _iovecs: &mut [Iovec]) -> Result<Option<u64>> {
+        Ok(None)
+    }
 
-        fd_slice.into()
+    #[cfg(unix)]
+    unsafe fn extract_fds(&self, reply: &[u8], has_fds: bool) -> ReplyFds {
+        if has_fds {
+            ReplyFds::from_reply(reply)
+        } else {
+            ReplyFds::default()
+        }
     }
 
     #[cfg(not(unix))]
-    unsafe fn extract_fds(&self, _reply: &[u8], _seq: u64) -> Vec<c_int> {
-        Vec::new()
+    unsafe fn extract_fds(&self, _reply: &[u8], _has_fds: bool) -> ReplyFds {
+        ReplyFds::default()
     }
 
     unsafe fn wrap_error(&self, error: *mut GenericError) -> Error {
-        use breadx::protocol::X11Error;
+        let error = unsafe { self.take_raw_error(error) };
+        self.convert_error(&error)
+    }
+
+    /// Convert an error into a `breadx` error.
+    fn convert_error(&self, error: &[u8; 32]) -> Error {
+        self.parse_x11_error(error)
+            .map_or_else(|err| err, Error::from)
+    }
+
+    /// Take ownership of an error returned by `libxcb`, noting that the
+    /// server has processed the request that caused it.
+    unsafe fn take_raw_error(&self, error: *mut GenericError) -> CBox<[u8; 32]> {
+        let error = unsafe { CBox::new(error as *mut [u8; 32]) };
+
+        // libxcb stores the lower 32 bits of the sequence number at the
+        // end of the error
+        let mut low = [0; 4];
+        low.copy_from_slice(&error[28..]);
+        let seq = cookie::widen(
+            self.last_sequence.load(Ordering::Relaxed),
+            u32::from_ne_bytes(low),
+        );
+        self.processed(seq);
+        self.request_hooks
+            .failed(seq, &error, |opcode| self.extension_name(opcode));
+
+        self.counters.error_received();
+
+        #[cfg(feature = "diagnostics")]
+        diagnostics::error_received(&error);
+
+        error
+    }
+
+    /// Get the name of the extension a major opcode belongs to, if it has
+    /// been used through this display.
+    fn extension_name(&self, major_opcode: u8) -> Option<&'static str> {
+        // core requests have opcodes below 128
+        if major_opcode < 128 {
+            None
+        } else {
+            self.extension_manager.extension_name(major_opcode)
+        }
+    }
 
-        let error_ptr = error as *mut [u8; 32];
-        let error_boxed = unsafe { CBox::new(error_ptr) };
+    /// Parse an error into an [`X11Error`], naming the extension and
+    /// request it's for if they're known.
+    ///
+    /// Errors claimed by a custom extension parser come back as `Err`.
+    fn parse_x11_error(&self, error: &[u8; 32]) -> Result<X11Error> {
+        if let Some(error) = self.custom_extensions.parse_error(error) {
+            return Err(error);
+        }
 
-        // parse it
-        X11Error::try_parse(&*error_boxed, &self.extension_manager)
-            .map_or_else(Error::make_parse_error, Error::from)
+        X11Error::try_parse(error, &self.extension_manager).map_err(Error::make_parse_error)
     }
 
     /// Poll for a reply.
     fn poll_for_reply_impl(&self, seq: u64) -> Result<Option<XcbReply>> {
+        self.poll_for_reply_inner(seq, || self.has_fds.remove(seq))
+    }
+
+    /// Poll for a reply, using exclusive access to skip locking the set of
+    /// replies with file descriptors.
+    pub(crate) fn poll_for_reply_mut(&mut self, seq: u64) -> Result<Option<RawReply>> {
+        let has_fds = self.has_fds.contains_mut(seq);
+        let reply = self.poll_for_reply_inner(seq, || has_fds);
+        if has_fds && !matches!(reply, Ok(None)) {
+            self.has_fds.remove_mut(seq);
+        }
+
+        reply?.map(RawReply::try_from).transpose()
+    }
+
+    /// Drop the replies with file descriptors that were sent so long
+    /// before the server's current request that nobody is going to wait
+    /// for them, closing the descriptors.
+    ///
+    /// Otherwise, their sequence numbers would be tracked for as long as
+    /// the connection lives.
+    fn collect_stale_fd_replies(&self) {
+        let horizon = match self.last_sequence_processed().checked_sub(STALE_FD_REPLIES) {
+            Some(horizon) => horizon,
+            None => return,
+        };
+
+        for seq in self.has_fds.remove_before(horizon) {
+            // the server has answered later requests, so the reply or its
+            // error has already arrived; dropping it closes the fds
+            let _ = self.poll_for_reply_inner(seq, || true);
+        }
+    }
+
+    /// Poll for a reply, calling `has_fds` to tell whether it carries file
+    /// descriptors once it or its error arrives.
+    fn poll_for_reply_inner(
+        &self,
+        seq: u64,
+        has_fds: impl FnOnce() -> bool,
+    ) -> Result<Option<XcbReply>> {
         // call poll_for_reply()
         let mut reply = null_mut();
         let mut error = null_mut();
@@ -458,25 +2454,61 @@ impl XcbDisplay {
 
         // wrap the c_void into a reply type if we have it
         let reply = match (reply.is_null(), error.is_null()) {
-            (true, true) => return Ok(None),
+            // libxcb reports a broken connection the same way as a reply
+            // that was discarded
+            (true, true) => return self.take_error().map_or(Ok(None), Err),
             (false, true) => {
                 // got back a reply
                 unsafe { wrap_reply(reply) }
             }
             (true, false) => {
-                // got back an error
+                // got back an error, so no fds will arrive either
+                has_fds();
                 return Err(unsafe { self.wrap_error(error) });
             }
             (false, false) => panic!("reply and error are both non-null"),
         };
 
-        let fds = unsafe { self.extract_fds(reply.as_ref(), seq) };
+        let fds = unsafe { self.extract_fds(reply.as_ref(), has_fds()) };
+        self.processed(seq);
+        self.request_hooks.replied(seq, reply.len());
+        self.counters.reply_received();
+
+        #[cfg(feature = "diagnostics")]
+        diagnostics::reply_received(&reply, seq);
 
         Ok(Some(XcbReply { reply, fds }))
     }
 
     // Wait for a reply.
     fn wait_for_reply_impl(&self, seq: u64) -> Result<XcbReply> {
+        self.wait_for_reply_inner(seq, || self.has_fds.remove(seq))
+    }
+
+    /// Wait for a reply, using exclusive access to skip locking the set of
+    /// replies with file descriptors.
+    pub(crate) fn wait_for_reply_mut(&mut self, seq: u64) -> Result<RawReply> {
+        let has_fds = self.has_fds.remove_mut(seq);
+
+        self.wait_for_reply_inner(seq, || has_fds)
+            .and_then(RawReply::try_from)
+    }
+
+    /// Wait for a reply, calling `has_fds` to tell whether it carries file
+    /// descriptors once it or its error arrives.
+    fn wait_for_reply_inner(&self, seq: u64, has_fds: impl FnOnce() -> bool) -> Result<XcbReply> {
+        self.wait_for_reply_or_raw_error(seq, has_fds)?
+            .map_err(|error| self.convert_error(&error))
+    }
+
+    /// Wait for a reply, returning the error it caused without parsing it.
+    fn wait_for_reply_or_raw_error(
+        &self,
+        seq: u64,
+        has_fds: impl FnOnce() -> bool,
+    ) -> Result<core::result::Result<XcbReply, CBox<[u8; 32]>>> {
+        self.flush_before_wait()?;
+
         // call wait_for_reply()
         let mut error = null_mut();
 
@@ -490,13 +2522,20 @@ impl XcbDisplay {
             (false, true) => {
                 // reply is non-null, return it
                 let reply = unsafe { wrap_reply(reply) };
-                let fds = unsafe { self.extract_fds(reply.as_ref(), seq) };
+                let fds = unsafe { self.extract_fds(reply.as_ref(), has_fds()) };
+                self.processed(seq);
+                self.request_hooks.replied(seq, reply.len());
+                self.counters.reply_received();
+
+                #[cfg(feature = "diagnostics")]
+                diagnostics::reply_received(&reply, seq);
 
-                Ok(XcbReply { reply, fds })
+                Ok(Ok(XcbReply { reply, fds }))
             }
             (true, false) => {
-                // error is non-null
-                Err(unsafe { self.wrap_error(error) })
+                // error is non-null, so no fds will arrive either
+                has_fds();
+                Ok(Err(unsafe { self.take_raw_error(error) }))
             }
             (false, false) => {
                 panic!("reply and error are both non-null")
@@ -505,15 +2544,193 @@ impl XcbDisplay {
     }
 
     fn check_for_error_impl(&self, seq: u64) -> Result<()> {
-        let seq = VoidCookie { sequence: seq as _ };
-        let err = unsafe { xcb().xcb_request_check(self.as_ptr(), seq) };
+        match self.check_request_inner(seq)? {
+            Some(error) => Err(self.convert_error(&error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a request, returning the error it caused without parsing it.
+    fn check_request_inner(&self, seq: u64) -> Result<Option<CBox<[u8; 32]>>> {
+        self.flush_before_wait()?;
+
+        let cookie = VoidCookie { sequence: seq as _ };
+        let err = unsafe { xcb().xcb_request_check(self.as_ptr(), cookie) };
 
         if err.is_null() {
-            return Ok(());
+            // libxcb reports a broken connection the same way as success
+            return match self.take_error() {
+                Some(err) => Err(err),
+                None => {
+                    self.processed(seq);
+                    self.request_hooks.forget(seq);
+                    Ok(None)
+                }
+            };
+        }
+
+        Ok(Some(unsafe { self.take_raw_error(err) }))
+    }
+}
+
+#[cfg(all(unix, feature = "std"))]
+impl XcbDisplay {
+    /// Send a request along with file descriptors that stay owned by the
+    /// caller.
+    ///
+    /// Sending a request hands its file descriptors over to `libxcb`,
+    /// which closes them once they're written. Each of `fds` is
+    /// duplicated instead, and the duplicates are sent after any the
+    /// request already carries. Whether or not sending succeeds, the
+    /// duplicates are closed and `fds` are left open.
+    pub fn send_request_with_borrowed_fds(
+        &self,
+        mut request: RawRequest<'_, '_>,
+        fds: &[c_int],
+    ) -> Result<u64> {
+        let (_, owned) = request.mut_parts();
+        owned.reserve(fds.len());
+
+        for &fd in fds {
+            let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 0) };
+            if dup == -1 {
+                // the duplicates made so far are closed with the request
+                return Err(io::Error::last_os_error().into());
+            }
+
+            owned.push(breadx::Fd::new(dup));
+        }
+
+        self.send_request_impl(request)
+    }
+
+    /// Wait for an event, giving up once `timeout` has passed.
+    ///
+    /// Returns `None` if no event arrived in time, so that the caller can
+    /// run animation ticks or other periodic work before waiting again.
+    /// Like [`Display::wait_for_event`], this only flushes if the flush
+    /// policy says to.
+    ///
+    /// The connection's socket is polled until it has data, so an event
+    /// read by another thread sharing the connection may not be noticed
+    /// until `timeout` has passed.
+    ///
+    /// [`Display::wait_for_event`]: breadx::display::Display::wait_for_event
+    pub fn wait_for_event_timeout(&self, timeout: Duration) -> Result<Option<Event>> {
+        self.wait_for_event_until(Instant::now() + timeout)
+    }
+
+    /// Wait for an event, giving up once `deadline` has passed.
+    ///
+    /// This is [`wait_for_event_timeout`] with an absolute deadline, which
+    /// suits loops paced to frames: the deadline can be the time the next
+    /// frame is due, and doesn't drift however many events are handled
+    /// before it. If `deadline` has already passed, this only returns an
+    /// event that can be had without waiting.
+    ///
+    /// [`wait_for_event_timeout`]: XcbDisplay::wait_for_event_timeout
+    pub fn wait_for_event_until(&self, deadline: Instant) -> Result<Option<Event>> {
+        self.flush_before_wait()?;
+
+        loop {
+            if let Some((event, _)) = self.poll_for_event_impl()? {
+                return Ok(Some(event));
+            }
+
+            if !self.wait_readable(deadline)? {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Wait for the reply to a request, giving up once `timeout` has
+    /// passed.
+    ///
+    /// Returns `None` if the reply didn't arrive in time. It can still be
+    /// waited on or polled for afterwards. The output buffer is flushed
+    /// first, since `libxcb` only writes the request out by itself when it
+    /// blocks on the reply.
+    pub fn wait_for_reply_timeout(&self, seq: u64, timeout: Duration) -> Result<Option<RawReply>> {
+        self.flush_impl()?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(reply) = self.poll_for_reply_impl(seq)? {
+                return RawReply::try_from(reply).map(Some);
+            }
+
+            if !self.wait_readable(deadline)? {
+                return Ok(None);
+            }
         }
+    }
+
+    /// Flush to the server, unless writing to the socket would block.
+    ///
+    /// Returns `false` without flushing if the socket can't be written to
+    /// right now, such as when the server is behind on reading what's been
+    /// sent. Event loops can try again once the connection's file
+    /// descriptor is writable, instead of stalling inside [`flush`].
+    ///
+    /// A writable socket only has room for some of the output, so if
+    /// `libxcb` has a lot buffered, flushing can still block until the
+    /// server has read the rest.
+    ///
+    /// [`flush`]: breadx::display::Display::flush
+    pub fn try_flush(&self) -> Result<bool> {
+        let mut pollfd = libc::pollfd {
+            fd: self.get_fd(),
+            events: libc::POLLOUT,
+            revents: 0,
+        };
+
+        match unsafe { libc::poll(&mut pollfd, 1, 0) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    Ok(false)
+                } else {
+                    Err(err.into())
+                }
+            }
+            0 => Ok(false),
+            _ => {
+                // errors on the socket come out of the flush
+                self.flush_impl()?;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Wait until the socket can be read from, returning `false` if
+    /// `deadline` passes first.
+    fn wait_readable(&self, deadline: Instant) -> Result<bool> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(false);
+        }
+
+        // round up, so that we don't spin for the last millisecond
+        let millis = (deadline - now + Duration::from_nanos(999_999)).as_millis();
+        let mut pollfd = libc::pollfd {
+            fd: self.get_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
 
-        let err = unsafe { self.wrap_error(err) };
-        Err(err)
+        match unsafe { libc::poll(&mut pollfd, 1, millis.min(c_int::MAX as u128) as c_int) } {
+            -1 => {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    // try again with what's left of the timeout
+                    Ok(true)
+                } else {
+                    Err(err.into())
+                }
+            }
+            0 => Ok(false),
+            _ => Ok(true),
+        }
     }
 }
 
@@ -534,6 +2751,35 @@ impl XcbDisplay {
         // or do we? take another look once i/o safety lands
         unsafe { Self::connect_to_fd(socket.as_raw_fd(), auth_name, auth_data, screen) }
     }
+
+    /// Connect to an existing socket, taking ownership of it.
+    ///
+    /// The authorization for the server at the other end of the socket is
+    /// looked up in the Xauthority file, from `$XAUTHORITY` or
+    /// `~/.Xauthority`, the same way `libxcb` does for sockets it opens.
+    /// If nothing is found, no authorization is sent.
+    ///
+    /// `OwnedFd`, `UnixStream` and `TcpStream` can all be passed here.
+    pub fn connect_to_owned_socket(socket: impl IntoRawFd, screen: usize) -> Result<Self> {
+        let fd = socket.into_raw_fd();
+
+        let auth = match crate::connect::peer(fd) {
+            Some((family, address, display)) => {
+                crate::xauth::authorize(fd, family, &address, display)
+            }
+            None => Ok((Vec::new(), Vec::new())),
+        };
+        let (name, data) = match auth {
+            Ok(auth) => auth,
+            Err(err) => {
+                unsafe { libc::close(fd) };
+                return Err(err);
+            }
+        };
+
+        // SAFETY: we own the socket, and libxcb takes it over
+        unsafe { Self::connect_to_fd(fd, &name, &data, screen) }
+    }
 }
 
 #[cfg(all(unix, feature = "to_socket"))]
@@ -553,11 +2799,13 @@ impl DisplayBase for XcbDisplay {
     }
 
     fn poll_for_event(&mut self) -> Result<Option<Event>> {
-        self.poll_for_event_impl()
+        Ok(self.poll_for_event_impl()?.map(|(event, _)| event))
     }
 
     fn poll_for_reply_raw(&mut self, seq: u64) -> Result<Option<RawReply>> {
-        self.poll_for_reply_impl(seq).map(|o| o.map(Into::into))
+        self.poll_for_reply_impl(seq)?
+            .map(RawReply::try_from)
+            .transpose()
     }
 }
 
@@ -571,11 +2819,13 @@ impl DisplayBase for &XcbDisplay {
     }
 
     fn poll_for_event(&mut self) -> Result<Option<Event>> {
-        self.poll_for_event_impl()
+        Ok(self.poll_for_event_impl()?.map(|(event, _)| event))
     }
 
     fn poll_for_reply_raw(&mut self, seq: u64) -> Result<Option<RawReply>> {
-        self.poll_for_reply_impl(seq).map(|o| o.map(Into::into))
+        self.poll_for_reply_impl(seq)?
+            .map(RawReply::try_from)
+            .transpose()
     }
 }
 
@@ -601,11 +2851,11 @@ impl Display for XcbDisplay {
     }
 
     fn wait_for_event(&mut self) -> Result<Event> {
-        self.wait_for_event_impl()
+        self.wait_for_event_impl().map(|(event, _)| event)
     }
 
     fn wait_for_reply_raw(&mut self, seq: u64) -> Result<RawReply> {
-        self.wait_for_reply_impl(seq).map(Into::into)
+        self.wait_for_reply_impl(seq).and_then(RawReply::try_from)
     }
 
     fn check_for_error(&mut self, seq: u64) -> Result<()> {
@@ -635,11 +2885,11 @@ impl Display for &XcbDisplay {
     }
 
     fn wait_for_event(&mut self) -> Result<Event> {
-        self.wait_for_event_impl()
+        self.wait_for_event_impl().map(|(event, _)| event)
     }
 
     fn wait_for_reply_raw(&mut self, seq: u64) -> Result<RawReply> {
-        self.wait_for_reply_impl(seq).map(Into::into)
+        self.wait_for_reply_impl(seq).and_then(RawReply::try_from)
     }
 
     fn check_for_error(&mut self, seq: u64) -> Result<()> {
@@ -657,6 +2907,31 @@ impl Drop for XcbDisplay {
     }
 }
 
+unsafe fn wrap_event(event: *mut GenericEvent) -> CBox<[u8]> {
+    // inspect the header for info
+    let header = event as *const GenericEvent as *const [u8; 32];
+    let evbytes = event as *mut u8;
+    let header = &*header;
+
+    // tell if we're dealing with a generic event
+    if header[0] & 0x7F != breadx::protocol::xproto::GE_GENERIC_EVENT {
+        let event = slice_from_raw_parts_mut(evbytes, 32);
+        return unsafe { CBox::new(event) };
+    }
+
+    // read the length
+    let xlen = u32::from_ne_bytes([header[4], header[5], header[6], header[7]]);
+    let length = 32 + xlen as usize * 4;
+
+    // xcb adds the sequence number for the event at 32 bytes, between
+    // the header and the rest of the event; move the header over it
+    // rather than moving the rest back, which may be much longer
+    core::ptr::copy(evbytes, evbytes.add(4), 32);
+
+    let event = slice_from_raw_parts_mut(evbytes.add(4), length);
+    unsafe { CBox::with_offset(event, 4) }
+}
+
 unsafe fn wrap_reply(reply: *mut c_void) -> CBox<[u8]> {
     // determine the total length
     let header = unsafe { slice::from_raw_parts(reply as *mut u8 as *const u8, 32) };
@@ -670,34 +2945,168 @@ unsafe fn wrap_reply(reply: *mut c_void) -> CBox<[u8]> {
     unsafe { CBox::new(reply) }
 }
 
+/// An event that hasn't been parsed.
+///
+/// See [`XcbDisplay::poll_for_raw_event`].
+#[derive(Debug)]
+pub struct RawEvent {
+    data: Box<[u8]>,
+    fds: Vec<breadx::Fd>,
+    sequence: u64,
+}
+
+impl RawEvent {
+    /// Get the bytes of the event.
+    ///
+    /// Generic events are longer than 32 bytes, and include all of their
+    /// data.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Get the file descriptors that came with the event.
+    ///
+    /// This is currently always empty. `libxcb`, up to and including 1.15,
+    /// only hands out the file descriptors that come with replies, through
+    /// `xcb_get_reply_fds`; there is no counterpart for events, in either
+    /// the main queue or a special event queue. Any descriptors the server
+    /// sends with an event stay with `libxcb` until the connection is
+    /// closed. This will be filled in if `libxcb` gains a way to take them.
+    pub fn fds(&self) -> &[breadx::Fd] {
+        &self.fds
+    }
+
+    /// Get the sequence number of the last request the server processed
+    /// before sending the event.
+    ///
+    /// Unlike the 16-bit sequence number in the event itself, this is not
+    /// truncated.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Take the bytes and file descriptors of the event.
+    pub fn into_parts(self) -> (Box<[u8]>, Vec<breadx::Fd>) {
+        (self.data, self.fds)
+    }
+}
+
+/// A reply, still in the buffer `libxcb` allocated for it.
+///
+/// `breadx` keeps replies in buffers from the Rust allocator, so handing
+/// one over as a [`RawReply`] copies it. Large replies, such as the ones
+/// to `GetImage`, can be read or parsed from here instead. The buffer is
+/// freed when this is dropped.
+///
+/// See [`XcbDisplay::wait_for_xcb_reply`].
 pub struct XcbReply {
     reply: CBox<[u8]>,
-    fds: Vec<c_int>,
+    fds: ReplyFds,
 }
 
-impl From<XcbReply> for RawReply {
-    fn from(xcr: XcbReply) -> Self {
-        let XcbReply { reply, fds } = xcr;
+unsafe impl Send for XcbReply {}
+unsafe impl Sync for XcbReply {}
 
-        let data = reply.clone_slice().into_boxed_slice();
-        let fds = fds
-            .into_iter()
-            .map(|fd| {
-                cfg_if::cfg_if! {
-                    if #[cfg(all(unix, feature = "std"))] {
-                        breadx::Fd::new(fd)
-                    } else {
-                        let _ = fd;
-                        unreachable!()
-                    }
+impl XcbReply {
+    /// Get the bytes of the reply.
+    pub fn data(&self) -> &[u8] {
+        &self.reply
+    }
+
+    /// Parse the reply straight from its buffer.
+    pub fn parse<T: TryParse>(&self) -> Result<T> {
+        T::try_parse(self.data())
+            .map(|(reply, _)| reply)
+            .map_err(Error::make_parse_error)
+    }
+
+    /// Take the file descriptors that came with the reply.
+    ///
+    /// Any that aren't taken are closed when the reply is dropped.
+    ///
+    /// These are `breadx` file descriptors rather than `OwnedFd`s, since
+    /// `OwnedFd` needs Rust 1.63 and this crate supports 1.49. They're
+    /// closed on drop all the same, and `into_raw_fd` takes one out.
+    #[cfg(all(unix, feature = "std"))]
+    pub fn take_fds(&mut self) -> Vec<breadx::Fd> {
+        mem::take(&mut self.fds).into_breadx()
+    }
+}
+
+impl TryFrom<XcbReply> for RawReply {
+    type Error = Error;
+
+    /// Copy the reply into a buffer `breadx` can own.
+    fn try_from(mut xcr: XcbReply) -> Result<Self> {
+        let fds = mem::take(&mut xcr.fds);
+        let data = xcr.reply.clone_slice().into_boxed_slice();
+
+        cfg_if::cfg_if! {
+            if #[cfg(all(unix, feature = "std"))] {
+                let fds = fds.into_breadx();
+            } else {
+                // we can't hand these fds to breadx, and dropping them
+                // closes them
+                if !fds.is_empty() {
+                    return Err(fd_passing_unsupported());
                 }
-            })
-            .collect::<Vec<breadx::Fd>>();
 
-        RawReply::new(data, fds)
+                let fds = Vec::new();
+            }
+        }
+
+        Ok(RawReply::new(data, fds))
     }
 }
 
+/// Whether file descriptors can be passed over the connection.
+///
+/// `breadx` can only represent fds with the standard library on Unix.
+const FD_PASSING: bool = cfg!(all(unix, feature = "std"));
+
+/// The number of I/O vectors a request can be sent with before they're
+/// kept on the heap.
+///
+/// `breadx` splits requests into a few slices at most, and with the two
+/// slots `libxcb` may write into, they fit here.
+const SMALL_IOVECS: usize = 8;
+
+/// The number of file descriptors a request can pass before they're kept
+/// on the heap.
+const SMALL_FDS: usize = 4;
+
+/// The number of requests the server can process after one whose reply
+/// carries file descriptors before that reply is dropped uncollected.
+const STALE_FD_REPLIES: u64 = 1 << 20;
+
+/// The length in bytes from which requests are written straight to the
+/// socket.
+///
+/// `libxcb` copies whatever fits into its 16 KiB output buffer.
+const DIRECT_WRITE_BYTES: usize = 4096;
+
+/// The number of requests since the last one with a reply before requests
+/// stop being written straight to the socket.
+#[cfg(all(unix, feature = "std"))]
+const DIRECT_WRITE_SEQUENCES: u64 = 1 << 15;
+
+/// The buffers a request is sent from, besides its own.
+///
+/// A batch of requests reuses the same ones, so only the first request
+/// that doesn't fit on the stack spills them onto the heap.
+#[derive(Default)]
+struct SendScratch {
+    /// The I/O vectors for requests sent with `libxcb`'s extension
+    /// records.
+    iovecs: SmallVec<[Iovec; SMALL_IOVECS]>,
+    /// The file descriptors the request passes.
+    fds: SmallVec<[c_int; SMALL_FDS]>,
+}
+
+fn fd_passing_unsupported() -> Error {
+    Error::make_msg("passing file descriptors is not supported on this platform")
+}
+
 fn auth_info(auth_name: &[u8], auth_data: &[u8]) -> AuthInfo {
     AuthInfo {
         namelen: auth_name.len() as _,
@@ -706,10 +3115,3 @@ fn auth_info(auth_name: &[u8], auth_data: &[u8]) -> AuthInfo {
         data: auth_data.as_ptr() as *const _ as *mut _,
     }
 }
-
-/// HashSet type with a slight speedup in comparison to the standard library
-/// implementation and the `ahash` crate used in the `breadx` crate.
-///
-/// Collision chances are higher, but given that the `has_fds` hash set
-/// isn't used that often, it shouldn't come up.
-type HashSet<T> = hashbrown::HashSet<T, core::hash::BuildHasherDefault<rustc_hash::FxHasher>>;
@@ -4,12 +4,32 @@
 //         https://www.boost.org/LICENSE_1_0.txt)
 
 use super::{
-    AuthInfo, Connection, GenericError, GenericEvent, Iovec, ProtocolRequest, Setup, VoidCookie,
-    XcbFfi,
+    AuthInfo, Connection, Extension, GenericError, GenericEvent, Iovec, ProtocolRequest,
+    QueryExtensionReply, ReturnSocket, Setup, SpecialEvent, VoidCookie, XcbFfi,
 };
+use crate::dl::open_library;
 use libc::{c_char, c_int, c_void};
 use libloading::Library;
 
+/// The names `libxcb` is looked for under.
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+const LIBXCB: &[&str] = &["libxcb.so.1", "libxcb.so", "/usr/local/lib/libxcb.so.1"];
+#[cfg(target_os = "openbsd")]
+const LIBXCB: &[&str] = &["libxcb.so", "/usr/X11R6/lib/libxcb.so"];
+#[cfg(target_os = "netbsd")]
+const LIBXCB: &[&str] = &[
+    "libxcb.so",
+    "/usr/X11R7/lib/libxcb.so",
+    "/usr/pkg/lib/libxcb.so",
+];
+#[cfg(not(any(
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+const LIBXCB: &[&str] = &["libxcb.so.1", "libxcb.so"];
+
 pub(crate) struct DynamicFfi {
     _library: Library,
     funcs: Funcs,
@@ -17,10 +37,11 @@ pub(crate) struct DynamicFfi {
 
 impl DynamicFfi {
     pub(crate) fn load() -> Self {
-        let path = "libxcb.so.1";
-
-        let library = unsafe { Library::new(path) }.expect("Unable to open libxcb dynamically");
+        Self::from_library(open_library(LIBXCB))
+    }
 
+    /// Use a `libxcb` that has already been loaded.
+    pub(crate) fn from_library(library: Library) -> Self {
         let funcs = unsafe { Funcs::load(&library) };
 
         Self {
@@ -91,8 +112,26 @@ define_funcs! {
     xcb_get_setup(conn: *mut Connection) -> *mut Setup,
     xcb_generate_id(conn: *mut Connection) -> u32,
     xcb_get_maximum_request_length(conn: *mut Connection) -> u32,
+    xcb_prefetch_maximum_request_length(conn: *mut Connection) -> (),
+    xcb_total_read(conn: *mut Connection) -> u64,
+    xcb_total_written(conn: *mut Connection) -> u64,
     xcb_wait_for_event(conn: *mut Connection) -> *mut GenericEvent,
     xcb_poll_for_event(conn: *mut Connection) -> *mut GenericEvent,
+    xcb_poll_for_queued_event(conn: *mut Connection) -> *mut GenericEvent,
+    xcb_register_for_special_xge(
+        conn: *mut Connection,
+        ext: *mut Extension,
+        eid: u32,
+        stamp: *mut u32
+    ) -> *mut SpecialEvent,
+    xcb_unregister_for_special_event(conn: *mut Connection, se: *mut SpecialEvent) -> (),
+    xcb_wait_for_special_event(conn: *mut Connection, se: *mut SpecialEvent) -> *mut GenericEvent,
+    xcb_poll_for_special_event(conn: *mut Connection, se: *mut SpecialEvent) -> *mut GenericEvent,
+    xcb_prefetch_extension_data(conn: *mut Connection, ext: *mut Extension) -> (),
+    xcb_get_extension_data(
+        conn: *mut Connection,
+        ext: *mut Extension
+    ) -> *const QueryExtensionReply,
     xcb_send_request64(
         conn: *mut Connection,
         flags: c_int,
@@ -107,6 +146,19 @@ define_funcs! {
         num_fds: c_int,
         fds: *mut c_int
     ) -> u64,
+    xcb_take_socket(
+        conn: *mut Connection,
+        return_socket: ReturnSocket,
+        closure: *mut c_void,
+        flags: c_int,
+        sent: *mut u64
+    ) -> c_int,
+    xcb_writev(
+        conn: *mut Connection,
+        vector: *mut Iovec,
+        count: c_int,
+        requests: u64
+    ) -> c_int,
     xcb_wait_for_reply64(
         conn: *mut Connection,
         seq: u64,
@@ -121,5 +173,6 @@ define_funcs! {
     xcb_request_check(
         conn: *mut Connection,
         request: VoidCookie
-    ) -> *mut GenericError
+    ) -> *mut GenericError,
+    xcb_discard_reply64(conn: *mut Connection, seq: u64) -> ()
 }
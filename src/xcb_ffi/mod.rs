@@ -3,8 +3,8 @@
 //       (See accompanying file LICENSE or copy at
 //         https://www.boost.org/LICENSE_1_0.txt)
 
-use crate::sync::Lazy;
-use libc::{c_char, c_int, c_uint, c_void};
+use crate::sync::{call_once, OnceCell};
+use libc::{c_char, c_int, c_void};
 
 #[cfg(feature = "dl")]
 mod dynamic_link;
@@ -32,10 +32,42 @@ pub(crate) unsafe trait XcbFfi {
     unsafe fn xcb_generate_id(&self, conn: *mut Connection) -> u32;
     unsafe fn xcb_flush(&self, conn: *mut Connection) -> c_int;
     unsafe fn xcb_get_maximum_request_length(&self, conn: *mut Connection) -> u32;
+    unsafe fn xcb_prefetch_maximum_request_length(&self, conn: *mut Connection);
+    unsafe fn xcb_total_read(&self, conn: *mut Connection) -> u64;
+    unsafe fn xcb_total_written(&self, conn: *mut Connection) -> u64;
 
     // events
     unsafe fn xcb_wait_for_event(&self, conn: *mut Connection) -> *mut GenericEvent;
     unsafe fn xcb_poll_for_event(&self, conn: *mut Connection) -> *mut GenericEvent;
+    unsafe fn xcb_poll_for_queued_event(&self, conn: *mut Connection) -> *mut GenericEvent;
+
+    // special events
+    unsafe fn xcb_register_for_special_xge(
+        &self,
+        conn: *mut Connection,
+        ext: *mut Extension,
+        eid: u32,
+        stamp: *mut u32,
+    ) -> *mut SpecialEvent;
+    unsafe fn xcb_unregister_for_special_event(&self, conn: *mut Connection, se: *mut SpecialEvent);
+    unsafe fn xcb_wait_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent;
+    unsafe fn xcb_poll_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent;
+
+    // extensions
+    unsafe fn xcb_prefetch_extension_data(&self, conn: *mut Connection, ext: *mut Extension);
+    unsafe fn xcb_get_extension_data(
+        &self,
+        conn: *mut Connection,
+        ext: *mut Extension,
+    ) -> *const QueryExtensionReply;
 
     // requests api
     unsafe fn xcb_send_request64(
@@ -54,6 +86,23 @@ pub(crate) unsafe trait XcbFfi {
         num_fds: c_int,
         fds: *mut c_int,
     ) -> u64;
+    #[cfg_attr(not(all(unix, feature = "std")), allow(dead_code))]
+    unsafe fn xcb_take_socket(
+        &self,
+        conn: *mut Connection,
+        return_socket: ReturnSocket,
+        closure: *mut c_void,
+        flags: c_int,
+        sent: *mut u64,
+    ) -> c_int;
+    #[cfg_attr(not(all(unix, feature = "std")), allow(dead_code))]
+    unsafe fn xcb_writev(
+        &self,
+        conn: *mut Connection,
+        vector: *mut Iovec,
+        count: c_int,
+        requests: u64,
+    ) -> c_int;
     unsafe fn xcb_wait_for_reply64(
         &self,
         conn: *mut Connection,
@@ -72,6 +121,7 @@ pub(crate) unsafe trait XcbFfi {
         conn: *mut Connection,
         cookie: VoidCookie,
     ) -> *mut GenericError;
+    unsafe fn xcb_discard_reply64(&self, conn: *mut Connection, seq: u64);
 }
 
 /// Opaque type for the `libxcb` connection.
@@ -80,10 +130,8 @@ pub(crate) struct Connection {
     _opaque_type: [u8; 0],
 }
 
-#[repr(C)]
-pub(crate) struct VoidCookie {
-    pub(crate) sequence: c_uint,
-}
+/// `xcb_void_cookie_t`, which is shared with the public cookie type.
+pub(crate) use crate::cookie::XcbCookie as VoidCookie;
 
 /// Type for authorization info.
 #[repr(C)]
@@ -106,6 +154,30 @@ pub(crate) struct Extension {
     _opaque_type: [u8; 0],
 }
 
+/// `xcb_query_extension_reply_t`, as `libxcb` caches it.
+#[repr(C)]
+pub(crate) struct QueryExtensionReply {
+    pub(crate) response_type: u8,
+    pub(crate) pad0: u8,
+    pub(crate) sequence: u16,
+    pub(crate) length: u32,
+    pub(crate) present: u8,
+    pub(crate) major_opcode: u8,
+    pub(crate) first_event: u8,
+    pub(crate) first_error: u8,
+}
+
+/// The callback `libxcb` calls to get the socket back from whoever took
+/// it.
+#[cfg_attr(not(all(unix, feature = "std")), allow(dead_code))]
+pub(crate) type ReturnSocket = Option<unsafe extern "C" fn(closure: *mut c_void)>;
+
+/// A queue of special events.
+#[repr(C)]
+pub(crate) struct SpecialEvent {
+    _opaque_type: [u8; 0],
+}
+
 #[cfg(unix)]
 pub(crate) use libc::iovec as Iovec;
 
@@ -138,23 +210,58 @@ pub(crate) struct GenericEvent {
 }
 
 #[cfg(not(feature = "dl"))]
-type Impl = static_link::StaticFfi;
+type Backend = static_link::StaticFfi;
 #[cfg(feature = "dl")]
-type Impl = dynamic_link::DynamicFfi;
+type Backend = dynamic_link::DynamicFfi;
+
+#[cfg(not(feature = "fault_injection"))]
+type Impl = Backend;
+#[cfg(feature = "fault_injection")]
+type Impl = crate::fault::FaultFfi<Backend>;
 
 /// Global object used to make `libxcb` calls.
-static XCB: Lazy<Impl> = Lazy::new(|| {
+///
+/// This is set the first time it's used, unless the application supplies
+/// a library beforehand.
+static XCB: OnceCell<Impl> = OnceCell::new();
+
+fn wrap(backend: Backend) -> Impl {
     cfg_if::cfg_if! {
-        if #[cfg(feature = "dl")] {
-            dynamic_link::DynamicFfi::load()
+        if #[cfg(feature = "fault_injection")] {
+            crate::fault::FaultFfi::new(backend)
         } else {
-            static_link::StaticFfi
+            backend
         }
     }
-});
+}
 
 pub(crate) fn xcb() -> &'static Impl {
-    &*XCB
+    call_once(&XCB, || {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "dl")] {
+                wrap(dynamic_link::DynamicFfi::load())
+            } else {
+                wrap(static_link::StaticFfi)
+            }
+        }
+    })
+}
+
+/// Use an already-loaded `libxcb` instead of loading one.
+///
+/// Gives the library back if `libxcb` has already been loaded.
+#[cfg(feature = "dl")]
+pub(crate) fn use_library(library: libloading::Library) -> Result<(), libloading::Library> {
+    let mut library = Some(library);
+    call_once(&XCB, || {
+        let library = library.take().unwrap();
+        wrap(dynamic_link::DynamicFfi::from_library(library))
+    });
+
+    match library {
+        Some(library) => Err(library),
+        None => Ok(()),
+    }
 }
 
 pub(crate) mod flags {
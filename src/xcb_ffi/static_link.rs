@@ -6,8 +6,8 @@
 #![cfg(not(feature = "dl"))]
 
 use super::{
-    AuthInfo, Connection, GenericError, GenericEvent, Iovec, ProtocolRequest, Setup, VoidCookie,
-    XcbFfi,
+    AuthInfo, Connection, Extension, GenericError, GenericEvent, Iovec, ProtocolRequest,
+    QueryExtensionReply, ReturnSocket, Setup, SpecialEvent, VoidCookie, XcbFfi,
 };
 use libc::{c_char, c_int, c_void};
 
@@ -55,6 +55,18 @@ unsafe impl XcbFfi for StaticFfi {
         xcb_get_maximum_request_length(conn)
     }
 
+    unsafe fn xcb_prefetch_maximum_request_length(&self, conn: *mut Connection) {
+        xcb_prefetch_maximum_request_length(conn)
+    }
+
+    unsafe fn xcb_total_read(&self, conn: *mut Connection) -> u64 {
+        xcb_total_read(conn)
+    }
+
+    unsafe fn xcb_total_written(&self, conn: *mut Connection) -> u64 {
+        xcb_total_written(conn)
+    }
+
     unsafe fn xcb_get_setup(&self, conn: *mut Connection) -> *mut Setup {
         xcb_get_setup(conn)
     }
@@ -63,6 +75,56 @@ unsafe impl XcbFfi for StaticFfi {
         xcb_poll_for_event(conn)
     }
 
+    unsafe fn xcb_poll_for_queued_event(&self, conn: *mut Connection) -> *mut GenericEvent {
+        xcb_poll_for_queued_event(conn)
+    }
+
+    unsafe fn xcb_register_for_special_xge(
+        &self,
+        conn: *mut Connection,
+        ext: *mut Extension,
+        eid: u32,
+        stamp: *mut u32,
+    ) -> *mut SpecialEvent {
+        xcb_register_for_special_xge(conn, ext, eid, stamp)
+    }
+
+    unsafe fn xcb_unregister_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) {
+        xcb_unregister_for_special_event(conn, se)
+    }
+
+    unsafe fn xcb_wait_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent {
+        xcb_wait_for_special_event(conn, se)
+    }
+
+    unsafe fn xcb_poll_for_special_event(
+        &self,
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent {
+        xcb_poll_for_special_event(conn, se)
+    }
+
+    unsafe fn xcb_prefetch_extension_data(&self, conn: *mut Connection, ext: *mut Extension) {
+        xcb_prefetch_extension_data(conn, ext)
+    }
+
+    unsafe fn xcb_get_extension_data(
+        &self,
+        conn: *mut Connection,
+        ext: *mut Extension,
+    ) -> *const QueryExtensionReply {
+        xcb_get_extension_data(conn, ext)
+    }
+
     unsafe fn xcb_poll_for_reply64(
         &self,
         conn: *mut Connection,
@@ -95,6 +157,27 @@ unsafe impl XcbFfi for StaticFfi {
         xcb_send_request_with_fds64(conn, flags, iov, request, num_fds, fds)
     }
 
+    unsafe fn xcb_take_socket(
+        &self,
+        conn: *mut Connection,
+        return_socket: ReturnSocket,
+        closure: *mut c_void,
+        flags: c_int,
+        sent: *mut u64,
+    ) -> c_int {
+        xcb_take_socket(conn, return_socket, closure, flags, sent)
+    }
+
+    unsafe fn xcb_writev(
+        &self,
+        conn: *mut Connection,
+        vector: *mut Iovec,
+        count: c_int,
+        requests: u64,
+    ) -> c_int {
+        xcb_writev(conn, vector, count, requests)
+    }
+
     unsafe fn xcb_wait_for_event(&self, conn: *mut Connection) -> *mut GenericEvent {
         xcb_wait_for_event(conn)
     }
@@ -115,10 +198,15 @@ unsafe impl XcbFfi for StaticFfi {
     ) -> *mut GenericError {
         xcb_request_check(conn, cookie)
     }
+
+    unsafe fn xcb_discard_reply64(&self, conn: *mut Connection, seq: u64) {
+        xcb_discard_reply64(conn, seq)
+    }
 }
 
 // actual import
-#[link(name = "xcb")]
+// with the `static` feature, the build script links the archives
+#[cfg_attr(not(feature = "static"), link(name = "xcb"))]
 extern "C" {
     fn xcb_connect(display: *const c_char, screenp: *mut c_int) -> *mut Connection;
     fn xcb_connect_to_display_with_auth_info(
@@ -134,8 +222,32 @@ extern "C" {
     fn xcb_generate_id(conn: *mut Connection) -> u32;
     fn xcb_flush(conn: *mut Connection) -> c_int;
     fn xcb_get_maximum_request_length(conn: *mut Connection) -> u32;
+    fn xcb_prefetch_maximum_request_length(conn: *mut Connection);
+    fn xcb_total_read(conn: *mut Connection) -> u64;
+    fn xcb_total_written(conn: *mut Connection) -> u64;
     fn xcb_wait_for_event(conn: *mut Connection) -> *mut GenericEvent;
     fn xcb_poll_for_event(conn: *mut Connection) -> *mut GenericEvent;
+    fn xcb_poll_for_queued_event(conn: *mut Connection) -> *mut GenericEvent;
+    fn xcb_register_for_special_xge(
+        conn: *mut Connection,
+        ext: *mut Extension,
+        eid: u32,
+        stamp: *mut u32,
+    ) -> *mut SpecialEvent;
+    fn xcb_unregister_for_special_event(conn: *mut Connection, se: *mut SpecialEvent);
+    fn xcb_wait_for_special_event(
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent;
+    fn xcb_poll_for_special_event(
+        conn: *mut Connection,
+        se: *mut SpecialEvent,
+    ) -> *mut GenericEvent;
+    fn xcb_prefetch_extension_data(conn: *mut Connection, ext: *mut Extension);
+    fn xcb_get_extension_data(
+        conn: *mut Connection,
+        ext: *mut Extension,
+    ) -> *const QueryExtensionReply;
     fn xcb_send_request64(
         conn: *mut Connection,
         flags: c_int,
@@ -150,6 +262,16 @@ extern "C" {
         num_fds: c_int,
         fds: *mut c_int,
     ) -> u64;
+    #[cfg_attr(not(all(unix, feature = "std")), allow(dead_code))]
+    fn xcb_take_socket(
+        conn: *mut Connection,
+        return_socket: ReturnSocket,
+        closure: *mut c_void,
+        flags: c_int,
+        sent: *mut u64,
+    ) -> c_int;
+    #[cfg_attr(not(all(unix, feature = "std")), allow(dead_code))]
+    fn xcb_writev(conn: *mut Connection, vector: *mut Iovec, count: c_int, requests: u64) -> c_int;
     fn xcb_wait_for_reply64(
         conn: *mut Connection,
         seq: u64,
@@ -162,4 +284,5 @@ extern "C" {
         error: *mut *mut GenericError,
     ) -> c_int;
     fn xcb_request_check(conn: *mut Connection, cookie: VoidCookie) -> *mut GenericError;
+    fn xcb_discard_reply64(conn: *mut Connection, seq: u64);
 }
@@ -0,0 +1,50 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+use breadx::Error;
+use core::fmt;
+use std::io;
+
+/// Every resource ID the server gave this client has been used.
+///
+/// `libxcb` asks for a new range through the `XC-MISC` extension once
+/// the first one runs out, so this means the server either doesn't
+/// support the extension or had no IDs left to give. It is returned as a
+/// [`std::io::Error`] wrapped in a `breadx` error, and can be recovered
+/// with `as_io_error()` and `downcast_ref()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XidsExhausted {
+    pub(crate) xc_misc: bool,
+}
+
+impl XidsExhausted {
+    /// Tell whether the server supports `XC-MISC`.
+    ///
+    /// If it does, asking it for more IDs failed as well, and freeing
+    /// resources is the only way to get some back.
+    pub fn xc_misc_supported(&self) -> bool {
+        self.xc_misc
+    }
+}
+
+impl fmt::Display for XidsExhausted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.xc_misc {
+            f.write_str("out of resource IDs, and the server has no more to give")
+        } else {
+            f.write_str("out of resource IDs, and the server doesn't support XC-MISC")
+        }
+    }
+}
+
+impl std::error::Error for XidsExhausted {}
+
+impl From<XidsExhausted> for Error {
+    // io::Error::other is newer than our MSRV
+    #[allow(unknown_lints, clippy::io_other_error)]
+    fn from(err: XidsExhausted) -> Self {
+        io::Error::new(io::ErrorKind::Other, err).into()
+    }
+}
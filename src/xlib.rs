@@ -5,35 +5,89 @@
 
 use crate::{
     sync::{call_once, OnceCell},
-    xlib_ffi::{xlib, X11Ffi, XDisplay},
+    xlib_ffi::{
+        xlib, Ffi, X11Ffi, XDisplay, XVisualInfo, XlibFunctions, VISUAL_ID_MASK,
+        XCB_OWNS_EVENT_QUEUE, XLIB_OWNS_EVENT_QUEUE,
+    },
     XcbDisplay,
 };
 use __private::Sealed;
 use alloc::sync::Arc;
 use breadx::{
     display::{Display, DisplayBase, RawReply, RawRequest},
-    protocol::{xproto::Setup, Event},
+    protocol::{
+        xproto::{Setup, Visualid, Window},
+        Event,
+    },
     Error, Result,
 };
 use core::{
     cell::Cell,
     marker::PhantomData,
+    mem::MaybeUninit,
     ptr::{null, NonNull},
 };
 use cstr_core::CStr;
-use libc::c_void;
+use libc::{c_int, c_void};
 
 #[cfg(all(unix, feature = "to_socket"))]
 use std::os::unix::io::{AsRawFd, RawFd};
 
+#[cfg(all(debug_assertions, feature = "std"))]
+use std::thread::{self, ThreadId};
+
+#[cfg(all(debug_assertions, feature = "std"))]
+mod usage;
+
+mod events;
+pub use events::XlibEvent;
+
+/// In debug builds, mark a thread-unsafe display as in use by the current
+/// thread for the rest of the scope.
+macro_rules! enter {
+    ($this: expr) => {
+        #[cfg(all(debug_assertions, feature = "std"))]
+        let _guard = $this
+            .creator
+            .map(|creator| usage::enter($this.display.as_ptr(), creator));
+    };
+}
+
 /// A display that acts as a wrapper around a `libX11` display.
+///
+/// `XlibDisplay`, `&XlibDisplay` and, through `breadx`, `Box<XlibDisplay>`
+/// implement [`Display`]. A display kept in an `Arc` or `Rc` can be used
+/// by borrowing it, as in `&*display`.
 pub struct XlibDisplay<ThreadSafety> {
     xcb: XcbDisplay,
     display: NonNull<XDisplay>,
     disconnect: bool,
+    /// The `libX11` functions to call.
+    ffi: Ffi,
+    /// Which library reads events from the connection.
+    event_queue_owner: EventQueueOwner,
+    /// The thread that created this display, if its usage is checked.
+    #[cfg(all(debug_assertions, feature = "std"))]
+    creator: Option<ThreadId>,
     _phantom: PhantomData<ThreadSafety>,
 }
 
+/// The library that reads events from the connection.
+///
+/// Events can be read by either `libX11` or `libxcb`, but not both at
+/// once. `libX11` is the owner by default. When it is, events are taken
+/// out of the `libX11` event queue and converted back to their wire
+/// format before `breadx` parses them, so they can be shared with
+/// toolkits that use `libX11` directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventQueueOwner {
+    /// Events are read by `libX11`, and taken out of its event queue.
+    Xlib,
+    /// Events are read straight from `libxcb`. `libX11` functions that
+    /// read events, such as `XNextEvent`, must not be used.
+    Xcb,
+}
+
 /// Represents a type that can define the thread-safety for the `XlibDisplay`.'
 pub trait ThreadSafety: Sealed {
     /// If a function needs to be called to initialize this variant,
@@ -44,7 +98,8 @@ pub trait ThreadSafety: Sealed {
 /// The display is not thread safe.
 ///
 /// It is able to be shared between threads, but cannot be used
-/// concurrently.
+/// concurrently. In debug builds with the standard library enabled,
+/// concurrent use is detected and causes a panic.
 pub struct ThreadUnsafe {
     _private: PhantomData<Cell<()>>,
 }
@@ -82,17 +137,36 @@ impl<TS: ThreadSafety> XlibDisplay<TS> {
         // initialize thread safety if applicable
         TS::initialize()?;
 
+        Self::connect_impl(name, xlib())
+    }
+
+    /// Connect to the server using the given `display_name`, calling
+    /// `functions` instead of `libX11`.
+    pub fn connect_with_functions(
+        name: Option<&CStr>,
+        functions: &'static XlibFunctions,
+    ) -> Result<Self> {
+        // the functions may come from a different libX11 than ours, so
+        // initialize threading there
+        if TS::INIT_THREADS && unsafe { (functions.XInitThreads)() } == 0 {
+            return Err(Error::make_msg("failed to initialize threading"));
+        }
+
+        Self::connect_impl(name, functions)
+    }
+
+    fn connect_impl(name: Option<&CStr>, ffi: Ffi) -> Result<Self> {
         let display_name = name.map_or(null(), |name| name.as_ptr());
 
         // connect!
-        let conn = unsafe { xlib().XOpenDisplay(display_name) };
+        let conn = unsafe { ffi.XOpenDisplay(display_name) };
 
         // check for null
         if conn.is_null() {
             return Err(Error::make_msg("failed to connect to X server"));
         }
 
-        Ok(unsafe { Self::from_ptr(conn.cast(), true) })
+        Ok(unsafe { Self::from_ptr_with_ffi(conn, true, ffi) })
     }
 
     /// Create a new `XlibDisplay` from an existing pointer to an
@@ -102,13 +176,30 @@ impl<TS: ThreadSafety> XlibDisplay<TS> {
     ///
     /// The pointer must be a valid, non-null pointer to an X11 `Display`.
     pub unsafe fn from_ptr(ptr: *mut c_void, disconnect: bool) -> Self {
-        let conn: *mut XDisplay = ptr.cast();
+        Self::from_ptr_with_ffi(ptr.cast(), disconnect, xlib())
+    }
+
+    /// Create a new `XlibDisplay` from an existing pointer to an
+    /// X11 `Display`, calling `functions` instead of `libX11`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid, non-null pointer to an X11 `Display`,
+    /// and `functions` must behave like their `libX11` counterparts on it.
+    pub unsafe fn from_ptr_with_functions(
+        ptr: *mut c_void,
+        disconnect: bool,
+        functions: &'static XlibFunctions,
+    ) -> Self {
+        Self::from_ptr_with_ffi(ptr.cast(), disconnect, functions)
+    }
 
+    unsafe fn from_ptr_with_ffi(conn: *mut XDisplay, disconnect: bool, ffi: Ffi) -> Self {
         // get the default screen, needed for XcbDisplay innards
-        let screen = unsafe { xlib().XDefaultScreen(conn) };
+        let screen = unsafe { ffi.XDefaultScreen(conn) };
 
         // get the internal XCB connection
-        let xcb_conn = unsafe { xlib().XGetXCBConnection(conn) };
+        let xcb_conn = unsafe { ffi.XGetXCBConnection(conn) };
 
         // create the XcbDisplay
         let xcb = unsafe { XcbDisplay::from_ptr(xcb_conn.cast(), false, screen as usize) };
@@ -118,6 +209,14 @@ impl<TS: ThreadSafety> XlibDisplay<TS> {
             xcb,
             display: NonNull::new_unchecked(conn),
             disconnect,
+            ffi,
+            event_queue_owner: EventQueueOwner::Xlib,
+            #[cfg(all(debug_assertions, feature = "std"))]
+            creator: if TS::CHECK_USAGE {
+                Some(thread::current().id())
+            } else {
+                None
+            },
             _phantom: PhantomData,
         }
     }
@@ -133,6 +232,225 @@ impl<TS> XlibDisplay<TS> {
     pub fn as_xcb_connection(&self) -> *mut c_void {
         self.xcb.as_raw_connection()
     }
+
+    /// Get the display string this display was opened with.
+    ///
+    /// Equivalent to the `DisplayString` macro. Returns `None` if the
+    /// string isn't valid UTF-8.
+    pub fn display_name(&self) -> Option<&str> {
+        enter!(self);
+        let name = unsafe { self.ffi.XDisplayString(self.display.as_ptr()) };
+        if name.is_null() {
+            return None;
+        }
+
+        unsafe { CStr::from_ptr(name) }.to_str().ok()
+    }
+
+    /// Get the number of screens on this display.
+    ///
+    /// Equivalent to the `ScreenCount` macro.
+    pub fn screen_count(&self) -> usize {
+        enter!(self);
+        unsafe { self.ffi.XScreenCount(self.display.as_ptr()) as usize }
+    }
+
+    /// Get the root window of the default screen.
+    ///
+    /// Equivalent to the `DefaultRootWindow` macro.
+    pub fn default_root_window(&self) -> Window {
+        enter!(self);
+        unsafe { self.ffi.XDefaultRootWindow(self.display.as_ptr()) as Window }
+    }
+
+    /// Get the default graphics context for the given screen.
+    ///
+    /// Equivalent to the `DefaultGC` macro. The `GC` is owned by
+    /// `libX11` and must not be freed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `screen` is not less than [`screen_count`].
+    ///
+    /// [`screen_count`]: XlibDisplay::screen_count
+    pub fn default_gc(&self, screen: usize) -> *mut c_void {
+        enter!(self);
+        let screen = self.screen_index(screen);
+        unsafe { self.ffi.XDefaultGC(self.display.as_ptr(), screen) }
+    }
+
+    /// Get the black pixel value for the given screen.
+    ///
+    /// Equivalent to the `BlackPixel` macro.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `screen` is not less than [`screen_count`].
+    ///
+    /// [`screen_count`]: XlibDisplay::screen_count
+    pub fn black_pixel(&self, screen: usize) -> u32 {
+        enter!(self);
+        let screen = self.screen_index(screen);
+        unsafe { self.ffi.XBlackPixel(self.display.as_ptr(), screen) as u32 }
+    }
+
+    /// Get the white pixel value for the given screen.
+    ///
+    /// Equivalent to the `WhitePixel` macro.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `screen` is not less than [`screen_count`].
+    ///
+    /// [`screen_count`]: XlibDisplay::screen_count
+    pub fn white_pixel(&self, screen: usize) -> u32 {
+        enter!(self);
+        let screen = self.screen_index(screen);
+        unsafe { self.ffi.XWhitePixel(self.display.as_ptr(), screen) as u32 }
+    }
+
+    /// Get a pointer to the `libX11` `Screen` structure for the given
+    /// screen.
+    ///
+    /// Equivalent to the `ScreenOfDisplay` macro. The pointer is valid
+    /// for as long as this display is open.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `screen` is not less than [`screen_count`].
+    ///
+    /// [`screen_count`]: XlibDisplay::screen_count
+    pub fn screen_of_display(&self, screen: usize) -> *mut c_void {
+        enter!(self);
+        let screen = self.screen_index(screen);
+        unsafe { self.ffi.XScreenOfDisplay(self.display.as_ptr(), screen) }
+    }
+
+    /// Get the `libX11` information for the visual with the given ID.
+    ///
+    /// This allows a visual chosen through `breadx` to be passed to
+    /// APIs that expect an Xlib `Visual*` or `XVisualInfo`. Returns
+    /// `None` if no visual with the given ID exists.
+    pub fn visual_info(&self, visual: Visualid) -> Option<XVisualInfo> {
+        enter!(self);
+        let mut template = MaybeUninit::<XVisualInfo>::zeroed();
+        unsafe {
+            (*template.as_mut_ptr()).visualid = visual.into();
+        }
+
+        let mut count = 0;
+        let infos = unsafe {
+            self.ffi.XGetVisualInfo(
+                self.display.as_ptr(),
+                VISUAL_ID_MASK,
+                template.as_mut_ptr(),
+                &mut count,
+            )
+        };
+
+        if infos.is_null() {
+            return None;
+        }
+
+        // visual IDs are unique, so take the first match
+        let info = if count > 0 {
+            Some(unsafe { *infos })
+        } else {
+            None
+        };
+
+        unsafe {
+            self.ffi.XFree(infos.cast());
+        }
+
+        info
+    }
+
+    /// Get a pointer to the `libX11` `Visual` with the given ID.
+    ///
+    /// The pointer is valid for as long as this display is open.
+    pub fn visual(&self, visual: Visualid) -> Option<*mut c_void> {
+        self.visual_info(visual).map(|info| info.visual)
+    }
+
+    /// Get the library that reads events from the connection.
+    pub fn event_queue_owner(&self) -> EventQueueOwner {
+        self.event_queue_owner
+    }
+
+    /// Set the library that reads events from the connection.
+    ///
+    /// Equivalent to `XSetEventQueueOwner`. Displays wrapped with
+    /// [`from_ptr`] are assumed to have `libX11` as the owner; call this
+    /// if the owner was changed before they were wrapped.
+    ///
+    /// [`from_ptr`]: XlibDisplay::from_ptr
+    pub fn set_event_queue_owner(&mut self, owner: EventQueueOwner) {
+        enter!(self);
+        let owner_code = match owner {
+            EventQueueOwner::Xlib => XLIB_OWNS_EVENT_QUEUE,
+            EventQueueOwner::Xcb => XCB_OWNS_EVENT_QUEUE,
+        };
+
+        unsafe {
+            self.ffi
+                .XSetEventQueueOwner(self.display.as_ptr(), owner_code);
+        }
+        self.event_queue_owner = owner;
+    }
+
+    /// Take the next event out of the `libX11` event queue without
+    /// converting it, if there is one.
+    ///
+    /// This is for `libX11`-based code that needs the native `XEvent`,
+    /// alongside `breadx` events for everything else. Generic event data
+    /// can be fetched with `XGetEventData` on [`XlibEvent::as_mut_ptr`].
+    ///
+    /// Returns an error if `libxcb` owns the event queue, since `libX11`
+    /// never sees the events then.
+    pub fn poll_xlib_event(&mut self) -> Result<Option<XlibEvent>> {
+        enter!(self);
+        if self.event_queue_owner != EventQueueOwner::Xlib {
+            return Err(Error::make_msg(
+                "libX11 events are unavailable while libxcb owns the event queue",
+            ));
+        }
+
+        unsafe { events::poll_for_xlib_event(self.ffi, self.display.as_ptr(), &self.xcb) }
+    }
+
+    fn poll_for_event_impl(&self) -> Result<Option<Event>> {
+        match self.event_queue_owner {
+            EventQueueOwner::Xlib => unsafe {
+                events::poll_for_event(self.ffi, self.display.as_ptr(), &self.xcb)
+            },
+            EventQueueOwner::Xcb => (&self.xcb).poll_for_event(),
+        }
+    }
+
+    fn wait_for_event_impl(&self) -> Result<Event> {
+        match self.event_queue_owner {
+            EventQueueOwner::Xlib => unsafe {
+                events::wait_for_event(self.ffi, self.display.as_ptr(), &self.xcb)
+            },
+            EventQueueOwner::Xcb => (&self.xcb).wait_for_event(),
+        }
+    }
+
+    /// Convert a screen index to a C integer, checking that it's valid.
+    ///
+    /// `libX11` doesn't check screen indices, so passing an invalid
+    /// one to it is undefined behavior.
+    fn screen_index(&self, screen: usize) -> c_int {
+        let count = self.screen_count();
+        assert!(
+            screen < count,
+            "screen index {} is out of range for a display with {} screens",
+            screen,
+            count
+        );
+        screen as c_int
+    }
 }
 
 #[cfg(all(unix, feature = "to_socket"))]
@@ -152,10 +470,12 @@ impl<TS> DisplayBase for XlibDisplay<TS> {
     }
 
     fn poll_for_event(&mut self) -> Result<Option<Event>> {
-        self.xcb.poll_for_event()
+        enter!(self);
+        self.poll_for_event_impl()
     }
 
     fn poll_for_reply_raw(&mut self, seq: u64) -> Result<Option<RawReply>> {
+        enter!(self);
         self.xcb.poll_for_reply_raw(seq)
     }
 }
@@ -170,87 +490,113 @@ impl<TS> DisplayBase for &XlibDisplay<TS> {
     }
 
     fn poll_for_event(&mut self) -> Result<Option<Event>> {
-        (&self.xcb).poll_for_event()
+        enter!(self);
+        self.poll_for_event_impl()
     }
 
     fn poll_for_reply_raw(&mut self, seq: u64) -> Result<Option<RawReply>> {
+        enter!(self);
         (&self.xcb).poll_for_reply_raw(seq)
     }
 }
 
 impl<TS> Display for XlibDisplay<TS> {
     fn flush(&mut self) -> Result<()> {
+        enter!(self);
         self.xcb.flush()
     }
 
     fn generate_xid(&mut self) -> Result<u32> {
+        enter!(self);
         self.xcb.generate_xid()
     }
 
     fn maximum_request_length(&mut self) -> Result<usize> {
+        enter!(self);
         self.xcb.maximum_request_length()
     }
 
     fn send_request_raw(&mut self, req: RawRequest<'_, '_>) -> Result<u64> {
+        enter!(self);
         self.xcb.send_request_raw(req)
     }
 
     fn synchronize(&mut self) -> Result<()> {
+        enter!(self);
         self.xcb.synchronize()
     }
 
     fn wait_for_event(&mut self) -> Result<Event> {
-        self.xcb.wait_for_event()
+        enter!(self);
+        self.wait_for_event_impl()
     }
 
     fn wait_for_reply_raw(&mut self, seq: u64) -> Result<RawReply> {
+        enter!(self);
         self.xcb.wait_for_reply_raw(seq)
     }
 
     fn check_for_error(&mut self, seq: u64) -> Result<()> {
+        enter!(self);
         self.xcb.check_for_error(seq)
     }
 }
 
 impl<TS> Display for &XlibDisplay<TS> {
     fn flush(&mut self) -> Result<()> {
+        enter!(self);
         (&self.xcb).flush()
     }
 
     fn generate_xid(&mut self) -> Result<u32> {
+        enter!(self);
         (&self.xcb).generate_xid()
     }
 
     fn maximum_request_length(&mut self) -> Result<usize> {
+        enter!(self);
         (&self.xcb).maximum_request_length()
     }
 
     fn send_request_raw(&mut self, req: RawRequest<'_, '_>) -> Result<u64> {
+        enter!(self);
         (&self.xcb).send_request_raw(req)
     }
 
     fn synchronize(&mut self) -> Result<()> {
+        enter!(self);
         (&self.xcb).synchronize()
     }
 
     fn wait_for_event(&mut self) -> Result<Event> {
-        (&self.xcb).wait_for_event()
+        enter!(self);
+        self.wait_for_event_impl()
     }
 
     fn wait_for_reply_raw(&mut self, seq: u64) -> Result<RawReply> {
+        enter!(self);
         (&self.xcb).wait_for_reply_raw(seq)
     }
 
     fn check_for_error(&mut self, seq: u64) -> Result<()> {
+        enter!(self);
         (&self.xcb).check_for_error(seq)
     }
 }
 
 impl<TS> Drop for XlibDisplay<TS> {
     fn drop(&mut self) {
+        enter!(self);
+
         if self.disconnect {
             unsafe {
-                xlib().XCloseDisplay(self.display.as_ptr());
+                self.ffi.XCloseDisplay(self.display.as_ptr());
+            }
+            events::forget(self.display.as_ptr());
+        } else if events::is_installed(self.display.as_ptr()) {
+            // the display outlives us, so give its converters back
+            unsafe {
+                events::uninstall(self.ffi, self.display.as_ptr());
             }
         }
     }
@@ -258,9 +604,21 @@ impl<TS> Drop for XlibDisplay<TS> {
 
 mod __private {
     pub trait Sealed {
+        /// Whether concurrent use of the display should be detected.
+        const CHECK_USAGE: bool;
+        /// Whether `XInitThreads` must be called before connecting.
+        const INIT_THREADS: bool;
+
         fn __sealed_trait_marker() {}
     }
 
-    impl Sealed for super::ThreadUnsafe {}
-    impl Sealed for super::ThreadSafe {}
+    impl Sealed for super::ThreadUnsafe {
+        const CHECK_USAGE: bool = true;
+        const INIT_THREADS: bool = false;
+    }
+
+    impl Sealed for super::ThreadSafe {
+        const CHECK_USAGE: bool = false;
+        const INIT_THREADS: bool = true;
+    }
 }
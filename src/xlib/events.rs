@@ -0,0 +1,541 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Reading events from the `libX11` event queue.
+//!
+//! When `libX11` owns the event queue, it converts every event into an
+//! `XEvent` as it arrives, and `breadx` needs the wire format back. Core
+//! events can be converted back with `_XEventToWire`, but extension events
+//! can't, and the payload of a generic event is only reachable through
+//! `XGetEventData`, already converted by whichever library registered for
+//! the extension.
+//!
+//! So, before reading from the queue, we put our own converters in front of
+//! the ones registered for extension and generic events. They pass each
+//! event along to the converter they replaced and keep a copy of its wire
+//! format, which is looked up again once the event comes out of the queue.
+//! Events that no library registered for are still queued, as bare
+//! `XAnyEvent`s or cookies holding the wire format, instead of being
+//! dropped.
+
+use crate::{
+    collections::Map,
+    sync::{call_once, mtx_lock, Mutex, OnceCell},
+    xlib_ffi::{
+        Ffi, WireToEvent, WireToEventCookie, XAnyEvent, XDisplay, XEvent, XGenericEventCookie,
+        XWire, FIRST_EXTENSION_EVENT, GENERIC_EVENT,
+    },
+    XcbDisplay,
+};
+use alloc::{boxed::Box, collections::VecDeque, vec};
+use breadx::{protocol::Event, Result};
+use core::{fmt, mem::MaybeUninit, ptr, slice};
+use libc::{c_int, c_ulong, c_void};
+
+/// The number of converters `libX11` keeps for each kind of event.
+const SLOTS: usize = 128;
+
+/// The most events whose wire format is kept at once.
+///
+/// Events taken out of the queue by other code are never looked up, so
+/// their wire format has to be dropped eventually.
+const MAX_CAPTURED: usize = 256;
+
+/// The converters and captured events for each display, by its address.
+static CAPTURES: OnceCell<Mutex<Map<usize, Box<Captures>>>> = OnceCell::new();
+
+fn captures() -> &'static Mutex<Map<usize, Box<Captures>>> {
+    call_once(&CAPTURES, || Mutex::new(Map::default()))
+}
+
+struct Captures {
+    /// The `libX11` functions the display is used with.
+    xlib: Ffi,
+    /// The converters we replaced for extension events, by event code.
+    event_procs: [Option<WireToEvent>; SLOTS],
+    /// The converters we replaced for generic events, by extension opcode.
+    cookie_procs: [Option<WireToEventCookie>; SLOTS],
+    /// The converters that were registered when we first replaced each
+    /// slot. If a library registered after us calls our converter in turn,
+    /// calling its converter again would never end, so these are called
+    /// instead.
+    original_event_procs: [Option<WireToEvent>; SLOTS],
+    original_cookie_procs: [Option<WireToEventCookie>; SLOTS],
+    /// Whether we ever replaced each slot.
+    event_installed: [bool; SLOTS],
+    cookie_installed: [bool; SLOTS],
+    /// The number of our converters currently running.
+    depth: usize,
+    /// The wire format of recently converted events, oldest first.
+    captured: VecDeque<(Key, Box<[u8]>)>,
+}
+
+/// Identifies a converted event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    /// An extension event, by its type and serial number.
+    Event { type_: c_int, serial: c_ulong },
+    /// A generic event, by the address of its converted payload.
+    Cookie(usize),
+}
+
+impl Captures {
+    fn new(xlib: Ffi) -> Self {
+        Self {
+            xlib,
+            event_procs: [None; SLOTS],
+            cookie_procs: [None; SLOTS],
+            original_event_procs: [None; SLOTS],
+            original_cookie_procs: [None; SLOTS],
+            event_installed: [false; SLOTS],
+            cookie_installed: [false; SLOTS],
+            depth: 0,
+            captured: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, key: Key, wire: Box<[u8]>) {
+        if self.captured.len() == MAX_CAPTURED {
+            self.captured.pop_front();
+        }
+
+        self.captured.push_back((key, wire));
+    }
+
+    fn take(&mut self, key: Key) -> Option<Box<[u8]>> {
+        // the same serial number can be shared by several events, so take
+        // the oldest; the same payload address may have been used by an
+        // event that was freed since, so take the newest
+        let index = match key {
+            Key::Event { .. } => self.captured.iter().position(|&(k, _)| k == key),
+            Key::Cookie(_) => self.captured.iter().rposition(|&(k, _)| k == key),
+        }?;
+
+        // the events captured before this one have already left the queue
+        self.captured.drain(..index);
+        self.captured.pop_front().map(|(_, wire)| wire)
+    }
+}
+
+/// Put our converters in front of the ones registered with `display`,
+/// wherever they aren't already.
+///
+/// # Safety
+///
+/// `display` must be a valid `Display*` that `xlib` works with.
+pub(crate) unsafe fn install(xlib: Ffi, display: *mut XDisplay) {
+    // hold the display so no events are converted while the converters
+    // are being swapped
+    xlib.XLockDisplay(display);
+
+    {
+        let mut all = mtx_lock(captures());
+        let captures = all
+            .entry(display as usize)
+            .or_insert_with(|| Box::new(Captures::new(xlib)));
+
+        for slot in 0..SLOTS {
+            let code = slot as c_int;
+
+            if code >= FIRST_EXTENSION_EVENT {
+                let old = xlib.XESetWireToEvent(display, code, Some(capture_event));
+                if !is_capture_event(old) {
+                    captures.event_procs[slot] = old;
+                    if !captures.event_installed[slot] {
+                        captures.original_event_procs[slot] = old;
+                        captures.event_installed[slot] = true;
+                    }
+                }
+            }
+
+            let old = xlib.XESetWireToEventCookie(display, code | 0x80, Some(capture_cookie));
+            if !is_capture_cookie(old) {
+                captures.cookie_procs[slot] = old;
+                if !captures.cookie_installed[slot] {
+                    captures.original_cookie_procs[slot] = old;
+                    captures.cookie_installed[slot] = true;
+                }
+            }
+        }
+    }
+
+    xlib.XUnlockDisplay(display);
+}
+
+/// Tell whether our converters have been installed on `display`.
+pub(crate) fn is_installed(display: *mut XDisplay) -> bool {
+    mtx_lock(captures()).contains_key(&(display as usize))
+}
+
+/// Put back the converters we replaced on `display`, and forget about it.
+///
+/// # Safety
+///
+/// `display` must be a valid `Display*` that `xlib` works with.
+pub(crate) unsafe fn uninstall(xlib: Ffi, display: *mut XDisplay) {
+    xlib.XLockDisplay(display);
+
+    let removed = mtx_lock(captures()).remove(&(display as usize));
+    if let Some(captures) = removed {
+        for slot in 0..SLOTS {
+            let code = slot as c_int;
+
+            // if another converter replaced ours, leave it where it is
+            if captures.event_installed[slot] {
+                let current = xlib.XESetWireToEvent(display, code, captures.event_procs[slot]);
+                if !is_capture_event(current) {
+                    xlib.XESetWireToEvent(display, code, current);
+                }
+            }
+
+            if captures.cookie_installed[slot] {
+                let current =
+                    xlib.XESetWireToEventCookie(display, code | 0x80, captures.cookie_procs[slot]);
+                if !is_capture_cookie(current) {
+                    xlib.XESetWireToEventCookie(display, code | 0x80, current);
+                }
+            }
+        }
+    }
+
+    xlib.XUnlockDisplay(display);
+}
+
+/// Forget about `display` without touching it, e.g. once it is closed.
+pub(crate) fn forget(display: *mut XDisplay) {
+    mtx_lock(captures()).remove(&(display as usize));
+}
+
+fn is_capture_event(proc_: Option<WireToEvent>) -> bool {
+    matches!(proc_, Some(proc_) if proc_ as usize == capture_event as WireToEvent as usize)
+}
+
+fn is_capture_cookie(proc_: Option<WireToEventCookie>) -> bool {
+    matches!(proc_, Some(proc_) if proc_ as usize == capture_cookie as WireToEventCookie as usize)
+}
+
+/// Get the length of an event in its wire format.
+unsafe fn wire_len(wire: *const XWire) -> usize {
+    let wire = &*wire;
+    if wire[0] & 0x7F == GENERIC_EVENT as u8 {
+        let length = u32::from_ne_bytes([wire[4], wire[5], wire[6], wire[7]]);
+        32 + length as usize * 4
+    } else {
+        32
+    }
+}
+
+unsafe fn copy_wire(wire: *const XWire) -> Box<[u8]> {
+    slice::from_raw_parts(wire as *const u8, wire_len(wire)).into()
+}
+
+/// Converts extension events, keeping their wire format.
+unsafe extern "C" fn capture_event(
+    display: *mut XDisplay,
+    event: *mut XEvent,
+    wire: *mut XWire,
+) -> c_int {
+    let slot = usize::from((*wire)[0] & 0x7F);
+
+    let (xlib, proc_) = {
+        let mut all = mtx_lock(captures());
+        match all.get_mut(&(display as usize)) {
+            Some(captures) => {
+                let proc_ = if captures.depth > 0 {
+                    captures.original_event_procs[slot]
+                } else {
+                    captures.event_procs[slot]
+                };
+                captures.depth += 1;
+                (captures.xlib, proc_)
+            }
+            None => return 0,
+        }
+    };
+
+    let mut status = match proc_ {
+        Some(proc_) => proc_(display, event, wire),
+        None => 0,
+    };
+
+    // nothing knows about this event, so queue it as a bare event instead
+    // of dropping it
+    if status == 0 {
+        let any = event as *mut XAnyEvent;
+        ptr::write_bytes(event, 0, 1);
+        (*any).type_ = c_int::from((*wire)[0] & 0x7F);
+        (*any).serial = xlib._XSetLastRequestRead(display, wire);
+        (*any).send_event = c_int::from((*wire)[0] & 0x80 != 0);
+        (*any).display = display;
+        status = 1;
+    }
+
+    let mut all = mtx_lock(captures());
+    if let Some(captures) = all.get_mut(&(display as usize)) {
+        captures.depth -= 1;
+
+        let any = &*(event as *const XAnyEvent);
+        let key = Key::Event {
+            type_: any.type_,
+            serial: any.serial,
+        };
+        captures.push(key, copy_wire(wire));
+    }
+
+    status
+}
+
+/// Converts generic events, keeping their wire format.
+unsafe extern "C" fn capture_cookie(
+    display: *mut XDisplay,
+    cookie: *mut XGenericEventCookie,
+    wire: *mut XWire,
+) -> c_int {
+    let slot = usize::from((*wire)[1] & 0x7F);
+
+    let (xlib, proc_) = {
+        let mut all = mtx_lock(captures());
+        match all.get_mut(&(display as usize)) {
+            Some(captures) => {
+                let proc_ = if captures.depth > 0 {
+                    captures.original_cookie_procs[slot]
+                } else {
+                    captures.cookie_procs[slot]
+                };
+                captures.depth += 1;
+                (captures.xlib, proc_)
+            }
+            None => return 0,
+        }
+    };
+
+    let status = match proc_ {
+        Some(proc_) => proc_(display, cookie, wire),
+        None => 0,
+    };
+
+    // nothing knows about this extension, so the payload is the wire
+    // format itself; XFreeEventData frees it with free()
+    if status == 0 {
+        let len = wire_len(wire);
+        let data = libc::malloc(len);
+        if data.is_null() {
+            let mut all = mtx_lock(captures());
+            if let Some(captures) = all.get_mut(&(display as usize)) {
+                captures.depth -= 1;
+            }
+            return 0;
+        }
+        ptr::copy_nonoverlapping(wire as *const u8, data as *mut u8, len);
+
+        let wire = &*wire;
+        (*cookie).type_ = GENERIC_EVENT;
+        (*cookie).serial = xlib._XSetLastRequestRead(display, wire as *const XWire as *mut _);
+        (*cookie).send_event = c_int::from(wire[0] & 0x80 != 0);
+        (*cookie).display = display;
+        (*cookie).extension = c_int::from(wire[1]);
+        (*cookie).evtype = c_int::from(u16::from_ne_bytes([wire[8], wire[9]]));
+        (*cookie).data = data;
+    }
+
+    let mut all = mtx_lock(captures());
+    if let Some(captures) = all.get_mut(&(display as usize)) {
+        captures.depth -= 1;
+        captures.push(Key::Cookie((*cookie).data as usize), copy_wire(wire));
+    }
+
+    1
+}
+
+/// Take the next event out of the `libX11` queue, blocking until there
+/// is one.
+///
+/// # Safety
+///
+/// `display` must be a valid `Display*` that `xlib` works with, and `xcb`
+/// its connection.
+pub(crate) unsafe fn wait_for_event(
+    xlib: Ffi,
+    display: *mut XDisplay,
+    xcb: &XcbDisplay,
+) -> Result<Event> {
+    if !is_installed(display) {
+        install(xlib, display);
+    }
+
+    xcb.flush_before_wait()?;
+
+    let mut event = MaybeUninit::<XEvent>::uninit();
+    xlib.XNextEvent(display, event.as_mut_ptr());
+    convert(xlib, display, xcb, event.as_mut_ptr())
+}
+
+/// Take the next event out of the `libX11` queue, if there is one.
+///
+/// # Safety
+///
+/// `display` must be a valid `Display*` that `xlib` works with, and `xcb`
+/// its connection.
+pub(crate) unsafe fn poll_for_event(
+    xlib: Ffi,
+    display: *mut XDisplay,
+    xcb: &XcbDisplay,
+) -> Result<Option<Event>> {
+    if !is_installed(display) {
+        install(xlib, display);
+    }
+
+    if xlib.XPending(display) == 0 {
+        return match xcb.take_error() {
+            Some(err) => Err(err),
+            None => Ok(None),
+        };
+    }
+
+    let mut event = MaybeUninit::<XEvent>::uninit();
+    xlib.XNextEvent(display, event.as_mut_ptr());
+    convert(xlib, display, xcb, event.as_mut_ptr()).map(Some)
+}
+
+/// Convert an event from the `libX11` queue back to its wire format and
+/// parse it.
+unsafe fn convert(
+    xlib: Ffi,
+    display: *mut XDisplay,
+    xcb: &XcbDisplay,
+    event: *mut XEvent,
+) -> Result<Event> {
+    let (type_, serial, send_event) = {
+        let any = &*(event as *const XAnyEvent);
+        (any.type_, any.serial, any.send_event != 0)
+    };
+
+    let wire = if type_ == GENERIC_EVENT {
+        let cookie = event as *mut XGenericEventCookie;
+        if xlib.XGetEventData(display, cookie) != 0 {
+            let wire = take(display, Key::Cookie((*cookie).data as usize));
+            xlib.XFreeEventData(display, cookie);
+            wire
+        } else {
+            None
+        }
+    } else if type_ < FIRST_EXTENSION_EVENT {
+        let mut wire: XWire = [0; 32];
+        if xlib._XEventToWire(display, event, &mut wire) != 0 {
+            // KeymapNotify has no sequence number
+            if type_ != 11 {
+                wire[2..4].copy_from_slice(&(serial as u16).to_ne_bytes());
+            }
+            if send_event {
+                wire[0] |= 0x80;
+            }
+            Some(Box::from(&wire[..]))
+        } else {
+            None
+        }
+    } else {
+        take(display, Key::Event { type_, serial })
+    };
+
+    match wire {
+        Some(wire) => xcb.parse_event_bytes(&wire),
+        None => {
+            // a library registered after us may have replaced our
+            // converters, so put them back for the next event
+            install(xlib, display);
+
+            // all that's left is the header
+            let mut header = vec![0; 32];
+            header[0] = type_ as u8 | if send_event { 0x80 } else { 0 };
+            header[2..4].copy_from_slice(&(serial as u16).to_ne_bytes());
+            Ok(Event::Unknown(header))
+        }
+    }
+}
+
+fn take(display: *mut XDisplay, key: Key) -> Option<Box<[u8]>> {
+    mtx_lock(captures())
+        .get_mut(&(display as usize))
+        .and_then(|captures| captures.take(key))
+}
+
+/// An event as `libX11` converted it.
+///
+/// This is for passing events to `libX11`-based code that needs the
+/// native structure, such as `XFilterEvent` or toolkit dispatch. The event
+/// is stored in a full `XEvent`, so it can be passed where one is
+/// expected.
+#[derive(Clone, Copy)]
+pub struct XlibEvent {
+    event: XEvent,
+}
+
+impl XlibEvent {
+    /// Get the type of the event, e.g. `KeyPress`.
+    pub fn event_type(&self) -> c_int {
+        self.any().type_
+    }
+
+    /// Get the serial number of the last request processed by the server
+    /// before this event.
+    pub fn serial(&self) -> c_ulong {
+        self.any().serial
+    }
+
+    /// Tell whether the event came from a `SendEvent` request.
+    pub fn send_event(&self) -> bool {
+        self.any().send_event != 0
+    }
+
+    /// Get a pointer to the `XEvent`.
+    pub fn as_ptr(&self) -> *const c_void {
+        &self.event as *const XEvent as *const c_void
+    }
+
+    /// Get a mutable pointer to the `XEvent`.
+    pub fn as_mut_ptr(&mut self) -> *mut c_void {
+        &mut self.event as *mut XEvent as *mut c_void
+    }
+
+    fn any(&self) -> &XAnyEvent {
+        unsafe { &*(&self.event as *const XEvent as *const XAnyEvent) }
+    }
+}
+
+impl fmt::Debug for XlibEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("XlibEvent")
+            .field("event_type", &self.event_type())
+            .field("serial", &self.serial())
+            .field("send_event", &self.send_event())
+            .finish()
+    }
+}
+
+/// Take the next event out of the `libX11` queue as it is, if there is
+/// one.
+///
+/// # Safety
+///
+/// `display` must be a valid `Display*` that `xlib` works with, and `xcb`
+/// its connection.
+pub(crate) unsafe fn poll_for_xlib_event(
+    xlib: Ffi,
+    display: *mut XDisplay,
+    xcb: &XcbDisplay,
+) -> Result<Option<XlibEvent>> {
+    if xlib.XPending(display) == 0 {
+        return match xcb.take_error() {
+            Some(err) => Err(err),
+            None => Ok(None),
+        };
+    }
+
+    let mut event = MaybeUninit::<XEvent>::uninit();
+    xlib.XNextEvent(display, event.as_mut_ptr());
+    Ok(Some(XlibEvent {
+        event: event.assume_init(),
+    }))
+}
@@ -0,0 +1,82 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Detecting concurrent use of thread-unsafe displays.
+//!
+//! When `libX11` threading isn't initialized, using a `Display*` from two
+//! threads at once corrupts its internal state, which usually shows up as
+//! a crash somewhere unrelated. In debug builds, every call into a
+//! thread-unsafe display marks it as in use by the current thread, and a
+//! call from another thread while it is marked panics instead.
+//!
+//! Displays are tracked by their `Display*`, so this also catches two
+//! `XlibDisplay`s wrapping the same pointer.
+
+use crate::{
+    collections::Map,
+    sync::{call_once, mtx_lock, Mutex, OnceCell},
+    xlib_ffi::XDisplay,
+};
+use std::thread::{self, ThreadId};
+
+/// The thread using each display, by its address.
+static IN_USE: OnceCell<Mutex<Map<usize, ThreadId>>> = OnceCell::new();
+
+fn in_use() -> &'static Mutex<Map<usize, ThreadId>> {
+    call_once(&IN_USE, || Mutex::new(Map::default()))
+}
+
+/// Marks a display as in use by the current thread until dropped.
+pub(crate) struct UsageGuard {
+    /// The address of the display, or `None` if an outer guard on this
+    /// thread already marks it.
+    display: Option<usize>,
+}
+
+/// Mark `display` as in use by the current thread.
+///
+/// `creator` is the thread that created the display, and is only used to
+/// make the panic message more helpful.
+///
+/// # Panics
+///
+/// Panics if another thread is using the display.
+pub(crate) fn enter(display: *mut XDisplay, creator: ThreadId) -> UsageGuard {
+    let address = display as usize;
+    let current = thread::current().id();
+
+    let user = {
+        let mut in_use = mtx_lock(in_use());
+        match in_use.get(&address) {
+            Some(&user) => user,
+            None => {
+                in_use.insert(address, current);
+                return UsageGuard {
+                    display: Some(address),
+                };
+            }
+        }
+    };
+
+    // calls on the same thread may nest, e.g. through a callback
+    if user == current {
+        return UsageGuard { display: None };
+    }
+
+    panic!(
+        "a thread-unsafe XlibDisplay created on thread {:?} was used on thread {:?} \
+         while thread {:?} was using it; use XlibDisplay<ThreadSafe> to share a display \
+         between threads",
+        creator, current, user,
+    );
+}
+
+impl Drop for UsageGuard {
+    fn drop(&mut self) {
+        if let Some(address) = self.display {
+            mtx_lock(in_use()).remove(&address);
+        }
+    }
+}
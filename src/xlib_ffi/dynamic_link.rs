@@ -3,11 +3,53 @@
 //       (See accompanying file LICENSE or copy at
 //         https://www.boost.org/LICENSE_1_0.txt)
 
-use super::{X11Ffi, XDisplay};
-use crate::xcb_ffi::Connection;
-use libc::{c_char, c_int};
+use super::{
+    WireToEvent, WireToEventCookie, X11Ffi, XDisplay, XEvent, XGenericEventCookie, XVisualInfo,
+    XWire,
+};
+use crate::{dl::open_library, xcb_ffi::Connection};
+use libc::{c_char, c_int, c_long, c_ulong, c_void};
 use libloading::Library;
 
+/// The names `libX11` and `libX11-xcb` are looked for under.
+#[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+const LIBS: (&[&str], &[&str]) = (
+    &["libX11.so.6", "libX11.so", "/usr/local/lib/libX11.so.6"],
+    &[
+        "libX11-xcb.so.1",
+        "libX11-xcb.so",
+        "/usr/local/lib/libX11-xcb.so.1",
+    ],
+);
+#[cfg(target_os = "openbsd")]
+const LIBS: (&[&str], &[&str]) = (
+    &["libX11.so", "/usr/X11R6/lib/libX11.so"],
+    &["libX11-xcb.so", "/usr/X11R6/lib/libX11-xcb.so"],
+);
+#[cfg(target_os = "netbsd")]
+const LIBS: (&[&str], &[&str]) = (
+    &[
+        "libX11.so",
+        "/usr/X11R7/lib/libX11.so",
+        "/usr/pkg/lib/libX11.so",
+    ],
+    &[
+        "libX11-xcb.so",
+        "/usr/X11R7/lib/libX11-xcb.so",
+        "/usr/pkg/lib/libX11-xcb.so",
+    ],
+);
+#[cfg(not(any(
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "openbsd",
+    target_os = "netbsd"
+)))]
+const LIBS: (&[&str], &[&str]) = (
+    &["libX11.so.6", "libX11.so"],
+    &["libX11-xcb.so.1", "libX11-xcb.so"],
+);
+
 pub(crate) struct DynamicLink {
     _xlib: Library,
     _xlib_xcb: Library,
@@ -16,11 +58,15 @@ pub(crate) struct DynamicLink {
 
 impl DynamicLink {
     pub(crate) fn load() -> Self {
-        let xlib =
-            unsafe { Library::new("libX11.so.6") }.expect("Unable to open libX11 dynamically");
-        let xlib_xcb = unsafe { Library::new("libX11-xcb.so.1") }
-            .expect("Unable to open libX11-xcb dynamically");
+        let (xlib, xlib_xcb) = LIBS;
+        let xlib = open_library(xlib);
+        let xlib_xcb = open_library(xlib_xcb);
+
+        Self::from_libraries(xlib, xlib_xcb)
+    }
 
+    /// Use a `libX11` and `libX11-xcb` that have already been loaded.
+    pub(crate) fn from_libraries(xlib: Library, xlib_xcb: Library) -> Self {
         let funcs = unsafe { Funcs::load(&xlib, &xlib_xcb) };
 
         Self {
@@ -31,73 +77,81 @@ impl DynamicLink {
     }
 }
 
-unsafe impl X11Ffi for DynamicLink {
-    unsafe fn XCloseDisplay(&self, display: *mut XDisplay) -> c_int {
-        (self.funcs.XCloseDisplay)(display)
-    }
-
-    unsafe fn XDefaultScreen(&self, display: *mut XDisplay) -> c_int {
-        (self.funcs.XDefaultScreen)(display)
-    }
-
-    unsafe fn XGetXCBConnection(&self, display: *mut XDisplay) -> *mut Connection {
-        (self.funcs.XGetXCBConnection)(display)
-    }
+macro_rules! define_funcs {
+    (
+        libraries($($library: ident),*);
+        $($lib: ident => $name: ident ($($arg: ident: $arg_ty: ty),*) -> $ret_ty: ty),*
+    ) => {
+        #[allow(non_snake_case)]
+        struct Funcs {
+            $(
+                $name: unsafe extern "C" fn($($arg_ty),*) -> $ret_ty,
+            )*
+        }
 
-    unsafe fn XInitThreads(&self) -> c_int {
-        (self.funcs.XInitThreads)()
-    }
+        impl Funcs {
+            unsafe fn load($($library: &Library),*) -> Self {
+                Self {
+                    $(
+                    $name: {
+                        let symbol = concat!(stringify!($name), "\0").as_bytes();
+                        *($lib
+                            .get(symbol)
+                            .expect(concat!("Could not find symbol: ", stringify!($name))))
+                    },
+                    )*
+                }
+            }
+        }
 
-    unsafe fn XOpenDisplay(&self, display: *const libc::c_char) -> *mut XDisplay {
-        (self.funcs.XOpenDisplay)(display)
+        unsafe impl X11Ffi for DynamicLink {
+            $(
+                unsafe fn $name(&self, $($arg: $arg_ty),*) -> $ret_ty {
+                    (self.funcs.$name)($($arg),*)
+                }
+            )*
+        }
     }
 }
 
-#[allow(non_snake_case)]
-struct Funcs {
-    XOpenDisplay: unsafe extern "C" fn(*const c_char) -> *mut XDisplay,
-    XCloseDisplay: unsafe extern "C" fn(*mut XDisplay) -> c_int,
-    XDefaultScreen: unsafe extern "C" fn(*mut XDisplay) -> c_int,
-    XGetXCBConnection: unsafe extern "C" fn(*mut XDisplay) -> *mut Connection,
-    XInitThreads: unsafe extern "C" fn() -> c_int,
-}
-
-impl Funcs {
-    unsafe fn load(xlib: &Library, xlib_xcb: &Library) -> Self {
-        Self {
-            XOpenDisplay: {
-                let symbol = concat!("XOpenDisplay\0").as_bytes();
-                *(xlib
-                    .get(symbol)
-                    .expect(concat!("Could not find symbol: ", stringify!(XOpenDisplay))))
-            },
-            XCloseDisplay: {
-                let symbol = concat!("XCloseDisplay\0").as_bytes();
-                *(xlib.get(symbol).expect(concat!(
-                    "Could not find symbol: ",
-                    stringify!(XCloseDisplay)
-                )))
-            },
-            XDefaultScreen: {
-                let symbol = concat!("XDefaultScreen\0").as_bytes();
-                *(xlib.get(symbol).expect(concat!(
-                    "Could not find symbol: ",
-                    stringify!(XDefaultScreen)
-                )))
-            },
-            XGetXCBConnection: {
-                let symbol = concat!("XGetXCBConnection\0").as_bytes();
-                *(xlib_xcb.get(symbol).expect(concat!(
-                    "Could not find symbol: ",
-                    stringify!(XGetXCBConnection)
-                )))
-            },
-            XInitThreads: {
-                let symbol = concat!("XInitThreads\0").as_bytes();
-                *(xlib
-                    .get(symbol)
-                    .expect(concat!("Could not find symbol: ", stringify!(XInitThreads))))
-            },
-        }
-    }
+define_funcs! {
+    libraries(xlib, xlib_xcb);
+    xlib => XOpenDisplay(display: *const c_char) -> *mut XDisplay,
+    xlib => XCloseDisplay(display: *mut XDisplay) -> c_int,
+    xlib => XDefaultScreen(display: *mut XDisplay) -> c_int,
+    xlib => XScreenCount(display: *mut XDisplay) -> c_int,
+    xlib => XDisplayString(display: *mut XDisplay) -> *mut c_char,
+    xlib => XDefaultRootWindow(display: *mut XDisplay) -> c_ulong,
+    xlib => XDefaultGC(display: *mut XDisplay, screen: c_int) -> *mut c_void,
+    xlib => XBlackPixel(display: *mut XDisplay, screen: c_int) -> c_ulong,
+    xlib => XWhitePixel(display: *mut XDisplay, screen: c_int) -> c_ulong,
+    xlib => XScreenOfDisplay(display: *mut XDisplay, screen: c_int) -> *mut c_void,
+    xlib => XInitThreads() -> c_int,
+    xlib => XGetVisualInfo(
+        display: *mut XDisplay,
+        vinfo_mask: c_long,
+        vinfo_template: *mut XVisualInfo,
+        nitems_return: *mut c_int
+    ) -> *mut XVisualInfo,
+    xlib => XFree(data: *mut c_void) -> c_int,
+    xlib => XPending(display: *mut XDisplay) -> c_int,
+    xlib => XNextEvent(display: *mut XDisplay, event: *mut XEvent) -> c_int,
+    xlib => XGetEventData(display: *mut XDisplay, cookie: *mut XGenericEventCookie) -> c_int,
+    xlib => XFreeEventData(display: *mut XDisplay, cookie: *mut XGenericEventCookie) -> (),
+    xlib => XESetWireToEvent(
+        display: *mut XDisplay,
+        event_number: c_int,
+        proc_: Option<WireToEvent>
+    ) -> Option<WireToEvent>,
+    xlib => XESetWireToEventCookie(
+        display: *mut XDisplay,
+        extension: c_int,
+        proc_: Option<WireToEventCookie>
+    ) -> Option<WireToEventCookie>,
+    xlib => _XEventToWire(display: *mut XDisplay, event: *mut XEvent, wire: *mut XWire) -> c_int,
+    xlib => _XSetLastRequestRead(display: *mut XDisplay, reply: *mut XWire) -> c_ulong,
+    xlib => XLockDisplay(display: *mut XDisplay) -> (),
+    xlib => XUnlockDisplay(display: *mut XDisplay) -> (),
+    xlib_xcb => XGetXCBConnection(display: *mut XDisplay) -> *mut Connection,
+    xlib_xcb => XSetEventQueueOwner(display: *mut XDisplay, owner: c_int) -> ()
 }
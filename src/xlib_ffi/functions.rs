@@ -0,0 +1,232 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+use super::{
+    WireToEvent, WireToEventCookie, X11Ffi, XDisplay, XEvent, XGenericEventCookie, XVisualInfo,
+    XWire,
+};
+use crate::xcb_ffi::Connection;
+use core::mem;
+use libc::{c_char, c_int, c_long, c_ulong, c_void};
+
+/// A converter passed to `XESetWireToEvent`, taking a `Display*`, an
+/// `XEvent*` and a pointer to the wire format.
+pub type XlibWireToEvent =
+    unsafe extern "C" fn(display: *mut c_void, event: *mut c_void, wire: *mut c_void) -> c_int;
+
+/// A converter passed to `XESetWireToEventCookie`, taking a `Display*`, an
+/// `XGenericEventCookie*` and a pointer to the wire format.
+pub type XlibWireToEventCookie =
+    unsafe extern "C" fn(display: *mut c_void, cookie: *mut c_void, wire: *mut c_void) -> c_int;
+
+/// The `libX11` functions used by an [`XlibDisplay`].
+///
+/// By default, displays call the `libX11` that this crate links to or
+/// loads. A display created with [`XlibDisplay::from_ptr_with_functions`]
+/// calls these instead, which lets interposers, tests and hosts that
+/// expose their own symbol table stand in for the library. Each field has
+/// the same signature as the `libX11` function it is named after, with
+/// `void*` standing in for pointers to `libX11` types.
+///
+/// [`XlibDisplay`]: crate::XlibDisplay
+/// [`XlibDisplay::from_ptr_with_functions`]: crate::XlibDisplay::from_ptr_with_functions
+#[allow(non_snake_case, missing_docs)]
+#[derive(Debug, Clone, Copy)]
+pub struct XlibFunctions {
+    pub XOpenDisplay: unsafe extern "C" fn(display_name: *const c_char) -> *mut c_void,
+    pub XCloseDisplay: unsafe extern "C" fn(display: *mut c_void) -> c_int,
+    pub XDefaultScreen: unsafe extern "C" fn(display: *mut c_void) -> c_int,
+    pub XScreenCount: unsafe extern "C" fn(display: *mut c_void) -> c_int,
+    pub XDisplayString: unsafe extern "C" fn(display: *mut c_void) -> *mut c_char,
+    pub XDefaultRootWindow: unsafe extern "C" fn(display: *mut c_void) -> c_ulong,
+    pub XDefaultGC: unsafe extern "C" fn(display: *mut c_void, screen: c_int) -> *mut c_void,
+    pub XBlackPixel: unsafe extern "C" fn(display: *mut c_void, screen: c_int) -> c_ulong,
+    pub XWhitePixel: unsafe extern "C" fn(display: *mut c_void, screen: c_int) -> c_ulong,
+    pub XScreenOfDisplay: unsafe extern "C" fn(display: *mut c_void, screen: c_int) -> *mut c_void,
+    pub XGetXCBConnection: unsafe extern "C" fn(display: *mut c_void) -> *mut c_void,
+    pub XInitThreads: unsafe extern "C" fn() -> c_int,
+    pub XGetVisualInfo: unsafe extern "C" fn(
+        display: *mut c_void,
+        vinfo_mask: c_long,
+        vinfo_template: *mut XVisualInfo,
+        nitems_return: *mut c_int,
+    ) -> *mut XVisualInfo,
+    pub XFree: unsafe extern "C" fn(data: *mut c_void) -> c_int,
+    pub XPending: unsafe extern "C" fn(display: *mut c_void) -> c_int,
+    pub XNextEvent: unsafe extern "C" fn(display: *mut c_void, event: *mut c_void) -> c_int,
+    pub XGetEventData: unsafe extern "C" fn(display: *mut c_void, cookie: *mut c_void) -> c_int,
+    pub XFreeEventData: unsafe extern "C" fn(display: *mut c_void, cookie: *mut c_void),
+    pub XESetWireToEvent: unsafe extern "C" fn(
+        display: *mut c_void,
+        event_number: c_int,
+        proc_: Option<XlibWireToEvent>,
+    ) -> Option<XlibWireToEvent>,
+    pub XESetWireToEventCookie: unsafe extern "C" fn(
+        display: *mut c_void,
+        extension: c_int,
+        proc_: Option<XlibWireToEventCookie>,
+    ) -> Option<XlibWireToEventCookie>,
+    pub _XEventToWire:
+        unsafe extern "C" fn(display: *mut c_void, event: *mut c_void, wire: *mut c_void) -> c_int,
+    pub _XSetLastRequestRead:
+        unsafe extern "C" fn(display: *mut c_void, reply: *mut c_void) -> c_ulong,
+    pub XLockDisplay: unsafe extern "C" fn(display: *mut c_void),
+    pub XUnlockDisplay: unsafe extern "C" fn(display: *mut c_void),
+    pub XSetEventQueueOwner: unsafe extern "C" fn(display: *mut c_void, owner: c_int),
+}
+
+// the converters only differ from ours in the pointer types they take, so
+// they have the same ABI
+
+fn to_raw_event(proc_: Option<WireToEvent>) -> Option<XlibWireToEvent> {
+    proc_.map(|proc_| unsafe { mem::transmute::<WireToEvent, XlibWireToEvent>(proc_) })
+}
+
+fn from_raw_event(proc_: Option<XlibWireToEvent>) -> Option<WireToEvent> {
+    proc_.map(|proc_| unsafe { mem::transmute::<XlibWireToEvent, WireToEvent>(proc_) })
+}
+
+fn to_raw_cookie(proc_: Option<WireToEventCookie>) -> Option<XlibWireToEventCookie> {
+    proc_.map(|proc_| unsafe { mem::transmute::<WireToEventCookie, XlibWireToEventCookie>(proc_) })
+}
+
+fn from_raw_cookie(proc_: Option<XlibWireToEventCookie>) -> Option<WireToEventCookie> {
+    proc_.map(|proc_| unsafe { mem::transmute::<XlibWireToEventCookie, WireToEventCookie>(proc_) })
+}
+
+unsafe impl X11Ffi for XlibFunctions {
+    unsafe fn XOpenDisplay(&self, display: *const c_char) -> *mut XDisplay {
+        (self.XOpenDisplay)(display).cast()
+    }
+
+    unsafe fn XCloseDisplay(&self, display: *mut XDisplay) -> c_int {
+        (self.XCloseDisplay)(display.cast())
+    }
+
+    unsafe fn XDefaultScreen(&self, display: *mut XDisplay) -> c_int {
+        (self.XDefaultScreen)(display.cast())
+    }
+
+    unsafe fn XScreenCount(&self, display: *mut XDisplay) -> c_int {
+        (self.XScreenCount)(display.cast())
+    }
+
+    unsafe fn XDisplayString(&self, display: *mut XDisplay) -> *mut c_char {
+        (self.XDisplayString)(display.cast())
+    }
+
+    unsafe fn XDefaultRootWindow(&self, display: *mut XDisplay) -> c_ulong {
+        (self.XDefaultRootWindow)(display.cast())
+    }
+
+    unsafe fn XDefaultGC(&self, display: *mut XDisplay, screen: c_int) -> *mut c_void {
+        (self.XDefaultGC)(display.cast(), screen)
+    }
+
+    unsafe fn XBlackPixel(&self, display: *mut XDisplay, screen: c_int) -> c_ulong {
+        (self.XBlackPixel)(display.cast(), screen)
+    }
+
+    unsafe fn XWhitePixel(&self, display: *mut XDisplay, screen: c_int) -> c_ulong {
+        (self.XWhitePixel)(display.cast(), screen)
+    }
+
+    unsafe fn XScreenOfDisplay(&self, display: *mut XDisplay, screen: c_int) -> *mut c_void {
+        (self.XScreenOfDisplay)(display.cast(), screen)
+    }
+
+    unsafe fn XGetXCBConnection(&self, display: *mut XDisplay) -> *mut Connection {
+        (self.XGetXCBConnection)(display.cast()).cast()
+    }
+
+    unsafe fn XInitThreads(&self) -> c_int {
+        (self.XInitThreads)()
+    }
+
+    unsafe fn XGetVisualInfo(
+        &self,
+        display: *mut XDisplay,
+        vinfo_mask: c_long,
+        vinfo_template: *mut XVisualInfo,
+        nitems_return: *mut c_int,
+    ) -> *mut XVisualInfo {
+        (self.XGetVisualInfo)(display.cast(), vinfo_mask, vinfo_template, nitems_return)
+    }
+
+    unsafe fn XFree(&self, data: *mut c_void) -> c_int {
+        (self.XFree)(data)
+    }
+
+    unsafe fn XPending(&self, display: *mut XDisplay) -> c_int {
+        (self.XPending)(display.cast())
+    }
+
+    unsafe fn XNextEvent(&self, display: *mut XDisplay, event: *mut XEvent) -> c_int {
+        (self.XNextEvent)(display.cast(), event.cast())
+    }
+
+    unsafe fn XGetEventData(
+        &self,
+        display: *mut XDisplay,
+        cookie: *mut XGenericEventCookie,
+    ) -> c_int {
+        (self.XGetEventData)(display.cast(), cookie.cast())
+    }
+
+    unsafe fn XFreeEventData(&self, display: *mut XDisplay, cookie: *mut XGenericEventCookie) {
+        (self.XFreeEventData)(display.cast(), cookie.cast())
+    }
+
+    unsafe fn XESetWireToEvent(
+        &self,
+        display: *mut XDisplay,
+        event_number: c_int,
+        proc_: Option<WireToEvent>,
+    ) -> Option<WireToEvent> {
+        from_raw_event((self.XESetWireToEvent)(
+            display.cast(),
+            event_number,
+            to_raw_event(proc_),
+        ))
+    }
+
+    unsafe fn XESetWireToEventCookie(
+        &self,
+        display: *mut XDisplay,
+        extension: c_int,
+        proc_: Option<WireToEventCookie>,
+    ) -> Option<WireToEventCookie> {
+        from_raw_cookie((self.XESetWireToEventCookie)(
+            display.cast(),
+            extension,
+            to_raw_cookie(proc_),
+        ))
+    }
+
+    unsafe fn _XEventToWire(
+        &self,
+        display: *mut XDisplay,
+        event: *mut XEvent,
+        wire: *mut XWire,
+    ) -> c_int {
+        (self._XEventToWire)(display.cast(), event.cast(), wire.cast())
+    }
+
+    unsafe fn _XSetLastRequestRead(&self, display: *mut XDisplay, reply: *mut XWire) -> c_ulong {
+        (self._XSetLastRequestRead)(display.cast(), reply.cast())
+    }
+
+    unsafe fn XLockDisplay(&self, display: *mut XDisplay) {
+        (self.XLockDisplay)(display.cast())
+    }
+
+    unsafe fn XUnlockDisplay(&self, display: *mut XDisplay) {
+        (self.XUnlockDisplay)(display.cast())
+    }
+
+    unsafe fn XSetEventQueueOwner(&self, display: *mut XDisplay, owner: c_int) {
+        (self.XSetEventQueueOwner)(display.cast(), owner)
+    }
+}
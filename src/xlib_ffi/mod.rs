@@ -5,8 +5,14 @@
 
 #![cfg(feature = "xlib")]
 
-use crate::{sync::Lazy, xcb_ffi::Connection};
-use libc::{c_char, c_int};
+use crate::{
+    sync::{call_once, OnceCell},
+    xcb_ffi::Connection,
+};
+use libc::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+
+mod functions;
+pub use functions::{XlibFunctions, XlibWireToEvent, XlibWireToEventCookie};
 
 #[cfg(feature = "dl")]
 mod dynamic_link;
@@ -24,8 +30,85 @@ pub(crate) unsafe trait X11Ffi {
     unsafe fn XOpenDisplay(&self, display: *const c_char) -> *mut XDisplay;
     unsafe fn XCloseDisplay(&self, display: *mut XDisplay) -> c_int;
     unsafe fn XDefaultScreen(&self, display: *mut XDisplay) -> c_int;
+    unsafe fn XScreenCount(&self, display: *mut XDisplay) -> c_int;
+    unsafe fn XDisplayString(&self, display: *mut XDisplay) -> *mut c_char;
+    unsafe fn XDefaultRootWindow(&self, display: *mut XDisplay) -> c_ulong;
+    unsafe fn XDefaultGC(&self, display: *mut XDisplay, screen: c_int) -> *mut c_void;
+    unsafe fn XBlackPixel(&self, display: *mut XDisplay, screen: c_int) -> c_ulong;
+    unsafe fn XWhitePixel(&self, display: *mut XDisplay, screen: c_int) -> c_ulong;
+    unsafe fn XScreenOfDisplay(&self, display: *mut XDisplay, screen: c_int) -> *mut c_void;
     unsafe fn XGetXCBConnection(&self, display: *mut XDisplay) -> *mut Connection;
     unsafe fn XInitThreads(&self) -> c_int;
+    unsafe fn XGetVisualInfo(
+        &self,
+        display: *mut XDisplay,
+        vinfo_mask: c_long,
+        vinfo_template: *mut XVisualInfo,
+        nitems_return: *mut c_int,
+    ) -> *mut XVisualInfo;
+    unsafe fn XFree(&self, data: *mut c_void) -> c_int;
+    unsafe fn XPending(&self, display: *mut XDisplay) -> c_int;
+    unsafe fn XNextEvent(&self, display: *mut XDisplay, event: *mut XEvent) -> c_int;
+    unsafe fn XGetEventData(
+        &self,
+        display: *mut XDisplay,
+        cookie: *mut XGenericEventCookie,
+    ) -> c_int;
+    unsafe fn XFreeEventData(&self, display: *mut XDisplay, cookie: *mut XGenericEventCookie);
+    unsafe fn XESetWireToEvent(
+        &self,
+        display: *mut XDisplay,
+        event_number: c_int,
+        proc_: Option<WireToEvent>,
+    ) -> Option<WireToEvent>;
+    unsafe fn XESetWireToEventCookie(
+        &self,
+        display: *mut XDisplay,
+        extension: c_int,
+        proc_: Option<WireToEventCookie>,
+    ) -> Option<WireToEventCookie>;
+    unsafe fn _XEventToWire(
+        &self,
+        display: *mut XDisplay,
+        event: *mut XEvent,
+        wire: *mut XWire,
+    ) -> c_int;
+    unsafe fn _XSetLastRequestRead(&self, display: *mut XDisplay, reply: *mut XWire) -> c_ulong;
+    unsafe fn XLockDisplay(&self, display: *mut XDisplay);
+    unsafe fn XUnlockDisplay(&self, display: *mut XDisplay);
+    unsafe fn XSetEventQueueOwner(&self, display: *mut XDisplay, owner: c_int);
+}
+
+/// Only match visuals with the same ID as the template.
+pub(crate) const VISUAL_ID_MASK: c_long = 0x1;
+
+/// Information about a visual, as returned by `XGetVisualInfo`.
+///
+/// This is laid out identically to the `XVisualInfo` structure from
+/// `libX11`.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+pub struct XVisualInfo {
+    /// Pointer to the `libX11` `Visual`.
+    pub visual: *mut c_void,
+    /// The ID of the visual.
+    pub visualid: c_ulong,
+    /// The screen that the visual belongs to.
+    pub screen: c_int,
+    /// The depth of the visual.
+    pub depth: c_int,
+    /// The class of the visual, e.g. `TrueColor`.
+    pub class: c_int,
+    /// The mask for the red component of a pixel.
+    pub red_mask: c_ulong,
+    /// The mask for the green component of a pixel.
+    pub green_mask: c_ulong,
+    /// The mask for the blue component of a pixel.
+    pub blue_mask: c_ulong,
+    /// The number of entries in the colormap.
+    pub colormap_size: c_int,
+    /// The number of significant bits in each RGB component.
+    pub bits_per_rgb: c_int,
 }
 
 #[repr(C)]
@@ -33,21 +116,105 @@ pub(crate) struct XDisplay {
     _opaque_type: [u8; 0],
 }
 
+/// Storage for any `libX11` event, laid out like the `XEvent` union.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub(crate) struct XEvent {
+    pub(crate) pad: [c_long; 24],
+}
+
+/// The fields shared by every `libX11` event.
+#[repr(C)]
+pub(crate) struct XAnyEvent {
+    pub(crate) type_: c_int,
+    pub(crate) serial: c_ulong,
+    pub(crate) send_event: c_int,
+    pub(crate) display: *mut XDisplay,
+    pub(crate) window: c_ulong,
+}
+
+/// A generic event, whose payload is fetched with `XGetEventData`.
+#[repr(C)]
+pub(crate) struct XGenericEventCookie {
+    pub(crate) type_: c_int,
+    pub(crate) serial: c_ulong,
+    pub(crate) send_event: c_int,
+    pub(crate) display: *mut XDisplay,
+    pub(crate) extension: c_int,
+    pub(crate) evtype: c_int,
+    pub(crate) cookie: c_uint,
+    pub(crate) data: *mut c_void,
+}
+
+/// An event in its wire format. Generic events may extend past the end.
+pub(crate) type XWire = [u8; 32];
+
+/// Converts an event from its wire format.
+pub(crate) type WireToEvent =
+    unsafe extern "C" fn(display: *mut XDisplay, event: *mut XEvent, wire: *mut XWire) -> c_int;
+
+/// Converts a generic event from its wire format.
+pub(crate) type WireToEventCookie = unsafe extern "C" fn(
+    display: *mut XDisplay,
+    cookie: *mut XGenericEventCookie,
+    wire: *mut XWire,
+) -> c_int;
+
+/// The event code of generic events.
+pub(crate) const GENERIC_EVENT: c_int = 35;
+
+/// The first event code available to extensions.
+pub(crate) const FIRST_EXTENSION_EVENT: c_int = 64;
+
+/// Passed to `XSetEventQueueOwner` to let `libX11` read events.
+pub(crate) const XLIB_OWNS_EVENT_QUEUE: c_int = 0;
+
+/// Passed to `XSetEventQueueOwner` to let `libxcb` users read events.
+pub(crate) const XCB_OWNS_EVENT_QUEUE: c_int = 1;
+
 #[cfg(not(feature = "dl"))]
 type Impl = static_link::StaticLink;
 #[cfg(feature = "dl")]
 type Impl = dynamic_link::DynamicLink;
 
-static XLIB: Lazy<Impl> = Lazy::new(|| {
-    cfg_if::cfg_if! {
-        if #[cfg(not(feature = "dl"))] {
-            static_link::StaticLink
-        } else {
-            dynamic_link::DynamicLink::load()
-        }
-    }
-});
+/// Global object used to make `libX11` calls.
+///
+/// This is set the first time it's used, unless the application supplies
+/// the libraries beforehand.
+static XLIB: OnceCell<Impl> = OnceCell::new();
 
 pub(crate) fn xlib() -> &'static Impl {
-    &*XLIB
+    call_once(&XLIB, || {
+        cfg_if::cfg_if! {
+            if #[cfg(not(feature = "dl"))] {
+                static_link::StaticLink
+            } else {
+                dynamic_link::DynamicLink::load()
+            }
+        }
+    })
+}
+
+/// The `libX11` functions used by a display.
+pub(crate) type Ffi = &'static (dyn X11Ffi + Send + Sync);
+
+/// Use an already-loaded `libX11` and `libX11-xcb` instead of loading
+/// them.
+///
+/// Gives the libraries back if `libX11` has already been loaded.
+#[cfg(feature = "dl")]
+pub(crate) fn use_libraries(
+    xlib: libloading::Library,
+    xlib_xcb: libloading::Library,
+) -> Result<(), (libloading::Library, libloading::Library)> {
+    let mut libraries = Some((xlib, xlib_xcb));
+    call_once(&XLIB, || {
+        let (xlib, xlib_xcb) = libraries.take().unwrap();
+        dynamic_link::DynamicLink::from_libraries(xlib, xlib_xcb)
+    });
+
+    match libraries {
+        Some(libraries) => Err(libraries),
+        None => Ok(()),
+    }
 }
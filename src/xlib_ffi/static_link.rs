@@ -5,9 +5,12 @@
 
 #![cfg(not(feature = "dl"))]
 
-use super::{X11Ffi, XDisplay};
+use super::{
+    WireToEvent, WireToEventCookie, X11Ffi, XDisplay, XEvent, XGenericEventCookie, XVisualInfo,
+    XWire,
+};
 use crate::xcb_ffi::Connection;
-use libc::{c_char, c_int};
+use libc::{c_char, c_int, c_long, c_ulong, c_void};
 
 pub(crate) struct StaticLink;
 
@@ -24,6 +27,34 @@ unsafe impl X11Ffi for StaticLink {
         XDefaultScreen(display)
     }
 
+    unsafe fn XScreenCount(&self, display: *mut XDisplay) -> c_int {
+        XScreenCount(display)
+    }
+
+    unsafe fn XDisplayString(&self, display: *mut XDisplay) -> *mut c_char {
+        XDisplayString(display)
+    }
+
+    unsafe fn XDefaultRootWindow(&self, display: *mut XDisplay) -> c_ulong {
+        XDefaultRootWindow(display)
+    }
+
+    unsafe fn XDefaultGC(&self, display: *mut XDisplay, screen: c_int) -> *mut c_void {
+        XDefaultGC(display, screen)
+    }
+
+    unsafe fn XBlackPixel(&self, display: *mut XDisplay, screen: c_int) -> c_ulong {
+        XBlackPixel(display, screen)
+    }
+
+    unsafe fn XWhitePixel(&self, display: *mut XDisplay, screen: c_int) -> c_ulong {
+        XWhitePixel(display, screen)
+    }
+
+    unsafe fn XScreenOfDisplay(&self, display: *mut XDisplay, screen: c_int) -> *mut c_void {
+        XScreenOfDisplay(display, screen)
+    }
+
     unsafe fn XGetXCBConnection(&self, display: *mut XDisplay) -> *mut Connection {
         XGetXCBConnection(display)
     }
@@ -31,17 +62,128 @@ unsafe impl X11Ffi for StaticLink {
     unsafe fn XInitThreads(&self) -> c_int {
         XInitThreads()
     }
+
+    unsafe fn XGetVisualInfo(
+        &self,
+        display: *mut XDisplay,
+        vinfo_mask: c_long,
+        vinfo_template: *mut XVisualInfo,
+        nitems_return: *mut c_int,
+    ) -> *mut XVisualInfo {
+        XGetVisualInfo(display, vinfo_mask, vinfo_template, nitems_return)
+    }
+
+    unsafe fn XFree(&self, data: *mut c_void) -> c_int {
+        XFree(data)
+    }
+
+    unsafe fn XPending(&self, display: *mut XDisplay) -> c_int {
+        XPending(display)
+    }
+
+    unsafe fn XNextEvent(&self, display: *mut XDisplay, event: *mut XEvent) -> c_int {
+        XNextEvent(display, event)
+    }
+
+    unsafe fn XGetEventData(
+        &self,
+        display: *mut XDisplay,
+        cookie: *mut XGenericEventCookie,
+    ) -> c_int {
+        XGetEventData(display, cookie)
+    }
+
+    unsafe fn XFreeEventData(&self, display: *mut XDisplay, cookie: *mut XGenericEventCookie) {
+        XFreeEventData(display, cookie)
+    }
+
+    unsafe fn XESetWireToEvent(
+        &self,
+        display: *mut XDisplay,
+        event_number: c_int,
+        proc_: Option<WireToEvent>,
+    ) -> Option<WireToEvent> {
+        XESetWireToEvent(display, event_number, proc_)
+    }
+
+    unsafe fn XESetWireToEventCookie(
+        &self,
+        display: *mut XDisplay,
+        extension: c_int,
+        proc_: Option<WireToEventCookie>,
+    ) -> Option<WireToEventCookie> {
+        XESetWireToEventCookie(display, extension, proc_)
+    }
+
+    unsafe fn _XEventToWire(
+        &self,
+        display: *mut XDisplay,
+        event: *mut XEvent,
+        wire: *mut XWire,
+    ) -> c_int {
+        _XEventToWire(display, event, wire)
+    }
+
+    unsafe fn _XSetLastRequestRead(&self, display: *mut XDisplay, reply: *mut XWire) -> c_ulong {
+        _XSetLastRequestRead(display, reply)
+    }
+
+    unsafe fn XLockDisplay(&self, display: *mut XDisplay) {
+        XLockDisplay(display)
+    }
+
+    unsafe fn XUnlockDisplay(&self, display: *mut XDisplay) {
+        XUnlockDisplay(display)
+    }
+
+    unsafe fn XSetEventQueueOwner(&self, display: *mut XDisplay, owner: c_int) {
+        XSetEventQueueOwner(display, owner)
+    }
 }
 
-#[link(name = "X11")]
+// with the `static` feature, the build script links the archives
+#[cfg_attr(not(feature = "static"), link(name = "X11"))]
 extern "C" {
     fn XOpenDisplay(display: *const c_char) -> *mut XDisplay;
     fn XCloseDisplay(display: *mut XDisplay) -> c_int;
     fn XDefaultScreen(display: *mut XDisplay) -> c_int;
+    fn XScreenCount(display: *mut XDisplay) -> c_int;
+    fn XDisplayString(display: *mut XDisplay) -> *mut c_char;
+    fn XDefaultRootWindow(display: *mut XDisplay) -> c_ulong;
+    fn XDefaultGC(display: *mut XDisplay, screen: c_int) -> *mut c_void;
+    fn XBlackPixel(display: *mut XDisplay, screen: c_int) -> c_ulong;
+    fn XWhitePixel(display: *mut XDisplay, screen: c_int) -> c_ulong;
+    fn XScreenOfDisplay(display: *mut XDisplay, screen: c_int) -> *mut c_void;
     fn XInitThreads() -> c_int;
+    fn XGetVisualInfo(
+        display: *mut XDisplay,
+        vinfo_mask: c_long,
+        vinfo_template: *mut XVisualInfo,
+        nitems_return: *mut c_int,
+    ) -> *mut XVisualInfo;
+    fn XFree(data: *mut c_void) -> c_int;
+    fn XPending(display: *mut XDisplay) -> c_int;
+    fn XNextEvent(display: *mut XDisplay, event: *mut XEvent) -> c_int;
+    fn XGetEventData(display: *mut XDisplay, cookie: *mut XGenericEventCookie) -> c_int;
+    fn XFreeEventData(display: *mut XDisplay, cookie: *mut XGenericEventCookie);
+    fn XESetWireToEvent(
+        display: *mut XDisplay,
+        event_number: c_int,
+        proc_: Option<WireToEvent>,
+    ) -> Option<WireToEvent>;
+    fn XESetWireToEventCookie(
+        display: *mut XDisplay,
+        extension: c_int,
+        proc_: Option<WireToEventCookie>,
+    ) -> Option<WireToEventCookie>;
+    fn _XEventToWire(display: *mut XDisplay, event: *mut XEvent, wire: *mut XWire) -> c_int;
+    fn _XSetLastRequestRead(display: *mut XDisplay, reply: *mut XWire) -> c_ulong;
+    fn XLockDisplay(display: *mut XDisplay);
+    fn XUnlockDisplay(display: *mut XDisplay);
 }
 
-#[link(name = "X11-xcb")]
+#[cfg_attr(not(feature = "static"), link(name = "X11-xcb"))]
 extern "C" {
     fn XGetXCBConnection(display: *mut XDisplay) -> *mut Connection;
+    fn XSetEventQueueOwner(display: *mut XDisplay, owner: c_int);
 }
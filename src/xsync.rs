@@ -0,0 +1,307 @@
+//               Copyright John Nunley, 2022.
+// Distributed under the Boost Software License, Version 1.0.
+//       (See accompanying file LICENSE or copy at
+//         https://www.boost.org/LICENSE_1_0.txt)
+
+//! Counters, alarms and fences from the SYNC extension.
+//!
+//! SYNC counters hold 64-bit values, which the protocol splits into a
+//! signed high half and an unsigned low half. The functions in this module
+//! take and return plain `i64`s instead; [`to_int64`] and [`from_int64`]
+//! convert between the two for requests made directly.
+//!
+//! The server needs every client to initialize the extension before using
+//! it, which the functions that create objects take care of.
+
+use alloc::vec::Vec;
+use breadx::{
+    display::{Display, DisplayExt, DisplayFunctionsExt},
+    protocol::{
+        sync::{ChangeAlarmAux, CreateAlarmAux, Int64, ALARMSTATE, TESTTYPE, VALUETYPE},
+        xproto::{Drawable, Timestamp},
+        Event,
+    },
+    Result,
+};
+
+pub use breadx::protocol::sync::{Alarm as AlarmId, Counter, Fence};
+
+/// Convert a value to the protocol's 64-bit encoding.
+pub fn to_int64(value: i64) -> Int64 {
+    Int64 {
+        hi: (value >> 32) as i32,
+        lo: value as u32,
+    }
+}
+
+/// Convert a value from the protocol's 64-bit encoding.
+pub fn from_int64(value: Int64) -> i64 {
+    (i64::from(value.hi) << 32) | i64::from(value.lo)
+}
+
+/// Initialize the SYNC extension for this client.
+fn use_sync<D: Display + ?Sized>(display: &mut D) -> Result<()> {
+    let version = display.sync_initialize(3, 1)?;
+    display.wait_for_reply(version)?;
+    Ok(())
+}
+
+/// Create a counter with the given initial value.
+pub fn create_counter<D: Display + ?Sized>(display: &mut D, initial_value: i64) -> Result<Counter> {
+    use_sync(display)?;
+
+    let counter = display.generate_xid()?;
+    display.sync_create_counter(counter, to_int64(initial_value))?;
+    Ok(counter)
+}
+
+/// Set the value of a counter.
+pub fn set_counter<D: Display + ?Sized>(
+    display: &mut D,
+    counter: Counter,
+    value: i64,
+) -> Result<()> {
+    display.sync_set_counter(counter, to_int64(value))?;
+    Ok(())
+}
+
+/// Add `amount` to the value of a counter.
+pub fn change_counter<D: Display + ?Sized>(
+    display: &mut D,
+    counter: Counter,
+    amount: i64,
+) -> Result<()> {
+    display.sync_change_counter(counter, to_int64(amount))?;
+    Ok(())
+}
+
+/// Get the current value of a counter.
+pub fn query_counter<D: Display + ?Sized>(display: &mut D, counter: Counter) -> Result<i64> {
+    let value = display.sync_query_counter(counter)?;
+    Ok(from_int64(display.wait_for_reply(value)?.counter_value))
+}
+
+/// Destroy a counter created with [`create_counter`].
+pub fn destroy_counter<D: Display + ?Sized>(display: &mut D, counter: Counter) -> Result<()> {
+    display.sync_destroy_counter(counter)?;
+    Ok(())
+}
+
+/// A counter maintained by the server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SystemCounter {
+    /// The counter.
+    pub counter: Counter,
+    /// The name of the counter, such as `SERVERTIME` or `IDLETIME`.
+    pub name: Vec<u8>,
+    /// The smallest change in value the counter can report.
+    pub resolution: i64,
+}
+
+/// List the counters maintained by the server.
+pub fn system_counters<D: Display + ?Sized>(display: &mut D) -> Result<Vec<SystemCounter>> {
+    use_sync(display)?;
+
+    let counters = display.sync_list_system_counters()?;
+    let counters = display.wait_for_reply(counters)?.counters;
+
+    Ok(counters
+        .into_iter()
+        .map(|counter| SystemCounter {
+            counter: counter.counter,
+            name: counter.name,
+            resolution: from_int64(counter.resolution),
+        })
+        .collect())
+}
+
+/// Find the server-maintained counter with the given name.
+pub fn system_counter<D: Display + ?Sized>(
+    display: &mut D,
+    name: &[u8],
+) -> Result<Option<Counter>> {
+    Ok(system_counters(display)?
+        .into_iter()
+        .find(|counter| counter.name == name)
+        .map(|counter| counter.counter))
+}
+
+/// When an alarm triggers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmTrigger {
+    /// The counter the alarm watches.
+    pub counter: Counter,
+    /// Whether `value` is absolute, or relative to the counter's value
+    /// when the alarm is created or changed.
+    pub value_type: VALUETYPE,
+    /// The value compared with the counter.
+    pub value: i64,
+    /// How the counter is compared with `value`.
+    pub test_type: TESTTYPE,
+    /// The amount added to `value` each time the alarm triggers. With a
+    /// delta of zero, the alarm only triggers once.
+    pub delta: i64,
+}
+
+impl AlarmTrigger {
+    fn aux(&self) -> CreateAlarmAux {
+        CreateAlarmAux::new()
+            .counter(self.counter)
+            .value_type(self.value_type)
+            .value(to_int64(self.value))
+            .test_type(self.test_type)
+            .delta(to_int64(self.delta))
+            .events(1u32)
+    }
+}
+
+/// An alarm, destroyed when dropped.
+///
+/// The alarm owns a handle to the display, which is usually a shared
+/// reference such as `&XcbDisplay`. The server sends an `AlarmNotify`
+/// event, which [`alarm_notify`] decodes, each time the alarm triggers.
+pub struct Alarm<D: Display> {
+    display: D,
+    alarm: AlarmId,
+    /// Whether the alarm was already destroyed by `destroy`.
+    destroyed: bool,
+}
+
+impl<D: Display> Alarm<D> {
+    /// Create an alarm.
+    pub fn new(mut display: D, trigger: &AlarmTrigger) -> Result<Self> {
+        use_sync(&mut display)?;
+
+        let alarm = display.generate_xid()?;
+        display.sync_create_alarm(alarm, trigger.aux())?;
+
+        Ok(Self {
+            display,
+            alarm,
+            destroyed: false,
+        })
+    }
+
+    /// Get the ID of the alarm.
+    pub fn id(&self) -> AlarmId {
+        self.alarm
+    }
+
+    /// Change when the alarm triggers.
+    pub fn change(&mut self, trigger: &AlarmTrigger) -> Result<()> {
+        // the aux types of the two requests are identical
+        let aux = trigger.aux();
+        let aux = ChangeAlarmAux {
+            counter: aux.counter,
+            value_type: aux.value_type,
+            value: aux.value,
+            test_type: aux.test_type,
+            delta: aux.delta,
+            events: aux.events,
+        };
+
+        self.display.sync_change_alarm(self.alarm, aux)?;
+        Ok(())
+    }
+
+    /// Get the state of the alarm.
+    pub fn state(&mut self) -> Result<ALARMSTATE> {
+        let alarm = self.display.sync_query_alarm(self.alarm)?;
+        Ok(self.display.wait_for_reply(alarm)?.state)
+    }
+
+    /// Destroy the alarm now, reporting any errors.
+    pub fn destroy(mut self) -> Result<()> {
+        self.destroyed = true;
+        self.destroy_impl()
+    }
+
+    fn destroy_impl(&mut self) -> Result<()> {
+        self.display.sync_destroy_alarm(self.alarm)?;
+        self.display.flush()
+    }
+}
+
+impl<D: Display> Drop for Alarm<D> {
+    fn drop(&mut self) {
+        // errors can't be reported from a destructor
+        if !self.destroyed {
+            let _ = self.destroy_impl();
+        }
+    }
+}
+
+/// A decoded `AlarmNotify` event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AlarmNotify {
+    /// The alarm that triggered.
+    pub alarm: AlarmId,
+    /// The value of the counter when the alarm triggered.
+    pub counter_value: i64,
+    /// The value the alarm was waiting for.
+    pub alarm_value: i64,
+    /// The server time at which the alarm triggered.
+    pub timestamp: Timestamp,
+    /// The state of the alarm after triggering.
+    pub state: ALARMSTATE,
+}
+
+/// Decode an `AlarmNotify` event.
+pub fn alarm_notify(event: &Event) -> Option<AlarmNotify> {
+    match event {
+        Event::SyncAlarmNotify(event) => Some(AlarmNotify {
+            alarm: event.alarm,
+            counter_value: from_int64(event.counter_value),
+            alarm_value: from_int64(event.alarm_value),
+            timestamp: event.timestamp,
+            state: event.state,
+        }),
+        _ => None,
+    }
+}
+
+/// Create a fence on the screen of `drawable`.
+pub fn create_fence<D: Display + ?Sized>(
+    display: &mut D,
+    drawable: Drawable,
+    triggered: bool,
+) -> Result<Fence> {
+    use_sync(display)?;
+
+    let fence = display.generate_xid()?;
+    display.sync_create_fence(drawable, fence, triggered)?;
+    Ok(fence)
+}
+
+/// Trigger a fence once the requests sent before it have completed.
+pub fn trigger_fence<D: Display + ?Sized>(display: &mut D, fence: Fence) -> Result<()> {
+    display.sync_trigger_fence(fence)?;
+    Ok(())
+}
+
+/// Put a triggered fence back into the untriggered state.
+pub fn reset_fence<D: Display + ?Sized>(display: &mut D, fence: Fence) -> Result<()> {
+    display.sync_reset_fence(fence)?;
+    Ok(())
+}
+
+/// Tell whether a fence has been triggered.
+pub fn query_fence<D: Display + ?Sized>(display: &mut D, fence: Fence) -> Result<bool> {
+    let fence = display.sync_query_fence(fence)?;
+    Ok(display.wait_for_reply(fence)?.triggered)
+}
+
+/// Destroy a fence created with [`create_fence`].
+pub fn destroy_fence<D: Display + ?Sized>(display: &mut D, fence: Fence) -> Result<()> {
+    display.sync_destroy_fence(fence)?;
+    Ok(())
+}
+
+/// Block until one of `fences` is triggered.
+///
+/// The server stops processing this client's requests until a fence is
+/// triggered, and this waits for it to start again.
+pub fn await_fences<D: Display + ?Sized>(display: &mut D, fences: &[Fence]) -> Result<()> {
+    display.sync_await_fence(fences)?;
+    display.synchronize()
+}